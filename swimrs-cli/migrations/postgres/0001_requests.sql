@@ -0,0 +1,16 @@
+-- Request queue shared by workers on any number of hosts.
+CREATE TABLE requests (
+    id TEXT PRIMARY KEY,
+    state TEXT NOT NULL,
+    request TEXT NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    num_results INTEGER,
+    error TEXT,
+    duration DOUBLE PRECISION,
+    fetch_duration DOUBLE PRECISION,
+    parse_duration DOUBLE PRECISION,
+    next_eligible_at BIGINT NOT NULL,
+    updated_at BIGINT NOT NULL
+);
+
+CREATE INDEX requests_state ON requests (state, next_eligible_at);
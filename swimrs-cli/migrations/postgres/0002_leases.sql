@@ -0,0 +1,5 @@
+-- Leases let workers in several processes share the queue. A job in flight
+-- belongs to lease_owner until lease_expires_at, a Unix timestamp.
+ALTER TABLE requests
+    ADD COLUMN lease_owner TEXT,
+    ADD COLUMN lease_expires_at BIGINT;
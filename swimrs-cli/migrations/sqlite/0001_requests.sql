@@ -0,0 +1,17 @@
+-- Request queue. Uses IF NOT EXISTS so that databases created before
+-- migrations were introduced are adopted as-is.
+CREATE TABLE IF NOT EXISTS requests (
+    id TEXT PRIMARY KEY,
+    state TEXT NOT NULL,
+    request TEXT NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    num_results INTEGER,
+    error TEXT,
+    duration REAL,
+    fetch_duration REAL,
+    parse_duration REAL,
+    next_eligible_at INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL
+) WITHOUT ROWID;
+
+CREATE INDEX IF NOT EXISTS requests_state ON requests (state, next_eligible_at);
@@ -0,0 +1,101 @@
+-- Normalized store for mirrored times and the history of sync runs.
+CREATE TABLE IF NOT EXISTS swimmers (
+    id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL,
+    gender TEXT NOT NULL,
+    lsc TEXT,
+    is_foreign INTEGER
+);
+
+CREATE TABLE IF NOT EXISTS meets (
+    id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL,
+    sanctioned INTEGER
+);
+
+CREATE TABLE IF NOT EXISTS teams (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL UNIQUE
+);
+
+-- Times are keyed on hundredths of a second so that the upsert key does not
+-- depend on float equality
+CREATE TABLE IF NOT EXISTS times (
+    swimmer_id INTEGER NOT NULL REFERENCES swimmers (id),
+    meet_id INTEGER NOT NULL REFERENCES meets (id),
+    distance INTEGER NOT NULL,
+    stroke TEXT NOT NULL,
+    course TEXT NOT NULL,
+    time_cs INTEGER NOT NULL,
+    team_id INTEGER NOT NULL REFERENCES teams (id),
+    date TEXT NOT NULL,
+    age INTEGER NOT NULL,
+    gender TEXT NOT NULL,
+    lsc TEXT,
+    relay INTEGER NOT NULL,
+    rank INTEGER,
+    time_standard TEXT,
+    power_points INTEGER,
+    PRIMARY KEY (swimmer_id, meet_id, distance, stroke, course, time_cs)
+) WITHOUT ROWID;
+
+CREATE INDEX IF NOT EXISTS times_event ON times (distance, stroke, course);
+CREATE INDEX IF NOT EXISTS times_date ON times (date);
+CREATE INDEX IF NOT EXISTS times_age ON times (age);
+CREATE INDEX IF NOT EXISTS times_lsc ON times (lsc);
+
+-- Relay results don't identify swimmers and may lack a meet ID, so they are
+-- keyed on team, meet name, event and time instead
+CREATE TABLE IF NOT EXISTS relay_times (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    team_id INTEGER NOT NULL REFERENCES teams (id),
+    meet_id INTEGER REFERENCES meets (id),
+    meet_name TEXT NOT NULL,
+    date TEXT,
+    distance INTEGER NOT NULL,
+    stroke TEXT NOT NULL,
+    course TEXT NOT NULL,
+    gender TEXT NOT NULL,
+    relay_name TEXT,
+    time_cs INTEGER NOT NULL,
+    age_group TEXT,
+    lsc TEXT,
+    rank INTEGER,
+    time_standard TEXT
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS relay_times_key ON relay_times (
+    team_id, meet_name, COALESCE(date, ''), distance, stroke, course, gender,
+    COALESCE(relay_name, ''), time_cs
+);
+CREATE INDEX IF NOT EXISTS relay_times_event ON relay_times (distance, stroke, course);
+CREATE INDEX IF NOT EXISTS relay_times_date ON relay_times (date);
+CREATE INDEX IF NOT EXISTS relay_times_meet ON relay_times (meet_id);
+
+CREATE TABLE IF NOT EXISTS sync_runs (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    from_date TEXT NOT NULL,
+    to_date TEXT NOT NULL,
+    started_at INTEGER NOT NULL,
+    finished_at INTEGER
+);
+
+CREATE TABLE IF NOT EXISTS sync_partitions (
+    date TEXT PRIMARY KEY,
+    sync_id INTEGER NOT NULL REFERENCES sync_runs (id),
+    refreshed_at INTEGER NOT NULL
+) WITHOUT ROWID;
+
+CREATE TABLE IF NOT EXISTS sync_changes (
+    sync_id INTEGER NOT NULL REFERENCES sync_runs (id),
+    change TEXT NOT NULL,
+    date TEXT NOT NULL,
+    swimmer_id INTEGER NOT NULL,
+    meet_id INTEGER NOT NULL,
+    distance INTEGER NOT NULL,
+    stroke TEXT NOT NULL,
+    course TEXT NOT NULL,
+    time_cs INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS sync_changes_sync ON sync_changes (sync_id);
@@ -0,0 +1,6 @@
+-- Archive of raw ListTimes responses, compressed with zstd.
+CREATE TABLE IF NOT EXISTS responses (
+    id TEXT PRIMARY KEY,
+    fetched_at INTEGER NOT NULL,
+    html BLOB NOT NULL
+) WITHOUT ROWID;
@@ -0,0 +1,10 @@
+-- Results from sources other than Top Times, such as Hy-Tek files, name
+-- swimmers and meets without giving their USA Swimming IDs. They are given
+-- synthetic IDs instead, negative so as never to clash with real ones, and
+-- looked up again by the natural key they were created for.
+CREATE TABLE IF NOT EXISTS synthetic_ids (
+    kind TEXT NOT NULL,
+    natural_key TEXT NOT NULL,
+    id INTEGER NOT NULL,
+    PRIMARY KEY (kind, natural_key)
+) WITHOUT ROWID;
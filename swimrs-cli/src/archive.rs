@@ -0,0 +1,92 @@
+use std::str::FromStr;
+
+use anyhow::{Context, Result};
+use chrono::Utc;
+use futures::{stream::BoxStream, StreamExt, TryStreamExt};
+use sqlx::{
+    query, query_as,
+    sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions},
+    SqlitePool,
+};
+use swimrs::usas::toptimes::TopTimesRequest;
+
+use crate::db;
+
+/// zstd level used for archived responses. HTML compresses extremely well, so
+/// a moderate level keeps writes cheap without giving up much space.
+const COMPRESSION_LEVEL: i32 = 9;
+
+/// A raw ListTimes response read back from the archive.
+pub struct ArchivedResponse {
+    pub id: String,
+    pub request: TopTimesRequest,
+    pub fetched_at: i64,
+    pub html: String,
+}
+
+/// Archive of raw ListTimes responses, compressed with zstd and keyed by
+/// request ID, from which the request that produced each response can be
+/// recovered.
+pub struct SqliteArchive {
+    pool: SqlitePool,
+}
+
+impl SqliteArchive {
+    pub async fn new(db_url: &str) -> Result<Self> {
+        let opts = SqliteConnectOptions::from_str(db_url)?
+            .create_if_missing(true)
+            .journal_mode(SqliteJournalMode::Wal);
+        let pool = SqlitePoolOptions::new().connect_with(opts).await?;
+        Ok(Self { pool })
+    }
+
+    pub async fn migrate(&self) -> Result<()> {
+        db::sqlite::migrate(&self.pool).await
+    }
+
+    /// Stores the raw HTML for a request, replacing any earlier response.
+    pub async fn put(&self, req_id: &str, html: &str) -> Result<()> {
+        let compressed = zstd::encode_all(html.as_bytes(), COMPRESSION_LEVEL)?;
+        query("REPLACE INTO responses (id, fetched_at, html) VALUES (?, ?, ?)")
+            .bind(req_id)
+            .bind(Utc::now().timestamp())
+            .bind(compressed)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn count(&self) -> Result<u64> {
+        let (count,): (i64,) = query_as("SELECT COUNT(*) FROM responses")
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(count as u64)
+    }
+
+    /// Streams every archived response along with the request that produced
+    /// it, decompressing as it goes. A response that can't be decompressed or
+    /// whose ID doesn't parse is an error naming its ID, and the stream
+    /// carries on with the next one.
+    pub fn iter(&self) -> BoxStream<'_, Result<ArchivedResponse>> {
+        query_as::<_, (String, i64, Vec<u8>)>(
+            "SELECT id, fetched_at, html FROM responses ORDER BY id",
+        )
+        .fetch(&self.pool)
+        .map_err(anyhow::Error::from)
+        .and_then(|(id, fetched_at, html)| async move {
+            let decode = || -> Result<_> {
+                let html = String::from_utf8(zstd::decode_all(html.as_slice())?)?;
+                Ok((TopTimesRequest::from_str(&id)?, html))
+            };
+            let (request, html) =
+                decode().with_context(|| format!("error reading archived response {}", id))?;
+            Ok(ArchivedResponse {
+                id,
+                request,
+                fetched_at,
+                html,
+            })
+        })
+        .boxed()
+    }
+}
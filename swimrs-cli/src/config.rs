@@ -0,0 +1,462 @@
+use std::{
+    fs,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    process,
+    str::FromStr,
+};
+
+use anyhow::{bail, Context, Result};
+use reqwest::Proxy;
+use serde::Deserialize;
+
+use crate::{
+    db::{self, RetryPolicy},
+    logging::LogConfig,
+    mirror::{ClientOptions, WorkerOptions},
+    output::{Compression, FileOptions, Layout, OutputFormat},
+    plan::{AgeBuckets, PartitionStrategy, Period, Split, TimeTypes},
+};
+
+const DEFAULT_USER_AGENT: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/102.0.5005.61/63 Safari/537.36";
+
+/// First port of the local SOCKS proxies used when none are configured.
+/// Client `i` connects through port `DEFAULT_PROXY_PORT + i`.
+const DEFAULT_PROXY_PORT: u16 = 53000;
+
+/// Settings for swimrs-cli, read from a TOML file. Every field is optional
+/// and command line flags take precedence over the file.
+///
+/// ```toml
+/// db_url = "sqlite://swimrs.db"
+/// times_db_url = "sqlite://times.db"
+/// metrics_addr = "0.0.0.0:9000"
+/// admin_addr = "127.0.0.1:9001"
+///
+/// [clients]
+/// count = 2
+/// proxies = ["socks5://127.0.0.1:53000", "socks5://127.0.0.1:53001"]
+/// user_agents = ["Mozilla/5.0 ..."]
+///
+/// [rate_limit]
+/// min_delay = 5
+/// max_delay = 10
+/// max_attempts = 5
+///
+/// [partition]
+/// period = "week"
+/// age_buckets = "0-10,11-12,13-14,15-16,17-18,19-"
+/// split = ["course"]
+/// time_types = "both"
+///
+/// [lease]
+/// timeout = 120
+/// worker_id = "mirror-1"
+///
+/// [output]
+/// dir = "results"
+/// format = "parquet"
+/// compression = "zstd"
+/// layout = "hive"
+/// archive = true
+///
+/// [logging]
+/// format = "json"
+/// dir = "logs"
+/// rotation = "daily"
+/// file_filter = "info,sqlx=warn,swimrs=debug,swimrs_cli=debug"
+/// ```
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Database holding the request queue: `sqlite:`, `postgres:` or
+    /// `memory:`
+    pub db_url: String,
+    /// SQLite database holding mirrored times and archived responses.
+    /// Defaults to `db_url` when that is a SQLite database
+    pub times_db_url: Option<String>,
+    /// Address to serve Prometheus metrics on
+    pub metrics_addr: SocketAddr,
+    /// Address to serve the admin API on
+    pub admin_addr: SocketAddr,
+    pub clients: ClientsConfig,
+    pub rate_limit: RateLimitConfig,
+    pub partition: PartitionConfig,
+    pub lease: LeaseConfig,
+    pub output: OutputConfig,
+    pub logging: LogConfig,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClientsConfig {
+    /// Number of HTTP clients to send requests with
+    pub count: u16,
+    /// Proxy URLs, assigned to clients in turn. Defaults to local SOCKS
+    /// proxies on consecutive ports starting at 53000
+    pub proxies: Vec<String>,
+    /// User agents, assigned to clients in turn
+    pub user_agents: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitConfig {
+    /// Each client waits a random number of seconds between `min_delay` and
+    /// `max_delay` between the start of one request and the next
+    pub min_delay: u64,
+    pub max_delay: u64,
+    /// Number of attempts after which a failing request is no longer retried
+    pub max_attempts: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PartitionConfig {
+    pub period: Period,
+    /// Age buckets, written like `0-7,8,9,10-12,23-`
+    pub age_buckets: Option<String>,
+    pub split: Vec<Split>,
+    pub time_types: TimeTypes,
+}
+
+/// How requests are leased from a request queue that several processes may
+/// share.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LeaseConfig {
+    /// Seconds a lease lasts without a heartbeat before its request is
+    /// returned to the queue. Heartbeats are sent every third of this
+    pub timeout: u64,
+    /// Identifies this process in leases. Defaults to the host name and
+    /// process ID, and must be unique among processes sharing a queue
+    pub worker_id: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutputConfig {
+    /// If set, result sets are also written to files under this directory
+    #[serde(alias = "csv_dir")]
+    pub dir: Option<PathBuf>,
+    pub format: OutputFormat,
+    pub compression: Compression,
+    pub layout: Layout,
+    /// Whether to archive raw responses for later reparsing
+    pub archive: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            db_url: "sqlite://swimrs.db".to_owned(),
+            times_db_url: None,
+            metrics_addr: ([0, 0, 0, 0], 9000).into(),
+            admin_addr: ([127, 0, 0, 1], 9001).into(),
+            clients: ClientsConfig::default(),
+            rate_limit: RateLimitConfig::default(),
+            partition: PartitionConfig::default(),
+            lease: LeaseConfig::default(),
+            output: OutputConfig::default(),
+            logging: LogConfig::default(),
+        }
+    }
+}
+
+impl Default for ClientsConfig {
+    fn default() -> Self {
+        ClientsConfig {
+            count: 1,
+            proxies: Vec::new(),
+            user_agents: vec![DEFAULT_USER_AGENT.to_owned()],
+        }
+    }
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        RateLimitConfig {
+            min_delay: 5,
+            max_delay: 10,
+            max_attempts: RetryPolicy::default().max_attempts,
+        }
+    }
+}
+
+impl Default for PartitionConfig {
+    fn default() -> Self {
+        PartitionConfig {
+            period: Period::Day,
+            age_buckets: None,
+            split: Vec::new(),
+            time_types: TimeTypes::Individual,
+        }
+    }
+}
+
+impl Default for OutputConfig {
+    fn default() -> Self {
+        OutputConfig {
+            dir: None,
+            format: OutputFormat::Csv,
+            compression: Compression::None,
+            layout: Layout::Request,
+            archive: false,
+        }
+    }
+}
+
+impl Default for LeaseConfig {
+    fn default() -> Self {
+        LeaseConfig {
+            timeout: 120,
+            worker_id: None,
+        }
+    }
+}
+
+impl Config {
+    /// Reads and validates the config file at `path`, or returns the defaults
+    /// if no path is given.
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let path = match path {
+            Some(p) => p,
+            None => return Ok(Config::default()),
+        };
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("error reading config file {}", path.display()))?;
+        Config::parse(&text).with_context(|| format!("error in config file {}", path.display()))
+    }
+
+    fn parse(text: &str) -> Result<Self> {
+        let config: Config = toml::from_str(text)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Checks settings that can't be expressed by their types alone. Config
+    /// files are checked as they are loaded, and again once command line
+    /// flags have been applied.
+    pub fn validate(&self) -> Result<()> {
+        if self.clients.count == 0 {
+            bail!("clients.count must be at least 1");
+        }
+        if self.clients.proxies.is_empty()
+            && DEFAULT_PROXY_PORT.checked_add(self.clients.count).is_none()
+        {
+            bail!("too many clients for the default proxy ports; set clients.proxies instead");
+        }
+        for p in &self.clients.proxies {
+            Proxy::all(p).with_context(|| format!("invalid proxy in clients.proxies: {}", p))?;
+        }
+        if self.clients.user_agents.is_empty() {
+            bail!("clients.user_agents must not be empty");
+        }
+        if self
+            .clients
+            .user_agents
+            .iter()
+            .any(|ua| ua.trim().is_empty())
+        {
+            bail!("clients.user_agents must not contain empty strings");
+        }
+        if self.rate_limit.min_delay > self.rate_limit.max_delay {
+            bail!(
+                "rate_limit.min_delay ({}) is greater than rate_limit.max_delay ({})",
+                self.rate_limit.min_delay,
+                self.rate_limit.max_delay
+            );
+        }
+        if self.rate_limit.max_attempts == 0 {
+            bail!("rate_limit.max_attempts must be at least 1");
+        }
+        if self.metrics_addr == self.admin_addr {
+            bail!(
+                "metrics_addr and admin_addr are both {}; they must differ",
+                self.metrics_addr
+            );
+        }
+        if self.lease.timeout < 3 {
+            bail!("lease.timeout must be at least 3 seconds");
+        }
+        if let Some(id) = &self.lease.worker_id {
+            if id.trim().is_empty() {
+                bail!("lease.worker_id must not be empty");
+            }
+        }
+        if !db::is_sqlite(&self.times_db_url()?) {
+            bail!("times_db_url must be a SQLite database");
+        }
+        self.age_buckets()?;
+        Ok(())
+    }
+
+    /// Database URL for mirrored times and archived responses.
+    pub fn times_db_url(&self) -> Result<String> {
+        match &self.times_db_url {
+            Some(url) => Ok(url.clone()),
+            None if db::is_sqlite(&self.db_url) => Ok(self.db_url.clone()),
+            None => bail!("times_db_url must be set when db_url is not a SQLite database"),
+        }
+    }
+
+    fn age_buckets(&self) -> Result<AgeBuckets> {
+        match &self.partition.age_buckets {
+            Some(b) => {
+                AgeBuckets::from_str(b).with_context(|| format!("invalid age buckets: {}", b))
+            }
+            None => Ok(AgeBuckets::default()),
+        }
+    }
+
+    fn worker_id(&self) -> String {
+        match &self.lease.worker_id {
+            Some(id) => id.clone(),
+            None => {
+                let host = hostname::get()
+                    .map(|h| h.to_string_lossy().into_owned())
+                    .unwrap_or_else(|_| "localhost".to_owned());
+                format!("{}-{}", host, process::id())
+            }
+        }
+    }
+
+    /// Where result sets are written to files, if anywhere.
+    pub fn files(&self) -> Option<FileOptions> {
+        self.output.dir.as_ref().map(|dir| FileOptions {
+            dir: dir.clone(),
+            format: self.output.format,
+            compression: self.output.compression,
+            layout: self.output.layout,
+        })
+    }
+
+    pub fn strategy(&self) -> Result<PartitionStrategy> {
+        Ok(PartitionStrategy {
+            period: self.partition.period,
+            age_buckets: self.age_buckets()?,
+            splits: self.partition.split.clone(),
+            time_types: self.partition.time_types,
+        })
+    }
+
+    /// Resolves the settings for mirror workers. Proxies and user agents are
+    /// assigned to clients round-robin.
+    pub fn worker_options(&self) -> Result<WorkerOptions> {
+        self.validate()?;
+
+        let clients = (0..self.clients.count)
+            .map(|i| {
+                let proxy = match self.clients.proxies.as_slice() {
+                    [] => format!("socks5://127.0.0.1:{}", DEFAULT_PROXY_PORT + i),
+                    proxies => proxies[i as usize % proxies.len()].clone(),
+                };
+                let agents = &self.clients.user_agents;
+                ClientOptions {
+                    proxy,
+                    user_agent: agents[i as usize % agents.len()].clone(),
+                }
+            })
+            .collect();
+
+        Ok(WorkerOptions {
+            clients,
+            db_url: self.db_url.clone(),
+            times_db_url: self.times_db_url()?,
+            policy: RetryPolicy {
+                max_attempts: self.rate_limit.max_attempts,
+                ..RetryPolicy::default()
+            },
+            min_delay: self.rate_limit.min_delay,
+            max_delay: self.rate_limit.max_delay,
+            files: self.files(),
+            archive: self.output.archive,
+            metrics_addr: self.metrics_addr,
+            admin_addr: self.admin_addr,
+            worker_id: self.worker_id(),
+            lease_timeout: self.lease.timeout,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_config() {
+        // The example in the docs
+        let example = r#"
+            db_url = "sqlite://swimrs.db"
+            times_db_url = "sqlite://times.db"
+            metrics_addr = "0.0.0.0:9000"
+            admin_addr = "127.0.0.1:9001"
+
+            [clients]
+            count = 2
+            proxies = ["socks5://127.0.0.1:53000", "socks5://127.0.0.1:53001"]
+            user_agents = ["Mozilla/5.0 ..."]
+
+            [rate_limit]
+            min_delay = 5
+            max_delay = 10
+            max_attempts = 5
+
+            [partition]
+            period = "week"
+            age_buckets = "0-10,11-12,13-14,15-16,17-18,19-"
+            split = ["course"]
+            time_types = "both"
+
+            [lease]
+            timeout = 120
+            worker_id = "mirror-1"
+
+            [output]
+            dir = "results"
+            format = "parquet"
+            compression = "zstd"
+            layout = "hive"
+            archive = true
+        "#;
+        let config = Config::parse(example).unwrap();
+        assert_eq!(config.clients.count, 2);
+        assert_eq!(config.partition.period, Period::Week);
+        assert_eq!(config.output.layout, Layout::Hive);
+        assert_eq!(config.times_db_url().unwrap(), "sqlite://times.db");
+        let opts = config.worker_options().unwrap();
+        assert_eq!(opts.clients.len(), 2);
+        assert_eq!(opts.clients[1].proxy, "socks5://127.0.0.1:53001");
+        assert_eq!(opts.policy.max_attempts, 5);
+        assert_eq!(config.strategy().unwrap().age_buckets.0.len(), 6);
+
+        assert_eq!(Config::parse("").unwrap(), Config::default());
+        assert_eq!(Config::load(None).unwrap(), Config::default());
+    }
+
+    #[test]
+    fn test_invalid_config() {
+        for bad in [
+            "unknown = 1",
+            "[clients]\ncount = 0",
+            "[clients]\ncount = 20000",
+            "[clients]\nproxies = [\"not a proxy\"]",
+            "[clients]\nuser_agents = []",
+            "[clients]\nuser_agents = [\" \"]",
+            "[rate_limit]\nmin_delay = 10\nmax_delay = 5",
+            "[rate_limit]\nmax_attempts = 0",
+            "metrics_addr = \"127.0.0.1:9001\"",
+            "[lease]\ntimeout = 2",
+            "[lease]\nworker_id = \"\"",
+            "db_url = \"postgres://localhost/swimrs\"",
+            "times_db_url = \"postgres://localhost/times\"",
+            "[partition]\nage_buckets = \"12-9\"",
+        ] {
+            assert!(Config::parse(bad).is_err(), "{}", bad);
+        }
+        assert!(Config::parse(
+            "db_url = \"postgres://localhost/swimrs\"\ntimes_db_url = \"sqlite://times.db\""
+        )
+        .is_ok());
+    }
+}
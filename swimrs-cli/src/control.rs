@@ -0,0 +1,361 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    convert::Infallible,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+use hyper::{
+    body,
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tokio::task::JoinHandle;
+use tracing::{error, info};
+
+/// Settings that can be changed while workers are running.
+#[derive(Debug, Clone, Serialize)]
+pub struct Settings {
+    /// Workers finish their current request and stop leasing new ones
+    pub paused: bool,
+    /// Only workers with an index below this lease requests
+    pub active_clients: u16,
+    /// Each client waits a random number of seconds in this range between
+    /// the start of one request and the next
+    pub min_delay: u64,
+    pub max_delay: u64,
+    /// Workers using these proxies stop leasing requests
+    pub drained_proxies: BTreeSet<String>,
+}
+
+/// A request currently leased by a worker.
+#[derive(Debug, Clone, Serialize)]
+pub struct InFlight {
+    pub id: String,
+    pub client: u16,
+    pub proxy: String,
+    pub attempt: u32,
+    pub started_at: DateTime<Utc>,
+}
+
+/// Shared state through which the admin API steers running workers. Workers
+/// consult it before leasing each request, so changes take effect without
+/// restarting and without touching the request queue.
+pub struct Controller {
+    proxies: Vec<String>,
+    settings: Mutex<Settings>,
+    in_flight: Mutex<BTreeMap<String, InFlight>>,
+}
+
+impl Controller {
+    /// Creates a controller for one worker per proxy, all of them active.
+    pub fn new(proxies: Vec<String>, min_delay: u64, max_delay: u64) -> Self {
+        let settings = Settings {
+            paused: false,
+            active_clients: proxies.len() as u16,
+            min_delay,
+            max_delay,
+            drained_proxies: BTreeSet::new(),
+        };
+        Controller {
+            proxies,
+            settings: Mutex::new(settings),
+            in_flight: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn settings(&self) -> Settings {
+        self.settings.lock().unwrap().clone()
+    }
+
+    /// Whether the worker with the given index and proxy should hold off
+    /// leasing requests.
+    pub fn is_held(&self, client: u16, proxy: &str) -> bool {
+        let s = self.settings.lock().unwrap();
+        s.paused || client >= s.active_clients || s.drained_proxies.contains(proxy)
+    }
+
+    /// Range of seconds to wait between the start of consecutive requests.
+    pub fn delay(&self) -> (u64, u64) {
+        let s = self.settings.lock().unwrap();
+        (s.min_delay, s.max_delay)
+    }
+
+    pub fn set_paused(&self, paused: bool) {
+        self.settings.lock().unwrap().paused = paused;
+    }
+
+    pub fn set_active_clients(&self, n: u16) -> Result<()> {
+        if n as usize > self.proxies.len() {
+            bail!(
+                "only {} clients were started; restart with more --clients to go higher",
+                self.proxies.len()
+            );
+        }
+        self.settings.lock().unwrap().active_clients = n;
+        Ok(())
+    }
+
+    pub fn set_delay(&self, min_delay: u64, max_delay: u64) -> Result<()> {
+        if min_delay > max_delay {
+            bail!("min_delay {} exceeds max_delay {}", min_delay, max_delay);
+        }
+        let mut s = self.settings.lock().unwrap();
+        s.min_delay = min_delay;
+        s.max_delay = max_delay;
+        Ok(())
+    }
+
+    pub fn set_drained(&self, proxy: &str, drained: bool) -> Result<()> {
+        if !self.proxies.iter().any(|p| p == proxy) {
+            bail!("unknown proxy: {}", proxy);
+        }
+        let mut s = self.settings.lock().unwrap();
+        match drained {
+            true => s.drained_proxies.insert(proxy.to_owned()),
+            false => s.drained_proxies.remove(proxy),
+        };
+        Ok(())
+    }
+
+    pub fn start(&self, job: InFlight) {
+        self.in_flight.lock().unwrap().insert(job.id.clone(), job);
+    }
+
+    pub fn finish(&self, id: &str) {
+        self.in_flight.lock().unwrap().remove(id);
+    }
+
+    pub fn in_flight(&self) -> Vec<InFlight> {
+        self.in_flight.lock().unwrap().values().cloned().collect()
+    }
+}
+
+#[derive(Deserialize)]
+struct ClientsBody {
+    active: u16,
+}
+
+#[derive(Deserialize)]
+struct RateBody {
+    min_delay: u64,
+    max_delay: u64,
+}
+
+#[derive(Deserialize)]
+struct ProxyBody {
+    proxy: String,
+}
+
+/// Serves the admin API on `addr` until the returned task is aborted.
+///
+/// - `GET /status`: current settings
+/// - `GET /in-flight`: requests currently being processed
+/// - `POST /pause`, `POST /resume`
+/// - `POST /clients` with `{"active": n}`
+/// - `POST /rate` with `{"min_delay": s, "max_delay": s}`
+/// - `POST /proxies/drain`, `POST /proxies/undrain` with `{"proxy": url}`
+pub fn serve(addr: SocketAddr, controller: Arc<Controller>) -> Result<JoinHandle<()>> {
+    let make_svc = make_service_fn(move |_| {
+        let controller = controller.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| {
+                let controller = controller.clone();
+                async move { Ok::<_, Infallible>(handle(&controller, req).await) }
+            }))
+        }
+    });
+    let server = Server::try_bind(&addr)?.serve(make_svc);
+    info!("admin API listening on {}", addr);
+
+    Ok(tokio::spawn(async move {
+        if let Err(e) = server.await {
+            error!("admin API stopped: {}", e);
+        }
+    }))
+}
+
+async fn handle(controller: &Controller, req: Request<Body>) -> Response<Body> {
+    let res = match (req.method(), req.uri().path()) {
+        (&Method::GET, "/status") => json(&controller.settings()),
+        (&Method::GET, "/in-flight") => json(&controller.in_flight()),
+        (&Method::POST, "/pause") => {
+            controller.set_paused(true);
+            info!("paused by admin API");
+            json(&controller.settings())
+        }
+        (&Method::POST, "/resume") => {
+            controller.set_paused(false);
+            info!("resumed by admin API");
+            json(&controller.settings())
+        }
+        (&Method::POST, "/clients") => match parse_body::<ClientsBody>(req).await {
+            Ok(b) => controller
+                .set_active_clients(b.active)
+                .and_then(|_| json(&controller.settings())),
+            Err(e) => Err(e),
+        },
+        (&Method::POST, "/rate") => match parse_body::<RateBody>(req).await {
+            Ok(b) => controller
+                .set_delay(b.min_delay, b.max_delay)
+                .and_then(|_| json(&controller.settings())),
+            Err(e) => Err(e),
+        },
+        (&Method::POST, path @ ("/proxies/drain" | "/proxies/undrain")) => {
+            let drained = path == "/proxies/drain";
+            match parse_body::<ProxyBody>(req).await {
+                Ok(b) => controller
+                    .set_drained(&b.proxy, drained)
+                    .and_then(|_| json(&controller.settings())),
+                Err(e) => Err(e),
+            }
+        }
+        _ => {
+            return Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body(Body::from("not found\n"))
+                .unwrap()
+        }
+    };
+
+    match res {
+        Ok(r) => r,
+        Err(e) => Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .header("content-type", "application/json")
+            .body(Body::from(
+                serde_json::json!({ "error": e.to_string() }).to_string(),
+            ))
+            .unwrap(),
+    }
+}
+
+async fn parse_body<T: DeserializeOwned>(req: Request<Body>) -> Result<T> {
+    let bytes = body::to_bytes(req.into_body()).await?;
+    Ok(serde_json::from_slice(&bytes)?)
+}
+
+fn json<T: Serialize>(value: &T) -> Result<Response<Body>> {
+    Ok(Response::builder()
+        .header("content-type", "application/json")
+        .body(Body::from(serde_json::to_string(value)?))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROXIES: [&str; 3] = [
+        "socks5://127.0.0.1:53000",
+        "socks5://127.0.0.1:53001",
+        "socks5://127.0.0.1:53002",
+    ];
+
+    fn controller() -> Controller {
+        Controller::new(PROXIES.map(str::to_owned).to_vec(), 5, 10)
+    }
+
+    #[test]
+    fn test_active_clients() {
+        let c = controller();
+        assert_eq!(c.settings().active_clients, 3);
+        assert!(!c.is_held(2, PROXIES[2]));
+
+        c.set_active_clients(1).unwrap();
+        assert!(!c.is_held(0, PROXIES[0]));
+        assert!(c.is_held(1, PROXIES[1]));
+
+        c.set_active_clients(0).unwrap();
+        assert!(c.is_held(0, PROXIES[0]));
+        c.set_active_clients(3).unwrap();
+        assert!(!c.is_held(2, PROXIES[2]));
+
+        assert!(c.set_active_clients(4).is_err());
+        assert_eq!(c.settings().active_clients, 3);
+    }
+
+    #[test]
+    fn test_delay() {
+        let c = controller();
+        assert_eq!(c.delay(), (5, 10));
+        c.set_delay(0, 0).unwrap();
+        assert_eq!(c.delay(), (0, 0));
+        c.set_delay(2, 30).unwrap();
+        assert_eq!(c.delay(), (2, 30));
+
+        assert!(c.set_delay(11, 10).is_err());
+        assert_eq!(c.delay(), (2, 30));
+    }
+
+    #[test]
+    fn test_pause_and_drain() {
+        let c = controller();
+        c.set_paused(true);
+        assert!(PROXIES
+            .iter()
+            .enumerate()
+            .all(|(i, p)| c.is_held(i as u16, p)));
+        c.set_paused(false);
+
+        c.set_drained(PROXIES[1], true).unwrap();
+        assert!(!c.is_held(0, PROXIES[0]));
+        assert!(c.is_held(1, PROXIES[1]));
+        // Draining twice is harmless, and undraining restores the proxy
+        c.set_drained(PROXIES[1], true).unwrap();
+        assert_eq!(c.settings().drained_proxies.len(), 1);
+        c.set_drained(PROXIES[1], false).unwrap();
+        assert!(!c.is_held(1, PROXIES[1]));
+
+        assert!(c.set_drained("socks5://127.0.0.1:9999", true).is_err());
+        assert!(c.settings().drained_proxies.is_empty());
+    }
+
+    #[test]
+    fn test_in_flight() {
+        let c = controller();
+        for id in ["a", "b"] {
+            c.start(InFlight {
+                id: id.to_owned(),
+                client: 0,
+                proxy: PROXIES[0].to_owned(),
+                attempt: 1,
+                started_at: Utc::now(),
+            });
+        }
+        c.finish("a");
+        let ids: Vec<String> = c.in_flight().into_iter().map(|j| j.id).collect();
+        assert_eq!(ids, ["b"]);
+    }
+
+    #[tokio::test]
+    async fn test_handle() {
+        let c = controller();
+        let post = |path: &str, body: &str| {
+            Request::post(path)
+                .body(Body::from(body.to_owned()))
+                .unwrap()
+        };
+
+        let res = handle(&c, post("/rate", r#"{"min_delay": 1, "max_delay": 2}"#)).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(c.delay(), (1, 2));
+
+        let res = handle(&c, post("/rate", r#"{"min_delay": 3, "max_delay": 2}"#)).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        let res = handle(&c, post("/clients", r#"{"active": 7}"#)).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        let res = handle(&c, post("/clients", "not json")).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+        let body = format!(r#"{{"proxy": "{}"}}"#, PROXIES[0]);
+        let res = handle(&c, post("/proxies/drain", &body)).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(c.is_held(0, PROXIES[0]));
+
+        let res = handle(&c, Request::get("/nowhere").body(Body::empty()).unwrap()).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+}
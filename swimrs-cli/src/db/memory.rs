@@ -0,0 +1,354 @@
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, HashMap},
+    sync::Mutex,
+};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{Duration, Utc};
+use swimrs::usas::toptimes::TopTimesRequest;
+
+use super::{DeadJob, Job, JobState, RequestStore, RetryPolicy, Timings};
+
+#[derive(Debug, Clone)]
+struct Row {
+    state: JobState,
+    request: TopTimesRequest,
+    attempts: u32,
+    num_results: Option<u32>,
+    error: Option<String>,
+    timings: Option<Timings>,
+    lease_owner: Option<String>,
+    lease_expires_at: Option<i64>,
+    next_eligible_at: i64,
+    updated_at: i64,
+}
+
+impl Row {
+    fn pending(request: TopTimesRequest, now: i64) -> Self {
+        Row {
+            state: JobState::Pending,
+            request,
+            attempts: 0,
+            num_results: None,
+            error: None,
+            timings: None,
+            lease_owner: None,
+            lease_expires_at: None,
+            next_eligible_at: now,
+            updated_at: now,
+        }
+    }
+
+    fn is_eligible(&self, now: i64) -> bool {
+        matches!(self.state, JobState::Pending | JobState::Failed) && self.next_eligible_at <= now
+    }
+
+    fn is_leased_to(&self, owner: &str) -> bool {
+        self.state == JobState::InFlight && self.lease_owner.as_deref() == Some(owner)
+    }
+
+    fn release(&mut self, state: JobState, now: i64) {
+        self.state = state;
+        self.lease_owner = None;
+        self.lease_expires_at = None;
+        self.updated_at = now;
+    }
+
+    fn completed_since(&self, since: i64) -> bool {
+        self.state == JobState::Success && self.updated_at >= since
+    }
+}
+
+/// Request store held in memory, for one-off runs and tests. Progress is lost
+/// when the process exits.
+#[derive(Default)]
+pub struct MemoryRequestDb {
+    rows: Mutex<BTreeMap<String, Row>>,
+}
+
+impl MemoryRequestDb {
+    /// The row for a job, if it is currently leased to `owner`.
+    fn leased_row<'a>(
+        rows: &'a mut BTreeMap<String, Row>,
+        req_id: &str,
+        owner: &str,
+    ) -> Option<&'a mut Row> {
+        rows.get_mut(req_id).filter(|row| row.is_leased_to(owner))
+    }
+}
+
+#[async_trait]
+impl RequestStore for MemoryRequestDb {
+    async fn migrate(&self) -> Result<()> {
+        Ok(())
+    }
+
+    async fn enqueue(&self, reqs: &[(String, TopTimesRequest)]) -> Result<u64> {
+        let now = Utc::now().timestamp();
+        let mut rows = self.rows.lock().unwrap();
+        let mut queued = 0;
+        for (req_id, req) in reqs {
+            if !rows.contains_key(req_id) {
+                rows.insert(req_id.clone(), Row::pending(req.clone(), now));
+                queued += 1;
+            }
+        }
+        Ok(queued)
+    }
+
+    async fn enqueue_refresh(&self, reqs: &[(String, TopTimesRequest)]) -> Result<u64> {
+        let now = Utc::now().timestamp();
+        let mut rows = self.rows.lock().unwrap();
+        let mut queued = 0;
+        for (req_id, req) in reqs {
+            match rows.get_mut(req_id) {
+                Some(row) => {
+                    if matches!(row.state, JobState::Success | JobState::Dead) {
+                        row.state = JobState::Pending;
+                        row.attempts = 0;
+                        row.next_eligible_at = now;
+                        row.updated_at = now;
+                        queued += 1;
+                    }
+                }
+                None => {
+                    rows.insert(req_id.clone(), Row::pending(req.clone(), now));
+                    queued += 1;
+                }
+            }
+        }
+        Ok(queued)
+    }
+
+    async fn lease(&self, owner: &str, ttl: Duration) -> Result<Option<Job>> {
+        let now = Utc::now().timestamp();
+        let mut rows = self.rows.lock().unwrap();
+        let next = rows
+            .iter_mut()
+            .filter(|(_, row)| row.is_eligible(now))
+            .min_by_key(|(_, row)| row.next_eligible_at);
+        Ok(next.map(|(id, row)| {
+            row.state = JobState::InFlight;
+            row.attempts += 1;
+            row.lease_owner = Some(owner.to_owned());
+            row.lease_expires_at = Some(now + ttl.num_seconds());
+            row.updated_at = now;
+            Job {
+                id: id.clone(),
+                request: row.request.clone(),
+                attempts: row.attempts,
+            }
+        }))
+    }
+
+    async fn heartbeat(&self, req_id: &str, owner: &str, ttl: Duration) -> Result<bool> {
+        let mut rows = self.rows.lock().unwrap();
+        Ok(match Self::leased_row(&mut rows, req_id, owner) {
+            Some(row) => {
+                row.lease_expires_at = Some((Utc::now() + ttl).timestamp());
+                true
+            }
+            None => false,
+        })
+    }
+
+    async fn reclaim_expired(&self, policy: &RetryPolicy) -> Result<u64> {
+        let now = Utc::now();
+        let mut rows = self.rows.lock().unwrap();
+        let mut reclaimed = 0;
+        for row in rows.values_mut() {
+            let expired = !matches!(row.lease_expires_at, Some(t) if t >= now.timestamp());
+            if row.state == JobState::InFlight && expired {
+                let (state, next_eligible_at) = policy.after_failure(row.attempts, now);
+                row.release(state, now.timestamp());
+                row.error = Some("lease expired".to_owned());
+                row.next_eligible_at = next_eligible_at.timestamp();
+                reclaimed += 1;
+            }
+        }
+        Ok(reclaimed)
+    }
+
+    async fn count_remaining(&self) -> Result<u64> {
+        let rows = self.rows.lock().unwrap();
+        let n = rows
+            .values()
+            .filter(|row| {
+                matches!(
+                    row.state,
+                    JobState::Pending | JobState::InFlight | JobState::Failed
+                )
+            })
+            .count();
+        Ok(n as u64)
+    }
+
+    async fn count_eligible(&self) -> Result<u64> {
+        let now = Utc::now().timestamp();
+        let rows = self.rows.lock().unwrap();
+        Ok(rows.values().filter(|row| row.is_eligible(now)).count() as u64)
+    }
+
+    async fn upsert_request_success(
+        &self,
+        req_id: &str,
+        owner: &str,
+        num_results: u32,
+        timings: &Timings,
+    ) -> Result<bool> {
+        let mut rows = self.rows.lock().unwrap();
+        let row = match Self::leased_row(&mut rows, req_id, owner) {
+            Some(row) => row,
+            None => return Ok(false),
+        };
+        row.release(JobState::Success, Utc::now().timestamp());
+        row.num_results = Some(num_results);
+        row.error = None;
+        row.timings = Some(*timings);
+        Ok(true)
+    }
+
+    async fn upsert_request_error(
+        &self,
+        req_id: &str,
+        owner: &str,
+        error_text: &str,
+        timings: &Timings,
+        policy: &RetryPolicy,
+    ) -> Result<Option<JobState>> {
+        let mut rows = self.rows.lock().unwrap();
+        let row = match Self::leased_row(&mut rows, req_id, owner) {
+            Some(row) => row,
+            None => return Ok(None),
+        };
+        let now = Utc::now();
+        let (state, next_eligible_at) = policy.after_failure(row.attempts, now);
+        row.release(state, now.timestamp());
+        row.error = Some(error_text.to_owned());
+        row.timings = Some(*timings);
+        row.next_eligible_at = next_eligible_at.timestamp();
+        Ok(Some(state))
+    }
+
+    async fn job_states(&self, req_ids: &[String]) -> Result<HashMap<String, JobState>> {
+        let rows = self.rows.lock().unwrap();
+        Ok(req_ids
+            .iter()
+            .filter_map(|id| rows.get(id).map(|row| (id.clone(), row.state)))
+            .collect())
+    }
+
+    async fn count_by_state(&self) -> Result<HashMap<JobState, u64>> {
+        let rows = self.rows.lock().unwrap();
+        let mut counts = HashMap::new();
+        for row in rows.values() {
+            *counts.entry(row.state).or_default() += 1;
+        }
+        Ok(counts)
+    }
+
+    async fn count_by_partition(&self) -> Result<Vec<(String, String, JobState, u64)>> {
+        let rows = self.rows.lock().unwrap();
+        let mut counts: BTreeMap<(String, String, &'static str), u64> = BTreeMap::new();
+        for row in rows.values() {
+            let month = row.request.from_date.format("%Y-%m").to_string();
+            let gender = serde_json::to_value(&row.request.gender)?
+                .as_str()
+                .unwrap_or_default()
+                .to_owned();
+            *counts
+                .entry((month, gender, row.state.as_str()))
+                .or_default() += 1;
+        }
+        counts
+            .into_iter()
+            .map(|((month, gender, state), n)| Ok((month, gender, state.parse()?, n)))
+            .collect()
+    }
+
+    async fn error_breakdown(&self, width: u32, limit: u32) -> Result<Vec<(String, u64)>> {
+        let rows = self.rows.lock().unwrap();
+        let mut counts: HashMap<String, u64> = HashMap::new();
+        for row in rows.values() {
+            if let (JobState::Failed | JobState::Dead, Some(e)) = (row.state, &row.error) {
+                let e = e.chars().take(width as usize).collect();
+                *counts.entry(e).or_default() += 1;
+            }
+        }
+        let mut counts: Vec<_> = counts.into_iter().collect();
+        counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        counts.truncate(limit as usize);
+        Ok(counts)
+    }
+
+    async fn count_completed_since(&self, since: i64) -> Result<u64> {
+        let rows = self.rows.lock().unwrap();
+        let n = rows
+            .values()
+            .filter(|row| row.completed_since(since))
+            .count();
+        Ok(n as u64)
+    }
+
+    async fn total_results(&self) -> Result<u64> {
+        let rows = self.rows.lock().unwrap();
+        Ok(rows
+            .values()
+            .filter(|row| row.state == JobState::Success)
+            .filter_map(|row| row.num_results)
+            .map(u64::from)
+            .sum())
+    }
+
+    async fn mean_timings_since(&self, since: i64) -> Result<(Option<f64>, Option<f64>)> {
+        let rows = self.rows.lock().unwrap();
+        let timings: Vec<Timings> = rows
+            .values()
+            .filter(|row| row.completed_since(since))
+            .filter_map(|row| row.timings)
+            .collect();
+        let mean = |values: Vec<f64>| match values.len() {
+            0 => None,
+            n => Some(values.iter().sum::<f64>() / n as f64),
+        };
+        Ok((
+            mean(timings.iter().filter_map(|t| t.fetch).collect()),
+            mean(timings.iter().filter_map(|t| t.parse).collect()),
+        ))
+    }
+
+    async fn dead_jobs(&self) -> Result<Vec<DeadJob>> {
+        let rows = self.rows.lock().unwrap();
+        let mut dead: Vec<DeadJob> = rows
+            .iter()
+            .filter(|(_, row)| row.state == JobState::Dead)
+            .map(|(id, row)| DeadJob {
+                id: id.clone(),
+                attempts: row.attempts,
+                error: row.error.clone(),
+                updated_at: row.updated_at,
+            })
+            .collect();
+        dead.sort_by_key(|job| Reverse(job.updated_at));
+        Ok(dead)
+    }
+
+    async fn requeue_dead(&self) -> Result<u64> {
+        let now = Utc::now().timestamp();
+        let mut rows = self.rows.lock().unwrap();
+        let mut requeued = 0;
+        for row in rows.values_mut() {
+            if row.state == JobState::Dead {
+                row.state = JobState::Pending;
+                row.attempts = 0;
+                row.error = None;
+                row.next_eligible_at = now;
+                row.updated_at = now;
+                requeued += 1;
+            }
+        }
+        Ok(requeued)
+    }
+}
@@ -0,0 +1,582 @@
+mod memory;
+mod postgres;
+pub mod sqlite;
+
+use std::{collections::HashMap, fmt, str::FromStr, sync::Arc};
+
+use anyhow::{bail, Error, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use swimrs::usas::toptimes::TopTimesRequest;
+
+pub use self::{memory::MemoryRequestDb, postgres::PgRequestDb, sqlite::SqliteRequestDb};
+
+/// Lifecycle of a job in the request queue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum JobState {
+    /// Waiting to be leased by a worker
+    Pending,
+    /// Leased by a worker and currently being processed. The lease must be
+    /// renewed by heartbeats or it expires and the job is reclaimed
+    InFlight,
+    /// Completed successfully
+    Success,
+    /// Failed at least once; eligible for retry after `next_eligible_at`
+    Failed,
+    /// Exceeded the maximum number of attempts; will not be retried
+    Dead,
+}
+
+impl JobState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            JobState::Pending => "pending",
+            JobState::InFlight => "in_flight",
+            JobState::Success => "success",
+            JobState::Failed => "failed",
+            JobState::Dead => "dead",
+        }
+    }
+}
+
+impl fmt::Display for JobState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for JobState {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "pending" => Ok(JobState::Pending),
+            "in_flight" => Ok(JobState::InFlight),
+            "success" => Ok(JobState::Success),
+            "failed" => Ok(JobState::Failed),
+            "dead" => Ok(JobState::Dead),
+            _ => bail!("unknown job state: {}", s),
+        }
+    }
+}
+
+/// Controls how failed jobs are retried.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Number of attempts after which a job is moved to the dead state
+    pub max_attempts: u32,
+    /// Delay before the first retry; doubled for each subsequent attempt
+    pub base_delay: Duration,
+    /// Upper bound on the delay between attempts
+    pub max_delay: Duration,
+}
+
+impl RetryPolicy {
+    /// Returns the delay to wait before retrying a job that has been
+    /// attempted `attempts` times.
+    pub fn delay(&self, attempts: u32) -> Duration {
+        let exp = attempts.saturating_sub(1).min(16);
+        let delay = self.base_delay * 2i32.pow(exp);
+        delay.min(self.max_delay)
+    }
+
+    /// State and next eligible time of a job that has just failed its
+    /// `attempts`th attempt.
+    pub fn after_failure(&self, attempts: u32, now: DateTime<Utc>) -> (JobState, DateTime<Utc>) {
+        let state = if attempts >= self.max_attempts {
+            JobState::Dead
+        } else {
+            JobState::Failed
+        };
+        (state, now + self.delay(attempts))
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 5,
+            base_delay: Duration::seconds(30),
+            max_delay: Duration::hours(1),
+        }
+    }
+}
+
+/// Time spent on each stage of processing a request, in seconds. Stages that
+/// were never reached, because an earlier one failed, are `None`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Timings {
+    pub fetch: Option<f64>,
+    pub parse: Option<f64>,
+    /// Wall time of the whole attempt, including writing results
+    pub total: f64,
+}
+
+/// A request leased from the queue by a worker.
+#[derive(Debug, Clone)]
+pub struct Job {
+    pub id: String,
+    pub request: TopTimesRequest,
+    pub attempts: u32,
+}
+
+/// A job that exceeded its maximum number of attempts.
+#[derive(Debug, Clone)]
+pub struct DeadJob {
+    pub id: String,
+    pub attempts: u32,
+    pub error: Option<String>,
+    pub updated_at: i64,
+}
+
+/// Persistent state of the request queue: which requests are known, which
+/// succeeded, and which are waiting to be leased by a worker.
+///
+/// Workers in any number of processes may share a store. Each job is leased
+/// to one owner at a time for a limited period, which the owner extends with
+/// heartbeats while it works; a lease that isn't renewed expires, and the job
+/// is reclaimed for another worker. Results are only recorded by the current
+/// owner.
+#[async_trait]
+pub trait RequestStore: Send + Sync {
+    /// Brings the schema up to date.
+    async fn migrate(&self) -> Result<()>;
+
+    /// Adds requests to the queue as pending jobs. Requests that are already
+    /// known, in any state, are left untouched. Returns the number of newly
+    /// queued jobs.
+    async fn enqueue(&self, reqs: &[(String, TopTimesRequest)]) -> Result<u64>;
+
+    /// Queues requests to be fetched again. New requests are added as
+    /// pending, and requests that previously succeeded or died are reset to
+    /// pending with a fresh attempt count. Requests already waiting to be
+    /// processed are left untouched. Returns the number of jobs queued.
+    async fn enqueue_refresh(&self, reqs: &[(String, TopTimesRequest)]) -> Result<u64>;
+
+    /// Leases the next eligible job to `owner` for `ttl`, marking it as
+    /// in-flight and incrementing its attempt count. Returns `None` if no job
+    /// is currently eligible.
+    async fn lease(&self, owner: &str, ttl: Duration) -> Result<Option<Job>>;
+
+    /// Extends `owner`'s lease on a job to `ttl` from now. Returns `false` if
+    /// the lease has expired and the job been reclaimed, in which case the
+    /// owner should abandon it.
+    async fn heartbeat(&self, req_id: &str, owner: &str, ttl: Duration) -> Result<bool>;
+
+    /// Returns in-flight jobs whose lease has expired, because their owner
+    /// died or lost contact with the store, to the queue as failed attempts.
+    /// Like any other failure, each is scheduled for retry according to
+    /// `policy`, or moved to the dead state once it has reached the maximum
+    /// number of attempts. Returns the number of jobs reclaimed.
+    async fn reclaim_expired(&self, policy: &RetryPolicy) -> Result<u64>;
+
+    /// Number of jobs that still need processing, including in-flight jobs and
+    /// failed jobs waiting to be retried.
+    async fn count_remaining(&self) -> Result<u64>;
+
+    /// Number of jobs that could be leased right now.
+    async fn count_eligible(&self) -> Result<u64>;
+
+    /// Records a successful attempt for a job leased to `owner`. Returns
+    /// `false`, and records nothing, if the lease is no longer held.
+    async fn upsert_request_success(
+        &self,
+        req_id: &str,
+        owner: &str,
+        num_results: u32,
+        timings: &Timings,
+    ) -> Result<bool>;
+
+    /// Records a failed attempt for a job leased to `owner`. The job is
+    /// scheduled for retry according to `policy`, or moved to the dead state
+    /// once it has reached the maximum number of attempts. Returns the job's
+    /// new state, or `None`, recording nothing, if the lease is no longer
+    /// held.
+    async fn upsert_request_error(
+        &self,
+        req_id: &str,
+        owner: &str,
+        error_text: &str,
+        timings: &Timings,
+        policy: &RetryPolicy,
+    ) -> Result<Option<JobState>>;
+
+    /// Current state of each of the given jobs. Unknown IDs are omitted.
+    async fn job_states(&self, req_ids: &[String]) -> Result<HashMap<String, JobState>>;
+
+    /// Number of jobs in each state.
+    async fn count_by_state(&self) -> Result<HashMap<JobState, u64>>;
+
+    /// Job counts grouped by the month of the requested date range, the
+    /// requested gender and state.
+    async fn count_by_partition(&self) -> Result<Vec<(String, String, JobState, u64)>>;
+
+    /// Most common errors among failed and dead jobs, truncated to their first
+    /// `width` characters so that errors differing only in detail are grouped
+    /// together.
+    async fn error_breakdown(&self, width: u32, limit: u32) -> Result<Vec<(String, u64)>>;
+
+    /// Number of jobs that completed successfully at or after `since`, a Unix
+    /// timestamp.
+    async fn count_completed_since(&self, since: i64) -> Result<u64>;
+
+    /// Total number of times returned by successful jobs.
+    async fn total_results(&self) -> Result<u64>;
+
+    /// Mean fetch and parse durations, in seconds, of successful requests
+    /// completed since the unix timestamp `since`.
+    async fn mean_timings_since(&self, since: i64) -> Result<(Option<f64>, Option<f64>)>;
+
+    /// Lists jobs in the dead-letter state, most recently failed first.
+    async fn dead_jobs(&self) -> Result<Vec<DeadJob>>;
+
+    /// Moves all dead jobs back to pending with a fresh attempt count and no
+    /// error.
+    async fn requeue_dead(&self) -> Result<u64>;
+}
+
+/// Opens the request store for `db_url` and brings its schema up to date. The
+/// backend is chosen by the URL scheme: `sqlite:`, `postgres:` or
+/// `postgresql:`, or `memory:` for a store that lasts only as long as the
+/// process.
+pub async fn open(db_url: &str) -> Result<Arc<dyn RequestStore>> {
+    let store: Arc<dyn RequestStore> = match db_url.split_once(':') {
+        Some(("sqlite", _)) => Arc::new(SqliteRequestDb::new(db_url).await?),
+        Some(("postgres" | "postgresql", _)) => Arc::new(PgRequestDb::new(db_url).await?),
+        Some(("memory", _)) => Arc::new(MemoryRequestDb::default()),
+        _ => bail!("unsupported database URL: {}", db_url),
+    };
+    store.migrate().await?;
+    Ok(store)
+}
+
+/// Whether `db_url` refers to a SQLite database, which can also hold the
+/// times store and response archive.
+pub fn is_sqlite(db_url: &str) -> bool {
+    db_url.starts_with("sqlite:")
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+    use crate::testing::TempDb;
+
+    fn requests(n: u32) -> Vec<(String, TopTimesRequest)> {
+        (1..=n)
+            .map(|d| {
+                let date = NaiveDate::from_ymd_opt(2022, 1, d).unwrap();
+                let req = TopTimesRequest {
+                    from_date: date,
+                    to_date: date,
+                    ..TopTimesRequest::default()
+                };
+                (req.id(), req)
+            })
+            .collect()
+    }
+
+    fn ttl() -> Duration {
+        Duration::seconds(60)
+    }
+
+    /// Runs a job through every state on `store`, which must be empty.
+    async fn exercise(store: Arc<dyn RequestStore>) -> Result<()> {
+        let reqs = requests(3);
+        assert_eq!(store.enqueue(&reqs).await?, 3);
+        assert_eq!(store.enqueue(&reqs).await?, 0);
+        assert_eq!(store.count_eligible().await?, 3);
+
+        let policy = RetryPolicy {
+            max_attempts: 1,
+            ..RetryPolicy::default()
+        };
+        let timings = Timings {
+            fetch: Some(1.0),
+            parse: Some(0.5),
+            total: 2.0,
+        };
+        let a = store.lease("w1", ttl()).await?.unwrap();
+        assert_eq!(a.attempts, 1);
+        assert!(
+            !store
+                .upsert_request_success(&a.id, "w2", 7, &timings)
+                .await?
+        );
+        assert!(
+            store
+                .upsert_request_success(&a.id, "w1", 7, &timings)
+                .await?
+        );
+        let b = store.lease("w1", ttl()).await?.unwrap();
+        assert_ne!(a.id, b.id);
+        let state = store
+            .upsert_request_error(&b.id, "w1", "HTTP 503", &timings, &policy)
+            .await?;
+        assert_eq!(state, Some(JobState::Dead));
+        let c = store.lease("w2", ttl()).await?.unwrap();
+        assert!(store.lease("w1", ttl()).await?.is_none());
+
+        let states = store.count_by_state().await?;
+        assert_eq!(states[&JobState::Success], 1);
+        assert_eq!(states[&JobState::Dead], 1);
+        assert_eq!(states[&JobState::InFlight], 1);
+        assert_eq!(store.count_remaining().await?, 1);
+        assert_eq!(store.total_results().await?, 7);
+        assert_eq!(store.count_completed_since(0).await?, 1);
+        assert_eq!(store.mean_timings_since(0).await?, (Some(1.0), Some(0.5)));
+        assert_eq!(
+            store.error_breakdown(4, 10).await?,
+            vec![("HTTP".to_owned(), 1)]
+        );
+        let partitions = store.count_by_partition().await?;
+        assert!(partitions
+            .iter()
+            .all(|(month, gender, _, _)| month == "2022-01" && gender == "Mixed"));
+
+        // Only the owner can renew a lease, and one that lapses is reclaimed
+        // and can no longer be completed
+        let retry_now = RetryPolicy {
+            base_delay: Duration::zero(),
+            ..RetryPolicy::default()
+        };
+        assert_eq!(store.reclaim_expired(&retry_now).await?, 0);
+        assert!(!store.heartbeat(&c.id, "w1", ttl()).await?);
+        assert!(store.heartbeat(&c.id, "w2", -ttl()).await?);
+        assert_eq!(store.reclaim_expired(&retry_now).await?, 1);
+        assert!(!store.heartbeat(&c.id, "w2", ttl()).await?);
+        assert!(
+            !store
+                .upsert_request_success(&c.id, "w2", 1, &timings)
+                .await?
+        );
+        let ids = vec![a.id.clone(), c.id.clone(), "unknown".to_owned()];
+        let states = store.job_states(&ids).await?;
+        assert_eq!(states.len(), 2);
+        assert_eq!(states[&c.id], JobState::Failed);
+        let retry = store.lease("w1", ttl()).await?.unwrap();
+        assert_eq!((retry.id.as_str(), retry.attempts), (c.id.as_str(), 2));
+        assert!(
+            store
+                .upsert_request_success(&c.id, "w1", 0, &timings)
+                .await?
+        );
+
+        let dead = store.dead_jobs().await?;
+        assert_eq!(dead.len(), 1);
+        assert_eq!(dead[0].error.as_deref(), Some("HTTP 503"));
+        assert_eq!(store.requeue_dead().await?, 1);
+        assert_eq!(store.enqueue_refresh(&reqs).await?, 2);
+        assert_eq!(store.count_remaining().await?, 3);
+
+        // A job whose worker keeps dying is given up on like any other
+        let d = store.lease("w1", ttl()).await?.unwrap();
+        assert!(store.heartbeat(&d.id, "w1", -ttl()).await?);
+        assert_eq!(store.reclaim_expired(&policy).await?, 1);
+        let dead = store.dead_jobs().await?;
+        assert_eq!(dead.len(), 1);
+        assert_eq!(
+            (dead[0].id.as_str(), dead[0].error.as_deref()),
+            (d.id.as_str(), Some("lease expired"))
+        );
+        assert_eq!(store.count_remaining().await?, 2);
+        Ok(())
+    }
+
+    /// Fails jobs on `store`, which must be empty, until they are dead,
+    /// checking that each is only leased again once its retry is due.
+    async fn exercise_retries(store: Arc<dyn RequestStore>) -> Result<()> {
+        let reqs = requests(2);
+        store.enqueue(&reqs).await?;
+        let timings = Timings::default();
+        let later = RetryPolicy {
+            max_attempts: 3,
+            ..RetryPolicy::default()
+        };
+        // Due a minute before it failed, so it can be leased straight away
+        let now = RetryPolicy {
+            base_delay: Duration::seconds(-60),
+            ..later.clone()
+        };
+
+        let a = store.lease("w1", ttl()).await?.unwrap();
+        let state = store
+            .upsert_request_error(&a.id, "w1", "HTTP 503", &timings, &later)
+            .await?;
+        assert_eq!(state, Some(JobState::Failed));
+        assert_eq!(store.count_eligible().await?, 1);
+        let b = store.lease("w1", ttl()).await?.unwrap();
+        assert_ne!(a.id, b.id);
+        assert!(store.lease("w1", ttl()).await?.is_none());
+
+        for attempt in 1..=3 {
+            if attempt > 1 {
+                let job = store.lease("w1", ttl()).await?.unwrap();
+                assert_eq!((job.id.as_str(), job.attempts), (b.id.as_str(), attempt));
+            }
+            let state = store
+                .upsert_request_error(&b.id, "w1", "timeout", &timings, &now)
+                .await?;
+            let expected = if attempt < 3 {
+                JobState::Failed
+            } else {
+                JobState::Dead
+            };
+            assert_eq!(state, Some(expected));
+        }
+        assert!(store.lease("w1", ttl()).await?.is_none());
+        assert_eq!(store.count_eligible().await?, 0);
+        // The job waiting out its delay still needs processing; the dead one
+        // doesn't
+        assert_eq!(store.count_remaining().await?, 1);
+        let states = store.job_states(&[a.id.clone(), b.id.clone()]).await?;
+        assert_eq!(states[&a.id], JobState::Failed);
+        assert_eq!(states[&b.id], JobState::Dead);
+        let dead = store.dead_jobs().await?;
+        assert_eq!(dead.len(), 1);
+        assert_eq!(
+            (
+                dead[0].id.as_str(),
+                dead[0].attempts,
+                dead[0].error.as_deref()
+            ),
+            (b.id.as_str(), 3, Some("timeout"))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_retry_policy() {
+        let policy = RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::seconds(30),
+            max_delay: Duration::minutes(2),
+        };
+        let delays: Vec<i64> = (1..=5).map(|n| policy.delay(n).num_seconds()).collect();
+        assert_eq!(delays, [30, 60, 120, 120, 120]);
+        assert_eq!(policy.delay(u32::MAX), Duration::minutes(2));
+
+        let now = Utc::now();
+        assert_eq!(
+            policy.after_failure(1, now),
+            (JobState::Failed, now + Duration::seconds(30))
+        );
+        assert_eq!(policy.after_failure(3, now).0, JobState::Dead);
+        assert_eq!(policy.after_failure(4, now).0, JobState::Dead);
+    }
+
+    /// Leases every job on `store`, which must be empty, from many workers at
+    /// once and checks that no job is handed out twice.
+    async fn exercise_concurrent(store: Arc<dyn RequestStore>) -> Result<()> {
+        store.enqueue(&requests(24)).await?;
+        let workers = (0..6).map(|i| {
+            let store = store.clone();
+            tokio::spawn(async move {
+                let owner = format!("w{}", i);
+                let mut leased = Vec::new();
+                while let Some(job) = store.lease(&owner, ttl()).await? {
+                    leased.push(job.id);
+                }
+                Ok::<_, Error>(leased)
+            })
+        });
+        let mut leased = Vec::new();
+        for w in futures::future::join_all(workers).await {
+            leased.extend(w??);
+        }
+        let total = leased.len();
+        leased.sort();
+        leased.dedup();
+        assert_eq!((total, leased.len()), (24, 24));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_memory_store() -> Result<()> {
+        exercise(open("memory:").await?).await?;
+        exercise_retries(open("memory:").await?).await?;
+        exercise_concurrent(open("memory:").await?).await
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_store() -> Result<()> {
+        let db = TempDb::new("store");
+        exercise(open(&db.url).await?).await?;
+
+        let db = TempDb::new("store-retries");
+        exercise_retries(open(&db.url).await?).await?;
+
+        let db = TempDb::new("store-concurrent");
+        exercise_concurrent(open(&db.url).await?).await
+    }
+
+    /// Opens a database created by the original mirror, before the request
+    /// table became a job queue.
+    #[tokio::test]
+    async fn test_sqlite_baseline_upgrade() -> Result<()> {
+        let db = TempDb::new("baseline");
+        let url = &db.url;
+        let opts = sqlx::sqlite::SqliteConnectOptions::from_str(url)?.create_if_missing(true);
+        let pool = sqlx::SqlitePool::connect_with(opts).await?;
+        sqlx::query(
+            r"
+            CREATE TABLE requests (
+                id TEXT PRIMARY KEY,
+                state TEXT,
+                num_results INTEGER,
+                error TEXT,
+                duration REAL
+            ) WITHOUT ROWID
+            ",
+        )
+        .execute(&pool)
+        .await?;
+        let reqs = requests(2);
+        for ((_, req), state) in reqs.iter().zip(["success", "error"]) {
+            sqlx::query("INSERT INTO requests (id, state, num_results) VALUES (?, ?, 3)")
+                .bind(req.to_string().to_lowercase())
+                .bind(state)
+                .execute(&pool)
+                .await?;
+        }
+        sqlx::query("INSERT INTO requests (id, state) VALUES ('bogus', 'error')")
+            .execute(&pool)
+            .await?;
+        pool.close().await;
+
+        let store = open(url).await?;
+        let states = store.count_by_state().await?;
+        assert_eq!(states.len(), 2);
+        assert_eq!(states[&JobState::Success], 1);
+        assert_eq!(states[&JobState::Pending], 1);
+        assert_eq!(store.total_results().await?, 3);
+        let ids: Vec<String> = reqs.iter().map(|(id, _)| id.clone()).collect();
+        assert_eq!(store.job_states(&ids).await?.len(), 2);
+
+        let job = store.lease("w1", ttl()).await?.unwrap();
+        assert_eq!((job.id.as_str(), job.attempts), (reqs[1].0.as_str(), 1));
+        assert_eq!(job.request.id(), reqs[1].0);
+        assert_eq!(store.enqueue(&reqs).await?, 0);
+        Ok(())
+    }
+
+    /// Runs against the Postgres database at `SWIMRS_TEST_POSTGRES_URL`, if
+    /// set. Its request queue is dropped first.
+    #[tokio::test]
+    async fn test_postgres_store() -> Result<()> {
+        let url = match std::env::var("SWIMRS_TEST_POSTGRES_URL") {
+            Ok(url) => url,
+            Err(_) => return Ok(()),
+        };
+        let pool = sqlx::PgPool::connect(&url).await?;
+        sqlx::query("DROP TABLE IF EXISTS requests, _sqlx_migrations")
+            .execute(&pool)
+            .await?;
+        exercise(open(&url).await?).await?;
+        sqlx::query("TRUNCATE requests").execute(&pool).await?;
+        exercise_retries(open(&url).await?).await?;
+        sqlx::query("TRUNCATE requests").execute(&pool).await?;
+        exercise_concurrent(open(&url).await?).await
+    }
+}
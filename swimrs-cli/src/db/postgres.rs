@@ -0,0 +1,395 @@
+use std::{collections::HashMap, str::FromStr};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{Duration, Utc};
+use sqlx::{migrate::Migrator, postgres::PgPoolOptions, query, query_as, PgPool};
+use swimrs::usas::toptimes::TopTimesRequest;
+
+use super::{DeadJob, Job, JobState, RequestStore, RetryPolicy, Timings};
+
+static MIGRATOR: Migrator = sqlx::migrate!("migrations/postgres");
+
+/// Request store in a PostgreSQL database, so that workers on several hosts
+/// can share one queue.
+pub struct PgRequestDb {
+    pool: PgPool,
+}
+
+impl PgRequestDb {
+    pub async fn new(db_url: &str) -> Result<Self> {
+        let pool = PgPoolOptions::new().connect(db_url).await?;
+        Ok(Self { pool })
+    }
+}
+
+#[async_trait]
+impl RequestStore for PgRequestDb {
+    async fn migrate(&self) -> Result<()> {
+        MIGRATOR.run(&self.pool).await?;
+        Ok(())
+    }
+
+    async fn enqueue(&self, reqs: &[(String, TopTimesRequest)]) -> Result<u64> {
+        let now = Utc::now().timestamp();
+        let mut tx = self.pool.begin().await?;
+        let mut queued = 0;
+        for (req_id, req) in reqs {
+            let res = query(
+                r"
+                INSERT INTO requests
+                    (id, state, request, attempts, next_eligible_at, updated_at)
+                VALUES ($1, 'pending', $2, 0, $3, $3)
+                ON CONFLICT (id) DO NOTHING
+                ",
+            )
+            .bind(req_id)
+            .bind(serde_json::to_string(req)?)
+            .bind(now)
+            .execute(&mut tx)
+            .await?;
+            queued += res.rows_affected();
+        }
+        tx.commit().await?;
+        Ok(queued)
+    }
+
+    async fn enqueue_refresh(&self, reqs: &[(String, TopTimesRequest)]) -> Result<u64> {
+        let now = Utc::now().timestamp();
+        let mut tx = self.pool.begin().await?;
+        let mut queued = 0;
+        for (req_id, req) in reqs {
+            let res = query(
+                r"
+                INSERT INTO requests
+                    (id, state, request, attempts, next_eligible_at, updated_at)
+                VALUES ($1, 'pending', $2, 0, $3, $3)
+                ON CONFLICT (id) DO UPDATE SET
+                    state = 'pending',
+                    attempts = 0,
+                    next_eligible_at = excluded.next_eligible_at,
+                    updated_at = excluded.updated_at
+                WHERE requests.state IN ('success', 'dead')
+                ",
+            )
+            .bind(req_id)
+            .bind(serde_json::to_string(req)?)
+            .bind(now)
+            .execute(&mut tx)
+            .await?;
+            queued += res.rows_affected();
+        }
+        tx.commit().await?;
+        Ok(queued)
+    }
+
+    async fn lease(&self, owner: &str, ttl: Duration) -> Result<Option<Job>> {
+        let now = Utc::now();
+        // SKIP LOCKED lets concurrent workers each claim a different job
+        // instead of queueing up behind the same row
+        let row: Option<(String, String, i32)> = query_as(
+            r"
+            UPDATE requests
+            SET state = 'in_flight', attempts = attempts + 1, lease_owner = $2,
+                lease_expires_at = $3, updated_at = $1
+            WHERE id = (
+                SELECT id FROM requests
+                WHERE state IN ('pending', 'failed') AND next_eligible_at <= $1
+                ORDER BY next_eligible_at
+                LIMIT 1
+                FOR UPDATE SKIP LOCKED
+            )
+            RETURNING id, request, attempts
+            ",
+        )
+        .bind(now.timestamp())
+        .bind(owner)
+        .bind((now + ttl).timestamp())
+        .fetch_optional(&self.pool)
+        .await?;
+
+        match row {
+            Some((id, request, attempts)) => Ok(Some(Job {
+                id,
+                request: serde_json::from_str(&request)?,
+                attempts: attempts as u32,
+            })),
+            None => Ok(None),
+        }
+    }
+
+    async fn heartbeat(&self, req_id: &str, owner: &str, ttl: Duration) -> Result<bool> {
+        let res = query(
+            r"
+            UPDATE requests SET lease_expires_at = $1
+            WHERE id = $2 AND state = 'in_flight' AND lease_owner = $3
+            ",
+        )
+        .bind((Utc::now() + ttl).timestamp())
+        .bind(req_id)
+        .bind(owner)
+        .execute(&self.pool)
+        .await?;
+        Ok(res.rows_affected() > 0)
+    }
+
+    async fn reclaim_expired(&self, policy: &RetryPolicy) -> Result<u64> {
+        // Leases taken before they could expire have no expiry at all
+        let now = Utc::now();
+        let expired: Vec<(String, i32)> = query_as(
+            r"
+            SELECT id, attempts FROM requests
+            WHERE state = 'in_flight' AND (lease_expires_at IS NULL OR lease_expires_at < $1)
+            ",
+        )
+        .bind(now.timestamp())
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut reclaimed = 0;
+        for (req_id, attempts) in expired {
+            let (state, next_eligible_at) = policy.after_failure(attempts as u32, now);
+            // The lease may have been renewed since it was found to be expired
+            let res = query(
+                r"
+                UPDATE requests
+                SET state = $1, error = 'lease expired', lease_owner = NULL,
+                    lease_expires_at = NULL, next_eligible_at = $2, updated_at = $3
+                WHERE id = $4 AND state = 'in_flight'
+                    AND (lease_expires_at IS NULL OR lease_expires_at < $3)
+                ",
+            )
+            .bind(state.as_str())
+            .bind(next_eligible_at.timestamp())
+            .bind(now.timestamp())
+            .bind(&req_id)
+            .execute(&self.pool)
+            .await?;
+            reclaimed += res.rows_affected();
+        }
+        Ok(reclaimed)
+    }
+
+    async fn count_remaining(&self) -> Result<u64> {
+        let (count,): (i64,) = query_as(
+            "SELECT COUNT(*) FROM requests WHERE state IN ('pending', 'in_flight', 'failed')",
+        )
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(count as u64)
+    }
+
+    async fn count_eligible(&self) -> Result<u64> {
+        let (count,): (i64,) = query_as(
+            r"
+            SELECT COUNT(*) FROM requests
+            WHERE state IN ('pending', 'failed') AND next_eligible_at <= $1
+            ",
+        )
+        .bind(Utc::now().timestamp())
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(count as u64)
+    }
+
+    async fn upsert_request_success(
+        &self,
+        req_id: &str,
+        owner: &str,
+        num_results: u32,
+        timings: &Timings,
+    ) -> Result<bool> {
+        let res = query(
+            r"
+            UPDATE requests
+            SET state = 'success', num_results = $1, error = NULL, duration = $2,
+                fetch_duration = $3, parse_duration = $4, lease_owner = NULL,
+                lease_expires_at = NULL, updated_at = $5
+            WHERE id = $6 AND state = 'in_flight' AND lease_owner = $7
+            ",
+        )
+        .bind(num_results as i32)
+        .bind(timings.total)
+        .bind(timings.fetch)
+        .bind(timings.parse)
+        .bind(Utc::now().timestamp())
+        .bind(req_id)
+        .bind(owner)
+        .execute(&self.pool)
+        .await?;
+        Ok(res.rows_affected() > 0)
+    }
+
+    async fn upsert_request_error(
+        &self,
+        req_id: &str,
+        owner: &str,
+        error_text: &str,
+        timings: &Timings,
+        policy: &RetryPolicy,
+    ) -> Result<Option<JobState>> {
+        let row: Option<(i32,)> = query_as(
+            "SELECT attempts FROM requests WHERE id = $1 AND state = 'in_flight' AND lease_owner = $2",
+        )
+        .bind(req_id)
+        .bind(owner)
+        .fetch_optional(&self.pool)
+        .await?;
+        let attempts = match row {
+            Some((attempts,)) => attempts as u32,
+            None => return Ok(None),
+        };
+
+        let now = Utc::now();
+        let (state, next_eligible_at) = policy.after_failure(attempts, now);
+
+        let res = query(
+            r"
+            UPDATE requests
+            SET state = $1, error = $2, duration = $3, fetch_duration = $4, parse_duration = $5,
+                lease_owner = NULL, lease_expires_at = NULL, next_eligible_at = $6,
+                updated_at = $7
+            WHERE id = $8 AND state = 'in_flight' AND lease_owner = $9
+            ",
+        )
+        .bind(state.as_str())
+        .bind(error_text)
+        .bind(timings.total)
+        .bind(timings.fetch)
+        .bind(timings.parse)
+        .bind(next_eligible_at.timestamp())
+        .bind(now.timestamp())
+        .bind(req_id)
+        .bind(owner)
+        .execute(&self.pool)
+        .await?;
+        Ok((res.rows_affected() > 0).then_some(state))
+    }
+
+    async fn job_states(&self, req_ids: &[String]) -> Result<HashMap<String, JobState>> {
+        let rows: Vec<(String, String)> =
+            query_as("SELECT id, state FROM requests WHERE id = ANY($1)")
+                .bind(req_ids)
+                .fetch_all(&self.pool)
+                .await?;
+        rows.into_iter()
+            .map(|(id, state)| Ok((id, JobState::from_str(&state)?)))
+            .collect()
+    }
+
+    async fn count_by_state(&self) -> Result<HashMap<JobState, u64>> {
+        let rows: Vec<(String, i64)> =
+            query_as("SELECT state, COUNT(*) FROM requests GROUP BY state")
+                .fetch_all(&self.pool)
+                .await?;
+        rows.into_iter()
+            .map(|(state, count)| Ok((JobState::from_str(&state)?, count as u64)))
+            .collect()
+    }
+
+    async fn count_by_partition(&self) -> Result<Vec<(String, String, JobState, u64)>> {
+        let rows: Vec<(String, String, String, i64)> = query_as(
+            r"
+            SELECT
+                substr(request::json->>'from_date', 1, 7) AS month,
+                request::json->>'gender' AS gender,
+                state,
+                COUNT(*)
+            FROM requests
+            GROUP BY month, gender, state
+            ORDER BY month, gender
+            ",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        rows.into_iter()
+            .map(|(month, gender, state, count)| {
+                Ok((month, gender, JobState::from_str(&state)?, count as u64))
+            })
+            .collect()
+    }
+
+    async fn error_breakdown(&self, width: u32, limit: u32) -> Result<Vec<(String, u64)>> {
+        let rows: Vec<(String, i64)> = query_as(
+            r"
+            SELECT substr(error, 1, $1) AS e, COUNT(*) AS n
+            FROM requests
+            WHERE state IN ('failed', 'dead') AND error IS NOT NULL
+            GROUP BY e
+            ORDER BY n DESC
+            LIMIT $2
+            ",
+        )
+        .bind(width as i32)
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows.into_iter().map(|(e, n)| (e, n as u64)).collect())
+    }
+
+    async fn count_completed_since(&self, since: i64) -> Result<u64> {
+        let (count,): (i64,) =
+            query_as("SELECT COUNT(*) FROM requests WHERE state = 'success' AND updated_at >= $1")
+                .bind(since)
+                .fetch_one(&self.pool)
+                .await?;
+        Ok(count as u64)
+    }
+
+    async fn total_results(&self) -> Result<u64> {
+        let (total,): (Option<i64>,) =
+            query_as("SELECT SUM(num_results) FROM requests WHERE state = 'success'")
+                .fetch_one(&self.pool)
+                .await?;
+        Ok(total.unwrap_or_default() as u64)
+    }
+
+    async fn mean_timings_since(&self, since: i64) -> Result<(Option<f64>, Option<f64>)> {
+        let row: (Option<f64>, Option<f64>) = query_as(
+            r"
+            SELECT AVG(fetch_duration), AVG(parse_duration) FROM requests
+            WHERE state = 'success' AND updated_at >= $1
+            ",
+        )
+        .bind(since)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(row)
+    }
+
+    async fn dead_jobs(&self) -> Result<Vec<DeadJob>> {
+        let rows: Vec<(String, i32, Option<String>, i64)> = query_as(
+            r"
+            SELECT id, attempts, error, updated_at FROM requests
+            WHERE state = 'dead'
+            ORDER BY updated_at DESC
+            ",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|(id, attempts, error, updated_at)| DeadJob {
+                id,
+                attempts: attempts as u32,
+                error,
+                updated_at,
+            })
+            .collect())
+    }
+
+    async fn requeue_dead(&self) -> Result<u64> {
+        let res = query(
+            r"
+            UPDATE requests
+            SET state = 'pending', attempts = 0, error = NULL, next_eligible_at = $1,
+                updated_at = $1
+            WHERE state = 'dead'
+            ",
+        )
+        .bind(Utc::now().timestamp())
+        .execute(&self.pool)
+        .await?;
+        Ok(res.rows_affected())
+    }
+}
@@ -0,0 +1,510 @@
+use std::{collections::HashMap, str::FromStr};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{Duration, Utc};
+use sqlx::{
+    migrate::Migrator,
+    query, query_as,
+    sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions},
+    SqlitePool,
+};
+use swimrs::usas::toptimes::TopTimesRequest;
+use tracing::{info, warn};
+
+use super::{DeadJob, Job, JobState, RequestStore, RetryPolicy, Timings};
+
+/// Schema for everything kept in SQLite: the request queue, the times store
+/// and the response archive.
+static MIGRATOR: Migrator = sqlx::migrate!("migrations/sqlite");
+
+/// Brings a SQLite database up to date with [`MIGRATOR`].
+pub async fn migrate(pool: &SqlitePool) -> Result<()> {
+    adopt_legacy(pool).await?;
+    MIGRATOR.run(pool).await?;
+    Ok(())
+}
+
+/// Prepares a database created before migrations were introduced. The first
+/// migrations only create missing tables, so a request table from the
+/// original mirror is upgraded to a job queue, and the stage timing columns
+/// added since are put in place, beforehand.
+async fn adopt_legacy(pool: &SqlitePool) -> Result<()> {
+    let columns: Vec<(String,)> = query_as("SELECT name FROM pragma_table_info('requests')")
+        .fetch_all(pool)
+        .await?;
+    if columns.is_empty() {
+        return Ok(());
+    }
+    if !columns.iter().any(|(c,)| c == "request") {
+        upgrade_baseline(pool).await?;
+    }
+    for column in ["fetch_duration", "parse_duration"] {
+        if !columns.iter().any(|(c,)| c == column) {
+            query(&format!("ALTER TABLE requests ADD COLUMN {} REAL", column))
+                .execute(pool)
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+/// Turns the original request table, which only recorded the outcome of each
+/// request under its legacy ID, into a job queue. Each request is rebuilt
+/// from its ID and re-keyed; those that succeeded keep their outcome and the
+/// rest are queued to be fetched again. Rows whose ID can't be parsed are
+/// dropped, and are queued again the next time the mirror is planned.
+async fn upgrade_baseline(pool: &SqlitePool) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    for column in [
+        "request TEXT NOT NULL DEFAULT ''",
+        "attempts INTEGER NOT NULL DEFAULT 0",
+        "next_eligible_at INTEGER NOT NULL DEFAULT 0",
+        "updated_at INTEGER NOT NULL DEFAULT 0",
+    ] {
+        query(&format!("ALTER TABLE requests ADD COLUMN {}", column))
+            .execute(&mut tx)
+            .await?;
+    }
+
+    let rows: Vec<(String, Option<String>)> = query_as("SELECT id, state FROM requests")
+        .fetch_all(&mut tx)
+        .await?;
+    let now = Utc::now().timestamp();
+    let (mut upgraded, mut dropped) = (0, 0);
+    for (id, state) in rows {
+        let req = match TopTimesRequest::from_legacy_id(&id) {
+            Ok(req) => req,
+            Err(e) => {
+                warn!("dropping request {} from the original schema: {}", id, e);
+                query("DELETE FROM requests WHERE id = ?")
+                    .bind(&id)
+                    .execute(&mut tx)
+                    .await?;
+                dropped += 1;
+                continue;
+            }
+        };
+        let (state, attempts) = match state.as_deref() {
+            Some("success") => (JobState::Success, 1),
+            _ => (JobState::Pending, 0),
+        };
+        query(
+            r"
+            UPDATE OR REPLACE requests
+            SET id = ?, state = ?, request = ?, attempts = ?, next_eligible_at = ?,
+                updated_at = ?
+            WHERE id = ?
+            ",
+        )
+        .bind(req.id())
+        .bind(state.as_str())
+        .bind(serde_json::to_string(&req)?)
+        .bind(attempts)
+        .bind(now)
+        .bind(now)
+        .bind(&id)
+        .execute(&mut tx)
+        .await?;
+        upgraded += 1;
+    }
+    tx.commit().await?;
+    info!(
+        "upgraded {} requests from the original schema, dropped {}",
+        upgraded, dropped
+    );
+    Ok(())
+}
+
+/// Request store in a SQLite database, which may also hold the times store
+/// and response archive.
+pub struct SqliteRequestDb {
+    pool: SqlitePool,
+}
+
+impl SqliteRequestDb {
+    pub async fn new(db_url: &str) -> Result<Self> {
+        let opts = SqliteConnectOptions::from_str(db_url)?
+            .create_if_missing(true)
+            .journal_mode(SqliteJournalMode::Wal);
+        let pool = SqlitePoolOptions::new().connect_with(opts).await?;
+        Ok(Self { pool })
+    }
+}
+
+#[async_trait]
+impl RequestStore for SqliteRequestDb {
+    async fn migrate(&self) -> Result<()> {
+        migrate(&self.pool).await
+    }
+
+    async fn enqueue(&self, reqs: &[(String, TopTimesRequest)]) -> Result<u64> {
+        let now = Utc::now().timestamp();
+        let mut tx = self.pool.begin().await?;
+        let mut queued = 0;
+        for (req_id, req) in reqs {
+            let res = query(
+                r"
+                INSERT OR IGNORE INTO requests
+                    (id, state, request, attempts, next_eligible_at, updated_at)
+                VALUES (?, 'pending', ?, 0, ?, ?)
+                ",
+            )
+            .bind(req_id)
+            .bind(serde_json::to_string(req)?)
+            .bind(now)
+            .bind(now)
+            .execute(&mut tx)
+            .await?;
+            queued += res.rows_affected();
+        }
+        tx.commit().await?;
+        Ok(queued)
+    }
+
+    async fn enqueue_refresh(&self, reqs: &[(String, TopTimesRequest)]) -> Result<u64> {
+        let now = Utc::now().timestamp();
+        let mut tx = self.pool.begin().await?;
+        let mut queued = 0;
+        for (req_id, req) in reqs {
+            let res = query(
+                r"
+                INSERT INTO requests
+                    (id, state, request, attempts, next_eligible_at, updated_at)
+                VALUES (?, 'pending', ?, 0, ?, ?)
+                ON CONFLICT (id) DO UPDATE SET
+                    state = 'pending',
+                    attempts = 0,
+                    next_eligible_at = excluded.next_eligible_at,
+                    updated_at = excluded.updated_at
+                WHERE state IN ('success', 'dead')
+                ",
+            )
+            .bind(req_id)
+            .bind(serde_json::to_string(req)?)
+            .bind(now)
+            .bind(now)
+            .execute(&mut tx)
+            .await?;
+            queued += res.rows_affected();
+        }
+        tx.commit().await?;
+        Ok(queued)
+    }
+
+    async fn lease(&self, owner: &str, ttl: Duration) -> Result<Option<Job>> {
+        let now = Utc::now();
+        // Fetch every row so the statement runs to completion; stopping after
+        // the first leaves it active, and the update uncommitted, until the
+        // connection's next query
+        let rows: Vec<(String, String, u32)> = query_as(
+            r"
+            UPDATE requests
+            SET state = 'in_flight', attempts = attempts + 1, lease_owner = ?,
+                lease_expires_at = ?, updated_at = ?
+            WHERE id = (
+                SELECT id FROM requests
+                WHERE state IN ('pending', 'failed') AND next_eligible_at <= ?
+                ORDER BY next_eligible_at
+                LIMIT 1
+            )
+            RETURNING id, request, attempts
+            ",
+        )
+        .bind(owner)
+        .bind((now + ttl).timestamp())
+        .bind(now.timestamp())
+        .bind(now.timestamp())
+        .fetch_all(&self.pool)
+        .await?;
+
+        match rows.into_iter().next() {
+            Some((id, request, attempts)) => Ok(Some(Job {
+                id,
+                request: serde_json::from_str(&request)?,
+                attempts,
+            })),
+            None => Ok(None),
+        }
+    }
+
+    async fn heartbeat(&self, req_id: &str, owner: &str, ttl: Duration) -> Result<bool> {
+        let res = query(
+            r"
+            UPDATE requests SET lease_expires_at = ?
+            WHERE id = ? AND state = 'in_flight' AND lease_owner = ?
+            ",
+        )
+        .bind((Utc::now() + ttl).timestamp())
+        .bind(req_id)
+        .bind(owner)
+        .execute(&self.pool)
+        .await?;
+        Ok(res.rows_affected() > 0)
+    }
+
+    async fn reclaim_expired(&self, policy: &RetryPolicy) -> Result<u64> {
+        // Leases taken before they could expire have no expiry at all
+        let now = Utc::now();
+        let expired: Vec<(String, u32)> = query_as(
+            r"
+            SELECT id, attempts FROM requests
+            WHERE state = 'in_flight' AND (lease_expires_at IS NULL OR lease_expires_at < ?)
+            ",
+        )
+        .bind(now.timestamp())
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut reclaimed = 0;
+        for (req_id, attempts) in expired {
+            let (state, next_eligible_at) = policy.after_failure(attempts, now);
+            // The lease may have been renewed since it was found to be expired
+            let res = query(
+                r"
+                UPDATE requests
+                SET state = ?, error = 'lease expired', lease_owner = NULL,
+                    lease_expires_at = NULL, next_eligible_at = ?, updated_at = ?
+                WHERE id = ? AND state = 'in_flight'
+                    AND (lease_expires_at IS NULL OR lease_expires_at < ?)
+                ",
+            )
+            .bind(state.as_str())
+            .bind(next_eligible_at.timestamp())
+            .bind(now.timestamp())
+            .bind(&req_id)
+            .bind(now.timestamp())
+            .execute(&self.pool)
+            .await?;
+            reclaimed += res.rows_affected();
+        }
+        Ok(reclaimed)
+    }
+
+    async fn count_remaining(&self) -> Result<u64> {
+        let (count,): (i64,) = query_as(
+            "SELECT COUNT(*) FROM requests WHERE state IN ('pending', 'in_flight', 'failed')",
+        )
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(count as u64)
+    }
+
+    async fn count_eligible(&self) -> Result<u64> {
+        let (count,): (i64,) = query_as(
+            r"
+            SELECT COUNT(*) FROM requests
+            WHERE state IN ('pending', 'failed') AND next_eligible_at <= ?
+            ",
+        )
+        .bind(Utc::now().timestamp())
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(count as u64)
+    }
+
+    async fn upsert_request_success(
+        &self,
+        req_id: &str,
+        owner: &str,
+        num_results: u32,
+        timings: &Timings,
+    ) -> Result<bool> {
+        let res = query(
+            r"
+            UPDATE requests
+            SET state = 'success', num_results = ?, error = NULL, duration = ?,
+                fetch_duration = ?, parse_duration = ?, lease_owner = NULL,
+                lease_expires_at = NULL, updated_at = ?
+            WHERE id = ? AND state = 'in_flight' AND lease_owner = ?
+            ",
+        )
+        .bind(num_results)
+        .bind(timings.total)
+        .bind(timings.fetch)
+        .bind(timings.parse)
+        .bind(Utc::now().timestamp())
+        .bind(req_id)
+        .bind(owner)
+        .execute(&self.pool)
+        .await?;
+        Ok(res.rows_affected() > 0)
+    }
+
+    async fn upsert_request_error(
+        &self,
+        req_id: &str,
+        owner: &str,
+        error_text: &str,
+        timings: &Timings,
+        policy: &RetryPolicy,
+    ) -> Result<Option<JobState>> {
+        let row: Option<(u32,)> = query_as(
+            "SELECT attempts FROM requests WHERE id = ? AND state = 'in_flight' AND lease_owner = ?",
+        )
+        .bind(req_id)
+        .bind(owner)
+        .fetch_optional(&self.pool)
+        .await?;
+        let attempts = match row {
+            Some((attempts,)) => attempts,
+            None => return Ok(None),
+        };
+
+        let now = Utc::now();
+        let (state, next_eligible_at) = policy.after_failure(attempts, now);
+
+        let res = query(
+            r"
+            UPDATE requests
+            SET state = ?, error = ?, duration = ?, fetch_duration = ?, parse_duration = ?,
+                lease_owner = NULL, lease_expires_at = NULL, next_eligible_at = ?,
+                updated_at = ?
+            WHERE id = ? AND state = 'in_flight' AND lease_owner = ?
+            ",
+        )
+        .bind(state.as_str())
+        .bind(error_text)
+        .bind(timings.total)
+        .bind(timings.fetch)
+        .bind(timings.parse)
+        .bind(next_eligible_at.timestamp())
+        .bind(now.timestamp())
+        .bind(req_id)
+        .bind(owner)
+        .execute(&self.pool)
+        .await?;
+        Ok((res.rows_affected() > 0).then_some(state))
+    }
+
+    async fn job_states(&self, req_ids: &[String]) -> Result<HashMap<String, JobState>> {
+        let mut states = HashMap::new();
+        for req_id in req_ids {
+            let row: Option<(String,)> = query_as("SELECT state FROM requests WHERE id = ?")
+                .bind(req_id)
+                .fetch_optional(&self.pool)
+                .await?;
+            if let Some((state,)) = row {
+                states.insert(req_id.clone(), JobState::from_str(&state)?);
+            }
+        }
+        Ok(states)
+    }
+
+    async fn count_by_state(&self) -> Result<HashMap<JobState, u64>> {
+        let rows: Vec<(String, i64)> =
+            query_as("SELECT state, COUNT(*) FROM requests GROUP BY state")
+                .fetch_all(&self.pool)
+                .await?;
+        rows.into_iter()
+            .map(|(state, count)| Ok((JobState::from_str(&state)?, count as u64)))
+            .collect()
+    }
+
+    async fn count_by_partition(&self) -> Result<Vec<(String, String, JobState, u64)>> {
+        let rows: Vec<(String, String, String, i64)> = query_as(
+            r"
+            SELECT
+                substr(json_extract(request, '$.from_date'), 1, 7) AS month,
+                json_extract(request, '$.gender') AS gender,
+                state,
+                COUNT(*)
+            FROM requests
+            GROUP BY month, gender, state
+            ORDER BY month, gender
+            ",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        rows.into_iter()
+            .map(|(month, gender, state, count)| {
+                Ok((month, gender, JobState::from_str(&state)?, count as u64))
+            })
+            .collect()
+    }
+
+    async fn error_breakdown(&self, width: u32, limit: u32) -> Result<Vec<(String, u64)>> {
+        let rows: Vec<(String, i64)> = query_as(
+            r"
+            SELECT substr(error, 1, ?) AS e, COUNT(*) AS n
+            FROM requests
+            WHERE state IN ('failed', 'dead') AND error IS NOT NULL
+            GROUP BY e
+            ORDER BY n DESC
+            LIMIT ?
+            ",
+        )
+        .bind(width)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows.into_iter().map(|(e, n)| (e, n as u64)).collect())
+    }
+
+    async fn count_completed_since(&self, since: i64) -> Result<u64> {
+        let (count,): (i64,) =
+            query_as("SELECT COUNT(*) FROM requests WHERE state = 'success' AND updated_at >= ?")
+                .bind(since)
+                .fetch_one(&self.pool)
+                .await?;
+        Ok(count as u64)
+    }
+
+    async fn total_results(&self) -> Result<u64> {
+        let (total,): (Option<i64>,) =
+            query_as("SELECT SUM(num_results) FROM requests WHERE state = 'success'")
+                .fetch_one(&self.pool)
+                .await?;
+        Ok(total.unwrap_or_default() as u64)
+    }
+
+    async fn mean_timings_since(&self, since: i64) -> Result<(Option<f64>, Option<f64>)> {
+        let row: (Option<f64>, Option<f64>) = query_as(
+            r"
+            SELECT AVG(fetch_duration), AVG(parse_duration) FROM requests
+            WHERE state = 'success' AND updated_at >= ?
+            ",
+        )
+        .bind(since)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(row)
+    }
+
+    async fn dead_jobs(&self) -> Result<Vec<DeadJob>> {
+        let rows: Vec<(String, u32, Option<String>, i64)> = query_as(
+            r"
+            SELECT id, attempts, error, updated_at FROM requests
+            WHERE state = 'dead'
+            ORDER BY updated_at DESC
+            ",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|(id, attempts, error, updated_at)| DeadJob {
+                id,
+                attempts,
+                error,
+                updated_at,
+            })
+            .collect())
+    }
+
+    async fn requeue_dead(&self) -> Result<u64> {
+        let res = query(
+            r"
+            UPDATE requests
+            SET state = 'pending', attempts = 0, error = NULL, next_eligible_at = ?,
+                updated_at = ?
+            WHERE state = 'dead'
+            ",
+        )
+        .bind(Utc::now().timestamp())
+        .bind(Utc::now().timestamp())
+        .execute(&self.pool)
+        .await?;
+        Ok(res.rows_affected())
+    }
+}
@@ -0,0 +1,75 @@
+use std::net::SocketAddr;
+
+use anyhow::Result;
+use metrics::{describe_counter, describe_gauge, describe_histogram, Unit};
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder};
+
+/// Histogram buckets for request stage durations, in seconds. Fetches
+/// usually take a few seconds, while parsing a large result set can take
+/// several more.
+const DURATION_BUCKETS: [f64; 12] = [
+    0.01, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0,
+];
+
+/// Installs the Prometheus exporter, listening on `addr`, and describes every
+/// metric the mirror records.
+pub fn install(addr: SocketAddr) -> Result<()> {
+    PrometheusBuilder::new()
+        .with_http_listener(addr)
+        .set_buckets_for_metric(
+            Matcher::Suffix("duration_seconds".to_owned()),
+            &DURATION_BUCKETS,
+        )?
+        .install()?;
+
+    describe_gauge!(
+        "swimrs_mirror_ready_clients",
+        "Number of clients with cookies populated and processing requests"
+    );
+    describe_gauge!(
+        "swimrs_mirror_request_queue_depth",
+        "Number of requests eligible to be leased"
+    );
+    describe_gauge!(
+        "swimrs_mirror_request_active_count",
+        "Number of requests currently being fetched or parsed"
+    );
+    describe_histogram!(
+        "swimrs_mirror_fetch_duration_seconds",
+        Unit::Seconds,
+        "Time spent sending a request and downloading its response"
+    );
+    describe_histogram!(
+        "swimrs_mirror_parse_duration_seconds",
+        Unit::Seconds,
+        "Time spent parsing a response"
+    );
+    describe_histogram!(
+        "swimrs_mirror_request_duration_seconds",
+        Unit::Seconds,
+        "Total time spent processing a request, including writing results"
+    );
+    describe_counter!(
+        "swimrs_mirror_requests_total",
+        "Requests processed, by outcome (a job state, or lost if the lease expired), client and proxy"
+    );
+    describe_counter!(
+        "swimrs_mirror_http_responses_total",
+        "HTTP responses received, by status code, client and proxy"
+    );
+    describe_counter!(
+        "swimrs_mirror_request_errors_total",
+        "Failed requests, by error class, client and proxy"
+    );
+    describe_counter!(
+        "swimrs_mirror_downloaded_bytes_total",
+        Unit::Bytes,
+        "Response bytes downloaded, by client and proxy"
+    );
+    describe_counter!(
+        "swimrs_mirror_rows_parsed_total",
+        "Rows parsed from responses, by time type"
+    );
+
+    Ok(())
+}
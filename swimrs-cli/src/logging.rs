@@ -0,0 +1,199 @@
+use std::{
+    io::{self, Write},
+    path::PathBuf,
+    sync::Mutex,
+};
+
+use anyhow::{Context, Result};
+use clap::ArgEnum;
+use indicatif::ProgressBar;
+use serde::Deserialize;
+use tracing_appender::{
+    non_blocking::WorkerGuard,
+    rolling::{RollingFileAppender, Rotation},
+};
+use tracing_subscriber::{
+    fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer, Registry,
+};
+
+/// Log file name prefix; the rotation period is appended to it.
+const LOG_FILE_PREFIX: &str = "swimrs.log";
+
+/// Filter for the log file when none is configured. Verbose enough to follow
+/// each request through fetching, parsing and writing.
+const DEFAULT_FILE_FILTER: &str = "info,sqlx=warn,swimrs=debug,swimrs_cli=debug";
+
+/// Progress bar currently drawn on stderr, if any. Events logged to stderr
+/// while it is shown are printed above it rather than over it.
+static PROGRESS_BAR: Mutex<Option<ProgressBar>> = Mutex::new(None);
+
+#[derive(Debug, Clone, Copy, PartialEq, ArgEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    Text,
+    /// One JSON object per event, including the fields of every enclosing
+    /// span
+    Json,
+}
+
+/// How often the log file is rotated.
+#[derive(Debug, Clone, Copy, PartialEq, ArgEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogRotation {
+    Hourly,
+    Daily,
+    Never,
+}
+
+impl From<LogRotation> for Rotation {
+    fn from(r: LogRotation) -> Self {
+        match r {
+            LogRotation::Hourly => Rotation::HOURLY,
+            LogRotation::Daily => Rotation::DAILY,
+            LogRotation::Never => Rotation::NEVER,
+        }
+    }
+}
+
+/// Where and how events are logged.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    pub format: LogFormat,
+    /// If set, events are also written to a rolling file in this directory
+    pub dir: Option<PathBuf>,
+    pub rotation: LogRotation,
+    /// Filter directives for the log file, in `RUST_LOG` syntax
+    pub file_filter: String,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        LogConfig {
+            format: LogFormat::Text,
+            dir: None,
+            rotation: LogRotation::Daily,
+            file_filter: DEFAULT_FILE_FILTER.to_owned(),
+        }
+    }
+}
+
+/// Installs the global subscriber. Events from the library, the CLI and
+/// dependencies that use `log` are written to stderr, filtered by `RUST_LOG`,
+/// and to the log file if one is configured. The returned guard flushes the
+/// log file when dropped, so it must be held until exit.
+pub fn init(config: &LogConfig) -> Result<Option<WorkerGuard>> {
+    let mut layers = vec![layer(config.format, || Stderr, true)
+        .with_filter(EnvFilter::from_default_env())
+        .boxed()];
+
+    let guard = match &config.dir {
+        Some(dir) => {
+            let appender = RollingFileAppender::new(config.rotation.into(), dir, LOG_FILE_PREFIX);
+            let (writer, guard) = tracing_appender::non_blocking(appender);
+            let filter = EnvFilter::try_new(&config.file_filter)
+                .with_context(|| format!("invalid log file filter: {}", config.file_filter))?;
+            layers.push(
+                layer(config.format, writer, false)
+                    .with_filter(filter)
+                    .boxed(),
+            );
+            Some(guard)
+        }
+        None => None,
+    };
+
+    tracing_subscriber::registry()
+        .with(layers)
+        .try_init()
+        .context("error installing log subscriber")?;
+    Ok(guard)
+}
+
+fn layer<W>(format: LogFormat, writer: W, ansi: bool) -> Box<dyn Layer<Registry> + Send + Sync>
+where
+    W: for<'w> fmt::MakeWriter<'w> + Send + Sync + 'static,
+{
+    let layer = fmt::layer().with_writer(writer).with_ansi(ansi);
+    match format {
+        LogFormat::Text => layer.boxed(),
+        LogFormat::Json => layer
+            .json()
+            .with_current_span(true)
+            .with_span_list(true)
+            .boxed(),
+    }
+}
+
+/// Sets the progress bar that events logged to stderr are drawn around, or
+/// clears it once the bar is no longer shown.
+pub fn set_progress_bar(bar: Option<ProgressBar>) {
+    *PROGRESS_BAR.lock().unwrap() = bar;
+}
+
+/// Writes to stderr, hiding the progress bar, if there is one, while it does.
+/// Each event is formatted in full and then written in one call, so lines are
+/// never split around the bar.
+struct Stderr;
+
+impl Write for Stderr {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let bar = PROGRESS_BAR.lock().unwrap().clone();
+        match bar {
+            Some(bar) => bar.suspend(|| io::stderr().write_all(buf))?,
+            None => io::stderr().write_all(buf)?,
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stderr().flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    #[test]
+    fn test_log_config() {
+        let config: LogConfig = toml::from_str(
+            r#"
+            format = "json"
+            dir = "logs"
+            rotation = "hourly"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.format, LogFormat::Json);
+        assert_eq!(config.dir, Some(PathBuf::from("logs")));
+        assert_eq!(config.rotation, LogRotation::Hourly);
+        assert_eq!(config.file_filter, DEFAULT_FILE_FILTER);
+        assert!(toml::from_str::<LogConfig>("rotation = \"weekly\"").is_err());
+    }
+
+    #[test]
+    fn test_invalid_file_filter() {
+        let dir = TempDir::new("invalid-file-filter");
+        let config = LogConfig {
+            dir: Some(dir.path().to_owned()),
+            file_filter: "swimrs=loud".to_owned(),
+            ..LogConfig::default()
+        };
+        let e = init(&config).unwrap_err();
+        assert!(e.to_string().contains("swimrs=loud"));
+    }
+
+    #[test]
+    fn test_stderr_around_progress_bar() {
+        let line = b"event\n";
+        assert_eq!(Stderr.write(line).unwrap(), line.len());
+
+        let bar = ProgressBar::hidden();
+        set_progress_bar(Some(bar.clone()));
+        assert_eq!(Stderr.write(line).unwrap(), line.len());
+        set_progress_bar(None);
+        assert!(PROGRESS_BAR.lock().unwrap().is_none());
+    }
+}
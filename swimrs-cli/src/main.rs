@@ -1,14 +1,45 @@
+mod archive;
+mod config;
+mod control;
 mod db;
+mod exporter;
+mod logging;
+mod merge;
 mod mirror;
+mod output;
+mod plan;
+mod psych;
+mod qualify;
+mod status;
+mod sync;
+#[cfg(test)]
+mod testing;
+mod times;
+
+use std::{net::SocketAddr, path::PathBuf, time::Duration};
 
 use anyhow::Result;
 use chrono::NaiveDate;
 use clap::{Args, Parser, Subcommand};
 
+use crate::{
+    config::Config,
+    logging::{LogFormat, LogRotation},
+    output::{Compression, Layout, OutputFormat},
+    plan::{Period, Split, TimeTypes},
+    psych::SheetFormat,
+    sync::SyncOptions,
+};
+
 #[derive(Parser)]
 #[clap(author, version, about, long_about = None)]
 #[clap(propagate_version = true)]
 struct Cli {
+    /// TOML file to read settings from. Command line flags take precedence
+    #[clap(long, global = true)]
+    config: Option<PathBuf>,
+    #[clap(flatten)]
+    log: LogArgs,
     #[clap(subcommand)]
     command: Commands,
 }
@@ -17,6 +48,21 @@ struct Cli {
 enum Commands {
     /// Mirror the USA Swimming times database
     Mirror(MirrorArgs),
+    /// Incrementally refresh recent days and fetch new ones
+    Sync(SyncArgs),
+    /// Summarize mirror progress from the request database
+    Status(StatusArgs),
+    /// List requests that exceeded the maximum number of attempts
+    DeadLetters(DeadLettersArgs),
+    /// Rebuild parsed times from archived raw responses without fetching
+    Reparse(ReparseArgs),
+    /// Combine existing CSV and JSON Lines output, and HY3 meet results, into
+    /// one dataset without duplicates
+    Merge(MergeArgs),
+    /// Check swimmers' times against a meet's qualifying standards
+    Qualify(QualifyArgs),
+    /// Seed a meet's entries into heats and lanes and print its psych sheet
+    Psych(PsychArgs),
 }
 
 #[derive(Args)]
@@ -25,22 +71,399 @@ struct MirrorArgs {
     from_date: NaiveDate,
     /// Ending date in the range to mirror
     to_date: NaiveDate,
-    /// Number of unique HTTP clients to send requests with
-    #[clap(long, default_value = "1")]
-    clients: u16,
-    /// Database URL to save request progress to
-    #[clap(long, default_value = "sqlite://swimrs.db")]
-    db_url: String,
+    #[clap(flatten)]
+    plan: PlanArgs,
+    #[clap(flatten)]
+    worker: WorkerArgs,
+}
+
+#[derive(Args)]
+struct SyncArgs {
+    /// Earliest date to sync. Defaults to the start of the first sync
+    #[clap(long)]
+    since: Option<NaiveDate>,
+    /// Number of trailing days to re-fetch on every sync
+    #[clap(long, default_value = "60")]
+    window_days: u32,
+    /// Keep running, syncing again after this many hours
+    #[clap(long)]
+    every_hours: Option<u64>,
+    /// Print every added, changed and removed time
+    #[clap(long)]
+    report: bool,
+    #[clap(flatten)]
+    plan: PlanArgs,
+    #[clap(flatten)]
+    worker: WorkerArgs,
+}
+
+#[derive(Args)]
+struct PlanArgs {
+    /// Date range covered by each request
+    #[clap(long, arg_enum)]
+    period: Option<Period>,
+    /// Comma-separated age buckets, e.g. "0-7,8,9,10-12,23-". Defaults to
+    /// single years from 8 to 22 plus 7 and under and 23 and over
+    #[clap(long)]
+    age_buckets: Option<String>,
+    /// Further split each request by this dimension; may be repeated
+    #[clap(long, arg_enum)]
+    split: Vec<Split>,
+    /// Kinds of results to fetch
+    #[clap(long, arg_enum)]
+    time_types: Option<TimeTypes>,
+    /// Print the planned requests and an estimated duration without fetching
+    #[clap(long)]
+    plan_only: bool,
+}
+
+impl PlanArgs {
+    fn apply(&self, config: &mut Config) {
+        let p = &mut config.partition;
+        if let Some(period) = self.period {
+            p.period = period;
+        }
+        if let Some(buckets) = &self.age_buckets {
+            p.age_buckets = Some(buckets.clone());
+        }
+        if !self.split.is_empty() {
+            p.split = self.split.clone();
+        }
+        if let Some(time_types) = self.time_types {
+            p.time_types = time_types;
+        }
+    }
+}
+
+#[derive(Args)]
+struct WorkerArgs {
+    /// Number of unique HTTP clients to send requests with [default: 1]
+    #[clap(long)]
+    clients: Option<u16>,
+    /// Proxy URL to send requests through, assigned to clients in turn; may
+    /// be repeated [default: socks5://127.0.0.1:53000 + client index]
+    #[clap(long)]
+    proxy: Vec<String>,
+    /// User agent to send, assigned to clients in turn; may be repeated
+    #[clap(long)]
+    user_agent: Vec<String>,
+    /// Minimum seconds between the start of consecutive requests per client
+    /// [default: 5]
+    #[clap(long)]
+    min_delay: Option<u64>,
+    /// Maximum seconds between the start of consecutive requests per client
+    /// [default: 10]
+    #[clap(long)]
+    max_delay: Option<u64>,
+    /// Database URL to save request progress to: sqlite://, postgres:// or
+    /// memory: [default: sqlite://swimrs.db]
+    #[clap(long)]
+    db_url: Option<String>,
+    /// SQLite database URL to save times and archived responses to
+    /// [default: --db-url if it is a SQLite database]
+    #[clap(long)]
+    times_db_url: Option<String>,
+    /// Number of attempts after which a failing request is no longer retried
+    /// [default: 5]
+    #[clap(long)]
+    max_attempts: Option<u32>,
+    #[clap(flatten)]
+    output: OutputArgs,
+    /// Archive each raw response so it can be reparsed later
+    #[clap(long)]
+    archive: bool,
+    /// Address to serve Prometheus metrics on [default: 0.0.0.0:9000]
+    #[clap(long)]
+    metrics_addr: Option<SocketAddr>,
+    /// Address to serve the admin API on, for pausing, resizing and
+    /// rate-limiting a running mirror [default: 127.0.0.1:9001]
+    #[clap(long)]
+    admin_addr: Option<SocketAddr>,
+    /// Name identifying this process in leases on a request queue shared with
+    /// other hosts [default: host name and process ID]
+    #[clap(long)]
+    worker_id: Option<String>,
+    /// Seconds a request stays leased without a heartbeat before another
+    /// worker may retry it [default: 120]
+    #[clap(long)]
+    lease_timeout: Option<u64>,
+}
+
+impl WorkerArgs {
+    fn apply(&self, config: &mut Config) {
+        if let Some(clients) = self.clients {
+            config.clients.count = clients;
+        }
+        if !self.proxy.is_empty() {
+            config.clients.proxies = self.proxy.clone();
+        }
+        if !self.user_agent.is_empty() {
+            config.clients.user_agents = self.user_agent.clone();
+        }
+        if let Some(d) = self.min_delay {
+            config.rate_limit.min_delay = d;
+        }
+        if let Some(d) = self.max_delay {
+            config.rate_limit.max_delay = d;
+        }
+        if let Some(db_url) = &self.db_url {
+            config.db_url = db_url.clone();
+        }
+        if let Some(url) = &self.times_db_url {
+            config.times_db_url = Some(url.clone());
+        }
+        if let Some(n) = self.max_attempts {
+            config.rate_limit.max_attempts = n;
+        }
+        self.output.apply(config);
+        config.output.archive |= self.archive;
+        if let Some(addr) = self.metrics_addr {
+            config.metrics_addr = addr;
+        }
+        if let Some(addr) = self.admin_addr {
+            config.admin_addr = addr;
+        }
+        if let Some(id) = &self.worker_id {
+            config.lease.worker_id = Some(id.clone());
+        }
+        if let Some(t) = self.lease_timeout {
+            config.lease.timeout = t;
+        }
+    }
+}
+
+#[derive(Args)]
+struct OutputArgs {
+    /// Also write each result set to files under this directory
+    #[clap(long, alias = "csv-dir")]
+    output_dir: Option<PathBuf>,
+    /// Format of files written under --output-dir [default: csv]
+    #[clap(long, arg_enum)]
+    output_format: Option<OutputFormat>,
+    /// Compression of files written under --output-dir; applied within the
+    /// file for Parquet [default: none]
+    #[clap(long, arg_enum)]
+    compression: Option<Compression>,
+    /// Arrangement of files under --output-dir: a directory per request, or
+    /// Hive-style year=/month=/gender= partitions [default: request]
+    #[clap(long, arg_enum)]
+    layout: Option<Layout>,
+}
+
+impl OutputArgs {
+    fn apply(&self, config: &mut Config) {
+        let o = &mut config.output;
+        if let Some(dir) = &self.output_dir {
+            o.dir = Some(dir.clone());
+        }
+        if let Some(format) = self.output_format {
+            o.format = format;
+        }
+        if let Some(compression) = self.compression {
+            o.compression = compression;
+        }
+        if let Some(layout) = self.layout {
+            o.layout = layout;
+        }
+    }
+}
+
+#[derive(Args)]
+struct LogArgs {
+    /// Format of log output [default: text]
+    #[clap(long, arg_enum, global = true)]
+    log_format: Option<LogFormat>,
+    /// Also write logs to a rolling file in this directory
+    #[clap(long, global = true)]
+    log_dir: Option<PathBuf>,
+    /// How often to start a new log file [default: daily]
+    #[clap(long, arg_enum, global = true)]
+    log_rotation: Option<LogRotation>,
+}
+
+impl LogArgs {
+    fn apply(&self, config: &mut Config) {
+        if let Some(format) = self.log_format {
+            config.logging.format = format;
+        }
+        if let Some(dir) = &self.log_dir {
+            config.logging.dir = Some(dir.clone());
+        }
+        if let Some(rotation) = self.log_rotation {
+            config.logging.rotation = rotation;
+        }
+    }
+}
+
+#[derive(Args)]
+struct StatusArgs {
+    /// Database URL to read request progress from [default: sqlite://swimrs.db]
+    #[clap(long)]
+    db_url: Option<String>,
+    /// SQLite database URL to count stored times in [default: --db-url if it
+    /// is a SQLite database]
+    #[clap(long)]
+    times_db_url: Option<String>,
+}
+
+#[derive(Args)]
+struct DeadLettersArgs {
+    /// Database URL to read request progress from [default: sqlite://swimrs.db]
+    #[clap(long)]
+    db_url: Option<String>,
+    /// Return dead requests to the queue so the next mirror run retries them
+    #[clap(long)]
+    requeue: bool,
+}
+
+#[derive(Args)]
+struct ReparseArgs {
+    /// SQLite database URL containing the response archive, which the times
+    /// are rebuilt in [default: sqlite://swimrs.db]
+    #[clap(long)]
+    times_db_url: Option<String>,
+    #[clap(flatten)]
+    output: OutputArgs,
+}
+
+#[derive(Args)]
+struct MergeArgs {
+    /// Directory of earlier output to merge, in either layout, and of HY3
+    /// results files
+    input_dir: PathBuf,
+    /// SQLite database URL to write the merged times to, in addition to or
+    /// instead of --output-dir
+    #[clap(long)]
+    times_db_url: Option<String>,
+    #[clap(flatten)]
+    output: OutputArgs,
+}
+
+#[derive(Args)]
+struct QualifyArgs {
+    /// TOML file of the meet's qualifying standards, window and accepted
+    /// course conversions
+    standards: PathBuf,
+    /// Directory of earlier output, and of HY3 results files, to read times
+    /// from
+    #[clap(long)]
+    times_dir: Option<PathBuf>,
+    /// SQLite database URL to read times from
+    #[clap(long)]
+    times_db_url: Option<String>,
+    /// Also list times within this percentage of a standard
+    #[clap(long, default_value = "2")]
+    near_miss: f32,
+}
+
+#[derive(Args)]
+struct PsychArgs {
+    /// TOML file of the meet's events, lanes and seeding
+    meet: PathBuf,
+    /// CSV file of entries, with columns event, name, team, age, seed and
+    /// course
+    #[clap(long)]
+    entries: Option<PathBuf>,
+    /// Directory of earlier output, and of HY3 results files, whose swimmers
+    /// and relays are entered with their best times
+    #[clap(long)]
+    times_dir: Option<PathBuf>,
+    /// SQLite database URL whose swimmers and relays are entered with their
+    /// best times
+    #[clap(long)]
+    times_db_url: Option<String>,
+    /// Earliest date of times read from --times-dir and --times-db-url
+    /// [default: all]
+    #[clap(long)]
+    since: Option<NaiveDate>,
+    /// Format to print the psych sheet in
+    #[clap(long, arg_enum, default_value = "text")]
+    format: SheetFormat,
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    pretty_env_logger::init();
-
     let cli = Cli::parse();
+    let mut config = Config::load(cli.config.as_deref())?;
+    cli.log.apply(&mut config);
+    let _guard = logging::init(&config.logging)?;
+
     match &cli.command {
         Commands::Mirror(args) => {
-            mirror::start_mirror(args.from_date, args.to_date, args.clients, &args.db_url).await?
+            args.plan.apply(&mut config);
+            args.worker.apply(&mut config);
+            let opts = config.worker_options()?;
+            mirror::start_mirror(
+                args.from_date,
+                args.to_date,
+                &config.strategy()?,
+                args.plan.plan_only,
+                opts,
+            )
+            .await?
+        }
+        Commands::Sync(args) => {
+            args.plan.apply(&mut config);
+            args.worker.apply(&mut config);
+            let opts = config.worker_options()?;
+            let sync = SyncOptions {
+                since: args.since,
+                window_days: args.window_days,
+                every: args.every_hours.map(|h| Duration::from_secs(h * 60 * 60)),
+                report: args.report,
+                plan_only: args.plan.plan_only,
+            };
+            sync::start_sync(sync, &config.strategy()?, opts).await?
+        }
+        Commands::Status(args) => {
+            if let Some(db_url) = &args.db_url {
+                config.db_url = db_url.clone();
+            }
+            if let Some(url) = &args.times_db_url {
+                config.times_db_url = Some(url.clone());
+            }
+            status::print_status(&config.db_url, &config.times_db_url()?).await?
+        }
+        Commands::DeadLetters(args) => {
+            let db_url = args.db_url.as_ref().unwrap_or(&config.db_url);
+            mirror::dead_letters(db_url, args.requeue).await?
+        }
+        Commands::Reparse(args) => {
+            let times_db_url = match &args.times_db_url {
+                Some(url) => url.clone(),
+                None => config.times_db_url()?,
+            };
+            args.output.apply(&mut config);
+            mirror::reparse(&times_db_url, config.files()).await?
+        }
+        Commands::Merge(args) => {
+            args.output.apply(&mut config);
+            merge::merge(
+                &args.input_dir,
+                config.files(),
+                args.times_db_url.as_deref(),
+            )
+            .await?
+        }
+        Commands::Qualify(args) => {
+            qualify::qualify(
+                &args.standards,
+                args.times_dir.as_deref(),
+                args.times_db_url.as_deref(),
+                args.near_miss,
+            )
+            .await?
+        }
+        Commands::Psych(args) => {
+            psych::psych(
+                &args.meet,
+                args.entries.as_deref(),
+                args.times_dir.as_deref(),
+                args.times_db_url.as_deref(),
+                args.since,
+                args.format,
+            )
+            .await?
         }
     }
 
@@ -0,0 +1,424 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    hash::Hash,
+    io::{BufRead, BufReader, Read},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+};
+
+use anyhow::{bail, Context, Result};
+use chrono::NaiveDate;
+use flate2::read::MultiGzDecoder;
+use serde::de::DeserializeOwned;
+use swimrs::{
+    common::{hundredths, Gender, TimeType},
+    hy3::Hy3File,
+    usas::toptimes::{TopRelayTime, TopTime, TopTimesRequest},
+};
+use tracing::{debug, info, warn};
+use walkdir::WalkDir;
+
+use crate::{
+    output::{Compression, FileOptions, FileSink, OutputSink, Results, TimesSink},
+    times::SqliteTimesDb,
+};
+
+/// Request ID the merged dataset is written under.
+const MERGED_ID: &str = "merged";
+
+/// A row that can be read back from file output and deduplicated.
+trait Mergeable: DeserializeOwned {
+    /// Identifies the same swim across the result sets of overlapping
+    /// requests.
+    type Key: Eq + Hash;
+
+    fn key(&self) -> Self::Key;
+
+    fn gender_mut(&mut self) -> &mut Gender;
+}
+
+impl Mergeable for TopTime {
+    /// Swimmer, event, date, meet and time. IDs are preferred to names
+    /// where the results have them.
+    type Key = (String, String, NaiveDate, String, u32);
+
+    fn key(&self) -> Self::Key {
+        (
+            self.swimmer_id
+                .map_or_else(|| self.swimmer_name.clone(), |id| id.to_string()),
+            format!(
+                "{} {} {}",
+                self.distance.clone() as u16,
+                self.stroke,
+                self.course
+            ),
+            self.date,
+            self.meet_id
+                .map_or_else(|| self.meet_name.clone(), |id| id.to_string()),
+            hundredths(self.time),
+        )
+    }
+
+    fn gender_mut(&mut self) -> &mut Gender {
+        &mut self.gender
+    }
+}
+
+impl Mergeable for TopRelayTime {
+    /// As for individual times, with the team, relay name and age group
+    /// standing in for the swimmer.
+    type Key = (String, String, Option<NaiveDate>, String, u32);
+
+    fn key(&self) -> Self::Key {
+        (
+            format!(
+                "{}/{}/{}",
+                self.team_name,
+                self.relay_name.as_deref().unwrap_or_default(),
+                self.age_group.as_deref().unwrap_or_default()
+            ),
+            format!(
+                "{} {} {}",
+                self.distance.clone() as u16,
+                self.stroke,
+                self.course
+            ),
+            self.date,
+            self.meet_id
+                .map_or_else(|| self.meet_name.clone(), |id| id.to_string()),
+            hundredths(self.time),
+        )
+    }
+
+    fn gender_mut(&mut self) -> &mut Gender {
+        &mut self.gender
+    }
+}
+
+/// Rows of one kind, deduplicated as they are added. The first row seen for
+/// a swim is kept.
+struct Merged<T: Mergeable> {
+    rows: Vec<T>,
+    index: HashMap<T::Key, usize>,
+    read: u64,
+}
+
+impl<T: Mergeable> Merged<T> {
+    fn new() -> Self {
+        Merged {
+            rows: Vec::new(),
+            index: HashMap::new(),
+            read: 0,
+        }
+    }
+
+    fn add(&mut self, mut row: T) {
+        self.read += 1;
+        match self.index.get(&row.key()) {
+            // A mixed-gender search doesn't know the swimmer's gender, so
+            // take it from a gendered search of the same swim if there is one
+            Some(&i) => {
+                if *self.rows[i].gender_mut() == Gender::Mixed {
+                    *self.rows[i].gender_mut() = row.gender_mut().clone();
+                }
+            }
+            None => {
+                self.index.insert(row.key(), self.rows.len());
+                self.rows.push(row);
+            }
+        }
+    }
+
+    fn read_file(&mut self, path: &Path, format: Format, compression: Compression) -> Result<()> {
+        let reader = decoder(File::open(path)?, compression)?;
+        match format {
+            Format::Csv => {
+                for row in csv::Reader::from_reader(reader).deserialize() {
+                    self.add(row?);
+                }
+            }
+            Format::Jsonl => {
+                for line in BufReader::new(reader).lines() {
+                    let line = line?;
+                    if !line.trim().is_empty() {
+                        self.add(serde_json::from_str(&line)?);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// File formats that can be read back. Parquet output is write-only.
+#[derive(Debug, Clone, Copy)]
+enum Format {
+    Csv,
+    Jsonl,
+}
+
+fn decoder(file: File, compression: Compression) -> Result<Box<dyn Read>> {
+    Ok(match compression {
+        Compression::None => Box::new(file),
+        Compression::Gzip => Box::new(MultiGzDecoder::new(file)),
+        Compression::Zstd => Box::new(zstd::Decoder::new(file)?),
+    })
+}
+
+/// Works out what a file under the output directory holds from its name,
+/// such as `results.csv.gz` in the request layout, or the `relays` directory
+/// it sits under in the Hive layout. Returns `None` for files that aren't
+/// readable output.
+fn classify(path: &Path) -> Option<(TimeType, Format, Compression)> {
+    let name = path.file_name()?.to_str()?;
+    let (name, compression) = match name.rsplit_once('.')? {
+        (rest, "gz") => (rest, Compression::Gzip),
+        (rest, "zst") => (rest, Compression::Zstd),
+        _ => (name, Compression::None),
+    };
+    let (stem, format) = match name.rsplit_once('.')? {
+        (stem, "csv") => (stem, Format::Csv),
+        (stem, "jsonl") => (stem, Format::Jsonl),
+        (_, "parquet") => {
+            warn!("skipping {}: Parquet can't be merged", path.display());
+            return None;
+        }
+        _ => return None,
+    };
+    let time_type = std::iter::once(stem)
+        .chain(path.iter().rev().skip(1).filter_map(|c| c.to_str()))
+        .find_map(|c| match c {
+            "results" => Some(TimeType::Individual),
+            "relays" => Some(TimeType::Relay),
+            _ => None,
+        })?;
+    Some((time_type, format, compression))
+}
+
+/// Reads every CSV and JSON Lines file under `input_dir`, as written by
+/// earlier mirrors in any layout or compression, along with any Hy-Tek HY3
+/// results files, and writes the times as a single dataset with duplicates from
+/// overlapping requests removed. Times are written to files if `files` is set
+/// and to the SQLite times store at `times_db_url` if that is set.
+pub async fn merge(
+    input_dir: &Path,
+    files: Option<FileOptions>,
+    times_db_url: Option<&str>,
+) -> Result<()> {
+    let mut outputs: Vec<Arc<dyn OutputSink>> = Vec::new();
+    if let Some(files) = files {
+        outputs.push(Arc::new(FileSink::new(files)));
+    }
+    if let Some(url) = times_db_url {
+        let times_db = SqliteTimesDb::new(url).await?;
+        times_db.migrate().await?;
+        outputs.push(Arc::new(TimesSink::new(Arc::new(times_db), None)));
+    }
+    if outputs.is_empty() {
+        bail!("nothing to write the merged times to; set an output directory or times database");
+    }
+
+    let dir = input_dir.to_owned();
+    let (times, relays, n_files) = tokio::task::spawn_blocking(move || read_all(&dir)).await??;
+    info!(
+        "read {} times and {} relay times from {} files; {} and {} remain after removing duplicates",
+        times.read,
+        relays.read,
+        n_files,
+        times.rows.len(),
+        relays.rows.len()
+    );
+
+    let dates = times
+        .rows
+        .iter()
+        .map(|t| t.date)
+        .chain(relays.rows.iter().filter_map(|t| t.date));
+    let (from_date, to_date) = match (dates.clone().min(), dates.max()) {
+        (Some(from), Some(to)) => (from, to),
+        _ => bail!("no times found under {}", input_dir.display()),
+    };
+    let req = TopTimesRequest {
+        from_date,
+        to_date,
+        ..TopTimesRequest::default()
+    };
+    let relay_req = TopTimesRequest {
+        time_type: TimeType::Relay,
+        ..req.clone()
+    };
+
+    for (req, results) in [
+        (req, Results::Individual(times.rows)),
+        (relay_req, Results::Relay(relays.rows)),
+    ] {
+        if results.is_empty() {
+            continue;
+        }
+        let results = Arc::new(results);
+        for output in &outputs {
+            output.write(MERGED_ID, &req, results.clone()).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Reads the individual and relay times under `input_dir`, as [`merge`]
+/// does, with duplicates removed.
+pub fn read_times(input_dir: &Path) -> Result<(Vec<TopTime>, Vec<TopRelayTime>)> {
+    let (times, relays, _) = read_all(input_dir)?;
+    Ok((times.rows, relays.rows))
+}
+
+fn read_all(input_dir: &Path) -> Result<(Merged<TopTime>, Merged<TopRelayTime>, u64)> {
+    let mut times = Merged::new();
+    let mut relays = Merged::new();
+    let mut n_files = 0;
+    for entry in WalkDir::new(input_dir).sort_by_file_name() {
+        let entry = entry?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let path: PathBuf = entry.into_path();
+        if path
+            .extension()
+            .is_some_and(|e| e.eq_ignore_ascii_case("hy3"))
+        {
+            read_hy3(&path, &mut times, &mut relays)
+                .with_context(|| format!("error reading {}", path.display()))?;
+            n_files += 1;
+            continue;
+        }
+        let (time_type, format, compression) = match classify(&path) {
+            Some(c) => c,
+            None => {
+                debug!("skipping {}", path.display());
+                continue;
+            }
+        };
+        match time_type {
+            TimeType::Individual => times.read_file(&path, format, compression),
+            TimeType::Relay => relays.read_file(&path, format, compression),
+        }
+        .with_context(|| format!("error reading {}", path.display()))?;
+        n_files += 1;
+    }
+    Ok((times, relays, n_files))
+}
+
+/// Adds the results of a meet from an HY3 file.
+fn read_hy3(
+    path: &Path,
+    times: &mut Merged<TopTime>,
+    relays: &mut Merged<TopRelayTime>,
+) -> Result<()> {
+    // HY3 files are usually ASCII, but older ones may have Latin-1 names
+    let text: String = std::fs::read(path)?.into_iter().map(char::from).collect();
+    let file = Hy3File::from_str(&text)?;
+    for t in file.top_times() {
+        times.add(t);
+    }
+    for t in file.top_relay_times() {
+        relays.add(t);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        output::{Layout, OutputFormat},
+        testing::{fixture, top_time, TempDb, TempDir},
+    };
+
+    fn sink(dir: &TempDir, format: OutputFormat) -> FileOptions {
+        FileOptions {
+            dir: dir.path().to_owned(),
+            format,
+            compression: Compression::None,
+            layout: Layout::Request,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_merge_overlapping_files() -> Result<()> {
+        let input = TempDir::new("merge-overlapping-input");
+        let mixed = |swimmer_id, seconds| TopTime {
+            gender: Gender::Mixed,
+            ..top_time(swimmer_id, seconds)
+        };
+        // A mixed-gender search, read first, and a girls' search that
+        // overlaps it
+        let writes = [
+            (
+                "a-mixed",
+                OutputFormat::Csv,
+                Gender::Mixed,
+                vec![mixed(1, 61.0), mixed(2, 62.0)],
+            ),
+            (
+                "b-female",
+                OutputFormat::Jsonl,
+                Gender::Female,
+                vec![top_time(1, 61.0), top_time(3, 63.0), top_time(3, 63.0)],
+            ),
+        ];
+        for (req_id, format, gender, times) in writes {
+            let req = TopTimesRequest {
+                gender,
+                ..TopTimesRequest::default()
+            };
+            FileSink::new(sink(&input, format))
+                .write(req_id, &req, Arc::new(Results::Individual(times)))
+                .await?;
+        }
+
+        let output = TempDir::new("merge-overlapping-output");
+        merge(input.path(), Some(sink(&output, OutputFormat::Csv)), None).await?;
+        let (mut times, relays) = read_times(output.path())?;
+        times.sort_by_key(|t| t.swimmer_id);
+        let rows: Vec<(Option<usize>, Gender)> = times
+            .iter()
+            .map(|t| (t.swimmer_id, t.gender.clone()))
+            .collect();
+        assert_eq!(
+            rows,
+            [
+                (Some(1), Gender::Female),
+                (Some(2), Gender::Mixed),
+                (Some(3), Gender::Female),
+            ]
+        );
+        assert!(relays.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_merge_hy3_into_sqlite() -> Result<()> {
+        let dir = TempDir::new("merge-hy3");
+        std::fs::copy(fixture("hy3_results.hy3"), dir.path().join("meet.hy3"))?;
+        let db = TempDb::new("merge-hy3");
+        merge(dir.path(), None, Some(&db.url)).await?;
+
+        let (times, relays) = read_times(dir.path())?;
+        assert!(!times.is_empty() && !relays.is_empty());
+        let times_db = SqliteTimesDb::new(&db.url).await?;
+        assert_eq!(times_db.count_times().await?, times.len() as u64);
+        assert_eq!(times_db.count_relay_times().await?, relays.len() as u64);
+
+        // Merging again finds the same swimmers and meets
+        merge(dir.path(), None, Some(&db.url)).await?;
+        assert_eq!(times_db.count_times().await?, times.len() as u64);
+        let (from, to) = (
+            NaiveDate::from_ymd_opt(1, 1, 1).unwrap(),
+            NaiveDate::from_ymd_opt(9999, 12, 31).unwrap(),
+        );
+        let stored = times_db.times_between(from, to).await?;
+        assert_eq!(stored.len(), times.len());
+        assert!(stored.iter().all(|t| t.swimmer_id.is_none()));
+        Ok(())
+    }
+}
@@ -1,202 +1,655 @@
-use std::{path::PathBuf, sync::Arc};
-
-use anyhow::Result;
-use async_channel::{unbounded, Receiver, Sender};
-use chrono::NaiveDate;
-use futures::future::join_all;
-use log::{debug, error, info};
-use metrics::{decrement_gauge, gauge, histogram, increment_gauge};
-use metrics_exporter_prometheus::PrometheusBuilder;
+use std::{net::SocketAddr, sync::Arc};
+
+use anyhow::{anyhow, Error, Result};
+use chrono::{NaiveDate, TimeZone, Utc};
+use futures::{future::join_all, StreamExt};
+use metrics::{counter, decrement_gauge, gauge, histogram, increment_counter, increment_gauge};
+use rand::Rng;
 use reqwest::{ClientBuilder, Proxy};
-use swimrs::{
-    common::Gender,
-    usas::toptimes::{parse_top_times, TopTimesClient, TopTimesRequest},
-};
+use swimrs::usas::toptimes::{TopTimesClient, TopTimesRequest};
 use tokio::{
-    fs, task,
-    time::{sleep, Duration, Instant},
+    task,
+    time::{sleep, timeout_at, Duration, Instant},
+};
+use tracing::{debug, error, info, info_span, warn, Instrument, Span};
+
+use crate::{
+    archive::SqliteArchive,
+    control::{self, Controller, InFlight},
+    db::{self, JobState, RequestStore, RetryPolicy, Timings},
+    exporter,
+    output::{FileOptions, FileSink, OutputSink, Results, TimesSink},
+    plan::{self, PartitionStrategy},
+    status,
+    times::SqliteTimesDb,
 };
 
-use crate::db::SqliteRequestDb;
+/// How long an idle worker waits before polling the queue again.
+const IDLE_POLL: Duration = Duration::from_secs(5);
 
-const USER_AGENT: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/102.0.5005.61/63 Safari/537.36";
+/// Settings for a single HTTP client.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClientOptions {
+    pub proxy: String,
+    pub user_agent: String,
+}
+
+/// Settings shared by every command that runs mirror workers.
+pub struct WorkerOptions {
+    /// One entry per unique HTTP client to send requests with
+    pub clients: Vec<ClientOptions>,
+    /// Database URL holding the request queue
+    pub db_url: String,
+    /// SQLite database URL holding mirrored times and archived responses
+    pub times_db_url: String,
+    pub policy: RetryPolicy,
+    /// Each client waits a random number of seconds in this range between
+    /// the start of one request and the next
+    pub min_delay: u64,
+    pub max_delay: u64,
+    /// If set, result sets are also written to files
+    pub files: Option<FileOptions>,
+    /// Whether to archive raw responses for later reparsing
+    pub archive: bool,
+    /// Address the Prometheus exporter listens on
+    pub metrics_addr: SocketAddr,
+    /// Address the admin API listens on
+    pub admin_addr: SocketAddr,
+    /// Identifies this process's workers in leases on the request queue, which
+    /// may be shared with other processes
+    pub worker_id: String,
+    /// Seconds a lease lasts without a heartbeat before its request is
+    /// returned to the queue
+    pub lease_timeout: u64,
+}
+
+impl WorkerOptions {
+    /// Creates a controller for these workers, with every client active.
+    pub(crate) fn controller(&self) -> Controller {
+        let proxies = self.clients.iter().map(|c| c.proxy.clone()).collect();
+        Controller::new(proxies, self.min_delay, self.max_delay)
+    }
+}
 
 pub async fn start_mirror(
     from_date: NaiveDate,
     to_date: NaiveDate,
-    num_clients: u16,
-    db_url: &str,
+    strategy: &PartitionStrategy,
+    plan_only: bool,
+    opts: WorkerOptions,
 ) -> Result<()> {
-    PrometheusBuilder::new().install()?;
+    let planned = strategy.plan(from_date, to_date);
+    if plan_only {
+        plan::print_plan(&planned, &opts);
+        return Ok(());
+    }
+
+    exporter::install(opts.metrics_addr)?;
+    let controller = Arc::new(opts.controller());
+    let admin = control::serve(opts.admin_addr, controller.clone())?;
 
-    let db = Arc::new(SqliteRequestDb::new(db_url).await?);
-    db.ensure_schema().await?;
+    let db = db::open(&opts.db_url).await?;
+    let sink = Sink::open(&opts.times_db_url, opts.files.clone(), opts.archive, None).await?;
 
-    let (req_tx, req_rx) = unbounded();
+    let reqs: Vec<(String, TopTimesRequest)> = planned.into_iter().map(|r| (r.id(), r)).collect();
+    let queued = db.enqueue(&reqs).await?;
+    info!(
+        "queued {} new requests out of {} planned",
+        queued,
+        reqs.len()
+    );
 
+    let res = run_workers(db, sink, controller, &opts).await;
+    admin.abort();
+    res
+}
+
+/// Spawns one worker per client and waits until the request queue has been
+/// drained.
+pub(crate) async fn run_workers(
+    db: Arc<dyn RequestStore>,
+    sink: Sink,
+    controller: Arc<Controller>,
+    opts: &WorkerOptions,
+) -> Result<()> {
+    let reclaimed = db.reclaim_expired(&opts.policy).await?;
+    if reclaimed > 0 {
+        info!(
+            "returned {} requests with expired leases to the queue",
+            reclaimed
+        );
+    }
+
+    let progress = status::spawn_progress(db.clone());
     let mut handles = Vec::new();
 
-    for i in 0..num_clients {
-        // FIXME: Pass proxy settings as arguments
-        let proxy = Proxy::all(format!("socks5://127.0.0.1:{}", 53000 + i))?;
-        let builder = ClientBuilder::new().proxy(proxy).user_agent(USER_AGENT);
+    for (i, c) in opts.clients.iter().enumerate() {
+        let proxy = Proxy::all(&c.proxy)?;
+        let builder = ClientBuilder::new()
+            .proxy(proxy)
+            .user_agent(c.user_agent.as_str());
         let client = TopTimesClient::new(builder)?;
+        let worker = Worker {
+            client,
+            index: i as u16,
+            proxy: c.proxy.clone(),
+            owner: format!("{}/{}", opts.worker_id, i),
+        };
 
-        let req_tx = req_tx.clone();
-        let req_rx = req_rx.clone();
-        let h = tokio::spawn(process_requests(client, req_tx, req_rx, db.clone()));
+        let h = tokio::spawn(process_requests(
+            worker,
+            db.clone(),
+            sink.clone(),
+            controller.clone(),
+            opts.policy.clone(),
+            opts.lease_timeout,
+        ));
         handles.push(h);
     }
 
-    let producer = tokio::spawn(produce_requests(from_date, to_date, req_tx));
-    handles.push(producer);
-
     join_all(handles).await;
+    drop(progress);
+
+    let dead = db.dead_jobs().await?;
+    if !dead.is_empty() {
+        warn!(
+            "{} requests exceeded the maximum number of attempts",
+            dead.len()
+        );
+    }
 
     Ok(())
 }
 
-// TODO: This is a logical place for a global rate limit! Let 'em drip
-async fn produce_requests(
-    from_date: NaiveDate,
-    to_date: NaiveDate,
-    req_tx: Sender<TopTimesRequest>,
-) -> Result<()> {
-    let age_range = [
-        (Some(0), Some(7)),
-        (Some(8), Some(8)),
-        (Some(9), Some(9)),
-        (Some(10), Some(10)),
-        (Some(11), Some(11)),
-        (Some(12), Some(12)),
-        (Some(13), Some(13)),
-        (Some(14), Some(14)),
-        (Some(15), Some(15)),
-        (Some(16), Some(16)),
-        (Some(17), Some(17)),
-        (Some(18), Some(18)),
-        (Some(19), Some(19)),
-        (Some(20), Some(20)),
-        (Some(21), Some(21)),
-        (Some(22), Some(22)),
-        (Some(23), None),
-    ];
-    let num_days = (to_date - from_date).num_days() as usize + 1;
-
-    for d in from_date.iter_days().take(num_days) {
-        for (start_age, end_age) in age_range {
-            let r_male = TopTimesRequest {
-                gender: Gender::Male,
-                from_date: d,
-                to_date: d,
-                start_age,
-                end_age,
-                ..TopTimesRequest::default()
-            };
-            let mut r_female = r_male.clone();
-            r_female.gender = Gender::Female;
+/// Lists the requests stuck in the dead-letter state, optionally returning
+/// them to the queue.
+pub async fn dead_letters(db_url: &str, requeue: bool) -> Result<()> {
+    let db = db::open(db_url).await?;
 
-            if let Err(e) = req_tx.send(r_male).await {
-                error!("error sending request into queue: {}", e);
+    for job in db.dead_jobs().await? {
+        let updated_at = Utc.timestamp_opt(job.updated_at, 0).unwrap();
+        println!(
+            "{}\t{}\t{}\t{}",
+            job.id,
+            job.attempts,
+            updated_at.to_rfc3339(),
+            job.error.unwrap_or_default()
+        );
+    }
+
+    if requeue {
+        let n = db.requeue_dead().await?;
+        info!("returned {} dead requests to the queue", n);
+    }
+
+    Ok(())
+}
+
+/// Rebuilds the times database, and optionally file output, from archived raw
+/// responses using the current parser. Makes no network requests. Responses
+/// that can't be read back or parsed are logged and counted as failed.
+pub async fn reparse(times_db_url: &str, files: Option<FileOptions>) -> Result<()> {
+    let archive = SqliteArchive::new(times_db_url).await?;
+    archive.migrate().await?;
+    let sink = Sink::open(times_db_url, files, false, None).await?;
+
+    let total = archive.count().await?;
+    info!("reparsing {} archived responses", total);
+
+    let mut responses = archive.iter();
+    let (mut done, mut failed, mut num_times) = (0u64, 0u64, 0u64);
+    while let Some(resp) = responses.next().await {
+        done += 1;
+        let resp = match resp {
+            Ok(resp) => resp,
+            Err(e) => {
+                error!("{:#}", e);
+                failed += 1;
+                continue;
             }
-            if let Err(e) = req_tx.send(r_female).await {
-                error!("error sending request into queue: {}", e);
+        };
+        debug!(
+            "reparsing {} fetched at {}",
+            resp.id,
+            Utc.timestamp_opt(resp.fetched_at, 0).unwrap().to_rfc3339()
+        );
+        let req = resp.request.clone();
+        let html = resp.html;
+        let span = info_span!("reparse", id = %resp.id);
+        match task::spawn_blocking(move || span.in_scope(|| Results::parse(&req, html))).await? {
+            Ok(results) => num_times += sink.write(&resp.id, &resp.request, results).await? as u64,
+            Err(e) => {
+                error!("error reparsing {}: {}", resp.id, e);
+                failed += 1;
             }
         }
+
+        if done % 1000 == 0 {
+            info!("reparsed {}/{} responses", done, total);
+        }
     }
 
+    info!(
+        "reparsed {} responses into {} times; {} failed",
+        done, num_times, failed
+    );
     Ok(())
 }
 
-async fn process_requests(
+/// An HTTP client along with its index and the proxy it sends requests
+/// through, which its metrics are labeled with.
+struct Worker {
     client: TopTimesClient,
-    req_tx: Sender<TopTimesRequest>,
-    req_rx: Receiver<TopTimesRequest>,
-    db: Arc<SqliteRequestDb>,
+    index: u16,
+    proxy: String,
+    /// Owner of the leases this worker takes, unique across processes
+    owner: String,
+}
+
+impl Worker {
+    fn labels(&self) -> [(&'static str, String); 2] {
+        [
+            ("client", self.index.to_string()),
+            ("proxy", self.proxy.clone()),
+        ]
+    }
+}
+
+async fn process_requests(
+    worker: Worker,
+    db: Arc<dyn RequestStore>,
+    sink: Sink,
+    controller: Arc<Controller>,
+    policy: RetryPolicy,
+    lease_timeout: u64,
 ) -> Result<()> {
-    client.populate_cookies().await?;
-    info!("populated cookies for client: {:?}", client);
+    let ttl = chrono::Duration::seconds(lease_timeout as i64);
+    worker.client.populate_cookies().await?;
+    info!("populated cookies for client: {:?}", worker.client);
     increment_gauge!("swimrs_mirror_ready_clients", 1.0);
 
     loop {
-        gauge!("swimrs_mirror_request_queue_depth", req_tx.len() as f64);
+        if let Ok(n) = db.count_eligible().await {
+            gauge!("swimrs_mirror_request_queue_depth", n as f64);
+        }
         let start = Instant::now();
 
-        let req = match req_rx.recv().await {
-            Ok(x) => x,
+        if controller.is_held(worker.index, &worker.proxy) {
+            // Held workers still stop once every request is done
+            if let Ok(0) = db.count_remaining().await {
+                break;
+            }
+            sleep(IDLE_POLL).await;
+            continue;
+        }
+
+        let leased_at = Instant::now();
+        let job = match db.lease(&worker.owner, ttl).await {
+            Ok(Some(x)) => x,
+            Ok(None) => match db.count_remaining().await {
+                Ok(0) => break,
+                Ok(_) => {
+                    // Remaining jobs are either in flight elsewhere or waiting
+                    // out their retry delay. Those held by workers that have
+                    // since died are put back once their leases expire
+                    match db.reclaim_expired(&policy).await {
+                        Ok(0) => {}
+                        Ok(n) => info!("returned {} requests with expired leases to the queue", n),
+                        Err(e) => error!("error reclaiming expired leases: {}", e),
+                    }
+                    sleep(IDLE_POLL).await;
+                    continue;
+                }
+                Err(e) => {
+                    error!("error checking request queue: {}", e);
+                    sleep(IDLE_POLL).await;
+                    continue;
+                }
+            },
             Err(e) => {
-                error!("error receiving from request queue: {}", e);
+                error!("error leasing from request queue: {}", e);
+                sleep(IDLE_POLL).await;
                 continue;
             }
         };
-        let req_id = &req.to_string().to_lowercase();
+        let req_id = &job.id;
 
-        // FIXME
-        if db.check_request_success(req_id).await.unwrap() {
-            debug!("already made request: {}", req_id);
-            continue;
-        }
+        // Every event while processing the request, including those from the
+        // library, carries the request and the client handling it
+        let span = info_span!(
+            "request",
+            id = %req_id,
+            client = worker.index,
+            proxy = %worker.proxy,
+            attempt = job.attempts
+        );
+        let outcome = async {
+            debug!("making request (attempt {}): {}", job.attempts, job.request);
+            let mut timings = Timings::default();
+            let attempt_start = Instant::now();
+            controller.start(InFlight {
+                id: req_id.clone(),
+                client: worker.index,
+                proxy: worker.proxy.clone(),
+                attempt: job.attempts,
+                started_at: Utc::now(),
+            });
+            increment_gauge!("swimrs_mirror_request_active_count", 1.0);
+            // Losing the lease cancels the fetch and parse, and their results
+            // are dropped unrecorded
+            let res = tokio::select! {
+                res = process_request(&worker, req_id, job.request, &sink, &mut timings) => {
+                    Some(res)
+                }
+                e = keep_lease(&*db, req_id, &worker.owner, lease_timeout, leased_at) => {
+                    warn!("abandoning request {}: {}", req_id, e);
+                    None
+                }
+            };
+            decrement_gauge!("swimrs_mirror_request_active_count", 1.0);
+            controller.finish(req_id);
+            timings.total = attempt_start.elapsed().as_secs_f64();
+            histogram!(
+                "swimrs_mirror_request_duration_seconds",
+                timings.total,
+                &worker.labels()
+            );
 
-        debug!("making request: {}", req);
-        let req2 = req.clone();
-        match process_request(&client, req).await {
-            Ok(l) => {
-                debug!("found times for {}: {}", req_id, l);
-                db.upsert_request_success(req_id, l, 0f64).await.unwrap(); // FIXME
-            }
-            Err(e) => {
-                error!("error processing request {}: {}", req_id, e);
-                db.upsert_request_error(req_id, &e.to_string(), 0f64)
-                    .await
-                    .unwrap(); // FIXME
-                if let Err(e) = req_tx.send(req2).await {
-                    error!("error sending request back into queue, DROPPING: {}", e);
-                    continue;
+            match res? {
+                Ok(l) => {
+                    debug!("found times for {}: {}", req_id, l);
+                    match db
+                        .upsert_request_success(req_id, &worker.owner, l, &timings)
+                        .await
+                    {
+                        Ok(true) => Some(JobState::Success),
+                        Ok(false) => {
+                            warn!("lease on {} expired before its result was recorded", req_id);
+                            None
+                        }
+                        Err(e) => {
+                            // The request stays leased until its lease expires
+                            // and it is retried, so it didn't succeed yet
+                            error!("error recording success for {}: {}", req_id, e);
+                            let mut labels = worker.labels().to_vec();
+                            labels.push(("class", error_class(&e, &timings).to_owned()));
+                            increment_counter!("swimrs_mirror_request_errors_total", &labels);
+                            Some(JobState::Failed)
+                        }
+                    }
+                }
+                Err(e) => {
+                    error!("error processing request {}: {}", req_id, e);
+                    let mut labels = worker.labels().to_vec();
+                    labels.push(("class", error_class(&e, &timings).to_owned()));
+                    increment_counter!("swimrs_mirror_request_errors_total", &labels);
+                    match db
+                        .upsert_request_error(
+                            req_id,
+                            &worker.owner,
+                            &e.to_string(),
+                            &timings,
+                            &policy,
+                        )
+                        .await
+                    {
+                        Ok(Some(JobState::Dead)) => {
+                            warn!("request {} is dead after {} attempts", req_id, job.attempts);
+                            Some(JobState::Dead)
+                        }
+                        Ok(Some(state)) => Some(state),
+                        Ok(None) => {
+                            warn!(
+                                "lease on {} expired before its failure was recorded",
+                                req_id
+                            );
+                            None
+                        }
+                        Err(e) => {
+                            error!("error recording failure for {}: {}", req_id, e);
+                            Some(JobState::Failed)
+                        }
+                    }
                 }
             }
         }
+        .instrument(span)
+        .await;
+        let mut labels = worker.labels().to_vec();
+        // Requests whose lease was lost are reported as such, since the
+        // worker that reclaimed them records their real outcome
+        let outcome = outcome.map_or("lost", |s| s.as_str());
+        labels.push(("outcome", outcome.to_owned()));
+        increment_counter!("swimrs_mirror_requests_total", &labels);
 
         let end = Instant::now();
         let delta = end.duration_since(start).as_secs();
-        let delay = (rand::random::<f32>() * 5.0 + 5.0) as u64;
+        let (min_delay, max_delay) = controller.delay();
+        let delay = rand::thread_rng().gen_range(min_delay..=max_delay);
         if delta < delay {
             debug!("waiting for {} seconds", delay - delta);
             sleep(Duration::from_secs(delay - delta)).await;
         }
     }
+
+    decrement_gauge!("swimrs_mirror_ready_clients", 1.0);
+    info!(
+        "request queue drained, stopping client: {:?}",
+        worker.client
+    );
+    Ok(())
+}
+
+/// Renews the lease on a request every third of `lease_timeout` seconds for
+/// as long as it is being processed. Resolves only once the lease has been
+/// lost, returning the error to abandon the request with, so that it never
+/// runs alongside the worker that reclaimed it. A lease that can't be renewed
+/// counts as lost once `lease_timeout` seconds have passed since it was last
+/// known to be held, from `leased_at` onwards, since another worker may have
+/// reclaimed it by then.
+async fn keep_lease(
+    db: &dyn RequestStore,
+    req_id: &str,
+    owner: &str,
+    lease_timeout: u64,
+    leased_at: Instant,
+) -> Error {
+    let ttl = chrono::Duration::seconds(lease_timeout as i64);
+    let timeout = Duration::from_secs(lease_timeout);
+    let mut renewed = leased_at;
+    loop {
+        sleep(timeout / 3).await;
+        let attempted = Instant::now();
+        match timeout_at(renewed + timeout, db.heartbeat(req_id, owner, ttl)).await {
+            Ok(Ok(true)) => {
+                renewed = attempted;
+                debug!("renewed lease on {}", req_id);
+            }
+            Ok(Ok(false)) => return anyhow!("lease on {} expired before it finished", req_id),
+            Ok(Err(e)) => warn!("error renewing lease on {}: {}", req_id, e),
+            Err(_) => warn!("timed out renewing lease on {}", req_id),
+        }
+        if renewed.elapsed() >= timeout {
+            return anyhow!(
+                "lease on {} not renewed for {} seconds",
+                req_id,
+                lease_timeout
+            );
+        }
+    }
 }
 
-async fn process_request(client: &TopTimesClient, req: TopTimesRequest) -> Result<u32> {
+/// Destinations that fetched and parsed results are written to.
+#[derive(Clone)]
+pub(crate) struct Sink {
+    outputs: Vec<Arc<dyn OutputSink>>,
+    /// If set, raw responses are archived before parsing
+    archive: Option<Arc<SqliteArchive>>,
+}
+
+impl Sink {
+    /// Opens the times store, which every result set is written to, along
+    /// with file output and the response archive if enabled. See
+    /// [`TimesSink`] for how `sync_id` is used.
+    pub(crate) async fn open(
+        times_db_url: &str,
+        files: Option<FileOptions>,
+        archive: bool,
+        sync_id: Option<i64>,
+    ) -> Result<Self> {
+        let times_db = SqliteTimesDb::new(times_db_url).await?;
+        times_db.migrate().await?;
+        let mut outputs: Vec<Arc<dyn OutputSink>> =
+            vec![Arc::new(TimesSink::new(Arc::new(times_db), sync_id))];
+        if let Some(files) = files {
+            outputs.push(Arc::new(FileSink::new(files)));
+        }
+        let archive = match archive {
+            true => {
+                let archive = SqliteArchive::new(times_db_url).await?;
+                archive.migrate().await?;
+                Some(Arc::new(archive))
+            }
+            false => None,
+        };
+        Ok(Sink { outputs, archive })
+    }
+
+    /// Writes a parsed result set to every output. Returns the number of
+    /// times written.
+    async fn write(&self, req_id: &str, req: &TopTimesRequest, results: Results) -> Result<u32> {
+        let l = results.len() as u32;
+        let results = Arc::new(results);
+        for output in &self.outputs {
+            output.write(req_id, req, results.clone()).await?;
+        }
+        Ok(l)
+    }
+}
+
+/// Fetches, parses and writes the results of a single request, recording the
+/// time spent on each stage in `timings`.
+async fn process_request(
+    worker: &Worker,
+    req_id: &str,
+    req: TopTimesRequest,
+    sink: &Sink,
+    timings: &mut Timings,
+) -> Result<u32> {
+    let labels = worker.labels();
     let req2 = req.clone();
-    let html = client.fetch_html(req).await?;
 
-    let gender = req2.gender.clone();
-    increment_gauge!("swimrs_mirror_request_active_count", 1.0);
     let start = Instant::now();
-    let times = task::spawn_blocking(move || parse_top_times(html, gender)).await??;
-    let end = Instant::now();
-    decrement_gauge!("swimrs_mirror_request_active_count", 1.0);
-    let req_duration = end.duration_since(start).as_secs_f64();
-    histogram!("swimrs_mirror_request_duration", req_duration);
+    let resp = worker.client.send(req).await?;
+    let mut status_labels = labels.to_vec();
+    status_labels.push(("status", resp.status().as_u16().to_string()));
+    increment_counter!("swimrs_mirror_http_responses_total", &status_labels);
+    let html = resp.error_for_status()?.text().await?;
+    let fetch_duration = start.elapsed().as_secs_f64();
+    timings.fetch = Some(fetch_duration);
+    histogram!(
+        "swimrs_mirror_fetch_duration_seconds",
+        fetch_duration,
+        &labels
+    );
+    counter!(
+        "swimrs_mirror_downloaded_bytes_total",
+        html.len() as u64,
+        &labels
+    );
 
-    debug!("{}: found {} times", req2, times.len());
-    if times.is_empty() {
-        return Ok(0);
+    if let Some(archive) = &sink.archive {
+        archive.put(req_id, &html).await?;
     }
-    let l = times.len() as u32;
 
-    let mut path = PathBuf::new();
-    path.push("results");
-    path.push(req2.to_string().to_lowercase());
-    fs::create_dir_all(&path).await?;
-    path.push("results.csv");
-    let mut writer = csv::Writer::from_path(&path)?;
+    let req3 = req2.clone();
+    let span = Span::current();
+    let start = Instant::now();
+    let results =
+        task::spawn_blocking(move || span.in_scope(|| Results::parse(&req3, html))).await??;
+    let parse_duration = start.elapsed().as_secs_f64();
+    timings.parse = Some(parse_duration);
+    let time_type = req2.time_type.to_string().to_lowercase();
+    histogram!(
+        "swimrs_mirror_parse_duration_seconds",
+        parse_duration,
+        "time_type" => time_type.clone()
+    );
+    counter!(
+        "swimrs_mirror_rows_parsed_total",
+        results.len() as u64,
+        "time_type" => time_type
+    );
+
+    debug!("{}: found {} times", req2, results.len());
+    sink.write(req_id, &req2, results).await
+}
 
-    // TODO: Consider moving this into a blocking thread pool
-    for t in times {
-        writer.serialize(t)?;
+/// Buckets an error into a coarse class for metrics, using the stages that
+/// completed before it occurred to tell parse failures apart.
+fn error_class(e: &anyhow::Error, timings: &Timings) -> &'static str {
+    if let Some(e) = e.downcast_ref::<reqwest::Error>() {
+        return match e {
+            e if e.is_timeout() => "timeout",
+            e if e.is_connect() => "connect",
+            e if e.is_status() => "http_status",
+            e if e.is_body() || e.is_decode() => "body",
+            _ => "http",
+        };
+    }
+    if e.downcast_ref::<sqlx::Error>().is_some() {
+        return "database";
     }
-    writer.flush()?;
+    if e.downcast_ref::<std::io::Error>().is_some() || e.downcast_ref::<csv::Error>().is_some() {
+        return "io";
+    }
+    match timings {
+        Timings {
+            fetch: Some(_),
+            parse: None,
+            ..
+        } => "parse",
+        _ => "other",
+    }
+}
 
-    Ok(l)
+#[cfg(test)]
+mod tests {
+    use sqlx::{query, SqlitePool};
+    use swimrs::common::Gender;
+
+    use super::*;
+    use crate::testing::{fixture, TempDb};
+
+    #[tokio::test]
+    async fn test_reparse() -> Result<()> {
+        let tmp = TempDb::new("reparse");
+        let archive = SqliteArchive::new(&tmp.url).await?;
+        archive.migrate().await?;
+        let req = TopTimesRequest {
+            gender: Gender::Male,
+            ..TopTimesRequest::default()
+        };
+        let html = std::fs::read_to_string(fixture("top_times_small.html"))?;
+        archive.put(&req.id(), &html).await?;
+        // Neither of these can be reparsed, but they don't stop the others
+        archive.put("unknown id", &html).await?;
+        let pool = SqlitePool::connect(&tmp.url).await?;
+        let corrupt = TopTimesRequest {
+            gender: Gender::Female,
+            ..req.clone()
+        };
+        query("INSERT INTO responses (id, fetched_at, html) VALUES (?, 0, ?)")
+            .bind(corrupt.id())
+            .bind(b"not zstd".to_vec())
+            .execute(&pool)
+            .await?;
+
+        reparse(&tmp.url, None).await?;
+        let times_db = SqliteTimesDb::new(&tmp.url).await?;
+        let expected = Results::parse(&req, html)?.len();
+        assert!(expected > 0);
+        assert_eq!(times_db.count_times().await?, expected as u64);
+        Ok(())
+    }
 }
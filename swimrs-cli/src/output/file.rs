@@ -0,0 +1,406 @@
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{Datelike, NaiveDate};
+use flate2::write::GzEncoder;
+use serde::Serialize;
+use swimrs::{
+    common::Gender,
+    usas::toptimes::{TopRelayTime, TopTime, TopTimesRequest},
+};
+use tokio::task;
+use walkdir::WalkDir;
+
+use super::{
+    parquet::{self, ParquetRows},
+    serde_name, Compression, FileOptions, Layout, OutputFormat, OutputSink, Results,
+};
+
+/// zstd level for compressed CSV and JSON Lines output.
+const ZSTD_LEVEL: i32 = 9;
+
+/// A row of output, with the columns it is partitioned by.
+pub(super) trait Row: Serialize + ParquetRows {
+    fn date(&self) -> Option<NaiveDate>;
+    fn gender(&self) -> &Gender;
+}
+
+impl Row for TopTime {
+    fn date(&self) -> Option<NaiveDate> {
+        Some(self.date)
+    }
+
+    fn gender(&self) -> &Gender {
+        &self.gender
+    }
+}
+
+impl Row for TopRelayTime {
+    fn date(&self) -> Option<NaiveDate> {
+        self.date
+    }
+
+    fn gender(&self) -> &Gender {
+        &self.gender
+    }
+}
+
+/// Writes result sets to files under a directory, one file per request, or
+/// per request and partition for the Hive layout. Each file is replaced
+/// whole, and files a request wrote before that its new result set doesn't
+/// fill are removed, so re-fetching a request never duplicates rows or
+/// leaves stale ones behind.
+#[derive(Clone)]
+pub struct FileSink {
+    opts: FileOptions,
+}
+
+impl FileSink {
+    pub fn new(opts: FileOptions) -> Self {
+        FileSink { opts }
+    }
+
+    fn extension(&self) -> &'static str {
+        match (self.opts.format, self.opts.compression) {
+            (OutputFormat::Parquet, _) => "parquet",
+            (OutputFormat::Csv, Compression::None) => "csv",
+            (OutputFormat::Csv, Compression::Gzip) => "csv.gz",
+            (OutputFormat::Csv, Compression::Zstd) => "csv.zst",
+            (OutputFormat::Jsonl, Compression::None) => "jsonl",
+            (OutputFormat::Jsonl, Compression::Gzip) => "jsonl.gz",
+            (OutputFormat::Jsonl, Compression::Zstd) => "jsonl.zst",
+        }
+    }
+
+    /// Groups the rows of a result set by the file they belong in. `table`
+    /// names the dataset: `results` for individual times or `relays`.
+    fn partition<'a, T: Row>(
+        &self,
+        req_id: &str,
+        req: &TopTimesRequest,
+        table: &str,
+        rows: &'a [T],
+    ) -> BTreeMap<PathBuf, Vec<&'a T>> {
+        let ext = self.extension();
+        let mut files: BTreeMap<PathBuf, Vec<&T>> = BTreeMap::new();
+        for row in rows {
+            let path = match self.opts.layout {
+                Layout::Request => self
+                    .opts
+                    .dir
+                    .join(req_id)
+                    .join(format!("{}.{}", table, ext)),
+                Layout::Hive => {
+                    // Relays without a date are filed under the start of the
+                    // requested range
+                    let date = row.date().unwrap_or(req.from_date);
+                    self.opts
+                        .dir
+                        .join(table)
+                        .join(format!("year={}", date.year()))
+                        .join(format!("month={:02}", date.month()))
+                        .join(format!(
+                            "gender={}",
+                            serde_name(row.gender()).to_lowercase()
+                        ))
+                        .join(format!("{}.{}", req_id, ext))
+                }
+            };
+            files.entry(path).or_default().push(row);
+        }
+        files
+    }
+
+    /// Every file written for the request `req_id` in `table`, in any
+    /// format, by earlier result sets.
+    fn existing_files(&self, req_id: &str, table: &str) -> Result<Vec<PathBuf>> {
+        let (dir, stem) = match self.opts.layout {
+            Layout::Request => (self.opts.dir.join(req_id), table),
+            Layout::Hive => (self.opts.dir.join(table), req_id),
+        };
+        if !dir.exists() {
+            return Ok(Vec::new());
+        }
+        let mut files = Vec::new();
+        for entry in WalkDir::new(dir) {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy();
+            if entry.file_type().is_file() && name.split_once('.').map(|(s, _)| s) == Some(stem) {
+                files.push(entry.into_path());
+            }
+        }
+        Ok(files)
+    }
+
+    fn write_rows<T: Row>(
+        &self,
+        req_id: &str,
+        req: &TopTimesRequest,
+        table: &str,
+        rows: &[T],
+    ) -> Result<()> {
+        let existing = self.existing_files(req_id, table)?;
+        let files = self.partition(req_id, req, table, rows);
+        for (path, rows) in &files {
+            self.write_file(path, rows)?;
+        }
+        // Removed only once the new files are in place, so readers never see
+        // the request without any results
+        for path in existing.iter().filter(|p| !files.contains_key(*p)) {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+
+    fn write_file<T: Row>(&self, path: &Path, rows: &[&T]) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // Written beside the destination and renamed into place, so readers
+        // never see a partially written file
+        let tmp = PathBuf::from(format!("{}.tmp", path.display()));
+        let file = File::create(&tmp)?;
+
+        match self.opts.format {
+            OutputFormat::Parquet => parquet::write(file, rows, self.opts.compression)?,
+            OutputFormat::Csv => {
+                let mut writer =
+                    csv::Writer::from_writer(Encoder::new(file, self.opts.compression)?);
+                for r in rows {
+                    writer.serialize(r)?;
+                }
+                writer
+                    .into_inner()
+                    .map_err(|e| io::Error::new(e.error().kind(), e.error().to_string()))?
+                    .finish()?;
+            }
+            OutputFormat::Jsonl => {
+                let mut writer = Encoder::new(file, self.opts.compression)?;
+                for r in rows {
+                    serde_json::to_writer(&mut writer, r)?;
+                    writer.write_all(b"\n")?;
+                }
+                writer.finish()?;
+            }
+        }
+
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl OutputSink for FileSink {
+    async fn write(
+        &self,
+        req_id: &str,
+        req: &TopTimesRequest,
+        results: Arc<Results>,
+    ) -> Result<()> {
+        let sink = self.clone();
+        let (req_id, req) = (req_id.to_owned(), req.clone());
+        task::spawn_blocking(move || match &*results {
+            Results::Individual(times) => sink.write_rows(&req_id, &req, "results", times),
+            Results::Relay(times) => sink.write_rows(&req_id, &req, "relays", times),
+        })
+        .await?
+    }
+}
+
+/// A buffered file writer, optionally compressed.
+enum Encoder {
+    Plain(BufWriter<File>),
+    Gzip(GzEncoder<BufWriter<File>>),
+    Zstd(zstd::Encoder<'static, BufWriter<File>>),
+}
+
+impl Encoder {
+    fn new(file: File, compression: Compression) -> Result<Self> {
+        let w = BufWriter::new(file);
+        Ok(match compression {
+            Compression::None => Encoder::Plain(w),
+            Compression::Gzip => Encoder::Gzip(GzEncoder::new(w, flate2::Compression::default())),
+            Compression::Zstd => Encoder::Zstd(zstd::Encoder::new(w, ZSTD_LEVEL)?),
+        })
+    }
+
+    /// Writes any trailing compressed data and flushes the file.
+    fn finish(self) -> io::Result<()> {
+        let mut w = match self {
+            Encoder::Plain(w) => w,
+            Encoder::Gzip(e) => e.finish()?,
+            Encoder::Zstd(e) => e.finish()?,
+        };
+        w.flush()
+    }
+}
+
+impl Write for Encoder {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Encoder::Plain(w) => w.write(buf),
+            Encoder::Gzip(e) => e.write(buf),
+            Encoder::Zstd(e) => e.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Encoder::Plain(w) => w.flush(),
+            Encoder::Gzip(e) => e.flush(),
+            Encoder::Zstd(e) => e.flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+
+    use ::parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    use super::*;
+    use crate::{
+        merge::read_times,
+        testing::{top_time, TempDir},
+    };
+
+    fn date(month: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2022, month, 1).unwrap()
+    }
+
+    fn time(swimmer_id: usize, month: u32) -> TopTime {
+        TopTime {
+            date: date(month),
+            meet_id: Some(month as usize),
+            ..top_time(swimmer_id, 60.0 + swimmer_id as f32)
+        }
+    }
+
+    fn request() -> TopTimesRequest {
+        TopTimesRequest {
+            gender: Gender::Female,
+            from_date: date(1),
+            to_date: date(12),
+            ..TopTimesRequest::default()
+        }
+    }
+
+    fn sink(
+        dir: &TempDir,
+        format: OutputFormat,
+        compression: Compression,
+        layout: Layout,
+    ) -> FileSink {
+        FileSink::new(FileOptions {
+            dir: dir.path().to_owned(),
+            format,
+            compression,
+            layout,
+        })
+    }
+
+    async fn write(sink: &FileSink, req_id: &str, times: Vec<TopTime>) -> Result<()> {
+        sink.write(req_id, &request(), Arc::new(Results::Individual(times)))
+            .await
+    }
+
+    fn files(dir: &TempDir) -> Vec<PathBuf> {
+        let mut files: Vec<PathBuf> = WalkDir::new(dir.path())
+            .into_iter()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().is_file())
+            .map(|e| e.path().strip_prefix(dir.path()).unwrap().to_owned())
+            .collect();
+        files.sort();
+        files
+    }
+
+    #[tokio::test]
+    async fn test_round_trip() -> Result<()> {
+        let times = vec![time(1, 3), time(2, 3), time(3, 4)];
+        for format in [OutputFormat::Csv, OutputFormat::Jsonl] {
+            for compression in [Compression::None, Compression::Gzip, Compression::Zstd] {
+                for layout in [Layout::Request, Layout::Hive] {
+                    let dir = TempDir::new(&format!(
+                        "round-trip-{:?}-{:?}-{:?}",
+                        format, compression, layout
+                    ));
+                    let sink = sink(&dir, format, compression, layout);
+                    write(&sink, "req", times.clone()).await?;
+                    let (mut read, relays) = read_times(dir.path())?;
+                    read.sort_by_key(|t| t.swimmer_id);
+                    assert_eq!(read, times, "{:?} {:?} {:?}", format, compression, layout);
+                    assert!(relays.is_empty());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_round_trip_parquet() -> Result<()> {
+        let dir = TempDir::new("round-trip-parquet");
+        let sink = sink(
+            &dir,
+            OutputFormat::Parquet,
+            Compression::Zstd,
+            Layout::Request,
+        );
+        write(&sink, "req", vec![time(1, 3), time(2, 3), time(3, 4)]).await?;
+
+        let file = File::open(dir.path().join("req/results.parquet"))?;
+        let reader = ParquetRecordBatchReaderBuilder::try_new(file)?.build()?;
+        let batches = reader.collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(batches.iter().map(|b| b.num_rows()).sum::<usize>(), 3);
+        assert_eq!(*batches[0].schema(), TopTime::schema());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_replace_files() -> Result<()> {
+        let dir = TempDir::new("replace-files");
+        let sink = sink(&dir, OutputFormat::Csv, Compression::None, Layout::Hive);
+        write(&sink, "other", vec![time(9, 3)]).await?;
+        write(&sink, "req", vec![time(1, 3), time(2, 4)]).await?;
+        assert_eq!(
+            files(&dir),
+            [
+                "results/year=2022/month=03/gender=female/other.csv",
+                "results/year=2022/month=03/gender=female/req.csv",
+                "results/year=2022/month=04/gender=female/req.csv",
+            ]
+            .map(PathBuf::from)
+        );
+
+        // The April time is gone, and so is its partition file
+        write(&sink, "req", vec![time(1, 3)]).await?;
+        assert_eq!(
+            files(&dir),
+            [
+                "results/year=2022/month=03/gender=female/other.csv",
+                "results/year=2022/month=03/gender=female/req.csv",
+            ]
+            .map(PathBuf::from)
+        );
+
+        // An empty result set leaves none of the request's files behind
+        write(&sink, "req", Vec::new()).await?;
+        assert_eq!(
+            files(&dir),
+            [PathBuf::from(
+                "results/year=2022/month=03/gender=female/other.csv"
+            )]
+        );
+        let (read, _) = read_times(dir.path())?;
+        assert_eq!(read, vec![time(9, 3)]);
+        Ok(())
+    }
+}
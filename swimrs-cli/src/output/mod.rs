@@ -0,0 +1,108 @@
+mod file;
+mod parquet;
+mod sqlite;
+
+use std::{path::PathBuf, sync::Arc};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use clap::ArgEnum;
+use serde::{Deserialize, Serialize};
+use swimrs::{
+    common::TimeType,
+    usas::toptimes::{
+        parse_top_relay_times, parse_top_times, TopRelayTime, TopTime, TopTimesRequest,
+    },
+};
+
+pub use self::{file::FileSink, sqlite::TimesSink};
+
+/// Parsed result set of either kind of request.
+pub enum Results {
+    Individual(Vec<TopTime>),
+    Relay(Vec<TopRelayTime>),
+}
+
+impl Results {
+    /// Parses a raw response with the parser matching the request's time
+    /// type. This is CPU-bound and should run on the blocking thread pool.
+    pub fn parse(req: &TopTimesRequest, html: String) -> Result<Self> {
+        let gender = req.gender.clone();
+        Ok(match req.time_type {
+            TimeType::Individual => Results::Individual(parse_top_times(html, gender)?),
+            TimeType::Relay => Results::Relay(parse_top_relay_times(html, gender)?),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            Results::Individual(times) => times.len(),
+            Results::Relay(times) => times.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// A destination for parsed result sets.
+#[async_trait]
+pub trait OutputSink: Send + Sync {
+    /// Writes the result set of the request `req_id`. Called for every result
+    /// set, including empty ones, and again whenever a request is re-fetched,
+    /// so writes must replace whatever the request produced before.
+    async fn write(&self, req_id: &str, req: &TopTimesRequest, results: Arc<Results>)
+        -> Result<()>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ArgEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    Csv,
+    /// One JSON object per line
+    Jsonl,
+    /// Apache Parquet, with typed columns
+    Parquet,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ArgEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+/// How output files are arranged under the output directory.
+#[derive(Debug, Clone, Copy, PartialEq, ArgEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Layout {
+    /// `<dir>/<request id>/results.csv`, or `relays.csv` for relay requests
+    Request,
+    /// `<dir>/results/year=<y>/month=<m>/gender=<g>/<request id>.csv`, with
+    /// relays under `<dir>/relays`, partitioned by the date and gender of
+    /// each row. Loads directly as a Hive-partitioned dataset in DuckDB,
+    /// pandas or Spark
+    Hive,
+}
+
+/// Where and how result sets are written to files.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileOptions {
+    pub dir: PathBuf,
+    pub format: OutputFormat,
+    /// For Parquet, the compression used within the file
+    pub compression: Compression,
+    pub layout: Layout,
+}
+
+/// Serde name of a unit enum variant, as written to CSV and JSON, so that
+/// every output format spells values the same way.
+fn serde_name<T: Serialize>(value: &T) -> String {
+    match serde_json::to_value(value) {
+        Ok(serde_json::Value::String(s)) => s,
+        Ok(v) => v.to_string(),
+        Err(_) => String::new(),
+    }
+}
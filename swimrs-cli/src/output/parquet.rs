@@ -0,0 +1,212 @@
+use std::{fs::File, sync::Arc};
+
+use anyhow::Result;
+use arrow_array::{
+    ArrayRef, BooleanArray, Date32Array, Float32Array, RecordBatch, StringArray, UInt16Array,
+    UInt64Array, UInt8Array,
+};
+use arrow_schema::{DataType, Field, Schema};
+use chrono::NaiveDate;
+use parquet::{
+    arrow::ArrowWriter,
+    basic::{Compression as Codec, GzipLevel, ZstdLevel},
+    file::properties::WriterProperties,
+};
+use swimrs::usas::toptimes::{TopRelayTime, TopTime};
+
+use super::{serde_name, Compression};
+
+/// A row type with a typed Parquet schema. Enums are stored as the strings
+/// they serialize to, as in CSV, and dates as dates.
+pub(super) trait ParquetRows: Sized {
+    fn schema() -> Schema;
+
+    /// One array per field of [`ParquetRows::schema`], in the same order.
+    fn columns(rows: &[&Self]) -> Vec<ArrayRef>;
+}
+
+/// Writes `rows` to `file` as a single row group.
+pub(super) fn write<T: ParquetRows>(
+    file: File,
+    rows: &[&T],
+    compression: Compression,
+) -> Result<()> {
+    let schema = Arc::new(T::schema());
+    let batch = RecordBatch::try_new(schema.clone(), T::columns(rows))?;
+    let codec = match compression {
+        Compression::None => Codec::UNCOMPRESSED,
+        Compression::Gzip => Codec::GZIP(GzipLevel::default()),
+        Compression::Zstd => Codec::ZSTD(ZstdLevel::default()),
+    };
+    let props = WriterProperties::builder().set_compression(codec).build();
+    let mut writer = ArrowWriter::try_new(file, schema, Some(props))?;
+    writer.write(&batch)?;
+    writer.close()?;
+    Ok(())
+}
+
+fn days_since_epoch(date: NaiveDate) -> i32 {
+    (date - NaiveDate::from_ymd_opt(1970, 1, 1).unwrap()).num_days() as i32
+}
+
+impl ParquetRows for TopTime {
+    fn schema() -> Schema {
+        Schema::new(vec![
+            Field::new("age", DataType::UInt8, false),
+            Field::new("course", DataType::Utf8, false),
+            Field::new("date", DataType::Date32, false),
+            Field::new("distance", DataType::UInt16, false),
+            Field::new("foreign", DataType::Boolean, true),
+            Field::new("gender", DataType::Utf8, false),
+            Field::new("lsc", DataType::Utf8, true),
+            Field::new("meet_id", DataType::UInt64, true),
+            Field::new("meet_name", DataType::Utf8, false),
+            Field::new("power_points", DataType::UInt16, true),
+            Field::new("rank", DataType::UInt64, true),
+            Field::new("relay", DataType::Boolean, false),
+            Field::new("sanctioned", DataType::Boolean, true),
+            Field::new("stroke", DataType::Utf8, false),
+            Field::new("swimmer_id", DataType::UInt64, true),
+            Field::new("swimmer_name", DataType::Utf8, false),
+            Field::new("team_name", DataType::Utf8, false),
+            Field::new("time", DataType::Float32, false),
+            Field::new("time_alt_adj", DataType::Float32, true),
+            Field::new("time_id", DataType::UInt64, true),
+            Field::new("time_standard", DataType::Utf8, true),
+        ])
+    }
+
+    fn columns(rows: &[&Self]) -> Vec<ArrayRef> {
+        let id = |i: Option<usize>| i.map(|i| i as u64);
+        vec![
+            Arc::new(UInt8Array::from_iter_values(rows.iter().map(|t| t.age))),
+            Arc::new(StringArray::from_iter_values(
+                rows.iter().map(|t| serde_name(&t.course)),
+            )),
+            Arc::new(Date32Array::from_iter_values(
+                rows.iter().map(|t| days_since_epoch(t.date)),
+            )),
+            Arc::new(UInt16Array::from_iter_values(
+                rows.iter().map(|t| t.distance.clone() as u16),
+            )),
+            Arc::new(rows.iter().map(|t| t.foreign).collect::<BooleanArray>()),
+            Arc::new(StringArray::from_iter_values(
+                rows.iter().map(|t| serde_name(&t.gender)),
+            )),
+            Arc::new(
+                rows.iter()
+                    .map(|t| t.lsc.as_ref().map(serde_name))
+                    .collect::<StringArray>(),
+            ),
+            Arc::new(rows.iter().map(|t| id(t.meet_id)).collect::<UInt64Array>()),
+            Arc::new(StringArray::from_iter_values(
+                rows.iter().map(|t| &t.meet_name),
+            )),
+            Arc::new(rows.iter().map(|t| t.power_points).collect::<UInt16Array>()),
+            Arc::new(rows.iter().map(|t| id(t.rank)).collect::<UInt64Array>()),
+            Arc::new(rows.iter().map(|t| Some(t.relay)).collect::<BooleanArray>()),
+            Arc::new(rows.iter().map(|t| t.sanctioned).collect::<BooleanArray>()),
+            Arc::new(StringArray::from_iter_values(
+                rows.iter().map(|t| serde_name(&t.stroke)),
+            )),
+            Arc::new(
+                rows.iter()
+                    .map(|t| id(t.swimmer_id))
+                    .collect::<UInt64Array>(),
+            ),
+            Arc::new(StringArray::from_iter_values(
+                rows.iter().map(|t| &t.swimmer_name),
+            )),
+            Arc::new(StringArray::from_iter_values(
+                rows.iter().map(|t| &t.team_name),
+            )),
+            Arc::new(Float32Array::from_iter_values(rows.iter().map(|t| t.time))),
+            Arc::new(
+                rows.iter()
+                    .map(|t| t.time_alt_adj)
+                    .collect::<Float32Array>(),
+            ),
+            Arc::new(rows.iter().map(|t| id(t.time_id)).collect::<UInt64Array>()),
+            Arc::new(
+                rows.iter()
+                    .map(|t| t.time_standard.as_deref())
+                    .collect::<StringArray>(),
+            ),
+        ]
+    }
+}
+
+impl ParquetRows for TopRelayTime {
+    fn schema() -> Schema {
+        Schema::new(vec![
+            Field::new("age_group", DataType::Utf8, true),
+            Field::new("course", DataType::Utf8, false),
+            Field::new("date", DataType::Date32, true),
+            Field::new("distance", DataType::UInt16, false),
+            Field::new("gender", DataType::Utf8, false),
+            Field::new("lsc", DataType::Utf8, true),
+            Field::new("meet_id", DataType::UInt64, true),
+            Field::new("meet_name", DataType::Utf8, false),
+            Field::new("rank", DataType::UInt64, true),
+            Field::new("relay_name", DataType::Utf8, true),
+            Field::new("sanctioned", DataType::Boolean, true),
+            Field::new("stroke", DataType::Utf8, false),
+            Field::new("team_name", DataType::Utf8, false),
+            Field::new("time", DataType::Float32, false),
+            Field::new("time_standard", DataType::Utf8, true),
+        ])
+    }
+
+    fn columns(rows: &[&Self]) -> Vec<ArrayRef> {
+        let id = |i: Option<usize>| i.map(|i| i as u64);
+        vec![
+            Arc::new(
+                rows.iter()
+                    .map(|t| t.age_group.as_deref())
+                    .collect::<StringArray>(),
+            ),
+            Arc::new(StringArray::from_iter_values(
+                rows.iter().map(|t| serde_name(&t.course)),
+            )),
+            Arc::new(
+                rows.iter()
+                    .map(|t| t.date.map(days_since_epoch))
+                    .collect::<Date32Array>(),
+            ),
+            Arc::new(UInt16Array::from_iter_values(
+                rows.iter().map(|t| t.distance.clone() as u16),
+            )),
+            Arc::new(StringArray::from_iter_values(
+                rows.iter().map(|t| serde_name(&t.gender)),
+            )),
+            Arc::new(
+                rows.iter()
+                    .map(|t| t.lsc.as_ref().map(serde_name))
+                    .collect::<StringArray>(),
+            ),
+            Arc::new(rows.iter().map(|t| id(t.meet_id)).collect::<UInt64Array>()),
+            Arc::new(StringArray::from_iter_values(
+                rows.iter().map(|t| &t.meet_name),
+            )),
+            Arc::new(rows.iter().map(|t| id(t.rank)).collect::<UInt64Array>()),
+            Arc::new(
+                rows.iter()
+                    .map(|t| t.relay_name.as_deref())
+                    .collect::<StringArray>(),
+            ),
+            Arc::new(rows.iter().map(|t| t.sanctioned).collect::<BooleanArray>()),
+            Arc::new(StringArray::from_iter_values(
+                rows.iter().map(|t| serde_name(&t.stroke)),
+            )),
+            Arc::new(StringArray::from_iter_values(
+                rows.iter().map(|t| &t.team_name),
+            )),
+            Arc::new(Float32Array::from_iter_values(rows.iter().map(|t| t.time))),
+            Arc::new(
+                rows.iter()
+                    .map(|t| t.time_standard.as_deref())
+                    .collect::<StringArray>(),
+            ),
+        ]
+    }
+}
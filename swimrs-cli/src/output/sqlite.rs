@@ -0,0 +1,49 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use swimrs::usas::toptimes::TopTimesRequest;
+use tracing::debug;
+
+use super::{OutputSink, Results};
+use crate::times::SqliteTimesDb;
+
+/// Writes result sets to the normalized SQLite times store.
+pub struct TimesSink {
+    db: Arc<SqliteTimesDb>,
+    /// If set, each individual result set replaces the times its request
+    /// covers and the differences are recorded against this sync run. Relay
+    /// times are always upserted, since they can't be attributed to swimmers.
+    sync_id: Option<i64>,
+}
+
+impl TimesSink {
+    pub fn new(db: Arc<SqliteTimesDb>, sync_id: Option<i64>) -> Self {
+        TimesSink { db, sync_id }
+    }
+}
+
+#[async_trait]
+impl OutputSink for TimesSink {
+    async fn write(
+        &self,
+        req_id: &str,
+        req: &TopTimesRequest,
+        results: Arc<Results>,
+    ) -> Result<()> {
+        match (&*results, self.sync_id) {
+            // Empty result sets still matter here, since they may remove times
+            (Results::Individual(times), Some(sync_id)) => {
+                let changes = self.db.replace_times(req, times, sync_id).await?;
+                debug!("{}: {}", req_id, changes);
+            }
+            (Results::Individual(times), None) => {
+                self.db.upsert_times(times).await?;
+            }
+            (Results::Relay(times), _) => {
+                self.db.upsert_relay_times(times).await?;
+            }
+        }
+        Ok(())
+    }
+}
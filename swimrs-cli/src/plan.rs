@@ -0,0 +1,453 @@
+use std::str::FromStr;
+
+use anyhow::{bail, Context, Error, Result};
+use chrono::{Datelike, Duration, NaiveDate};
+use clap::ArgEnum;
+use serde::Deserialize;
+use swimrs::{
+    common::{Course, Gender, Stroke, TimeType, Zone, LSC},
+    usas::toptimes::TopTimesRequest,
+};
+
+use crate::{mirror::WorkerOptions, status::format_hours};
+
+/// Length of the date range covered by each planned request.
+#[derive(Debug, Clone, Copy, PartialEq, ArgEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Period {
+    Day,
+    /// Monday through Sunday
+    Week,
+    /// Calendar month
+    Month,
+}
+
+/// Which kinds of results to plan requests for.
+#[derive(Debug, Clone, Copy, PartialEq, ArgEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TimeTypes {
+    Individual,
+    Relay,
+    Both,
+}
+
+/// Additional dimension to split each date range and age bucket by.
+#[derive(Debug, Clone, Copy, PartialEq, ArgEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Split {
+    Course,
+    Stroke,
+    Lsc,
+    Zone,
+}
+
+/// Inclusive age range for a single request. `None` means unbounded.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AgeBucket(pub Option<u8>, pub Option<u8>);
+
+/// List of age buckets, written like `0-7,8,9,10-12,23-`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AgeBuckets(pub Vec<AgeBucket>);
+
+impl FromStr for AgeBuckets {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let parse_age = |a: &str| -> Result<Option<u8>> {
+            match a {
+                "" => Ok(None),
+                a => Ok(Some(
+                    a.parse::<u8>()
+                        .with_context(|| format!("invalid age: {}", a))?,
+                )),
+            }
+        };
+
+        let buckets = s
+            .split(',')
+            .map(|b| {
+                let b = b.trim();
+                match b.split_once('-') {
+                    Some((start, end)) => Ok(AgeBucket(parse_age(start)?, parse_age(end)?)),
+                    None => {
+                        let age = parse_age(b)?.context("empty age bucket")?;
+                        Ok(AgeBucket(Some(age), Some(age)))
+                    }
+                }
+            })
+            .collect::<Result<Vec<AgeBucket>>>()?;
+
+        for AgeBucket(start, end) in &buckets {
+            if let (Some(start), Some(end)) = (start, end) {
+                if start > end {
+                    bail!("age bucket {}-{} is empty", start, end);
+                }
+            }
+        }
+        Ok(AgeBuckets(buckets))
+    }
+}
+
+impl Default for AgeBuckets {
+    /// Single years from 8 to 22, with 7 and under and 23 and over grouped
+    /// together.
+    fn default() -> Self {
+        let mut buckets = vec![AgeBucket(Some(0), Some(7))];
+        buckets.extend((8..=22).map(|a| AgeBucket(Some(a), Some(a))));
+        buckets.push(AgeBucket(Some(23), None));
+        AgeBuckets(buckets)
+    }
+}
+
+/// Describes how a date range is broken up into Top Times requests. Each
+/// individual request covers one period, one age bucket and one gender,
+/// further split by any of course, stroke, LSC or zone. Relay requests cover
+/// every age, since relay age groups don't line up with individual ages, but
+/// include mixed relays as a third gender.
+#[derive(Debug, Clone)]
+pub struct PartitionStrategy {
+    pub period: Period,
+    pub age_buckets: AgeBuckets,
+    pub splits: Vec<Split>,
+    pub time_types: TimeTypes,
+}
+
+impl Default for PartitionStrategy {
+    fn default() -> Self {
+        PartitionStrategy {
+            period: Period::Day,
+            age_buckets: AgeBuckets::default(),
+            splits: Vec::new(),
+            time_types: TimeTypes::Individual,
+        }
+    }
+}
+
+impl PartitionStrategy {
+    /// Plans every request needed to cover `from_date` through `to_date`,
+    /// inclusive. The first and last periods are clipped to the range.
+    pub fn plan(&self, from_date: NaiveDate, to_date: NaiveDate) -> Vec<TopTimesRequest> {
+        let mut reqs = Vec::new();
+        let individual = self.time_types != TimeTypes::Relay;
+        let relay = self.time_types != TimeTypes::Individual;
+
+        for (start, end) in self.periods(from_date, to_date) {
+            if individual {
+                for AgeBucket(start_age, end_age) in &self.age_buckets.0 {
+                    for gender in [Gender::Male, Gender::Female] {
+                        let base = TopTimesRequest {
+                            gender,
+                            from_date: start,
+                            to_date: end,
+                            start_age: *start_age,
+                            end_age: *end_age,
+                            ..TopTimesRequest::default()
+                        };
+                        reqs.extend(self.split(base));
+                    }
+                }
+            }
+            if relay {
+                for gender in [Gender::Male, Gender::Female, Gender::Mixed] {
+                    let base = TopTimesRequest {
+                        gender,
+                        from_date: start,
+                        to_date: end,
+                        time_type: TimeType::Relay,
+                        ..TopTimesRequest::default()
+                    };
+                    reqs.extend(self.split(base));
+                }
+            }
+        }
+
+        reqs
+    }
+
+    /// Breaks the date range into consecutive periods.
+    fn periods(&self, from_date: NaiveDate, to_date: NaiveDate) -> Vec<(NaiveDate, NaiveDate)> {
+        let mut periods = Vec::new();
+        let mut start = from_date;
+
+        while start <= to_date {
+            let end = match self.period {
+                Period::Day => start,
+                Period::Week => {
+                    let days_left = 6 - start.weekday().num_days_from_monday();
+                    start + Duration::days(days_left.into())
+                }
+                Period::Month => {
+                    let (y, m) = match start.month() {
+                        12 => (start.year() + 1, 1),
+                        m => (start.year(), m + 1),
+                    };
+                    NaiveDate::from_ymd_opt(y, m, 1).unwrap() - Duration::days(1)
+                }
+            };
+            let end = end.min(to_date);
+            periods.push((start, end));
+            start = end + Duration::days(1);
+        }
+
+        periods
+    }
+
+    fn split(&self, base: TopTimesRequest) -> Vec<TopTimesRequest> {
+        let mut reqs = vec![base];
+
+        for split in &self.splits {
+            reqs = reqs
+                .into_iter()
+                .flat_map(|r| match split {
+                    Split::Course => [Course::SCY, Course::SCM, Course::LCM]
+                        .into_iter()
+                        .map(|course| TopTimesRequest {
+                            course,
+                            ..r.clone()
+                        })
+                        .collect::<Vec<_>>(),
+                    Split::Stroke => match r.time_type {
+                        TimeType::Individual => vec![
+                            Stroke::Freestyle,
+                            Stroke::Backstroke,
+                            Stroke::Breaststroke,
+                            Stroke::Butterfly,
+                            Stroke::IndividualMedley,
+                        ],
+                        TimeType::Relay => vec![Stroke::FreestyleRelay, Stroke::MedleyRelay],
+                    }
+                    .into_iter()
+                    .map(|stroke| TopTimesRequest {
+                        stroke,
+                        ..r.clone()
+                    })
+                    .collect(),
+                    Split::Lsc => ALL_LSCS
+                        .iter()
+                        .map(|lsc| TopTimesRequest {
+                            lscs: Some(vec![lsc.clone()]),
+                            ..r.clone()
+                        })
+                        .collect(),
+                    Split::Zone => [Zone::Central, Zone::Eastern, Zone::Southern, Zone::Western]
+                        .into_iter()
+                        .map(|zone| TopTimesRequest { zone, ..r.clone() })
+                        .collect(),
+                })
+                .collect();
+        }
+
+        reqs
+    }
+}
+
+/// Prints every planned request followed by an estimate of how long they will
+/// take to fetch with the configured clients and request delay.
+pub fn print_plan(reqs: &[TopTimesRequest], opts: &WorkerOptions) {
+    for r in reqs {
+        println!("{}", r.id());
+    }
+
+    let num_clients = opts.clients.len();
+    let mean_delay = (opts.min_delay + opts.max_delay) as f64 / 2.0;
+    let hours = reqs.len() as f64 * mean_delay / num_clients.max(1) as f64 / 3600.0;
+    eprintln!(
+        "{} requests; estimated {} with {} client(s)",
+        reqs.len(),
+        format_hours(hours),
+        num_clients
+    );
+}
+
+/// Every LSC that can be searched on its own.
+const ALL_LSCS: [LSC; 61] = [
+    LSC::Unattached,
+    LSC::AD,
+    LSC::AK,
+    LSC::AM,
+    LSC::AZ,
+    LSC::AR,
+    LSC::BD,
+    LSC::CC,
+    LSC::CO,
+    LSC::CT,
+    LSC::FG,
+    LSC::FL,
+    LSC::GA,
+    LSC::GU,
+    LSC::HI,
+    LSC::IL,
+    LSC::IN,
+    LSC::IE,
+    LSC::IA,
+    LSC::KY,
+    LSC::LE,
+    LSC::LA,
+    LSC::ME,
+    LSC::MD,
+    LSC::MR,
+    LSC::MI,
+    LSC::MA,
+    LSC::MW,
+    LSC::MN,
+    LSC::MS,
+    LSC::MV,
+    LSC::MT,
+    LSC::NE,
+    LSC::NJ,
+    LSC::NM,
+    LSC::NI,
+    LSC::NC,
+    LSC::ND,
+    LSC::NT,
+    LSC::OH,
+    LSC::OK,
+    LSC::OR,
+    LSC::OZ,
+    LSC::PN,
+    LSC::PC,
+    LSC::PV,
+    LSC::SI,
+    LSC::SN,
+    LSC::SR,
+    LSC::SC,
+    LSC::SD,
+    LSC::ST,
+    LSC::SE,
+    LSC::CA,
+    LSC::US,
+    LSC::UT,
+    LSC::VA,
+    LSC::WT,
+    LSC::WV,
+    LSC::WI,
+    LSC::WY,
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(s: &str) -> NaiveDate {
+        s.parse().unwrap()
+    }
+
+    fn strategy(period: Period, splits: Vec<Split>, time_types: TimeTypes) -> PartitionStrategy {
+        PartitionStrategy {
+            period,
+            splits,
+            time_types,
+            ..PartitionStrategy::default()
+        }
+    }
+
+    #[test]
+    fn test_age_buckets() {
+        let buckets = AgeBuckets::from_str("0-7, 8,9-12,23-").unwrap();
+        assert_eq!(
+            buckets.0,
+            vec![
+                AgeBucket(Some(0), Some(7)),
+                AgeBucket(Some(8), Some(8)),
+                AgeBucket(Some(9), Some(12)),
+                AgeBucket(Some(23), None),
+            ]
+        );
+        assert_eq!(
+            AgeBuckets::from_str("-10").unwrap().0,
+            vec![AgeBucket(None, Some(10))]
+        );
+        assert_eq!(AgeBuckets::default().0.len(), 17);
+
+        for bad in ["", "8,", "12-9", "x", "8-300"] {
+            assert!(AgeBuckets::from_str(bad).is_err(), "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn test_periods() {
+        let from = date("2024-02-28");
+        let to = date("2024-03-12");
+
+        let days = strategy(Period::Day, Vec::new(), TimeTypes::Individual).periods(from, to);
+        assert_eq!(days.len(), 14);
+        assert!(days.iter().all(|(start, end)| start == end));
+
+        // 2024-02-28 is a Wednesday; the first and last weeks are clipped
+        let weeks = strategy(Period::Week, Vec::new(), TimeTypes::Individual).periods(from, to);
+        assert_eq!(
+            weeks,
+            vec![
+                (date("2024-02-28"), date("2024-03-03")),
+                (date("2024-03-04"), date("2024-03-10")),
+                (date("2024-03-11"), date("2024-03-12")),
+            ]
+        );
+
+        let months = strategy(Period::Month, Vec::new(), TimeTypes::Individual)
+            .periods(date("2023-12-15"), date("2024-02-10"));
+        assert_eq!(
+            months,
+            vec![
+                (date("2023-12-15"), date("2023-12-31")),
+                (date("2024-01-01"), date("2024-01-31")),
+                (date("2024-02-01"), date("2024-02-10")),
+            ]
+        );
+
+        assert!(strategy(Period::Day, Vec::new(), TimeTypes::Individual)
+            .periods(to, from)
+            .is_empty());
+    }
+
+    #[test]
+    fn test_split() {
+        let base = TopTimesRequest::default();
+        let s = strategy(
+            Period::Day,
+            vec![Split::Course, Split::Stroke],
+            TimeTypes::Both,
+        );
+        let reqs = s.split(base.clone());
+        assert_eq!(reqs.len(), 3 * 5);
+        assert_eq!(reqs[0].course, Course::SCY);
+        assert_eq!(reqs[0].stroke, Stroke::Freestyle);
+        assert_eq!(reqs[14].course, Course::LCM);
+        assert_eq!(reqs[14].stroke, Stroke::IndividualMedley);
+
+        let relay = TopTimesRequest {
+            time_type: TimeType::Relay,
+            ..base.clone()
+        };
+        let strokes: Vec<Stroke> = s.split(relay).into_iter().map(|r| r.stroke).collect();
+        assert_eq!(strokes.len(), 3 * 2);
+        assert!(strokes
+            .iter()
+            .all(|s| matches!(s, Stroke::FreestyleRelay | Stroke::MedleyRelay)));
+
+        let s = strategy(Period::Day, vec![Split::Lsc, Split::Zone], TimeTypes::Both);
+        let reqs = s.split(base.clone());
+        assert_eq!(reqs.len(), ALL_LSCS.len() * 4);
+        assert_eq!(reqs[0].lscs, Some(vec![LSC::Unattached]));
+        assert_eq!(reqs[0].zone, Zone::Central);
+
+        assert_eq!(
+            strategy(Period::Day, Vec::new(), TimeTypes::Both)
+                .split(base)
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_plan() {
+        let from = date("2024-03-01");
+        let to = date("2024-03-02");
+        let reqs = strategy(Period::Day, Vec::new(), TimeTypes::Both).plan(from, to);
+        // Two days of 17 age buckets for two genders, and three relay genders
+        assert_eq!(reqs.len(), 2 * (17 * 2 + 3));
+        let relays = reqs.iter().filter(|r| r.time_type == TimeType::Relay);
+        assert!(relays.clone().any(|r| r.gender == Gender::Mixed));
+        assert!(relays.clone().all(|r| r.start_age.is_none()));
+    }
+}
@@ -0,0 +1,422 @@
+use std::{fs, io, path::Path};
+
+use anyhow::{bail, Context, Result};
+use chrono::{Local, NaiveDate};
+use clap::ArgEnum;
+use serde::Deserialize;
+use swimrs::{
+    common::{Course, Gender, SwimTime},
+    psych::{PsychSheet, Seeding},
+    scoring::{Entry, EventEntries},
+};
+use tracing::info;
+
+use crate::{merge, times::SqliteTimesDb};
+
+#[derive(Debug, Clone, Copy, PartialEq, ArgEnum)]
+pub enum SheetFormat {
+    Text,
+    Html,
+    Csv,
+}
+
+/// How a meet's events are seeded.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum SeedingConfig {
+    TimedFinal,
+    /// Prelims, with the fastest `circle_heats` heats circle seeded
+    Prelims,
+}
+
+/// A meet's program, read from a TOML file.
+///
+/// ```toml
+/// name = "Anytown Spring Invitational"
+/// lanes = 8
+/// seeding = "prelims"
+/// circle_heats = 3
+///
+/// [[event]]
+/// number = 12
+/// event = "100 FL SCY"
+/// gender = "Female"
+/// min_age = 13
+/// max_age = 14
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MeetFile {
+    name: Option<String>,
+    #[serde(default = "default_lanes")]
+    lanes: u8,
+    #[serde(default = "default_seeding")]
+    seeding: SeedingConfig,
+    #[serde(default = "default_circle_heats")]
+    circle_heats: u16,
+    event: Vec<EventEntry>,
+}
+
+/// The most lanes a pool is built with.
+const MAX_LANES: u8 = 10;
+
+fn default_lanes() -> u8 {
+    8
+}
+
+fn default_seeding() -> SeedingConfig {
+    SeedingConfig::TimedFinal
+}
+
+fn default_circle_heats() -> u16 {
+    3
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EventEntry {
+    number: u16,
+    event: String,
+    /// Open to either gender if not given
+    gender: Option<Gender>,
+    min_age: Option<u8>,
+    max_age: Option<u8>,
+}
+
+/// A row of an entries CSV file. The seed is converted to the event's course
+/// if `course` names another.
+#[derive(Debug, Deserialize)]
+struct EntryRow {
+    event: u16,
+    name: String,
+    team: String,
+    age: Option<u8>,
+    /// Seed time, or `NT` or nothing for no time
+    seed: Option<String>,
+    course: Option<Course>,
+}
+
+fn load_meet(path: &Path) -> Result<(MeetFile, Vec<EventEntries>)> {
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("error reading meet file {}", path.display()))?;
+    let meet: MeetFile = toml::from_str(&text)
+        .with_context(|| format!("error parsing meet file {}", path.display()))?;
+    if !(1..=MAX_LANES).contains(&meet.lanes) {
+        bail!("lanes must be between 1 and {}", MAX_LANES);
+    }
+    let events = meet
+        .event
+        .iter()
+        .map(|e| {
+            Ok(EventEntries {
+                number: e.number,
+                gender: e.gender.clone().unwrap_or(Gender::Mixed),
+                min_age: e.min_age,
+                max_age: e.max_age,
+                event: e
+                    .event
+                    .parse()
+                    .with_context(|| format!("invalid event: {}", e.event))?,
+                entries: Vec::new(),
+            })
+        })
+        .collect::<Result<_>>()?;
+    Ok((meet, events))
+}
+
+fn read_entries(path: &Path, events: &mut [EventEntries]) -> Result<()> {
+    let mut reader = csv::Reader::from_path(path)
+        .with_context(|| format!("error reading entries file {}", path.display()))?;
+    for row in reader.deserialize() {
+        let row: EntryRow = row?;
+        let e = events
+            .iter_mut()
+            .find(|e| e.number == row.event)
+            .with_context(|| format!("no event {} for {}", row.event, row.name))?;
+        let seed = match row.seed.as_deref().map(str::trim) {
+            None | Some("") | Some("NT") => None,
+            Some(s) => {
+                let time: SwimTime = s
+                    .parse()
+                    .with_context(|| format!("invalid seed time: {}", s))?;
+                match row.course {
+                    Some(course) if course != e.event.2 => {
+                        let (_, seconds) = e
+                            .event
+                            .in_course(&course)
+                            .and_then(|swum| swum.convert(time.seconds, &e.event.2))
+                            .with_context(|| {
+                                format!("can't convert {} {} to {}", s, course, e.event)
+                            })?;
+                        Some(SwimTime { seconds, ..time })
+                    }
+                    _ => Some(time),
+                }
+            }
+        };
+        e.entries.push(Entry {
+            name: row.name,
+            team: row.team,
+            age: row.age,
+            seed,
+        });
+    }
+    Ok(())
+}
+
+/// Builds the psych sheet for the meet in `meet_path` and prints it in
+/// `format`. Entries are read from the CSV file at `entries_path`, and every
+/// eligible swimmer and relay is also entered with their best time found
+/// under `times_dir` or in the times store at `times_db_url`. Only times swum
+/// on or after `since` are used, if it is given, which leaves out relay times
+/// without a date.
+pub async fn psych(
+    meet_path: &Path,
+    entries_path: Option<&Path>,
+    times_dir: Option<&Path>,
+    times_db_url: Option<&str>,
+    since: Option<NaiveDate>,
+    format: SheetFormat,
+) -> Result<()> {
+    if entries_path.is_none() && times_dir.is_none() && times_db_url.is_none() {
+        bail!(
+            "nothing to read entries from; set an entries file, times directory or times database"
+        );
+    }
+    let (meet, mut events) = load_meet(meet_path)?;
+    if let Some(path) = entries_path {
+        read_entries(path, &mut events)?;
+    }
+
+    let (mut times, mut relays) = (Vec::new(), Vec::new());
+    if let Some(dir) = times_dir {
+        let dir = dir.to_owned();
+        let (t, r) = tokio::task::spawn_blocking(move || merge::read_times(&dir)).await??;
+        times.extend(
+            t.into_iter()
+                .filter(|t| since.is_none_or(|since| t.date >= since)),
+        );
+        relays.extend(
+            r.into_iter()
+                .filter(|r| since.is_none_or(|since| r.date.is_some_and(|d| d >= since))),
+        );
+    }
+    if let Some(url) = times_db_url {
+        let times_db = SqliteTimesDb::new(url).await?;
+        times_db.migrate().await?;
+        let from_date = since.unwrap_or_else(|| NaiveDate::from_ymd_opt(1, 1, 1).unwrap());
+        let to_date = Local::now().date_naive();
+        times.extend(times_db.times_between(from_date, to_date).await?);
+        relays.extend(times_db.relay_times_since(since).await?);
+    }
+    for e in &mut events {
+        e.add_times(&times);
+        e.add_relay_times(&relays);
+    }
+    info!(
+        "seeding {} entries in {} events",
+        events.iter().map(|e| e.entries.len()).sum::<usize>(),
+        events.len()
+    );
+
+    let seeding = match meet.seeding {
+        SeedingConfig::TimedFinal => Seeding::TimedFinal,
+        SeedingConfig::Prelims => Seeding::Circle(meet.circle_heats),
+    };
+    let sheet = PsychSheet::new(meet.name, &events, meet.lanes, seeding);
+    match format {
+        SheetFormat::Text => print!("{}", sheet),
+        SheetFormat::Html => print!("{}", sheet.to_html()),
+        SheetFormat::Csv => write_csv(&sheet, io::stdout())?,
+    }
+    Ok(())
+}
+
+/// Writes `sheet` as CSV, a row per entry.
+fn write_csv(sheet: &PsychSheet, w: impl io::Write) -> Result<()> {
+    let mut writer = csv::Writer::from_writer(w);
+    writer.write_record([
+        "event",
+        "event_name",
+        "rank",
+        "name",
+        "age",
+        "team",
+        "seed",
+        "heat",
+        "lane",
+    ])?;
+    for e in &sheet.events {
+        let event_name = e.name();
+        for n in &e.entries {
+            let seed = n.entry.seed.as_ref().map_or_else(
+                || "NT".to_owned(),
+                |t| {
+                    SwimTime {
+                        relay: false,
+                        ..t.clone()
+                    }
+                    .to_string()
+                },
+            );
+            writer.write_record([
+                &e.number.to_string(),
+                &event_name,
+                &n.rank.to_string(),
+                &n.entry.name,
+                &n.entry.age.map(|a| a.to_string()).unwrap_or_default(),
+                &n.entry.team,
+                &seed,
+                &n.heat.to_string(),
+                &n.lane.to_string(),
+            ])?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use swimrs::common::SwimEvent;
+
+    use super::*;
+    use crate::testing::TempDir;
+
+    const MEET: &str = r#"
+name = "Anytown Spring Invitational"
+
+[[event]]
+number = 12
+event = "100 FL SCY"
+gender = "Female"
+min_age = 13
+max_age = 14
+
+[[event]]
+number = 14
+event = "200 FR-R SCY"
+"#;
+
+    fn load(dir: &TempDir, meet: &str) -> Result<(MeetFile, Vec<EventEntries>)> {
+        let path = dir.path().join("meet.toml");
+        fs::write(&path, meet)?;
+        load_meet(&path)
+    }
+
+    fn read(dir: &TempDir, entries: &str, events: &mut [EventEntries]) -> Result<()> {
+        let path = dir.path().join("entries.csv");
+        fs::write(&path, entries)?;
+        read_entries(&path, events)
+    }
+
+    #[test]
+    fn test_load_meet() -> Result<()> {
+        let dir = TempDir::new("psych-load-meet");
+        let (meet, events) = load(&dir, MEET)?;
+        assert_eq!(meet.lanes, 8);
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[1].gender, Gender::Mixed);
+
+        for lanes in [0, 11] {
+            let meet = format!("lanes = {}\n{}", lanes, MEET);
+            assert!(load(&dir, &meet).is_err());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_entries() -> Result<()> {
+        let dir = TempDir::new("psych-read-entries");
+        let (_, mut events) = load(&dir, MEET)?;
+        read(
+            &dir,
+            "event,name,team,age,seed,course
+12,\"Swimmer, Alpha\",Anytown Aquatics,14,1:05.00,LCM
+12,\"Swimmer, Bravo\",Anytown Aquatics,13,59.99,SCY
+12,\"Swimmer, Charlie\",Anytown Aquatics,13,NT,
+12,\"Swimmer, Delta\",Anytown Aquatics,13,,
+14,Anytown Aquatics A,Anytown Aquatics,,1:45.12r,
+",
+            &mut events,
+        )?;
+
+        let seeds: Vec<Option<f32>> = events[0]
+            .entries
+            .iter()
+            .map(|n| n.seed.as_ref().map(|t| t.seconds))
+            .collect();
+        // Seeds in another course are converted to the event's
+        let (_, converted) = "100 FL LCM"
+            .parse::<SwimEvent>()?
+            .convert(65.0, &Course::SCY)
+            .unwrap();
+        assert_eq!(seeds, [Some(converted), Some(59.99), None, None]);
+        assert_eq!(events[0].entries[0].age, Some(14));
+        let relay = &events[1].entries[0];
+        assert_eq!(relay.age, None);
+        assert_eq!(relay.seed.as_ref().map(SwimTime::hundredths), Some(10512));
+
+        let err = read(
+            &dir,
+            "event,name,team,age,seed,course\n15,\"Swimmer, Echo\",Anytown Aquatics,13,NT,\n",
+            &mut events,
+        )
+        .unwrap_err();
+        assert_eq!(err.to_string(), "no event 15 for Swimmer, Echo");
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_csv() -> Result<()> {
+        let dir = TempDir::new("psych-write-csv");
+        let (_, mut events) = load(&dir, MEET)?;
+        events[0].entries = vec![
+            Entry {
+                name: "Swimmer, \"Al\"\rAlpha".to_owned(),
+                team: "Anytown Aquatics".to_owned(),
+                age: Some(14),
+                seed: Some(SwimTime {
+                    seconds: 60.0,
+                    relay: false,
+                }),
+            },
+            Entry {
+                name: "Swimmer, Bravo".to_owned(),
+                team: "Anytown Aquatics".to_owned(),
+                age: None,
+                seed: None,
+            },
+        ];
+        let sheet = PsychSheet::new(None, &events, 8, Seeding::TimedFinal);
+        let mut csv = Vec::new();
+        write_csv(&sheet, &mut csv)?;
+
+        let csv = String::from_utf8(csv)?;
+        let mut lines = csv.split('\n');
+        assert_eq!(
+            lines.next(),
+            Some("event,event_name,rank,name,age,team,seed,heat,lane")
+        );
+        let mut reader = csv::Reader::from_reader(csv.as_bytes());
+        let rows: Vec<csv::StringRecord> = reader.records().collect::<Result<_, _>>()?;
+        assert_eq!(rows.len(), 2);
+        assert_eq!(
+            rows[0].iter().collect::<Vec<_>>(),
+            [
+                "12",
+                "Girls 13-14 100 Yard Butterfly",
+                "1",
+                "Swimmer, \"Al\"\rAlpha",
+                "14",
+                "Anytown Aquatics",
+                "1:00.00",
+                "1",
+                "4"
+            ]
+        );
+        assert_eq!(&rows[1][3], "Swimmer, Bravo");
+        assert_eq!((&rows[1][4], &rows[1][6]), ("", "NT"));
+        Ok(())
+    }
+}
@@ -0,0 +1,186 @@
+use std::{fs, path::Path};
+
+use anyhow::{bail, Context, Result};
+use chrono::NaiveDate;
+use serde::Deserialize;
+use swimrs::{
+    common::{Course, Gender, SwimTime},
+    qualify::{Check, Conversion, Standard, Standards},
+};
+use tracing::info;
+
+use crate::{merge, times::SqliteTimesDb};
+
+/// A meet's qualifying standards, read from a TOML file. Times from courses
+/// listed under `convert` are accepted for every standard, converted with
+/// the standard factors unless a factor is given. Swimmers' ages are taken on
+/// `age_up_date` when their times settle it.
+///
+/// ```toml
+/// from_date = "2024-09-01"
+/// to_date = "2025-02-16"
+/// age_up_date = "2025-03-06"
+///
+/// [[convert]]
+/// from = "LCM"
+///
+/// [[convert]]
+/// from = "SCM"
+/// factor = 0.9
+///
+/// [[standard]]
+/// event = "100 FR SCY"
+/// gender = "Female"
+/// min_age = 13
+/// max_age = 14
+/// time = "55.89"
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StandardsFile {
+    from_date: NaiveDate,
+    to_date: NaiveDate,
+    age_up_date: Option<NaiveDate>,
+    #[serde(default)]
+    convert: Vec<ConvertEntry>,
+    standard: Vec<StandardEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConvertEntry {
+    from: Course,
+    factor: Option<f32>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StandardEntry {
+    event: String,
+    /// Open to either gender if not given
+    gender: Option<Gender>,
+    min_age: Option<u8>,
+    max_age: Option<u8>,
+    time: String,
+}
+
+fn load_standards(path: &Path) -> Result<Standards> {
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("error reading standards file {}", path.display()))?;
+    let file: StandardsFile = toml::from_str(&text)
+        .with_context(|| format!("error parsing standards file {}", path.display()))?;
+    if file.from_date > file.to_date {
+        bail!("from_date must not be after to_date");
+    }
+    Ok(Standards {
+        standards: file
+            .standard
+            .into_iter()
+            .map(|s| {
+                Ok(Standard {
+                    event: s
+                        .event
+                        .parse()
+                        .with_context(|| format!("invalid event: {}", s.event))?,
+                    gender: s.gender.unwrap_or(Gender::Mixed),
+                    min_age: s.min_age,
+                    max_age: s.max_age,
+                    time: s
+                        .time
+                        .parse::<SwimTime>()
+                        .with_context(|| format!("invalid time: {}", s.time))?
+                        .seconds,
+                })
+            })
+            .collect::<Result<_>>()?,
+        from_date: file.from_date,
+        to_date: file.to_date,
+        age_up_date: file.age_up_date,
+        conversions: file
+            .convert
+            .into_iter()
+            .map(|c| Conversion {
+                from: c.from,
+                factor: c.factor,
+            })
+            .collect(),
+    })
+}
+
+/// Checks the individual times found under `times_dir`, in the times store
+/// at `times_db_url`, or both, against the standards in `standards_path`,
+/// and prints the events swimmers qualify for followed by their near misses,
+/// which are within `near_miss_percent` of a standard.
+pub async fn qualify(
+    standards_path: &Path,
+    times_dir: Option<&Path>,
+    times_db_url: Option<&str>,
+    near_miss_percent: f32,
+) -> Result<()> {
+    if times_dir.is_none() && times_db_url.is_none() {
+        bail!("nothing to read times from; set a times directory or times database");
+    }
+    let standards = load_standards(standards_path)?;
+
+    let mut times = Vec::new();
+    if let Some(dir) = times_dir {
+        let dir = dir.to_owned();
+        times.extend(
+            tokio::task::spawn_blocking(move || merge::read_times(&dir))
+                .await??
+                .0,
+        );
+    }
+    if let Some(url) = times_db_url {
+        let times_db = SqliteTimesDb::new(url).await?;
+        times_db.migrate().await?;
+        times.extend(
+            times_db
+                .times_between(standards.from_date, standards.to_date)
+                .await?,
+        );
+    }
+    info!(
+        "checking {} times against {} standards",
+        times.len(),
+        standards.standards.len()
+    );
+
+    let report = standards.check(&times, near_miss_percent / 100.0);
+    println!("QUALIFIED");
+    print_checks(&report.qualified);
+    println!();
+    println!("NEAR MISSES");
+    print_checks(&report.near_misses);
+    Ok(())
+}
+
+fn print_checks(checks: &[Check]) {
+    println!(
+        "{:<24} {:>3} {:<12} {:>9} {:<4} {:>9} {:>9} {:>7} {:<10} MEET",
+        "SWIMMER", "AGE", "EVENT", "TIME", "", "CONVERTED", "STANDARD", "GAP", "DATE"
+    );
+    for c in checks {
+        println!(
+            "{:<24} {:>3} {:<12} {:>9} {:<4} {:>9} {:>9} {:>+7.2} {:<10} {}",
+            c.swimmer_name,
+            c.age,
+            c.event.to_string(),
+            time(c.time),
+            c.course.to_string(),
+            time(c.converted),
+            time(c.standard),
+            c.gap(),
+            c.date.to_string(),
+            c.meet_name
+        );
+    }
+}
+
+fn time(seconds: f32) -> String {
+    SwimTime {
+        seconds,
+        relay: false,
+    }
+    .to_string()
+}
@@ -0,0 +1,242 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use anyhow::Result;
+use chrono::{Duration, Utc};
+use indicatif::{ProgressBar, ProgressStyle};
+use tokio::{task::JoinHandle, time::sleep};
+use tracing::error;
+
+use crate::{
+    db::{self, JobState, RequestStore},
+    logging,
+    times::SqliteTimesDb,
+};
+
+/// Window used to measure recent throughput.
+const THROUGHPUT_WINDOW_MINUTES: i64 = 60;
+
+/// How often the progress bar is refreshed from the database.
+const PROGRESS_INTERVAL: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// Per-state job counts for one group of requests.
+#[derive(Default)]
+struct Counts {
+    success: u64,
+    failed: u64,
+    dead: u64,
+    pending: u64,
+}
+
+impl Counts {
+    fn add(&mut self, state: JobState, n: u64) {
+        match state {
+            JobState::Success => self.success += n,
+            JobState::Failed => self.failed += n,
+            JobState::Dead => self.dead += n,
+            JobState::Pending | JobState::InFlight => self.pending += n,
+        }
+    }
+
+    fn total(&self) -> u64 {
+        self.success + self.failed + self.dead + self.pending
+    }
+}
+
+/// Prints a summary of mirror progress read from the request and times
+/// databases.
+pub async fn print_status(db_url: &str, times_db_url: &str) -> Result<()> {
+    let db = db::open(db_url).await?;
+    let times_db = SqliteTimesDb::new(times_db_url).await?;
+    times_db.migrate().await?;
+
+    let mut partitions: BTreeMap<(String, String), Counts> = BTreeMap::new();
+    let mut overall = Counts::default();
+    for (month, gender, state, n) in db.count_by_partition().await? {
+        partitions.entry((month, gender)).or_default().add(state, n);
+        overall.add(state, n);
+    }
+
+    println!(
+        "{:<8} {:<7} {:>9} {:>9} {:>9} {:>9}",
+        "MONTH", "GENDER", "SUCCESS", "FAILED", "DEAD", "PENDING"
+    );
+    for ((month, gender), c) in &partitions {
+        println!(
+            "{:<8} {:<7} {:>9} {:>9} {:>9} {:>9}",
+            month, gender, c.success, c.failed, c.dead, c.pending
+        );
+    }
+    println!(
+        "{:<16} {:>9} {:>9} {:>9} {:>9}",
+        "TOTAL", overall.success, overall.failed, overall.dead, overall.pending
+    );
+    println!();
+
+    println!("results reported: {}", db.total_results().await?);
+    println!("times stored:     {}", times_db.count_times().await?);
+    println!("relays stored:    {}", times_db.count_relay_times().await?);
+    println!();
+
+    let since = Utc::now() - Duration::minutes(THROUGHPUT_WINDOW_MINUTES);
+    let recent = db.count_completed_since(since.timestamp()).await?;
+    let per_hour = recent as f64 * 60.0 / THROUGHPUT_WINDOW_MINUTES as f64;
+    let remaining = overall.pending + overall.failed;
+    println!("throughput:       {:.0} requests/hour", per_hour);
+    if let (Some(fetch), Some(parse)) = db.mean_timings_since(since.timestamp()).await? {
+        println!("mean fetch time:  {:.2}s", fetch);
+        println!("mean parse time:  {:.2}s", parse);
+    }
+    match remaining {
+        0 => println!("eta:              done"),
+        _ if recent == 0 => println!("eta:              unknown (no recent progress)"),
+        _ => println!(
+            "eta:              {}",
+            format_hours(remaining as f64 / per_hour)
+        ),
+    }
+
+    let errors = db.error_breakdown(80, 10).await?;
+    if !errors.is_empty() {
+        println!();
+        println!("{:>7} ERROR", "COUNT");
+        for (e, n) in errors {
+            println!("{:>7} {}", n, e.replace('\n', " "));
+        }
+    }
+
+    Ok(())
+}
+
+/// Progress bar for the request queue, refreshed by a background task until
+/// dropped. Events logged to stderr while it is shown are printed above it.
+pub struct Progress {
+    bar: ProgressBar,
+    task: JoinHandle<()>,
+}
+
+impl Drop for Progress {
+    /// Stops refreshing the bar and leaves its last state on screen.
+    fn drop(&mut self) {
+        self.task.abort();
+        logging::set_progress_bar(None);
+        self.bar.abandon();
+    }
+}
+
+/// Starts drawing a progress bar for the request queue. The bar is only drawn
+/// when stderr is a terminal.
+pub fn spawn_progress(db: Arc<dyn RequestStore>) -> Progress {
+    let bar = ProgressBar::new(0);
+    bar.set_style(
+        ProgressStyle::with_template(
+            "{elapsed_precise} [{wide_bar}] {pos}/{len} requests ({per_sec}, eta {eta}) {msg}",
+        )
+        .expect("progress template is valid"),
+    );
+    logging::set_progress_bar(Some(bar.clone()));
+
+    let task = tokio::spawn({
+        let bar = bar.clone();
+        async move {
+            let mut first = true;
+            loop {
+                match db.count_by_state().await {
+                    Ok(states) => {
+                        let mut c = Counts::default();
+                        for (state, n) in states {
+                            c.add(state, n);
+                        }
+                        bar.set_length(c.total());
+                        bar.set_position(c.success + c.dead);
+                        bar.set_message(format!("{} failed, {} dead", c.failed, c.dead));
+                        // Jobs completed by earlier runs shouldn't count
+                        // towards the rate
+                        if first {
+                            bar.reset_eta();
+                            first = false;
+                        }
+                    }
+                    Err(e) => error!("error reading queue progress: {}", e),
+                }
+                sleep(PROGRESS_INTERVAL).await;
+            }
+        }
+    });
+    Progress { bar, task }
+}
+
+pub(crate) fn format_hours(hours: f64) -> String {
+    let minutes = (hours * 60.0).round() as u64;
+    match minutes {
+        m if m < 60 => format!("{}m", m),
+        m if m < 60 * 24 => format!("{}h {}m", m / 60, m % 60),
+        m => format!("{}d {}h", m / (60 * 24), (m / 60) % 24),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+    use swimrs::usas::toptimes::TopTimesRequest;
+
+    use super::*;
+    use crate::{db::Timings, testing::TempDb};
+
+    #[test]
+    fn test_counts() {
+        let mut c = Counts::default();
+        c.add(JobState::Success, 3);
+        c.add(JobState::Pending, 2);
+        c.add(JobState::InFlight, 1);
+        c.add(JobState::Failed, 4);
+        c.add(JobState::Dead, 5);
+        assert_eq!((c.success, c.failed, c.dead, c.pending), (3, 4, 5, 3));
+        assert_eq!(c.total(), 15);
+    }
+
+    #[test]
+    fn test_format_hours() {
+        assert_eq!(format_hours(0.0), "0m");
+        assert_eq!(format_hours(0.5), "30m");
+        assert_eq!(format_hours(2.25), "2h 15m");
+        assert_eq!(format_hours(23.99), "23h 59m");
+        assert_eq!(format_hours(50.0), "2d 2h");
+    }
+
+    #[tokio::test]
+    async fn test_status() -> Result<()> {
+        let tmp = TempDb::new("status");
+        let db = db::open(&tmp.url).await?;
+        let reqs: Vec<(String, TopTimesRequest)> = (1..=3)
+            .map(|d| {
+                let date = NaiveDate::from_ymd_opt(2022, 1, d).unwrap();
+                let req = TopTimesRequest {
+                    from_date: date,
+                    to_date: date,
+                    ..TopTimesRequest::default()
+                };
+                (req.id(), req)
+            })
+            .collect();
+        db.enqueue(&reqs).await?;
+        let job = db.lease("w1", Duration::seconds(60)).await?.unwrap();
+        db.upsert_request_success(&job.id, "w1", 5, &Timings::default())
+            .await?;
+
+        print_status(&tmp.url, &tmp.url).await?;
+
+        // The bar shows completed jobs out of every job in the queue
+        let progress = spawn_progress(db.clone());
+        for _ in 0..100 {
+            if progress.bar.length() == Some(3) {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert_eq!(progress.bar.length(), Some(3));
+        assert_eq!(progress.bar.position(), 1);
+        assert_eq!(progress.bar.message(), "0 failed, 0 dead");
+        drop(progress);
+        Ok(())
+    }
+}
@@ -0,0 +1,384 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use anyhow::{Context, Result};
+use chrono::{offset::Local, Duration, NaiveDate};
+use swimrs::usas::toptimes::TopTimesRequest;
+use tokio::time::sleep;
+use tracing::info;
+
+use crate::{
+    control::{self, Controller},
+    db::{self, JobState, RequestStore},
+    exporter,
+    mirror::{run_workers, Sink, WorkerOptions},
+    plan::{self, PartitionStrategy},
+    times::{ChangeCounts, SqliteTimesDb},
+};
+
+/// Settings for incremental syncs.
+pub struct SyncOptions {
+    /// Earliest date to sync. Defaults to the start of the first sync
+    pub since: Option<NaiveDate>,
+    /// Number of trailing days to re-fetch on every pass
+    pub window_days: u32,
+    /// If set, passes repeat on this interval; otherwise a single pass is made
+    pub every: Option<std::time::Duration>,
+    /// Whether to print every added, changed and removed time
+    pub report: bool,
+    /// Print the planned requests for the next pass instead of running it
+    pub plan_only: bool,
+}
+
+/// Incrementally keeps the mirror up to date. Each pass re-fetches the
+/// trailing window of days, along with any earlier day that has never been
+/// fully refreshed, and reports the times that were added, changed or
+/// removed.
+pub async fn start_sync(
+    sync: SyncOptions,
+    strategy: &PartitionStrategy,
+    opts: WorkerOptions,
+) -> Result<()> {
+    let db = db::open(&opts.db_url).await?;
+    let times_db = SqliteTimesDb::new(&opts.times_db_url).await?;
+    times_db.migrate().await?;
+
+    // Settings changed through the admin API persist across passes
+    let controller = Arc::new(opts.controller());
+    let admin = match sync.plan_only {
+        true => None,
+        false => {
+            exporter::install(opts.metrics_addr)?;
+            Some(control::serve(opts.admin_addr, controller.clone())?)
+        }
+    };
+
+    let res = async {
+        loop {
+            sync_once(&db, &times_db, &sync, strategy, &controller, &opts).await?;
+            match sync.every {
+                Some(every) if !sync.plan_only => {
+                    info!("next sync in {} seconds", every.as_secs());
+                    sleep(every).await;
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+    .await;
+    if let Some(admin) = admin {
+        admin.abort();
+    }
+    res
+}
+
+/// Requests grouped by the dates they cover, so that each date can be marked
+/// refreshed once all of its requests have succeeded.
+type Periods = BTreeMap<(NaiveDate, NaiveDate), Vec<(String, TopTimesRequest)>>;
+
+async fn sync_once(
+    db: &Arc<dyn RequestStore>,
+    times_db: &SqliteTimesDb,
+    sync: &SyncOptions,
+    strategy: &PartitionStrategy,
+    controller: &Arc<Controller>,
+    opts: &WorkerOptions,
+) -> Result<()> {
+    let today = Local::now().naive_local().date();
+    let dates = dates_to_sync(times_db, sync, today).await?;
+    let from_date = match dates.first() {
+        Some(d) => *d,
+        None => {
+            info!("nothing to sync");
+            return Ok(());
+        }
+    };
+
+    let periods = plan_periods(strategy, &dates);
+    let all: Vec<(String, TopTimesRequest)> = periods.values().flatten().cloned().collect();
+    if sync.plan_only {
+        let planned: Vec<TopTimesRequest> = all.into_iter().map(|(_, r)| r).collect();
+        plan::print_plan(&planned, opts);
+        return Ok(());
+    }
+
+    let sync_id = times_db.start_sync_run(from_date, today).await?;
+    let queued = db.enqueue_refresh(&all).await?;
+    info!(
+        "sync {}: queued {} requests across {} days from {} to {}",
+        sync_id,
+        queued,
+        dates.len(),
+        from_date,
+        today
+    );
+
+    let sink = Sink::open(
+        &opts.times_db_url,
+        opts.files.clone(),
+        opts.archive,
+        Some(sync_id),
+    )
+    .await?;
+    run_workers(db.clone(), sink, controller.clone(), opts).await?;
+
+    let counts = finish_sync(&**db, times_db, &periods, sync_id, sync.report).await?;
+    info!("sync {} finished: {}", sync_id, counts);
+
+    Ok(())
+}
+
+/// The days a pass ending `today` fetches: the trailing window, along with
+/// any earlier day since the start of the sync that has never been fully
+/// refreshed.
+async fn dates_to_sync(
+    times_db: &SqliteTimesDb,
+    sync: &SyncOptions,
+    today: NaiveDate,
+) -> Result<Vec<NaiveDate>> {
+    let since = match sync.since {
+        Some(d) => d,
+        None => times_db
+            .first_sync_date()
+            .await?
+            .context("database has never been synced; pass --since to choose a start date")?,
+    };
+    let window_start = (today - Duration::days(sync.window_days.into())).max(since);
+
+    // Days before the trailing window are only fetched until they have been
+    // fully refreshed once
+    let refreshed = match window_start > since {
+        true => {
+            times_db
+                .refreshed_dates(since, window_start - Duration::days(1))
+                .await?
+        }
+        false => Default::default(),
+    };
+    Ok(since
+        .iter_days()
+        .take_while(|d| *d <= today)
+        .filter(|d| *d >= window_start || !refreshed.contains(d))
+        .collect())
+}
+
+/// Plans the requests covering `dates`. Each contiguous run of dates is
+/// planned separately so that periods never span days that don't need
+/// refreshing.
+fn plan_periods(strategy: &PartitionStrategy, dates: &[NaiveDate]) -> Periods {
+    let mut periods = Periods::new();
+    for (start, end) in contiguous_ranges(dates) {
+        for r in strategy.plan(start, end) {
+            periods
+                .entry((r.from_date, r.to_date))
+                .or_default()
+                .push((r.id(), r));
+        }
+    }
+    periods
+}
+
+/// Marks the days of every period whose requests all succeeded as refreshed
+/// by the sync run `sync_id`, and finishes the run. Returns the number of
+/// times it added, changed and removed, printing each one if `report` is
+/// set.
+async fn finish_sync(
+    db: &dyn RequestStore,
+    times_db: &SqliteTimesDb,
+    periods: &Periods,
+    sync_id: i64,
+    report: bool,
+) -> Result<ChangeCounts> {
+    for ((start, end), reqs) in periods {
+        let ids: Vec<String> = reqs.iter().map(|(id, _)| id.clone()).collect();
+        let states = db.job_states(&ids).await?;
+        if ids
+            .iter()
+            .all(|id| states.get(id) == Some(&JobState::Success))
+        {
+            for date in start.iter_days().take_while(|d| d <= end) {
+                times_db.mark_partition_refreshed(date, sync_id).await?;
+            }
+        }
+    }
+    times_db.finish_sync_run(sync_id).await?;
+
+    let changes = times_db.sync_changes(sync_id).await?;
+    let mut counts = ChangeCounts::default();
+    for c in &changes {
+        match c.change.as_str() {
+            "added" => counts.added += 1,
+            "changed" => counts.changed += 1,
+            _ => counts.removed += 1,
+        }
+        if report {
+            println!(
+                "{}\t{}\t{}\t{}\t{} {} {}\t{:.2}",
+                c.change,
+                c.date,
+                c.swimmer_id,
+                c.meet_id,
+                c.distance,
+                c.stroke,
+                c.course,
+                c.time_cs as f64 / 100.0
+            );
+        }
+    }
+    Ok(counts)
+}
+
+/// Collapses a sorted list of dates into inclusive ranges of consecutive days.
+fn contiguous_ranges(dates: &[NaiveDate]) -> Vec<(NaiveDate, NaiveDate)> {
+    let mut ranges: Vec<(NaiveDate, NaiveDate)> = Vec::new();
+    for d in dates {
+        match ranges.last_mut() {
+            Some((_, end)) if *end + Duration::days(1) == *d => *end = *d,
+            _ => ranges.push((*d, *d)),
+        }
+    }
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use swimrs::{common::Gender, usas::toptimes::TopTime};
+
+    use super::*;
+    use crate::{
+        db::{MemoryRequestDb, RetryPolicy, Timings},
+        plan::{AgeBucket, AgeBuckets},
+        testing::{top_time, TempDb},
+    };
+
+    fn d(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2022, 1, day).unwrap()
+    }
+
+    fn swim(swimmer_id: usize, day: u32) -> TopTime {
+        TopTime {
+            date: d(day),
+            ..top_time(swimmer_id, 60.0)
+        }
+    }
+
+    fn options(since: Option<NaiveDate>) -> SyncOptions {
+        SyncOptions {
+            since,
+            window_days: 3,
+            every: None,
+            report: false,
+            plan_only: false,
+        }
+    }
+
+    /// Runs a pass ending `today` with a three day window, as workers would,
+    /// fetching each request from `published` except girls' requests for
+    /// `failing`, which fail for good. Returns the dates the pass fetched and
+    /// the changes it made.
+    async fn pass(
+        db: &MemoryRequestDb,
+        times_db: &SqliteTimesDb,
+        since: Option<NaiveDate>,
+        today: NaiveDate,
+        published: &[TopTime],
+        failing: Option<NaiveDate>,
+    ) -> Result<(Vec<NaiveDate>, ChangeCounts)> {
+        let strategy = PartitionStrategy {
+            age_buckets: AgeBuckets(vec![AgeBucket(None, None)]),
+            ..PartitionStrategy::default()
+        };
+        let dates = dates_to_sync(times_db, &options(since), today).await?;
+        let periods = plan_periods(&strategy, &dates);
+        let all: Vec<(String, TopTimesRequest)> = periods.values().flatten().cloned().collect();
+        let sync_id = times_db.start_sync_run(dates[0], today).await?;
+        db.enqueue_refresh(&all).await?;
+
+        let policy = RetryPolicy {
+            max_attempts: 1,
+            ..RetryPolicy::default()
+        };
+        let timings = Timings::default();
+        while let Some(job) = db.lease("worker", Duration::minutes(1)).await? {
+            let req = &job.request;
+            if failing == Some(req.from_date) && req.gender == Gender::Female {
+                db.upsert_request_error(&job.id, "worker", "unavailable", &timings, &policy)
+                    .await?;
+                continue;
+            }
+            let times: Vec<TopTime> = published
+                .iter()
+                .filter(|t| t.date >= req.from_date && t.date <= req.to_date)
+                .filter(|t| t.gender == req.gender)
+                .cloned()
+                .collect();
+            times_db.replace_times(req, &times, sync_id).await?;
+            db.upsert_request_success(&job.id, "worker", times.len() as u32, &timings)
+                .await?;
+        }
+
+        let counts = finish_sync(db, times_db, &periods, sync_id, false).await?;
+        Ok((dates, counts))
+    }
+
+    #[tokio::test]
+    async fn test_sync_passes() -> Result<()> {
+        let tmp = TempDb::new("sync-passes");
+        let times_db = SqliteTimesDb::new(&tmp.url).await?;
+        times_db.migrate().await?;
+        let db = MemoryRequestDb::default();
+        assert!(dates_to_sync(&times_db, &options(None), d(10))
+            .await
+            .is_err());
+
+        // The first pass fetches every day, but the 4th never succeeds
+        let mut published = vec![swim(1, 2), swim(2, 2), swim(5, 8), swim(3, 9)];
+        let (dates, counts) =
+            pass(&db, &times_db, Some(d(1)), d(10), &published, Some(d(4))).await?;
+        assert_eq!(dates, (1..=10).map(d).collect::<Vec<_>>());
+        assert_eq!(
+            counts,
+            ChangeCounts {
+                added: 4,
+                changed: 0,
+                removed: 0,
+            }
+        );
+        let refreshed = times_db.refreshed_dates(d(1), d(10)).await?;
+        assert_eq!(refreshed.len(), 9);
+        assert!(!refreshed.contains(&d(4)));
+
+        // A day later, the window has moved on and the results have changed.
+        // The 4th is fetched again, since it was never refreshed
+        published.retain(|t| t.swimmer_id != Some(5));
+        published[2].time_standard = Some("AA".to_owned());
+        published.push(swim(4, 10));
+        let (dates, counts) = pass(&db, &times_db, None, d(11), &published, None).await?;
+        assert_eq!(dates, [d(4), d(8), d(9), d(10), d(11)]);
+        assert_eq!(
+            counts,
+            ChangeCounts {
+                added: 1,
+                changed: 1,
+                removed: 1,
+            }
+        );
+        assert_eq!(times_db.refreshed_dates(d(1), d(11)).await?.len(), 11);
+        assert_eq!(times_db.count_times().await?, 4);
+        Ok(())
+    }
+
+    #[test]
+    fn test_contiguous_ranges() {
+        assert!(contiguous_ranges(&[]).is_empty());
+        assert_eq!(contiguous_ranges(&[d(5)]), vec![(d(5), d(5))]);
+        assert_eq!(
+            contiguous_ranges(&[d(1), d(2), d(3), d(5), d(7), d(8)]),
+            vec![(d(1), d(3)), (d(5), d(5)), (d(7), d(8))]
+        );
+        let across_months = [NaiveDate::from_ymd_opt(2021, 12, 31).unwrap(), d(1)];
+        assert_eq!(
+            contiguous_ranges(&across_months),
+            vec![(across_months[0], d(1))]
+        );
+    }
+}
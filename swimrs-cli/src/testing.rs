@@ -0,0 +1,100 @@
+//! Scratch files and directories for tests, and results to fill them with.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use chrono::NaiveDate;
+use swimrs::{
+    common::{Course, Distance, Gender, Stroke},
+    usas::toptimes::TopTime,
+};
+
+/// SQLite database file, removed when dropped.
+pub struct TempDb {
+    path: PathBuf,
+    pub url: String,
+}
+
+impl TempDb {
+    /// A database file named after `name`, which must be unique among tests.
+    pub fn new(name: &str) -> Self {
+        let path = scratch_path(&format!("{}.db", name));
+        let url = format!("sqlite://{}", path.display());
+        TempDb { path, url }
+    }
+}
+
+impl Drop for TempDb {
+    fn drop(&mut self) {
+        for suffix in ["", "-wal", "-shm"] {
+            let mut path = self.path.clone().into_os_string();
+            path.push(suffix);
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+/// Empty directory, removed along with its contents when dropped.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    /// A directory named after `name`, which must be unique among tests.
+    pub fn new(name: &str) -> Self {
+        let path = scratch_path(name);
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// A test fixture from the library's `testdata` directory.
+pub fn fixture(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("../swimrs/testdata")
+        .join(name)
+}
+
+/// A 100 yard freestyle result for swimmer `swimmer_id`, swum on January 1st,
+/// 2022, with every column filled in. Tests override the fields they care
+/// about.
+pub fn top_time(swimmer_id: usize, seconds: f32) -> TopTime {
+    TopTime {
+        age: 14,
+        course: Course::SCY,
+        date: NaiveDate::from_ymd_opt(2022, 1, 1).unwrap(),
+        distance: Distance::_100,
+        foreign: Some(false),
+        gender: Gender::Female,
+        lsc: None,
+        meet_id: Some(1),
+        meet_name: "Spring Invitational".to_owned(),
+        power_points: Some(500),
+        rank: Some(swimmer_id),
+        relay: false,
+        sanctioned: Some(true),
+        stroke: Stroke::Freestyle,
+        swimmer_id: Some(swimmer_id),
+        swimmer_name: format!("Swimmer {}", swimmer_id),
+        team_name: "Anytown Aquatics".to_owned(),
+        time: seconds,
+        time_alt_adj: None,
+        time_id: Some(swimmer_id),
+        time_standard: Some("A".to_owned()),
+    }
+}
+
+fn scratch_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("swimrs-test-{}-{}", std::process::id(), name))
+}
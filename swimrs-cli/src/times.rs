@@ -0,0 +1,956 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    str::FromStr,
+};
+
+use anyhow::Result;
+use chrono::{Datelike, NaiveDate, Utc};
+use num_enum::TryFromPrimitive;
+use sqlx::{
+    query, query_as,
+    sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions},
+    Row, Sqlite, SqlitePool, Transaction,
+};
+use swimrs::{
+    common::{hundredths, Course, Distance, Gender, Stroke, Zone, LSC},
+    usas::toptimes::{TopRelayTime, TopTime, TopTimesRequest},
+};
+use tracing::warn;
+
+use crate::db;
+
+/// Primary key of a row in the `times` table: swimmer, meet, distance, stroke,
+/// course and time in hundredths of a second.
+type TimeKey = (i64, i64, u16, String, String, i64);
+
+/// Columns of a time that may change between syncs without it becoming a
+/// different swim. Rank is deliberately excluded, since it shifts whenever
+/// any faster time is added.
+type TimeValues = (String, u8, Option<String>, Option<String>, Option<u16>);
+
+/// Number of times added, changed and removed by a sync.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct ChangeCounts {
+    pub added: u64,
+    pub changed: u64,
+    pub removed: u64,
+}
+
+impl fmt::Display for ChangeCounts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} added, {} changed, {} removed",
+            self.added, self.changed, self.removed
+        )
+    }
+}
+
+/// A single time recorded as added, changed or removed by a sync run.
+#[derive(Debug, Clone)]
+pub struct Change {
+    pub change: String,
+    pub date: String,
+    pub swimmer_id: i64,
+    pub meet_id: i64,
+    pub distance: u16,
+    pub stroke: String,
+    pub course: String,
+    pub time_cs: i64,
+}
+
+/// Normalized store for mirrored times, kept in a SQLite database alongside
+/// the response archive and, usually, the request queue.
+pub struct SqliteTimesDb {
+    pool: SqlitePool,
+}
+
+impl SqliteTimesDb {
+    pub async fn new(db_url: &str) -> Result<Self> {
+        let opts = SqliteConnectOptions::from_str(db_url)?
+            .create_if_missing(true)
+            .journal_mode(SqliteJournalMode::Wal);
+        let pool = SqlitePoolOptions::new().connect_with(opts).await?;
+        Ok(Self { pool })
+    }
+
+    pub async fn migrate(&self) -> Result<()> {
+        db::sqlite::migrate(&self.pool).await
+    }
+
+    /// Upserts a batch of times, along with their swimmers, meets and teams,
+    /// in a single transaction. Re-inserting the same times is a no-op apart
+    /// from refreshing mutable columns such as rank. Swimmers and meets
+    /// without an ID are given synthetic ones, see [`synthetic_id`]. Returns
+    /// the number of times inserted or changed.
+    pub async fn upsert_times(&self, times: &[TopTime]) -> Result<u64> {
+        let mut tx = self.pool.begin().await?;
+        let mut written = 0;
+
+        for t in times {
+            let (_, affected) = upsert_time(&mut tx, t).await?;
+            written += affected;
+        }
+
+        tx.commit().await?;
+        Ok(written)
+    }
+
+    /// Replaces the times covered by `req` with `times` in a single
+    /// transaction, recording every added, changed or removed time against
+    /// the sync run `sync_id`.
+    ///
+    /// Removals can only be detected for requests whose filters map onto
+    /// columns of the `times` table, and whose result set is known to be
+    /// complete. Requests filtered by zone or LSC, and result sets that reach
+    /// the request's `max_results` and so may have been cut short, are
+    /// upserted without removing anything.
+    pub async fn replace_times(
+        &self,
+        req: &TopTimesRequest,
+        times: &[TopTime],
+        sync_id: i64,
+    ) -> Result<ChangeCounts> {
+        let mut tx = self.pool.begin().await?;
+        let mut counts = ChangeCounts::default();
+
+        let existing = existing_times(&mut tx, req).await?;
+        let mut seen = HashSet::new();
+
+        for t in times {
+            let (key, _) = upsert_time(&mut tx, t).await?;
+            let values = (
+                t.team_name.clone(),
+                t.age,
+                t.lsc.as_ref().map(|l| l.to_string()),
+                t.time_standard.clone(),
+                t.power_points,
+            );
+            let change = match existing.get(&key) {
+                None => Some("added"),
+                Some((_, old)) if *old != values => Some("changed"),
+                Some(_) => None,
+            };
+            match change {
+                Some("added") => counts.added += 1,
+                Some(_) => counts.changed += 1,
+                None => {}
+            }
+            if let Some(change) = change {
+                record_change(&mut tx, sync_id, change, &t.date.to_string(), &key).await?;
+            }
+            seen.insert(key);
+        }
+
+        let truncated = times.len() >= req.max_results as usize;
+        if truncated {
+            warn!(
+                "{} results reach the limit of {}; not removing missing times",
+                times.len(),
+                req.max_results
+            );
+        }
+        if removals_supported(req) && !truncated {
+            for (key, (date, _)) in existing.iter().filter(|(k, _)| !seen.contains(*k)) {
+                let (swimmer_id, meet_id, distance, stroke, course, time_cs) = key;
+                query(
+                    r"
+                    DELETE FROM times
+                    WHERE swimmer_id = ? AND meet_id = ? AND distance = ?
+                        AND stroke = ? AND course = ? AND time_cs = ?
+                    ",
+                )
+                .bind(swimmer_id)
+                .bind(meet_id)
+                .bind(distance)
+                .bind(stroke)
+                .bind(course)
+                .bind(time_cs)
+                .execute(&mut tx)
+                .await?;
+                record_change(&mut tx, sync_id, "removed", date, key).await?;
+                counts.removed += 1;
+            }
+        }
+
+        tx.commit().await?;
+        Ok(counts)
+    }
+
+    /// Upserts a batch of relay times, along with their teams and any meets
+    /// with a known ID, in a single transaction. Returns the number of relay
+    /// times inserted or changed, so times already stored as they are, or
+    /// repeated within the batch, aren't counted.
+    pub async fn upsert_relay_times(&self, times: &[TopRelayTime]) -> Result<u64> {
+        let mut tx = self.pool.begin().await?;
+        let mut written = 0;
+
+        for t in times {
+            written += upsert_relay_time(&mut tx, t).await?;
+        }
+
+        tx.commit().await?;
+        Ok(written)
+    }
+
+    /// Number of distinct times stored.
+    pub async fn count_times(&self) -> Result<u64> {
+        let (count,): (i64,) = query_as("SELECT COUNT(*) FROM times")
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(count as u64)
+    }
+
+    /// Number of distinct relay times stored.
+    pub async fn count_relay_times(&self) -> Result<u64> {
+        let (count,): (i64,) = query_as("SELECT COUNT(*) FROM relay_times")
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(count as u64)
+    }
+
+    /// Starts a new sync run over the given date range and returns its ID.
+    pub async fn start_sync_run(&self, from_date: NaiveDate, to_date: NaiveDate) -> Result<i64> {
+        let res = query("INSERT INTO sync_runs (from_date, to_date, started_at) VALUES (?, ?, ?)")
+            .bind(from_date.to_string())
+            .bind(to_date.to_string())
+            .bind(Utc::now().timestamp())
+            .execute(&self.pool)
+            .await?;
+        Ok(res.last_insert_rowid())
+    }
+
+    pub async fn finish_sync_run(&self, sync_id: i64) -> Result<()> {
+        query("UPDATE sync_runs SET finished_at = ? WHERE id = ?")
+            .bind(Utc::now().timestamp())
+            .bind(sync_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Records that every request for `date` completed during sync run
+    /// `sync_id`.
+    pub async fn mark_partition_refreshed(&self, date: NaiveDate, sync_id: i64) -> Result<()> {
+        query("REPLACE INTO sync_partitions (date, sync_id, refreshed_at) VALUES (?, ?, ?)")
+            .bind(date.to_string())
+            .bind(sync_id)
+            .bind(Utc::now().timestamp())
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Start of the date range covered by the first sync run, if any.
+    pub async fn first_sync_date(&self) -> Result<Option<NaiveDate>> {
+        let (date,): (Option<String>,) = query_as("SELECT MIN(from_date) FROM sync_runs")
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(match date {
+            Some(d) => Some(NaiveDate::from_str(&d)?),
+            None => None,
+        })
+    }
+
+    /// Dates in the given range that have been fully refreshed at least once.
+    pub async fn refreshed_dates(
+        &self,
+        from_date: NaiveDate,
+        to_date: NaiveDate,
+    ) -> Result<HashSet<NaiveDate>> {
+        let rows: Vec<(String,)> =
+            query_as("SELECT date FROM sync_partitions WHERE date BETWEEN ? AND ?")
+                .bind(from_date.to_string())
+                .bind(to_date.to_string())
+                .fetch_all(&self.pool)
+                .await?;
+        rows.into_iter()
+            .map(|(d,)| Ok(NaiveDate::from_str(&d)?))
+            .collect()
+    }
+
+    /// Every change recorded by a sync run, ordered by date.
+    pub async fn sync_changes(&self, sync_id: i64) -> Result<Vec<Change>> {
+        #[allow(clippy::type_complexity)]
+        let rows: Vec<(String, String, i64, i64, u16, String, String, i64)> = query_as(
+            r"
+            SELECT change, date, swimmer_id, meet_id, distance, stroke, course, time_cs
+            FROM sync_changes
+            WHERE sync_id = ?
+            ORDER BY date, change
+            ",
+        )
+        .bind(sync_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(
+                |(change, date, swimmer_id, meet_id, distance, stroke, course, time_cs)| Change {
+                    change,
+                    date,
+                    swimmer_id,
+                    meet_id,
+                    distance,
+                    stroke,
+                    course,
+                    time_cs,
+                },
+            )
+            .collect())
+    }
+
+    /// Every stored time swum between `from_date` and `to_date`, with the
+    /// swimmer, meet and team it references. Columns that aren't stored, such
+    /// as the time ID, are left empty, as are synthetic swimmer and meet IDs.
+    pub async fn times_between(
+        &self,
+        from_date: NaiveDate,
+        to_date: NaiveDate,
+    ) -> Result<Vec<TopTime>> {
+        let rows = query(
+            r"
+            SELECT t.swimmer_id, s.name AS swimmer_name, s.is_foreign, t.meet_id,
+                m.name AS meet_name, m.sanctioned, tm.name AS team_name, t.distance,
+                t.stroke, t.course, t.time_cs, t.date, t.age, t.gender, t.lsc, t.relay,
+                t.rank, t.time_standard, t.power_points
+            FROM times t
+            JOIN swimmers s ON s.id = t.swimmer_id
+            JOIN meets m ON m.id = t.meet_id
+            JOIN teams tm ON tm.id = t.team_id
+            WHERE t.date BETWEEN ? AND ?
+            ORDER BY t.date
+            ",
+        )
+        .bind(from_date.to_string())
+        .bind(to_date.to_string())
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter()
+            .map(|r| {
+                let distance: u16 = r.try_get("distance")?;
+                let stroke: String = r.try_get("stroke")?;
+                let course: String = r.try_get("course")?;
+                let gender: String = r.try_get("gender")?;
+                let lsc: Option<String> = r.try_get("lsc")?;
+                let date: String = r.try_get("date")?;
+                let time_cs: i64 = r.try_get("time_cs")?;
+                Ok(TopTime {
+                    age: r.try_get("age")?,
+                    course: Course::from_str(&course)?,
+                    date: NaiveDate::from_str(&date)?,
+                    distance: Distance::try_from_primitive(distance)?,
+                    foreign: r.try_get("is_foreign")?,
+                    gender: match gender.as_str() {
+                        "Male" => Gender::Male,
+                        "Female" => Gender::Female,
+                        _ => Gender::Mixed,
+                    },
+                    lsc: lsc.as_deref().map(LSC::from_str).transpose()?,
+                    meet_id: real_id(r.try_get("meet_id")?),
+                    meet_name: r.try_get("meet_name")?,
+                    power_points: r.try_get("power_points")?,
+                    rank: r.try_get::<Option<i64>, _>("rank")?.map(|r| r as usize),
+                    relay: r.try_get("relay")?,
+                    sanctioned: r.try_get("sanctioned")?,
+                    stroke: Stroke::from_str(&stroke)?,
+                    swimmer_id: real_id(r.try_get("swimmer_id")?),
+                    swimmer_name: r.try_get("swimmer_name")?,
+                    team_name: r.try_get("team_name")?,
+                    time: time_cs as f32 / 100.0,
+                    time_alt_adj: None,
+                    time_id: None,
+                    time_standard: r.try_get("time_standard")?,
+                })
+            })
+            .collect()
+    }
+
+    /// Every stored relay time swum on or after `since`, or every relay time
+    /// if `since` isn't given. Relay times without a date are only included
+    /// when `since` isn't given.
+    pub async fn relay_times_since(&self, since: Option<NaiveDate>) -> Result<Vec<TopRelayTime>> {
+        let since = since.map(|d| d.to_string());
+        let rows = query(
+            r"
+            SELECT tm.name AS team_name, r.meet_id, r.meet_name, m.sanctioned, r.date,
+                r.distance, r.stroke, r.course, r.gender, r.relay_name, r.time_cs,
+                r.age_group, r.lsc, r.rank, r.time_standard
+            FROM relay_times r
+            JOIN teams tm ON tm.id = r.team_id
+            LEFT JOIN meets m ON m.id = r.meet_id
+            WHERE ?1 IS NULL OR r.date >= ?1
+            ORDER BY r.date
+            ",
+        )
+        .bind(since)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter()
+            .map(|r| {
+                let distance: u16 = r.try_get("distance")?;
+                let stroke: String = r.try_get("stroke")?;
+                let course: String = r.try_get("course")?;
+                let gender: String = r.try_get("gender")?;
+                let lsc: Option<String> = r.try_get("lsc")?;
+                let date: Option<String> = r.try_get("date")?;
+                let time_cs: i64 = r.try_get("time_cs")?;
+                Ok(TopRelayTime {
+                    age_group: r.try_get("age_group")?,
+                    course: Course::from_str(&course)?,
+                    date: date.as_deref().map(NaiveDate::from_str).transpose()?,
+                    distance: Distance::try_from_primitive(distance)?,
+                    gender: match gender.as_str() {
+                        "Male" => Gender::Male,
+                        "Female" => Gender::Female,
+                        _ => Gender::Mixed,
+                    },
+                    lsc: lsc.as_deref().map(LSC::from_str).transpose()?,
+                    meet_id: r
+                        .try_get::<Option<i64>, _>("meet_id")?
+                        .map(|id| id as usize),
+                    meet_name: r.try_get("meet_name")?,
+                    rank: r.try_get::<Option<i64>, _>("rank")?.map(|r| r as usize),
+                    relay_name: r.try_get("relay_name")?,
+                    sanctioned: r.try_get("sanctioned")?,
+                    stroke: Stroke::from_str(&stroke)?,
+                    team_name: r.try_get("team_name")?,
+                    time: time_cs as f32 / 100.0,
+                    time_standard: r.try_get("time_standard")?,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Upserts a single time and the swimmer, meet and team it references.
+/// Returns the time's key and the number of time rows inserted or changed,
+/// 0 or 1.
+async fn upsert_time(tx: &mut Transaction<'_, Sqlite>, t: &TopTime) -> Result<(TimeKey, u64)> {
+    let swimmer_id = match t.swimmer_id {
+        Some(id) => id as i64,
+        None => {
+            let key = format!("{}/{}/{}", t.swimmer_name, t.gender, t.team_name);
+            synthetic_id(tx, "swimmer", &key).await?
+        }
+    };
+    let meet_id = match t.meet_id {
+        Some(id) => id as i64,
+        None => {
+            let key = format!("{}/{}", t.meet_name, t.date.year());
+            synthetic_id(tx, "meet", &key).await?
+        }
+    };
+    let lsc = t.lsc.as_ref().map(|l| l.to_string());
+    let distance = t.distance.clone() as u16;
+    let stroke = t.stroke.to_string();
+    let course = t.course.to_string();
+    let time_cs = i64::from(hundredths(t.time));
+
+    query(
+        r"
+        INSERT INTO swimmers (id, name, gender, lsc, is_foreign)
+        VALUES (?, ?, ?, ?, ?)
+        ON CONFLICT (id) DO UPDATE SET
+            name = excluded.name,
+            lsc = COALESCE(excluded.lsc, lsc),
+            is_foreign = COALESCE(excluded.is_foreign, is_foreign)
+        ",
+    )
+    .bind(swimmer_id)
+    .bind(&t.swimmer_name)
+    .bind(t.gender.to_string())
+    .bind(&lsc)
+    .bind(t.foreign)
+    .execute(&mut *tx)
+    .await?;
+
+    query(
+        r"
+        INSERT INTO meets (id, name, sanctioned)
+        VALUES (?, ?, ?)
+        ON CONFLICT (id) DO UPDATE SET
+            name = excluded.name,
+            sanctioned = COALESCE(excluded.sanctioned, sanctioned)
+        ",
+    )
+    .bind(meet_id)
+    .bind(&t.meet_name)
+    .bind(t.sanctioned)
+    .execute(&mut *tx)
+    .await?;
+
+    query("INSERT OR IGNORE INTO teams (name) VALUES (?)")
+        .bind(&t.team_name)
+        .execute(&mut *tx)
+        .await?;
+
+    let res = query(
+        r"
+        INSERT INTO times (
+            swimmer_id, meet_id, distance, stroke, course, time_cs, team_id,
+            date, age, gender, lsc, relay, rank, time_standard, power_points
+        )
+        VALUES (
+            ?, ?, ?, ?, ?, ?, (SELECT id FROM teams WHERE name = ?),
+            ?, ?, ?, ?, ?, ?, ?, ?
+        )
+        ON CONFLICT (swimmer_id, meet_id, distance, stroke, course, time_cs)
+        DO UPDATE SET
+            team_id = excluded.team_id,
+            date = excluded.date,
+            age = excluded.age,
+            lsc = excluded.lsc,
+            rank = excluded.rank,
+            time_standard = excluded.time_standard,
+            power_points = excluded.power_points
+        WHERE team_id IS NOT excluded.team_id
+            OR date IS NOT excluded.date
+            OR age IS NOT excluded.age
+            OR lsc IS NOT excluded.lsc
+            OR rank IS NOT excluded.rank
+            OR time_standard IS NOT excluded.time_standard
+            OR power_points IS NOT excluded.power_points
+        ",
+    )
+    .bind(swimmer_id)
+    .bind(meet_id)
+    .bind(distance)
+    .bind(&stroke)
+    .bind(&course)
+    .bind(time_cs)
+    .bind(&t.team_name)
+    .bind(t.date.to_string())
+    .bind(t.age)
+    .bind(t.gender.to_string())
+    .bind(&lsc)
+    .bind(t.relay)
+    .bind(t.rank.map(|r| r as i64))
+    .bind(&t.time_standard)
+    .bind(t.power_points)
+    .execute(&mut *tx)
+    .await?;
+
+    Ok((
+        (swimmer_id, meet_id, distance, stroke, course, time_cs),
+        res.rows_affected(),
+    ))
+}
+
+/// ID standing in for a swimmer or meet that results don't give the USA
+/// Swimming ID of, found by `kind`, either `swimmer` or `meet`, and a natural
+/// key. Swimmers are known by name, gender and team, and meets by name and
+/// year. Synthetic IDs count down from -1, so they never clash with real
+/// ones.
+async fn synthetic_id(tx: &mut Transaction<'_, Sqlite>, kind: &str, key: &str) -> Result<i64> {
+    query(
+        r"
+        INSERT OR IGNORE INTO synthetic_ids (kind, natural_key, id)
+        VALUES (?, ?, (SELECT COALESCE(MIN(id), 0) - 1 FROM synthetic_ids WHERE kind = ?))
+        ",
+    )
+    .bind(kind)
+    .bind(key)
+    .bind(kind)
+    .execute(&mut *tx)
+    .await?;
+    let (id,): (i64,) = query_as("SELECT id FROM synthetic_ids WHERE kind = ? AND natural_key = ?")
+        .bind(kind)
+        .bind(key)
+        .fetch_one(&mut *tx)
+        .await?;
+    Ok(id)
+}
+
+/// A stored swimmer or meet ID, unless it is synthetic.
+fn real_id(id: i64) -> Option<usize> {
+    usize::try_from(id).ok().filter(|id| *id > 0)
+}
+
+/// Upserts a single relay time and the team and meet it references.
+/// Returns the number of relay time rows inserted or changed, 0 or 1.
+async fn upsert_relay_time(tx: &mut Transaction<'_, Sqlite>, t: &TopRelayTime) -> Result<u64> {
+    let meet_id = t.meet_id.map(|m| m as i64);
+    if let Some(meet_id) = meet_id {
+        query(
+            r"
+            INSERT INTO meets (id, name, sanctioned)
+            VALUES (?, ?, ?)
+            ON CONFLICT (id) DO UPDATE SET
+                name = excluded.name,
+                sanctioned = COALESCE(excluded.sanctioned, sanctioned)
+            ",
+        )
+        .bind(meet_id)
+        .bind(&t.meet_name)
+        .bind(t.sanctioned)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    query("INSERT OR IGNORE INTO teams (name) VALUES (?)")
+        .bind(&t.team_name)
+        .execute(&mut *tx)
+        .await?;
+
+    let res = query(
+        r"
+        INSERT INTO relay_times (
+            team_id, meet_id, meet_name, date, distance, stroke, course, gender,
+            relay_name, time_cs, age_group, lsc, rank, time_standard
+        )
+        VALUES (
+            (SELECT id FROM teams WHERE name = ?), ?, ?, ?, ?, ?, ?, ?,
+            ?, ?, ?, ?, ?, ?
+        )
+        ON CONFLICT (
+            team_id, meet_name, COALESCE(date, ''), distance, stroke, course, gender,
+            COALESCE(relay_name, ''), time_cs
+        )
+        DO UPDATE SET
+            meet_id = COALESCE(excluded.meet_id, meet_id),
+            age_group = excluded.age_group,
+            lsc = excluded.lsc,
+            rank = excluded.rank,
+            time_standard = excluded.time_standard
+        WHERE excluded.meet_id IS NOT NULL AND meet_id IS NOT excluded.meet_id
+            OR age_group IS NOT excluded.age_group
+            OR lsc IS NOT excluded.lsc
+            OR rank IS NOT excluded.rank
+            OR time_standard IS NOT excluded.time_standard
+        ",
+    )
+    .bind(&t.team_name)
+    .bind(meet_id)
+    .bind(&t.meet_name)
+    .bind(t.date.map(|d| d.to_string()))
+    .bind(t.distance.clone() as u16)
+    .bind(t.stroke.to_string())
+    .bind(t.course.to_string())
+    .bind(t.gender.to_string())
+    .bind(&t.relay_name)
+    .bind(hundredths(t.time))
+    .bind(&t.age_group)
+    .bind(t.lsc.as_ref().map(|l| l.to_string()))
+    .bind(t.rank.map(|r| r as i64))
+    .bind(&t.time_standard)
+    .execute(&mut *tx)
+    .await?;
+
+    Ok(res.rows_affected())
+}
+
+/// Loads the times currently stored for the slice of data covered by `req`,
+/// keyed by primary key and paired with their date.
+async fn existing_times(
+    tx: &mut Transaction<'_, Sqlite>,
+    req: &TopTimesRequest,
+) -> Result<HashMap<TimeKey, (String, TimeValues)>> {
+    let gender = match req.gender {
+        Gender::Mixed => None,
+        ref g => Some(g.to_string()),
+    };
+    let distance = match req.distance {
+        Distance::All => None,
+        ref d => Some(d.clone() as u16),
+    };
+    let stroke = match req.stroke {
+        Stroke::All => None,
+        ref s => Some(s.to_string()),
+    };
+    let course = match req.course {
+        Course::All => None,
+        ref c => Some(c.to_string()),
+    };
+
+    #[allow(clippy::type_complexity)]
+    let rows: Vec<(
+        i64,
+        i64,
+        u16,
+        String,
+        String,
+        i64,
+        String,
+        String,
+        u8,
+        Option<String>,
+        Option<String>,
+        Option<u16>,
+    )> = query_as(
+        r"
+        SELECT t.swimmer_id, t.meet_id, t.distance, t.stroke, t.course, t.time_cs, t.date,
+            tm.name, t.age, t.lsc, t.time_standard, t.power_points
+        FROM times t
+        JOIN teams tm ON tm.id = t.team_id
+        WHERE t.date BETWEEN ? AND ?
+            AND (? IS NULL OR t.gender = ?)
+            AND (? IS NULL OR t.age >= ?)
+            AND (? IS NULL OR t.age <= ?)
+            AND (? IS NULL OR t.distance = ?)
+            AND (? IS NULL OR t.stroke = ?)
+            AND (? IS NULL OR t.course = ?)
+        ",
+    )
+    .bind(req.from_date.to_string())
+    .bind(req.to_date.to_string())
+    .bind(&gender)
+    .bind(&gender)
+    .bind(req.start_age)
+    .bind(req.start_age)
+    .bind(req.end_age)
+    .bind(req.end_age)
+    .bind(distance)
+    .bind(distance)
+    .bind(&stroke)
+    .bind(&stroke)
+    .bind(&course)
+    .bind(&course)
+    .fetch_all(&mut *tx)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(
+            |(s, m, d, st, c, t, date, team, age, lsc, standard, points)| {
+                (
+                    (s, m, d, st, c, t),
+                    (date, (team, age, lsc, standard, points)),
+                )
+            },
+        )
+        .collect())
+}
+
+async fn record_change(
+    tx: &mut Transaction<'_, Sqlite>,
+    sync_id: i64,
+    change: &str,
+    date: &str,
+    key: &TimeKey,
+) -> Result<()> {
+    let (swimmer_id, meet_id, distance, stroke, course, time_cs) = key;
+    query(
+        r"
+        INSERT INTO sync_changes
+            (sync_id, change, date, swimmer_id, meet_id, distance, stroke, course, time_cs)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+        ",
+    )
+    .bind(sync_id)
+    .bind(change)
+    .bind(date)
+    .bind(swimmer_id)
+    .bind(meet_id)
+    .bind(distance)
+    .bind(stroke)
+    .bind(course)
+    .bind(time_cs)
+    .execute(&mut *tx)
+    .await?;
+    Ok(())
+}
+
+/// Whether every filter on `req` can be expressed against the `times` table,
+/// so that times missing from a fresh result set can safely be removed.
+fn removals_supported(req: &TopTimesRequest) -> bool {
+    req.zone == Zone::All && req.lscs.is_none()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{top_time, TempDb};
+
+    fn date() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2022, 1, 1).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_upsert_times() -> Result<()> {
+        let tmp = TempDb::new("upsert-times");
+        let db = SqliteTimesDb::new(&tmp.url).await?;
+        db.migrate().await?;
+
+        let a = top_time(1, 60.0);
+        let times = [a.clone(), top_time(2, 61.0), top_time(1, 59.5)];
+        assert_eq!(db.upsert_times(&times).await?, 3);
+        assert_eq!(db.count_times().await?, 3);
+
+        // Only times that are new or whose mutable columns changed count
+        assert_eq!(db.upsert_times(&times).await?, 0);
+        let ranked = TopTime {
+            rank: Some(10),
+            ..a.clone()
+        };
+        assert_eq!(db.upsert_times(&[ranked.clone(), a.clone()]).await?, 2);
+        assert_eq!(db.upsert_times(std::slice::from_ref(&a)).await?, 0);
+        assert_eq!(db.count_times().await?, 3);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_replace_times() -> Result<()> {
+        let tmp = TempDb::new("replace-times");
+        let db = SqliteTimesDb::new(&tmp.url).await?;
+        db.migrate().await?;
+        let sync_id = db.start_sync_run(date(), date()).await?;
+        let mut req = TopTimesRequest {
+            gender: Gender::Female,
+            from_date: date(),
+            to_date: date(),
+            ..TopTimesRequest::default()
+        };
+
+        let counts = db
+            .replace_times(
+                &req,
+                &[top_time(1, 55.0), top_time(2, 56.0), top_time(3, 57.0)],
+                sync_id,
+            )
+            .await?;
+        assert_eq!(counts.added, 3);
+
+        let mut upgraded = top_time(2, 56.0);
+        upgraded.time_standard = Some("AAAA".to_owned());
+        let counts = db
+            .replace_times(&req, &[top_time(1, 55.0), upgraded], sync_id)
+            .await?;
+        assert_eq!(
+            counts,
+            ChangeCounts {
+                added: 0,
+                changed: 1,
+                removed: 1,
+            }
+        );
+        assert_eq!(db.count_times().await?, 2);
+        let removed: Vec<Change> = db
+            .sync_changes(sync_id)
+            .await?
+            .into_iter()
+            .filter(|c| c.change == "removed")
+            .collect();
+        assert_eq!(removed.len(), 1);
+        assert_eq!(removed[0].swimmer_id, 3);
+
+        // A result set that reaches the limit may be missing times
+        req.max_results = 1;
+        let counts = db
+            .replace_times(&req, &[top_time(4, 54.0)], sync_id)
+            .await?;
+        assert_eq!(counts.added, 1);
+        assert_eq!(counts.removed, 0);
+
+        // Times outside the request's slice are never removed
+        req.max_results = 50000;
+        req.gender = Gender::Male;
+        let counts = db.replace_times(&req, &[], sync_id).await?;
+        assert_eq!(counts, ChangeCounts::default());
+
+        // Nor are any for requests filtered by columns that aren't stored
+        req.gender = Gender::Female;
+        req.zone = Zone::Western;
+        let counts = db.replace_times(&req, &[], sync_id).await?;
+        assert_eq!(counts.removed, 0);
+        assert_eq!(db.count_times().await?, 3);
+        Ok(())
+    }
+
+    fn relay(team_name: &str, relay_name: Option<&str>, seconds: f32) -> TopRelayTime {
+        TopRelayTime {
+            age_group: Some("15-18".to_owned()),
+            course: Course::SCY,
+            date: Some(date()),
+            distance: Distance::_400,
+            gender: Gender::Female,
+            lsc: None,
+            meet_id: None,
+            meet_name: "Spring Invitational".to_owned(),
+            rank: Some(1),
+            relay_name: relay_name.map(str::to_owned),
+            sanctioned: None,
+            stroke: Stroke::FreestyleRelay,
+            team_name: team_name.to_owned(),
+            time: seconds,
+            time_standard: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_upsert_relay_times() -> Result<()> {
+        let tmp = TempDb::new("upsert-relay-times");
+        let db = SqliteTimesDb::new(&tmp.url).await?;
+        db.migrate().await?;
+
+        // The same relay twice in one batch is one row
+        let a = relay("Anytown Aquatics", Some("A"), 200.0);
+        let relays = [
+            a.clone(),
+            a.clone(),
+            relay("Anytown Aquatics", None, 200.0),
+            relay("Riverside Swim Club", Some("A"), 200.0),
+        ];
+        assert_eq!(db.upsert_relay_times(&relays).await?, 3);
+        assert_eq!(db.count_relay_times().await?, 3);
+
+        assert_eq!(db.upsert_relay_times(&relays).await?, 0);
+
+        // Mutable columns are updated in place, and a known meet ID isn't
+        // forgotten by a later result without one
+        let updated = TopRelayTime {
+            rank: Some(2),
+            meet_id: Some(7),
+            ..a.clone()
+        };
+        assert_eq!(db.upsert_relay_times(&[updated]).await?, 1);
+        assert_eq!(db.upsert_relay_times(std::slice::from_ref(&a)).await?, 1);
+        assert_eq!(db.upsert_relay_times(std::slice::from_ref(&a)).await?, 0);
+        let (rank, meet_id): (i64, Option<i64>) = query_as(
+            "SELECT rank, meet_id FROM relay_times WHERE relay_name = 'A' AND team_id = 1",
+        )
+        .fetch_one(&db.pool)
+        .await?;
+        assert_eq!((rank, meet_id), (1, Some(7)));
+
+        // Any part of the key that differs makes a new row
+        let faster = relay("Anytown Aquatics", Some("A"), 199.5);
+        assert_eq!(db.upsert_relay_times(&[faster]).await?, 1);
+        assert_eq!(db.count_relay_times().await?, 4);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_relay_times_since() -> Result<()> {
+        let tmp = TempDb::new("relay-times-since");
+        let db = SqliteTimesDb::new(&tmp.url).await?;
+        db.migrate().await?;
+
+        let recent = TopRelayTime {
+            meet_id: Some(7),
+            ..relay("Anytown Aquatics", Some("A"), 200.0)
+        };
+        let older = TopRelayTime {
+            date: date().pred_opt(),
+            ..relay("Anytown Aquatics", Some("B"), 210.0)
+        };
+        let undated = TopRelayTime {
+            date: None,
+            ..relay("Riverside Swim Club", Some("A"), 205.5)
+        };
+        db.upsert_relay_times(&[recent.clone(), older.clone(), undated.clone()])
+            .await?;
+
+        assert_eq!(
+            db.relay_times_since(Some(date())).await?,
+            vec![recent.clone()]
+        );
+        let all = db.relay_times_since(None).await?;
+        assert_eq!(all.len(), 3);
+        assert!(all.contains(&older) && all.contains(&undated));
+        Ok(())
+    }
+}
@@ -0,0 +1,260 @@
+//! USA Swimming age groups, seasons, and swimmers' ages.
+//!
+//! A swimmer's age for a meet is their age on the meet's age-up date, usually
+//! its first day, not their age on the day of each swim. Results only record
+//! the age on the day of the swim, so a swimmer's birth date is narrowed down
+//! to a window from the ages seen in their results, which is often enough to
+//! know their age on any other date.
+
+use std::fmt;
+
+use chrono::{Datelike, Days, Months, NaiveDate};
+
+/// A standard age group.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum AgeGroup {
+    EightAndUnder,
+    NineTen,
+    ElevenTwelve,
+    ThirteenFourteen,
+    FifteenSixteen,
+    SeventeenEighteen,
+    /// Swimmers of any age, and the group of swimmers 19 and over
+    Open,
+}
+
+impl AgeGroup {
+    pub const ALL: [AgeGroup; 7] = [
+        AgeGroup::EightAndUnder,
+        AgeGroup::NineTen,
+        AgeGroup::ElevenTwelve,
+        AgeGroup::ThirteenFourteen,
+        AgeGroup::FifteenSixteen,
+        AgeGroup::SeventeenEighteen,
+        AgeGroup::Open,
+    ];
+
+    /// The age group a swimmer of `age` swims in.
+    pub fn of(age: u8) -> AgeGroup {
+        match age {
+            0..=8 => AgeGroup::EightAndUnder,
+            9..=10 => AgeGroup::NineTen,
+            11..=12 => AgeGroup::ElevenTwelve,
+            13..=14 => AgeGroup::ThirteenFourteen,
+            15..=16 => AgeGroup::FifteenSixteen,
+            17..=18 => AgeGroup::SeventeenEighteen,
+            _ => AgeGroup::Open,
+        }
+    }
+
+    /// Youngest age in the group, `None` for no lower limit.
+    pub fn min_age(&self) -> Option<u8> {
+        match self {
+            AgeGroup::EightAndUnder | AgeGroup::Open => None,
+            AgeGroup::NineTen => Some(9),
+            AgeGroup::ElevenTwelve => Some(11),
+            AgeGroup::ThirteenFourteen => Some(13),
+            AgeGroup::FifteenSixteen => Some(15),
+            AgeGroup::SeventeenEighteen => Some(17),
+        }
+    }
+
+    /// Oldest age in the group, `None` for no upper limit.
+    pub fn max_age(&self) -> Option<u8> {
+        match self {
+            AgeGroup::EightAndUnder => Some(8),
+            AgeGroup::NineTen => Some(10),
+            AgeGroup::ElevenTwelve => Some(12),
+            AgeGroup::ThirteenFourteen => Some(14),
+            AgeGroup::FifteenSixteen => Some(16),
+            AgeGroup::SeventeenEighteen => Some(18),
+            AgeGroup::Open => None,
+        }
+    }
+
+    /// Whether a swimmer of `age` may swim events for the group.
+    pub fn admits(&self, age: u8) -> bool {
+        self.min_age().is_none_or(|min| age >= min) && self.max_age().is_none_or(|max| age <= max)
+    }
+}
+
+impl fmt::Display for AgeGroup {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            AgeGroup::EightAndUnder => "8&U",
+            AgeGroup::NineTen => "9-10",
+            AgeGroup::ElevenTwelve => "11-12",
+            AgeGroup::ThirteenFourteen => "13-14",
+            AgeGroup::FifteenSixteen => "15-16",
+            AgeGroup::SeventeenEighteen => "17-18",
+            AgeGroup::Open => "Open",
+        };
+        f.write_str(s)
+    }
+}
+
+/// Age on `date` of someone born on `birth`. Someone born on 29 February
+/// turns a year older on 1 March in common years.
+pub fn age_on(birth: NaiveDate, date: NaiveDate) -> u8 {
+    let mut years = date.year() - birth.year();
+    if (date.month(), date.day()) < (birth.month(), birth.day()) {
+        years -= 1;
+    }
+    years.clamp(0, u8::MAX as i32) as u8
+}
+
+/// A swimming season, which runs from 1 September to 31 August.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Season {
+    /// Year the season starts in, 2024 for 2024-2025
+    pub start_year: i32,
+}
+
+impl Season {
+    /// The season `date` falls in.
+    pub fn of(date: NaiveDate) -> Season {
+        Season {
+            start_year: if date.month() >= 9 {
+                date.year()
+            } else {
+                date.year() - 1
+            },
+        }
+    }
+
+    /// First day of the season, 1 September.
+    pub fn start(&self) -> NaiveDate {
+        NaiveDate::from_ymd_opt(self.start_year, 9, 1).unwrap()
+    }
+
+    /// Last day of the season, 31 August.
+    pub fn end(&self) -> NaiveDate {
+        NaiveDate::from_ymd_opt(self.start_year + 1, 8, 31).unwrap()
+    }
+
+    pub fn contains(&self, date: NaiveDate) -> bool {
+        date >= self.start() && date <= self.end()
+    }
+
+    pub fn next(&self) -> Season {
+        Season {
+            start_year: self.start_year + 1,
+        }
+    }
+}
+
+impl fmt::Display for Season {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.start_year, self.start_year + 1)
+    }
+}
+
+/// The dates a swimmer may have been born on, from the first to the last.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BirthWindow {
+    pub earliest: NaiveDate,
+    pub latest: NaiveDate,
+}
+
+impl BirthWindow {
+    /// The birth dates of someone who was `age` on `date`.
+    pub fn from_age(date: NaiveDate, age: u8) -> Option<BirthWindow> {
+        let years = |n: u32| date.checked_sub_months(Months::new(12 * n));
+        Some(BirthWindow {
+            earliest: years(age as u32 + 1)?.checked_add_days(Days::new(1))?,
+            latest: years(age as u32)?,
+        })
+    }
+
+    /// Narrows the birth window down to the dates consistent with every
+    /// `(date, age)` observation, such as the date and age of each of a
+    /// swimmer's swims. `None` if there are no observations or they
+    /// contradict each other.
+    pub fn infer(observations: impl IntoIterator<Item = (NaiveDate, u8)>) -> Option<BirthWindow> {
+        let mut window: Option<BirthWindow> = None;
+        for (date, age) in observations {
+            let w = BirthWindow::from_age(date, age)?;
+            window = Some(match window {
+                None => w,
+                Some(prev) => BirthWindow {
+                    earliest: prev.earliest.max(w.earliest),
+                    latest: prev.latest.min(w.latest),
+                },
+            });
+        }
+        window.filter(|w| w.earliest <= w.latest)
+    }
+
+    /// Youngest and oldest the swimmer can be on `date`.
+    pub fn ages_on(&self, date: NaiveDate) -> (u8, u8) {
+        (age_on(self.latest, date), age_on(self.earliest, date))
+    }
+
+    /// Age on `date`, or `None` if the window doesn't settle it. A meet's
+    /// ages are those on its age-up date.
+    pub fn age_on(&self, date: NaiveDate) -> Option<u8> {
+        match self.ages_on(date) {
+            (youngest, oldest) if youngest == oldest => Some(youngest),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(s: &str) -> NaiveDate {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_age_group() {
+        assert_eq!(AgeGroup::of(6), AgeGroup::EightAndUnder);
+        assert_eq!(AgeGroup::of(10), AgeGroup::NineTen);
+        assert_eq!(AgeGroup::of(13), AgeGroup::ThirteenFourteen);
+        assert_eq!(AgeGroup::of(19), AgeGroup::Open);
+        assert_eq!(AgeGroup::of(12).to_string(), "11-12");
+        assert!(AgeGroup::Open.admits(12));
+        assert!(!AgeGroup::FifteenSixteen.admits(14));
+        for group in AgeGroup::ALL.iter().filter(|g| **g != AgeGroup::Open) {
+            let max = group.max_age().unwrap();
+            assert_eq!(AgeGroup::of(max), *group);
+        }
+    }
+
+    #[test]
+    fn test_age_and_season() {
+        let birth = date("2012-02-29");
+        assert_eq!(age_on(birth, date("2025-02-28")), 12);
+        assert_eq!(age_on(birth, date("2025-03-01")), 13);
+        assert_eq!(age_on(birth, date("2024-02-29")), 12);
+
+        let season = Season::of(date("2025-03-15"));
+        assert_eq!(season.to_string(), "2024-2025");
+        assert_eq!(season.start(), date("2024-09-01"));
+        assert_eq!(season.end(), date("2025-08-31"));
+        assert_eq!(Season::of(date("2025-09-01")), season.next());
+        assert!(!season.contains(date("2025-09-01")));
+    }
+
+    #[test]
+    fn test_birth_window() {
+        // 12 in March, 13 in June, and still 13 the next February
+        let window = BirthWindow::infer([
+            (date("2024-03-10"), 12),
+            (date("2024-06-01"), 13),
+            (date("2025-02-15"), 13),
+        ])
+        .unwrap();
+        assert_eq!(window.earliest, date("2011-03-11"));
+        assert_eq!(window.latest, date("2011-06-01"));
+        // Every possible birthday is past by July, so 13 on a July age-up date
+        assert_eq!(window.age_on(date("2024-07-20")), Some(13));
+        assert_eq!(window.age_on(date("2025-04-01")), None);
+        assert_eq!(window.ages_on(date("2025-04-01")), (13, 14));
+
+        assert!(BirthWindow::infer([(date("2024-03-10"), 12), (date("2024-03-11"), 14)]).is_none());
+        assert!(BirthWindow::infer([]).is_none());
+    }
+}
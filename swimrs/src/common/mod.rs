@@ -1,4 +1,4 @@
-use std::str::FromStr;
+use std::{fmt, str::FromStr};
 
 use anyhow::{bail, Error, Result};
 use num_enum::TryFromPrimitive;
@@ -96,6 +96,7 @@ pub enum Zone {
 pub enum LSC {
     #[strum(serialize = "All")]
     All,
+    #[serde(alias = "UN")]
     #[strum(serialize = "UN")]
     Unattached,
     #[strum(serialize = "AD")]
@@ -226,10 +227,10 @@ pub enum TimeType {
     Relay,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct SwimEvent(pub Distance, pub Stroke, pub Course);
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct SwimTime {
     pub seconds: f32,
     pub relay: bool,
@@ -279,6 +280,100 @@ impl FromStr for SwimTime {
     }
 }
 
+impl fmt::Display for SwimEvent {
+    /// Formats the event as it is parsed, like "100 FR SCY".
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}", self.0.clone() as u16, self.1, self.2)
+    }
+}
+
+/// `seconds` rounded to whole hundredths of a second, the precision times are
+/// recorded to, so that times that print the same compare equal.
+pub fn hundredths(seconds: f32) -> u32 {
+    (seconds * 100.0).round() as u32
+}
+
+impl SwimTime {
+    /// The time in hundredths of a second, as it is printed.
+    pub fn hundredths(&self) -> u32 {
+        hundredths(self.seconds)
+    }
+}
+
+impl fmt::Display for SwimTime {
+    /// Formats the time as it is parsed, like "19.79", "1:04.02" or
+    /// "1:04.02r".
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let hundredths = self.hundredths();
+        let (minutes, hundredths) = (hundredths / 6000, hundredths % 6000);
+        if minutes > 0 {
+            write!(f, "{}:{:02}", minutes, hundredths / 100)?;
+        } else {
+            write!(f, "{}", hundredths / 100)?;
+        }
+        write!(f, ".{:02}", hundredths % 100)?;
+        if self.relay {
+            write!(f, "r")?;
+        }
+        Ok(())
+    }
+}
+
+impl SwimEvent {
+    /// The equivalent event in `course`. Distances are the same, except that
+    /// the 500, 1000 and 1650 yard freestyles correspond to the 400, 800 and
+    /// 1500 meter freestyles. Returns `None` if the event isn't swum in
+    /// `course`, such as the 100 IM in long course.
+    pub fn in_course(&self, course: &Course) -> Option<SwimEvent> {
+        let SwimEvent(distance, stroke, from) = self;
+        if *course == Course::All || *from == Course::All {
+            return None;
+        }
+        let yards = *course == Course::SCY;
+        let distance = match (stroke, distance) {
+            (Stroke::Freestyle, Distance::_400 | Distance::_500) if yards => Distance::_500,
+            (Stroke::Freestyle, Distance::_800 | Distance::_1000) if yards => Distance::_1000,
+            (Stroke::Freestyle, Distance::_1500 | Distance::_1650) if yards => Distance::_1650,
+            (Stroke::Freestyle, Distance::_400 | Distance::_500) => Distance::_400,
+            (Stroke::Freestyle, Distance::_800 | Distance::_1000) => Distance::_800,
+            (Stroke::Freestyle, Distance::_1500 | Distance::_1650) => Distance::_1500,
+            (_, d) => d.clone(),
+        };
+        let event = SwimEvent(distance, stroke.clone(), course.clone());
+        match stroke {
+            Stroke::FreestyleRelay | Stroke::MedleyRelay => Some(event),
+            _ => VALID_EVENTS.contains(&event).then_some(event),
+        }
+    }
+
+    /// Converts a time swum in this event to the equivalent event in
+    /// `course`, using the commonly published factors: meter times are 1.11
+    /// times yard times over the same distance, 0.875 times over the 400 and
+    /// 800 freestyles and equal over the 1500, and long course times are
+    /// 1.02 times short course meter times.
+    pub fn convert(&self, seconds: f32, course: &Course) -> Option<(SwimEvent, f32)> {
+        let event = self.in_course(course)?;
+        let seconds = seconds / self.yards_factor() * event.yards_factor();
+        Some((event, seconds))
+    }
+
+    /// Ratio of a time in this event to the time in the equivalent yards
+    /// event.
+    fn yards_factor(&self) -> f32 {
+        let SwimEvent(distance, stroke, course) = self;
+        let meters = match (stroke, distance) {
+            (Stroke::Freestyle, Distance::_400 | Distance::_800) => 0.875,
+            (Stroke::Freestyle, Distance::_1500) => 1.0,
+            _ => 1.11,
+        };
+        match course {
+            Course::SCM => meters,
+            Course::LCM => meters * 1.02,
+            _ => 1.0,
+        }
+    }
+}
+
 pub const VALID_EVENTS: [SwimEvent; 53] = [
     // SCY
     SwimEvent(Distance::_50, Stroke::Freestyle, Course::SCY),
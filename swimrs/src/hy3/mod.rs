@@ -0,0 +1,472 @@
+//! Hy-Tek's HY3 format, the fixed-width results and entries files exported
+//! by Meet Manager and Team Manager, usually inside a `.zip`.
+//!
+//! Files are parsed with [`FromStr`](std::str::FromStr). HY3 isn't
+//! published, so only the records describing meets, teams, athletes, entries,
+//! results and splits are read, from the columns they are commonly found in:
+//! A1, B1, B2, C1, C2, D1, E1, E2, F1, F2, F3 and G1. Other records are
+//! skipped, and the checksums ending each record aren't checked.
+//!
+//! The parsed results can be turned into the same [`TopTime`] and
+//! [`TopRelayTime`] rows as Top Times searches with [`Hy3File::top_times`]
+//! and [`Hy3File::top_relay_times`].
+
+mod parse;
+
+use std::str::FromStr;
+
+use chrono::{Datelike, NaiveDate};
+
+use crate::{
+    common::{Course, Gender, Stroke, SwimEvent, LSC},
+    sdif::{Round, Time},
+    usas::toptimes::{TopRelayTime, TopTime},
+};
+
+/// An HY3 file, which holds a single meet.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hy3File {
+    pub header: Header,
+    pub meet: Meet,
+    pub teams: Vec<Team>,
+}
+
+/// The A1 record, describing the file itself.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Header {
+    /// Such as "Results From MM to TM"
+    pub description: Option<String>,
+    pub software_name: Option<String>,
+    pub software_version: Option<String>,
+    pub created: Option<NaiveDate>,
+}
+
+/// The meet, from the B1 and B2 records.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Meet {
+    pub name: String,
+    pub facility: Option<String>,
+    pub start_date: Option<NaiveDate>,
+    pub end_date: Option<NaiveDate>,
+    /// Date that ages for the meet's age groups are taken on
+    pub age_up_date: Option<NaiveDate>,
+    /// Feet above sea level
+    pub altitude: Option<u16>,
+    /// Course of the meet's pool
+    pub course: Option<Course>,
+}
+
+/// A team, from the C1 and C2 records, with the athletes and relays that
+/// follow it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Team {
+    pub code: String,
+    pub name: String,
+    pub short_name: Option<String>,
+    pub lsc: Option<String>,
+    pub city: Option<String>,
+    pub state: Option<String>,
+    pub country: Option<String>,
+    pub athletes: Vec<Athlete>,
+    pub relays: Vec<Relay>,
+}
+
+/// An athlete, from a D1 record, with the individual entries that follow.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Athlete {
+    /// Meet Manager's number for the athlete, which relay legs refer to
+    pub id: u32,
+    pub gender: Option<Gender>,
+    pub last_name: String,
+    pub first_name: String,
+    pub preferred_name: Option<String>,
+    pub middle_initial: Option<char>,
+    pub uss_id: Option<String>,
+    pub birth_date: Option<NaiveDate>,
+    /// Age on the meet's age-up date
+    pub age: Option<u8>,
+    pub entries: Vec<Entry>,
+}
+
+/// An entry in an event, from an E1 or F1 record, with the results of each
+/// round it was swum in.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    /// The event, in the course it was swum in
+    pub event: SwimEvent,
+    pub event_number: Option<String>,
+    pub event_gender: Gender,
+    pub min_age: Option<u8>,
+    pub max_age: Option<u8>,
+    pub seed: Option<Time>,
+    pub results: Vec<RoundResult>,
+}
+
+/// The result of a round, from an E2 or F2 record and the G1 records
+/// following it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RoundResult {
+    pub round: Round,
+    pub time: Time,
+    pub date: Option<NaiveDate>,
+    pub heat: Option<u8>,
+    pub lane: Option<u8>,
+    pub heat_place: Option<u16>,
+    pub place: Option<u16>,
+    pub points: Option<f32>,
+    pub splits: Vec<Split>,
+}
+
+/// A cumulative split. HY3 numbers splits rather than giving their
+/// distance, which depends on how the meet's timing system was set up.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Split {
+    pub number: u8,
+    pub seconds: f32,
+}
+
+/// A relay, from an F1 record and the F2 and F3 records following it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Relay {
+    /// Relay letter, such as `A`
+    pub name: String,
+    pub entry: Entry,
+    pub legs: Vec<RelayLeg>,
+}
+
+/// An athlete swimming a leg of a relay, from an F3 record.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RelayLeg {
+    /// ID of one of the team's athletes
+    pub athlete_id: u32,
+    pub round: Round,
+    /// Leg from 1 to 4. Alternates have leg 0.
+    pub leg: u8,
+}
+
+impl Athlete {
+    /// Name as Top Times gives it, such as "Swimmer, Alpha".
+    pub fn name(&self) -> String {
+        format!("{}, {}", self.last_name, self.first_name)
+    }
+
+    /// Age on `date`, from the athlete's birth date or failing that the age
+    /// given for the meet.
+    pub fn age_on(&self, date: NaiveDate) -> Option<u8> {
+        match self.birth_date {
+            Some(birth) => {
+                let had_birthday = (date.month(), date.day()) >= (birth.month(), birth.day());
+                let age = date.year() - birth.year() - i32::from(!had_birthday);
+                u8::try_from(age).ok()
+            }
+            None => self.age,
+        }
+    }
+}
+
+impl Team {
+    pub fn athlete(&self, id: u32) -> Option<&Athlete> {
+        self.athletes.iter().find(|a| a.id == id)
+    }
+}
+
+impl Entry {
+    /// Age group as Top Times gives it, such as "11-12" or "15 & Over".
+    pub fn age_group(&self) -> Option<String> {
+        match (self.min_age, self.max_age) {
+            (Some(min), Some(max)) => Some(format!("{}-{}", min, max)),
+            (Some(min), None) => Some(format!("{} & Over", min)),
+            (None, Some(max)) => Some(format!("{} & Under", max)),
+            (None, None) => None,
+        }
+    }
+}
+
+impl Hy3File {
+    /// Date a round was swum, taken to be the first day of the meet where
+    /// the file doesn't say.
+    fn date(&self, result: &RoundResult) -> Option<NaiveDate> {
+        result.date.or(self.meet.start_date)
+    }
+
+    /// Every individual swim with a time, as Top Times would list it. Swims
+    /// by athletes whose age isn't known are left out.
+    pub fn top_times(&self) -> Vec<TopTime> {
+        let mut times = Vec::new();
+        for team in &self.teams {
+            let lsc = team.lsc.as_deref().and_then(|l| LSC::from_str(l).ok());
+            for athlete in &team.athletes {
+                for entry in &athlete.entries {
+                    for result in &entry.results {
+                        let (time, course) = match &result.time {
+                            Time::Swum(time, course) => (time, course),
+                            _ => continue,
+                        };
+                        let date = match self.date(result) {
+                            Some(date) => date,
+                            None => continue,
+                        };
+                        let age = match athlete.age_on(date) {
+                            Some(age) => age,
+                            None => continue,
+                        };
+                        times.push(TopTime {
+                            age,
+                            course: course.clone(),
+                            date,
+                            distance: entry.event.0.clone(),
+                            foreign: None,
+                            gender: athlete
+                                .gender
+                                .clone()
+                                .unwrap_or_else(|| entry.event_gender.clone()),
+                            lsc: lsc.clone(),
+                            meet_id: None,
+                            meet_name: self.meet.name.clone(),
+                            power_points: None,
+                            rank: None,
+                            relay: false,
+                            sanctioned: None,
+                            stroke: entry.event.1.clone(),
+                            swimmer_id: None,
+                            swimmer_name: athlete.name(),
+                            team_name: team.name.clone(),
+                            time: time.seconds,
+                            time_alt_adj: None,
+                            time_id: None,
+                            time_standard: None,
+                        });
+                    }
+                }
+            }
+        }
+        times
+    }
+
+    /// Every relay swim with a time, as Top Times would list it.
+    pub fn top_relay_times(&self) -> Vec<TopRelayTime> {
+        let mut times = Vec::new();
+        for team in &self.teams {
+            let lsc = team.lsc.as_deref().and_then(|l| LSC::from_str(l).ok());
+            for relay in &team.relays {
+                for result in &relay.entry.results {
+                    let (time, course) = match &result.time {
+                        Time::Swum(time, course) => (time, course),
+                        _ => continue,
+                    };
+                    times.push(TopRelayTime {
+                        age_group: relay.entry.age_group(),
+                        course: course.clone(),
+                        date: self.date(result),
+                        distance: relay.entry.event.0.clone(),
+                        gender: relay.entry.event_gender.clone(),
+                        lsc: lsc.clone(),
+                        meet_id: None,
+                        meet_name: self.meet.name.clone(),
+                        rank: None,
+                        relay_name: Some(relay.name.clone()),
+                        sanctioned: None,
+                        stroke: relay.entry.event.1.clone(),
+                        team_name: team.name.clone(),
+                        time: time.seconds,
+                        time_standard: None,
+                    });
+                }
+            }
+        }
+        times
+    }
+}
+
+/// Course codes, as in SDIF.
+fn course_from_code(code: char) -> Option<Course> {
+    match code {
+        'S' => Some(Course::SCM),
+        'Y' => Some(Course::SCY),
+        'L' => Some(Course::LCM),
+        _ => None,
+    }
+}
+
+/// Strokes are lettered from `A` for freestyle to `E` for the IM. Relays
+/// use `A` and `E` for freestyle and medley relays.
+fn stroke_from_code(code: char, relay: bool) -> Option<Stroke> {
+    Some(match (code, relay) {
+        ('A', false) => Stroke::Freestyle,
+        ('B', false) => Stroke::Backstroke,
+        ('C', false) => Stroke::Breaststroke,
+        ('D', false) => Stroke::Butterfly,
+        ('E', false) => Stroke::IndividualMedley,
+        ('A', true) => Stroke::FreestyleRelay,
+        ('E', true) => Stroke::MedleyRelay,
+        _ => return None,
+    })
+}
+
+/// Athletes are `M` or `F`. Events may also be `X`, for mixed events.
+fn gender_from_code(code: char) -> Option<Gender> {
+    match code {
+        'M' => Some(Gender::Male),
+        'F' | 'W' => Some(Gender::Female),
+        'X' => Some(Gender::Mixed),
+        _ => None,
+    }
+}
+
+fn round_from_code(code: char) -> Option<Round> {
+    match code {
+        'P' => Some(Round::Prelims),
+        'S' => Some(Round::SwimOff),
+        'F' => Some(Round::Finals),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::{Distance, SwimTime};
+
+    fn fixture() -> Hy3File {
+        let text = std::fs::read_to_string("testdata/hy3_results.hy3").unwrap();
+        Hy3File::from_str(&text).unwrap()
+    }
+
+    #[test]
+    fn test_parse_hy3() {
+        let file = fixture();
+        assert_eq!(
+            file.header.description.as_deref(),
+            Some("Results From MM to TM")
+        );
+        assert_eq!(file.header.software_name.as_deref(), Some("Hy-Tek, Ltd"));
+
+        let meet = &file.meet;
+        assert_eq!(meet.name, "Anytown Fall Classic");
+        assert_eq!(meet.course, Some(Course::SCY));
+        assert_eq!(
+            meet.age_up_date,
+            Some(NaiveDate::from_ymd_opt(2023, 10, 20).unwrap())
+        );
+        assert_eq!(meet.altitude, Some(120));
+
+        assert_eq!(file.teams.len(), 2);
+        let team = &file.teams[0];
+        assert_eq!(team.code, "ANYT");
+        assert_eq!(team.lsc.as_deref(), Some("PC"));
+        assert_eq!(team.city.as_deref(), Some("Anytown"));
+        assert_eq!(team.athletes.len(), 6);
+
+        let athlete = &team.athletes[0];
+        assert_eq!(athlete.id, 101);
+        assert_eq!(athlete.name(), "Swimmer, Alpha");
+        assert_eq!(athlete.preferred_name.as_deref(), Some("Ally"));
+        assert_eq!(athlete.middle_initial, Some('A'));
+        assert_eq!(athlete.gender, Some(Gender::Female));
+        assert_eq!(athlete.uss_id.as_deref(), Some("102109ALPASWIM"));
+        assert_eq!(athlete.age, Some(13));
+        assert_eq!(athlete.entries.len(), 3);
+
+        let entry = &athlete.entries[0];
+        assert_eq!(
+            entry.event,
+            SwimEvent(Distance::_100, Stroke::Freestyle, Course::SCY)
+        );
+        assert_eq!(entry.event_number.as_deref(), Some("12"));
+        assert_eq!((entry.min_age, entry.max_age), (Some(13), Some(14)));
+        assert_eq!(entry.age_group().as_deref(), Some("13-14"));
+        assert!(matches!(entry.seed, Some(Time::Swum(_, Course::SCY))));
+        assert_eq!(entry.results.len(), 2);
+        let prelims = &entry.results[0];
+        assert_eq!(prelims.round, Round::Prelims);
+        assert_eq!(prelims.time.seconds(), Some(61.1));
+        assert_eq!((prelims.heat, prelims.lane), (Some(3), Some(4)));
+        assert_eq!(prelims.splits.len(), 2);
+        let finals = &entry.results[1];
+        assert_eq!(finals.round, Round::Finals);
+        assert_eq!(finals.place, Some(1));
+        assert_eq!(finals.points, Some(20.0));
+        assert_eq!(
+            finals.date,
+            Some(NaiveDate::from_ymd_opt(2023, 10, 21).unwrap())
+        );
+        assert_eq!(
+            finals.splits,
+            vec![
+                Split {
+                    number: 1,
+                    seconds: 29.1
+                },
+                Split {
+                    number: 2,
+                    seconds: 60.55
+                }
+            ]
+        );
+
+        // A seed of 0.00 is no time, and disqualified swims keep no time
+        let entry = &athlete.entries[1];
+        assert_eq!(entry.seed, Some(Time::NoTime));
+        assert_eq!(entry.results[0].time, Time::Disqualified);
+        assert_eq!(entry.age_group().as_deref(), Some("13 & Over"));
+
+        // A mile's splits span several G1 records
+        let entry = &athlete.entries[2];
+        assert_eq!(entry.event.0, Distance::_1650);
+        let splits = &entry.results[0].splits;
+        assert_eq!(splits.len(), 33);
+        assert_eq!(splits[32].number, 33);
+        assert!((splits[32].seconds - 1092.34).abs() < 0.01);
+
+        let relay = &team.relays[0];
+        assert_eq!(relay.name, "A");
+        assert_eq!(relay.entry.event.1, Stroke::MedleyRelay);
+        assert_eq!(relay.entry.event_gender, Gender::Female);
+        assert_eq!(relay.entry.results[0].place, Some(3));
+        assert_eq!(relay.entry.results[0].splits.len(), 4);
+        assert_eq!(relay.legs.len(), 5);
+        assert_eq!(relay.legs[0].athlete_id, 102);
+        assert_eq!(relay.legs[3].leg, 4);
+        assert_eq!(relay.legs[4].leg, 0);
+        assert!(team.athlete(relay.legs[1].athlete_id).is_some());
+
+        // Seed times may be in another course than the meet
+        let entry = &file.teams[1].athletes[0].entries[0];
+        assert!(matches!(entry.seed, Some(Time::Swum(_, Course::LCM))));
+        assert_eq!(entry.results[0].time.seconds(), Some(121.99));
+    }
+
+    #[test]
+    fn test_hy3_top_times() {
+        let file = fixture();
+        let times = file.top_times();
+        // Two rounds of the 100 free, the mile, and the other team's 200 IM
+        assert_eq!(times.len(), 4);
+        let t = &times[1];
+        assert_eq!(t.swimmer_name, "Swimmer, Alpha");
+        assert_eq!(t.team_name, "Anytown Aquatics");
+        assert_eq!(t.lsc, Some(LSC::PC));
+        assert_eq!(t.meet_name, "Anytown Fall Classic");
+        assert_eq!(t.gender, Gender::Female);
+        assert_eq!(t.date, NaiveDate::from_ymd_opt(2023, 10, 21).unwrap());
+        assert_eq!(
+            (t.distance.clone(), t.stroke.clone()),
+            (Distance::_100, Stroke::Freestyle)
+        );
+        assert_eq!(t.course, Course::SCY);
+        assert_eq!(t.time, 60.55);
+        assert!(!t.relay);
+        // Ages are taken on the day of the swim, not the age-up date
+        assert_eq!(t.age, 14);
+        assert_eq!(file.teams[0].athletes[0].age, Some(13));
+
+        let relays = file.top_relay_times();
+        assert_eq!(relays.len(), 1);
+        let r = &relays[0];
+        assert_eq!(r.relay_name.as_deref(), Some("A"));
+        assert_eq!(r.age_group.as_deref(), Some("11-12"));
+        assert_eq!(r.stroke, Stroke::MedleyRelay);
+        assert_eq!(r.gender, Gender::Female);
+        assert_eq!(r.time, SwimTime::from_str("2:10.44").unwrap().seconds);
+    }
+}
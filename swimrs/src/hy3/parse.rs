@@ -0,0 +1,352 @@
+use std::{error::Error as StdError, str::FromStr};
+
+use anyhow::{bail, Context, Error, Result};
+use chrono::NaiveDate;
+use num_enum::TryFromPrimitive;
+
+use super::{
+    course_from_code, gender_from_code, round_from_code, stroke_from_code, Athlete, Entry, Header,
+    Hy3File, Meet, Relay, RelayLeg, RoundResult, Split, Team,
+};
+use crate::{
+    common::{Course, Distance, SwimEvent, SwimTime},
+    sdif::Time,
+};
+
+const DATE_FMT: &str = "%m%d%Y";
+
+/// Number of splits in each G1 record.
+const SPLITS_PER_RECORD: usize = 10;
+
+/// Number of athletes in each F3 record.
+const LEGS_PER_RECORD: usize = 8;
+
+/// A fixed-width record. Columns are numbered from 1.
+struct Record<'a>(&'a str);
+
+impl Record<'_> {
+    fn text(&self, col: usize, len: usize) -> String {
+        self.0
+            .chars()
+            .skip(col - 1)
+            .take(len)
+            .collect::<String>()
+            .trim()
+            .to_owned()
+    }
+
+    fn opt(&self, col: usize, len: usize) -> Option<String> {
+        Some(self.text(col, len)).filter(|t| !t.is_empty())
+    }
+
+    fn char(&self, col: usize) -> Option<char> {
+        self.0.chars().nth(col - 1).filter(|c| !c.is_whitespace())
+    }
+
+    fn num<T>(&self, col: usize, len: usize) -> Result<Option<T>>
+    where
+        T: FromStr,
+        T::Err: StdError + Send + Sync + 'static,
+    {
+        match self.opt(col, len) {
+            Some(n) => Ok(Some(
+                n.parse()
+                    .with_context(|| format!("invalid number: {}", n))?,
+            )),
+            None => Ok(None),
+        }
+    }
+
+    fn date(&self, col: usize) -> Result<Option<NaiveDate>> {
+        match self.opt(col, 8) {
+            Some(d) => Ok(Some(
+                NaiveDate::parse_from_str(&d, DATE_FMT)
+                    .with_context(|| format!("invalid date: {}", d))?,
+            )),
+            None => Ok(None),
+        }
+    }
+
+    /// Reads a time and its course code. Hy-Tek writes `0.00` where there
+    /// is no time.
+    fn time(&self, col: usize, course_col: usize) -> Result<Time> {
+        let text = match self.opt(col, 8) {
+            Some(t) => t,
+            None => return Ok(Time::NoTime),
+        };
+        let time = SwimTime::from_str(&text)?;
+        if time.seconds == 0.0 {
+            return Ok(Time::NoTime);
+        }
+        let course = self
+            .char(course_col)
+            .and_then(course_from_code)
+            .with_context(|| format!("no course for time {}", text))?;
+        Ok(Time::Swum(time, course))
+    }
+
+    /// Reads the event columns shared by E1 and F1 records, from the event's
+    /// sex code in column 15 to the seed time.
+    fn entry(&self, relay: bool, meet_course: Option<&Course>) -> Result<Entry> {
+        let event_gender = self
+            .char(15)
+            .and_then(gender_from_code)
+            .context("missing event sex code")?;
+        let distance: u16 = self.num(16, 6)?.context("missing event distance")?;
+        let distance = Distance::try_from_primitive(distance)?;
+        let stroke = self
+            .char(22)
+            .and_then(|c| stroke_from_code(c, relay))
+            .context("missing stroke code")?;
+        let min_age: Option<u8> = self.num(23, 3)?;
+        let max_age: Option<u8> = self.num(26, 3)?;
+        let seed = self.time(43, 51)?;
+        // The course the event was swum in is taken from its first result,
+        // but until then the meet's course is the best guess
+        let course = match (meet_course, &seed) {
+            (Some(course), _) | (None, Time::Swum(_, course)) => course.clone(),
+            _ => Course::All,
+        };
+        Ok(Entry {
+            event: SwimEvent(distance, stroke, course),
+            event_number: self.opt(39, 4),
+            event_gender,
+            min_age: min_age.filter(|a| *a > 0),
+            // Events open at the top are aged up to 109
+            max_age: max_age.filter(|a| *a > 0 && *a < 109),
+            seed: Some(seed),
+            results: Vec::new(),
+        })
+    }
+
+    /// Reads the result of a round from an E2 or F2 record.
+    fn result(&self) -> Result<RoundResult> {
+        let round = self
+            .char(3)
+            .and_then(round_from_code)
+            .context("missing round code")?;
+        let time = match self.char(13) {
+            Some('Q') | Some('F') => Time::Disqualified,
+            Some('R') => Time::NoSwim,
+            Some('S') => Time::Scratch,
+            Some('D') => Time::DidNotFinish,
+            _ => self.time(4, 12)?,
+        };
+        Ok(RoundResult {
+            round,
+            time,
+            date: self.date(103)?,
+            heat: self.num(21, 3)?,
+            lane: self.num(24, 3)?,
+            heat_place: self.num(27, 3)?.filter(|p| *p > 0),
+            place: self.num(30, 3)?.filter(|p| *p > 0),
+            points: self.num(89, 6)?.filter(|p| *p > 0.0),
+            splits: Vec::new(),
+        })
+    }
+}
+
+/// Which entry the E2, F2, F3 and G1 records that follow belong to.
+enum Last {
+    None,
+    Swim,
+    Relay,
+}
+
+struct Parser {
+    header: Option<Header>,
+    meet: Option<Meet>,
+    teams: Vec<Team>,
+    last: Last,
+}
+
+impl Parser {
+    fn meet(&mut self) -> Result<&mut Meet> {
+        self.meet.as_mut().context("record before the B1 record")
+    }
+
+    fn team(&mut self) -> Result<&mut Team> {
+        self.teams
+            .last_mut()
+            .context("record before the first C1 record")
+    }
+
+    fn athlete(&mut self) -> Result<&mut Athlete> {
+        self.team()?
+            .athletes
+            .last_mut()
+            .context("record before the team's first D1 record")
+    }
+
+    fn relay(&mut self) -> Result<&mut Relay> {
+        match self.last {
+            Last::Relay => Ok(self.team()?.relays.last_mut().unwrap()),
+            _ => bail!("record doesn't follow an F1 record"),
+        }
+    }
+
+    fn entry(&mut self) -> Result<&mut Entry> {
+        match self.last {
+            Last::Swim => Ok(self.athlete()?.entries.last_mut().unwrap()),
+            Last::Relay => Ok(&mut self.relay()?.entry),
+            Last::None => bail!("record doesn't follow an E1 or F1 record"),
+        }
+    }
+
+    fn record(&mut self, r: &Record) -> Result<()> {
+        match &r.text(1, 2)[..] {
+            "A1" => {
+                self.header = Some(Header {
+                    description: r.opt(5, 25),
+                    software_name: r.opt(30, 15),
+                    software_version: r.opt(45, 14),
+                    created: r.date(59)?,
+                });
+            }
+            "B1" => {
+                self.meet = Some(Meet {
+                    name: r.text(3, 45),
+                    facility: r.opt(48, 45),
+                    start_date: r.date(93)?,
+                    end_date: r.date(101)?,
+                    age_up_date: r.date(109)?,
+                    altitude: r.num(117, 5)?,
+                    course: None,
+                });
+            }
+            "B2" => self.meet()?.course = r.char(99).and_then(course_from_code),
+            "C1" => {
+                self.teams.push(Team {
+                    code: r.text(3, 5),
+                    name: r.text(8, 30),
+                    short_name: r.opt(38, 16),
+                    lsc: r.opt(54, 2),
+                    city: None,
+                    state: None,
+                    country: None,
+                    athletes: Vec::new(),
+                    relays: Vec::new(),
+                });
+                self.last = Last::None;
+            }
+            "C2" => {
+                let team = self.team()?;
+                team.city = r.opt(63, 30);
+                team.state = r.opt(93, 2);
+                team.country = r.opt(105, 3);
+            }
+            "D1" => {
+                let athlete = Athlete {
+                    id: r.num(4, 5)?.context("missing athlete ID")?,
+                    gender: r.char(3).and_then(gender_from_code),
+                    last_name: r.text(9, 20),
+                    first_name: r.text(29, 20),
+                    preferred_name: r.opt(49, 20),
+                    middle_initial: r.char(69),
+                    uss_id: r.opt(70, 14),
+                    birth_date: r.date(89)?,
+                    age: r.num(97, 3)?,
+                    entries: Vec::new(),
+                };
+                self.team()?.athletes.push(athlete);
+                self.last = Last::None;
+            }
+            "E1" => {
+                let course = self.meet()?.course.clone();
+                let entry = r.entry(false, course.as_ref())?;
+                let id: Option<u32> = r.num(4, 5)?;
+                let athlete = self.athlete()?;
+                if id != Some(athlete.id) {
+                    bail!("E1 record doesn't follow the D1 record of its athlete");
+                }
+                athlete.entries.push(entry);
+                self.last = Last::Swim;
+            }
+            "E2" | "F2" => {
+                let result = r.result()?;
+                let entry = self.entry()?;
+                if let (true, Time::Swum(_, course)) = (entry.results.is_empty(), &result.time) {
+                    entry.event.2 = course.clone();
+                }
+                entry.results.push(result);
+            }
+            "F1" => {
+                let course = self.meet()?.course.clone();
+                let relay = Relay {
+                    name: r.text(8, 1),
+                    entry: r.entry(true, course.as_ref())?,
+                    legs: Vec::new(),
+                };
+                self.team()?.relays.push(relay);
+                self.last = Last::Relay;
+            }
+            "F3" => {
+                let relay = self.relay()?;
+                for i in 0..LEGS_PER_RECORD {
+                    let col = 3 + 13 * i;
+                    let athlete_id = match r.num(col + 1, 5)? {
+                        Some(id) => id,
+                        None => continue,
+                    };
+                    relay.legs.push(RelayLeg {
+                        athlete_id,
+                        round: r
+                            .char(col + 11)
+                            .and_then(round_from_code)
+                            .context("missing relay leg round")?,
+                        // Alternates are given as leg 0
+                        leg: r.num(col + 12, 1)?.unwrap_or(0),
+                    });
+                }
+            }
+            "G1" => {
+                let result = self
+                    .entry()?
+                    .results
+                    .last_mut()
+                    .context("G1 record doesn't follow an E2 or F2 record")?;
+                for i in 0..SPLITS_PER_RECORD {
+                    let col = 3 + 11 * i;
+                    let number = match r.num(col + 1, 2)? {
+                        Some(n) => n,
+                        None => continue,
+                    };
+                    let time = r.text(col + 3, 8);
+                    result.splits.push(Split {
+                        number,
+                        seconds: SwimTime::from_str(&time)?.seconds,
+                    });
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Hy3File {
+    type Err = Error;
+
+    /// Parses the records of an HY3 file, one per line.
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parser = Parser {
+            header: None,
+            meet: None,
+            teams: Vec::new(),
+            last: Last::None,
+        };
+        for (i, line) in s.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            parser
+                .record(&Record(line))
+                .with_context(|| format!("error parsing HY3 record on line {}", i + 1))?;
+        }
+        Ok(Hy3File {
+            header: parser.header.context("HY3 file has no A1 record")?,
+            meet: parser.meet.context("HY3 file has no B1 record")?,
+            teams: parser.teams,
+        })
+    }
+}
@@ -0,0 +1,490 @@
+//! LENEX version 3, the XML format of meet entries and results used by
+//! European meet managers. Files are `.lef`, or `.lxf` when zipped.
+//!
+//! Files are parsed with [`FromStr`](std::str::FromStr) and written with
+//! [`Display`](std::fmt::Display), or read and written zipped with
+//! [`Lenex::from_lxf`] and [`Lenex::write_lxf`]. Meets, sessions, events,
+//! age groups and their rankings, clubs, athletes, entries, results and
+//! splits are read. Other elements, such as officials and records, are
+//! skipped.
+
+mod parse;
+mod write;
+
+use chrono::NaiveDate;
+
+use crate::common::{Course, Gender, Stroke, SwimEvent, SwimTime};
+
+/// A LENEX document.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lenex {
+    pub version: String,
+    pub constructor: Constructor,
+    pub meets: Vec<Meet>,
+}
+
+/// The software that wrote a document.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Constructor {
+    pub name: String,
+    pub version: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Meet {
+    pub name: String,
+    pub city: Option<String>,
+    /// Three-letter country code
+    pub nation: Option<String>,
+    /// Course of the meet's pool. Pools other than the standard three, such
+    /// as 20 m pools, have no course.
+    pub course: Option<Course>,
+    pub age_date: Option<AgeDate>,
+    pub sessions: Vec<Session>,
+    pub clubs: Vec<Club>,
+}
+
+/// How ages are calculated for the meet's age groups.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AgeDate {
+    /// Such as `YEAR` for age at the end of the year, or `DATE` for age on
+    /// `value`
+    pub kind: String,
+    pub value: NaiveDate,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Session {
+    pub number: u16,
+    pub date: NaiveDate,
+    pub name: Option<String>,
+    /// Course of the session, where it differs from the meet's
+    pub course: Option<Course>,
+    pub events: Vec<Event>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    /// Unique across the meet, and referred to by entries and results
+    pub id: u32,
+    pub number: Option<u16>,
+    /// Sex of the event, or `None` for events open to all
+    pub gender: Option<Gender>,
+    pub round: Option<Round>,
+    /// The event, in the course of its session. Relays have their full
+    /// distance, such as 200 for a 4x50.
+    pub event: SwimEvent,
+    /// Number of swimmers in a relay, or 1
+    pub relay_count: u8,
+    pub age_groups: Vec<AgeGroup>,
+}
+
+/// Round of an event.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Round {
+    /// Timed finals (TIM)
+    Timed,
+    /// Fastest heat of timed finals (FHT)
+    FastHeat,
+    /// Finals (FIN)
+    Final,
+    /// Semi-finals (SEM)
+    SemiFinal,
+    /// Quarter-finals (QUA)
+    QuarterFinal,
+    /// Prelims (PRE)
+    Prelims,
+    /// Swim-off after prelims (SOP)
+    SwimOffPrelims,
+    /// Swim-off after semi-finals (SOS)
+    SwimOffSemiFinal,
+    /// Swim-off after quarter-finals (SOQ)
+    SwimOffQuarterFinal,
+}
+
+/// An age group of an event, with the places of its results.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AgeGroup {
+    pub id: u32,
+    /// Youngest age, or `None` where there is no limit
+    pub min: Option<u8>,
+    /// Oldest age, or `None` where there is no limit
+    pub max: Option<u8>,
+    pub gender: Option<Gender>,
+    pub name: Option<String>,
+    pub rankings: Vec<Ranking>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ranking {
+    pub place: u16,
+    pub result_id: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Club {
+    pub name: String,
+    pub short_name: Option<String>,
+    pub code: Option<String>,
+    pub nation: Option<String>,
+    pub region: Option<String>,
+    pub athletes: Vec<Athlete>,
+    pub relays: Vec<Relay>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Athlete {
+    /// Unique across the meet, and referred to by relay positions
+    pub id: u32,
+    pub last_name: String,
+    pub first_name: String,
+    pub gender: Gender,
+    pub birth_date: Option<NaiveDate>,
+    /// Registration number with the athlete's federation
+    pub license: Option<String>,
+    pub nation: Option<String>,
+    pub entries: Vec<Entry>,
+    pub results: Vec<SwimResult>,
+}
+
+/// An entry in an event, with its entry time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub event_id: u32,
+    /// Entry time, or `None` for no time (NT)
+    pub entry_time: Option<SwimTime>,
+    /// Course of the entry time, where it differs from the meet's
+    pub entry_course: Option<Course>,
+}
+
+/// The result of an individual or relay swim in an event.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SwimResult {
+    /// Unique across the meet, and referred to by rankings
+    pub id: u32,
+    pub event_id: u32,
+    /// Time swum, or `None` for no time (NT)
+    pub swim_time: Option<SwimTime>,
+    pub status: Option<Status>,
+    /// FINA points
+    pub points: Option<u16>,
+    pub heat_id: Option<u32>,
+    pub lane: Option<u8>,
+    pub splits: Vec<Split>,
+    /// Swimmers of a relay, in order
+    pub relay_positions: Vec<RelayPosition>,
+}
+
+/// Why a result has no time, or doesn't count.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Status {
+    /// Disqualified (DSQ)
+    Disqualified,
+    /// Did not start (DNS)
+    DidNotStart,
+    /// Did not finish (DNF)
+    DidNotFinish,
+    /// Withdrawn through sickness (SICK)
+    Sick,
+    /// Withdrawn (WDR)
+    Withdrawn,
+    /// Swum out of competition (EXH)
+    Exhibition,
+}
+
+/// A cumulative split.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Split {
+    pub distance: u16,
+    pub seconds: f32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Relay {
+    /// Number of the club's relay team in its events, from 1
+    pub number: Option<u8>,
+    pub gender: Gender,
+    pub age_min: Option<u8>,
+    pub age_max: Option<u8>,
+    pub name: Option<String>,
+    pub results: Vec<SwimResult>,
+}
+
+/// A swimmer of a relay.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RelayPosition {
+    /// ID of one of the club's athletes
+    pub athlete_id: u32,
+    /// Leg from 1, or 0 for an alternate
+    pub number: u8,
+}
+
+impl Round {
+    fn from_code(code: &str) -> Option<Self> {
+        Some(match code {
+            "TIM" => Round::Timed,
+            "FHT" => Round::FastHeat,
+            "FIN" => Round::Final,
+            "SEM" => Round::SemiFinal,
+            "QUA" => Round::QuarterFinal,
+            "PRE" => Round::Prelims,
+            "SOP" => Round::SwimOffPrelims,
+            "SOS" => Round::SwimOffSemiFinal,
+            "SOQ" => Round::SwimOffQuarterFinal,
+            _ => return None,
+        })
+    }
+
+    fn code(&self) -> &'static str {
+        match self {
+            Round::Timed => "TIM",
+            Round::FastHeat => "FHT",
+            Round::Final => "FIN",
+            Round::SemiFinal => "SEM",
+            Round::QuarterFinal => "QUA",
+            Round::Prelims => "PRE",
+            Round::SwimOffPrelims => "SOP",
+            Round::SwimOffSemiFinal => "SOS",
+            Round::SwimOffQuarterFinal => "SOQ",
+        }
+    }
+}
+
+impl Status {
+    fn from_code(code: &str) -> Option<Self> {
+        Some(match code {
+            "DSQ" => Status::Disqualified,
+            "DNS" => Status::DidNotStart,
+            "DNF" => Status::DidNotFinish,
+            "SICK" => Status::Sick,
+            "WDR" => Status::Withdrawn,
+            "EXH" => Status::Exhibition,
+            _ => return None,
+        })
+    }
+
+    fn code(&self) -> &'static str {
+        match self {
+            Status::Disqualified => "DSQ",
+            Status::DidNotStart => "DNS",
+            Status::DidNotFinish => "DNF",
+            Status::Sick => "SICK",
+            Status::Withdrawn => "WDR",
+            Status::Exhibition => "EXH",
+        }
+    }
+}
+
+/// Pools other than 25 m, 50 m and 25 yd, such as `SCM20`, have no
+/// [`Course`].
+fn course_from_code(code: &str) -> Option<Course> {
+    match code {
+        "SCM" => Some(Course::SCM),
+        "SCY" => Some(Course::SCY),
+        "LCM" => Some(Course::LCM),
+        _ => None,
+    }
+}
+
+fn course_code(course: &Course) -> Option<&'static str> {
+    match course {
+        Course::SCM => Some("SCM"),
+        Course::SCY => Some("SCY"),
+        Course::LCM => Some("LCM"),
+        Course::All => None,
+    }
+}
+
+/// LENEX tells relays from individual events by their number of swimmers,
+/// so `MEDLEY` is the IM for one swimmer and a medley relay for more.
+fn stroke_from_code(code: &str, relay_count: u8) -> Option<Stroke> {
+    Some(match (code, relay_count > 1) {
+        ("FREE", false) => Stroke::Freestyle,
+        ("BACK", false) => Stroke::Backstroke,
+        ("BREAST", false) => Stroke::Breaststroke,
+        ("FLY", false) => Stroke::Butterfly,
+        ("MEDLEY", false) => Stroke::IndividualMedley,
+        ("FREE", true) => Stroke::FreestyleRelay,
+        ("MEDLEY", true) => Stroke::MedleyRelay,
+        _ => return None,
+    })
+}
+
+fn stroke_code(stroke: &Stroke) -> Option<&'static str> {
+    match stroke {
+        Stroke::Freestyle | Stroke::FreestyleRelay => Some("FREE"),
+        Stroke::Backstroke => Some("BACK"),
+        Stroke::Breaststroke => Some("BREAST"),
+        Stroke::Butterfly => Some("FLY"),
+        Stroke::IndividualMedley | Stroke::MedleyRelay => Some("MEDLEY"),
+        Stroke::All => None,
+    }
+}
+
+/// Athletes are `M` or `F`. Events may also be `X`, for mixed events, or
+/// `A` for events open to all, which have no [`Gender`].
+fn gender_from_code(code: &str) -> Option<Gender> {
+    match code {
+        "M" => Some(Gender::Male),
+        "F" => Some(Gender::Female),
+        "X" => Some(Gender::Mixed),
+        _ => None,
+    }
+}
+
+fn gender_code(gender: &Gender) -> &'static str {
+    match gender {
+        Gender::Male => "M",
+        Gender::Female => "F",
+        Gender::Mixed => "X",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Cursor, str::FromStr};
+
+    use super::*;
+    use crate::common::Distance;
+
+    fn fixture() -> Lenex {
+        let text = std::fs::read_to_string("testdata/lenex_results.lef").unwrap();
+        Lenex::from_str(&text).unwrap()
+    }
+
+    #[test]
+    fn test_parse_lenex() {
+        let lenex = fixture();
+        assert_eq!(lenex.version, "3.0");
+        assert_eq!(lenex.constructor.name, "Splash Meet Manager 11");
+
+        let meet = &lenex.meets[0];
+        assert_eq!(meet.name, "Anytown Open");
+        assert_eq!(meet.course, Some(Course::LCM));
+        assert_eq!(meet.age_date.as_ref().unwrap().kind, "YEAR");
+        assert_eq!(meet.sessions.len(), 2);
+
+        let session = &meet.sessions[0];
+        assert_eq!(session.date, NaiveDate::from_ymd_opt(2024, 6, 8).unwrap());
+        let event = &session.events[0];
+        assert_eq!(event.id, 1);
+        assert_eq!(event.gender, Some(Gender::Female));
+        assert_eq!(event.round, Some(Round::Timed));
+        assert_eq!(
+            event.event,
+            SwimEvent(Distance::_100, Stroke::Freestyle, Course::LCM)
+        );
+        assert_eq!(event.age_groups.len(), 2);
+        let group = &event.age_groups[1];
+        assert_eq!((group.min, group.max), (Some(13), Some(14)));
+        assert_eq!(group.rankings[0].result_id, 1001);
+        assert_eq!(event.age_groups[0].min, None);
+
+        // Relay distances are given per swimmer
+        let relay = &session.events[1];
+        assert_eq!(relay.relay_count, 4);
+        assert_eq!(relay.event.0, Distance::_400);
+        assert_eq!(relay.event.1, Stroke::MedleyRelay);
+        assert_eq!(relay.gender, Some(Gender::Mixed));
+
+        // The second session is in a short course pool
+        let event = &meet.sessions[1].events[0];
+        assert_eq!(event.event.1, Stroke::IndividualMedley);
+        assert_eq!(event.event.2, Course::SCM);
+
+        let club = &meet.clubs[0];
+        assert_eq!(club.name, "Anytown Swimming Club");
+        assert_eq!(club.code.as_deref(), Some("ANYT"));
+        let athlete = &club.athletes[0];
+        assert_eq!(athlete.last_name, "Swimmer");
+        assert_eq!(athlete.first_name, "Alpha");
+        assert_eq!(athlete.gender, Gender::Female);
+        assert_eq!(
+            athlete.birth_date,
+            Some(NaiveDate::from_ymd_opt(2010, 1, 2).unwrap())
+        );
+        assert_eq!(
+            athlete.entries[0].entry_time.as_ref().unwrap().seconds,
+            62.34
+        );
+        assert_eq!(athlete.entries[1].entry_time, None);
+        assert_eq!(athlete.entries[1].entry_course, Some(Course::SCM));
+
+        let result = &athlete.results[0];
+        assert_eq!(result.id, 1001);
+        assert_eq!(result.swim_time.as_ref().unwrap().seconds, 61.1);
+        assert_eq!(result.points, Some(512));
+        assert_eq!(result.lane, Some(4));
+        assert_eq!(
+            result.splits,
+            vec![Split {
+                distance: 50,
+                seconds: 29.6
+            }]
+        );
+        let result = &athlete.results[1];
+        assert_eq!(result.status, Some(Status::Disqualified));
+        assert_eq!(result.swim_time, None);
+
+        let relay = &club.relays[0];
+        assert_eq!(relay.number, Some(1));
+        assert_eq!(relay.gender, Gender::Mixed);
+        let result = &relay.results[0];
+        assert_eq!(result.swim_time.as_ref().unwrap().seconds, 262.05);
+        assert_eq!(result.relay_positions.len(), 4);
+        assert_eq!(result.relay_positions[2].athlete_id, 103);
+        assert_eq!(result.splits.len(), 8);
+
+        assert_eq!(meet.clubs[1].athletes[0].results[0].status, None);
+    }
+
+    #[test]
+    fn test_unsupported_distance() {
+        let text = std::fs::read_to_string("testdata/lenex_results.lef").unwrap();
+        for (distance, relay_count) in [("20000", "4"), ("150", "4")] {
+            let text = text.replace(
+                r#"distance="100" relaycount="4""#,
+                &format!(r#"distance="{}" relaycount="{}""#, distance, relay_count),
+            );
+            let err = format!("{:#}", Lenex::from_str(&text).unwrap_err());
+            let expected = format!("unsupported distance: {}x{}", relay_count, distance);
+            assert!(err.contains(&expected), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_lenex_round_trip() {
+        let lenex = fixture();
+        let text = lenex.to_string();
+        assert_eq!(Lenex::from_str(&text).unwrap(), lenex);
+        assert_eq!(Lenex::from_str(&text).unwrap().to_string(), text);
+    }
+
+    #[test]
+    fn test_lxf_round_trip() {
+        let lenex = fixture();
+        let mut buf = Cursor::new(Vec::new());
+        lenex.write_lxf(&mut buf, "anytown").unwrap();
+        buf.set_position(0);
+        assert_eq!(Lenex::from_lxf(buf).unwrap(), lenex);
+    }
+
+    #[test]
+    fn test_lenex_codes() {
+        assert_eq!(
+            stroke_from_code("MEDLEY", 1),
+            Some(Stroke::IndividualMedley)
+        );
+        assert_eq!(stroke_from_code("MEDLEY", 4), Some(Stroke::MedleyRelay));
+        assert_eq!(stroke_from_code("FLY", 4), None);
+        assert_eq!(course_from_code("SCM20"), None);
+        for code in [
+            "TIM", "FHT", "FIN", "SEM", "QUA", "PRE", "SOP", "SOS", "SOQ",
+        ] {
+            assert_eq!(Round::from_code(code).unwrap().code(), code);
+        }
+        for code in ["DSQ", "DNS", "DNF", "SICK", "WDR", "EXH"] {
+            assert_eq!(Status::from_code(code).unwrap().code(), code);
+        }
+    }
+}
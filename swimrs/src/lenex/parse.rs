@@ -0,0 +1,339 @@
+use std::{
+    error::Error as StdError,
+    io::{Read, Seek},
+    str::FromStr,
+};
+
+use anyhow::{bail, Context, Error, Result};
+use chrono::NaiveDate;
+use num_enum::TryFromPrimitive;
+use roxmltree::{Document, Node};
+
+use super::{
+    course_from_code, gender_from_code, stroke_from_code, AgeDate, AgeGroup, Athlete, Club,
+    Constructor, Entry, Event, Lenex, Meet, Ranking, Relay, RelayPosition, Round, Session, Split,
+    Status, SwimResult,
+};
+use crate::common::{Course, Distance, SwimEvent, SwimTime};
+
+pub(super) const DATE_FMT: &str = "%Y-%m-%d";
+
+/// The attributes of an element.
+struct Element<'a, 'input>(Node<'a, 'input>);
+
+impl<'a, 'input> Element<'a, 'input> {
+    fn opt(&self, name: &str) -> Option<&'a str> {
+        self.0.attribute(name).filter(|v| !v.is_empty())
+    }
+
+    fn text(&self, name: &str) -> Result<&'a str> {
+        self.opt(name)
+            .with_context(|| format!("missing {} attribute on {}", name, self.0.tag_name().name()))
+    }
+
+    fn num<T>(&self, name: &str) -> Result<Option<T>>
+    where
+        T: FromStr,
+        T::Err: StdError + Send + Sync + 'static,
+    {
+        match self.opt(name) {
+            Some(n) => Ok(Some(
+                n.parse()
+                    .with_context(|| format!("invalid number: {}", n))?,
+            )),
+            None => Ok(None),
+        }
+    }
+
+    fn req_num<T>(&self, name: &str) -> Result<T>
+    where
+        T: FromStr,
+        T::Err: StdError + Send + Sync + 'static,
+    {
+        let n = self.text(name)?;
+        n.parse().with_context(|| format!("invalid number: {}", n))
+    }
+
+    /// Ages are given as -1 where there is no limit.
+    fn age(&self, name: &str) -> Result<Option<u8>> {
+        Ok(self
+            .num::<i16>(name)?
+            .filter(|a| *a >= 0)
+            .map(u8::try_from)
+            .transpose()?)
+    }
+
+    fn date(&self, name: &str) -> Result<Option<NaiveDate>> {
+        match self.opt(name) {
+            Some(d) => Ok(Some(
+                NaiveDate::parse_from_str(d, DATE_FMT)
+                    .with_context(|| format!("invalid date: {}", d))?,
+            )),
+            None => Ok(None),
+        }
+    }
+
+    fn time(&self, name: &str) -> Result<Option<SwimTime>> {
+        match self.opt(name) {
+            Some(t) => parse_time(t),
+            None => Ok(None),
+        }
+    }
+
+    /// The first child element named `tag`.
+    fn child(&self, tag: &str) -> Option<Element<'a, 'input>> {
+        self.0.children().find(|n| n.has_tag_name(tag)).map(Element)
+    }
+
+    /// The elements named `item` in the list element named `list`, such as
+    /// the EVENT elements of EVENTS.
+    fn list(&self, list: &str, item: &'a str) -> impl Iterator<Item = Element<'a, 'input>> + 'a {
+        let list = self.0.children().find(|n| n.has_tag_name(list));
+        list.into_iter()
+            .flat_map(|l| l.children())
+            .filter(move |n| n.has_tag_name(item))
+            .map(Element)
+    }
+}
+
+/// Parses times like `00:01:02.34`, or `NT` for no time.
+fn parse_time(s: &str) -> Result<Option<SwimTime>> {
+    if s == "NT" {
+        return Ok(None);
+    }
+    let parts: Vec<&str> = s.split(':').collect();
+    if parts.len() != 3 {
+        bail!("Unexpected LENEX time: {}", s);
+    }
+    let hours: f32 = parts[0].parse()?;
+    let minutes: f32 = parts[1].parse()?;
+    let seconds: f32 = parts[2].parse()?;
+    Ok(Some(SwimTime {
+        seconds: 3600.0 * hours + 60.0 * minutes + seconds,
+        relay: false,
+    }))
+}
+
+fn course(e: &Element, name: &str) -> Option<Course> {
+    e.opt(name).and_then(course_from_code)
+}
+
+fn event(e: &Element, course: Option<&Course>) -> Result<Event> {
+    let style = e.child("SWIMSTYLE").context("EVENT has no SWIMSTYLE")?;
+    let relay_count = style.num::<u8>("relaycount")?.unwrap_or(1).max(1);
+    let distance: u16 = style.req_num("distance")?;
+    let distance = distance
+        .checked_mul(u16::from(relay_count))
+        .and_then(|d| Distance::try_from_primitive(d).ok())
+        .with_context(|| format!("unsupported distance: {}x{}", relay_count, distance))?;
+    let code = style.text("stroke")?;
+    let stroke = stroke_from_code(code, relay_count)
+        .with_context(|| format!("unsupported stroke: {}x {}", relay_count, code))?;
+    Ok(Event {
+        id: e.req_num("eventid")?,
+        number: e.num("number")?,
+        gender: e.opt("gender").and_then(gender_from_code),
+        round: e.opt("round").and_then(Round::from_code),
+        event: SwimEvent(distance, stroke, course.cloned().unwrap_or(Course::All)),
+        relay_count,
+        age_groups: e
+            .list("AGEGROUPS", "AGEGROUP")
+            .map(|g| {
+                Ok(AgeGroup {
+                    id: g.req_num("agegroupid")?,
+                    min: g.age("agemin")?,
+                    max: g.age("agemax")?,
+                    gender: g.opt("gender").and_then(gender_from_code),
+                    name: g.opt("name").map(str::to_owned),
+                    rankings: g
+                        .list("RANKINGS", "RANKING")
+                        .map(|r| {
+                            Ok(Ranking {
+                                place: r.req_num("place")?,
+                                result_id: r.req_num("resultid")?,
+                            })
+                        })
+                        .collect::<Result<_>>()?,
+                })
+            })
+            .collect::<Result<_>>()?,
+    })
+}
+
+fn result(e: &Element) -> Result<SwimResult> {
+    Ok(SwimResult {
+        id: e.req_num("resultid")?,
+        event_id: e.req_num("eventid")?,
+        swim_time: e.time("swimtime")?,
+        status: e.opt("status").and_then(Status::from_code),
+        points: e.num("points")?,
+        heat_id: e.num("heatid")?,
+        lane: e.num("lane")?,
+        splits: e
+            .list("SPLITS", "SPLIT")
+            .map(|s| {
+                Ok(Split {
+                    distance: s.req_num("distance")?,
+                    seconds: s.time("swimtime")?.context("SPLIT has no time")?.seconds,
+                })
+            })
+            .collect::<Result<_>>()?,
+        relay_positions: e
+            .list("RELAYPOSITIONS", "RELAYPOSITION")
+            .map(|p| {
+                Ok(RelayPosition {
+                    athlete_id: p.req_num("athleteid")?,
+                    number: p.req_num("number")?,
+                })
+            })
+            .collect::<Result<_>>()?,
+    })
+}
+
+fn athlete(e: &Element) -> Result<Athlete> {
+    Ok(Athlete {
+        id: e.req_num("athleteid")?,
+        last_name: e.text("lastname")?.to_owned(),
+        first_name: e.text("firstname")?.to_owned(),
+        gender: gender_from_code(e.text("gender")?).context("invalid athlete gender")?,
+        birth_date: e.date("birthdate")?,
+        license: e.opt("license").map(str::to_owned),
+        nation: e.opt("nation").map(str::to_owned),
+        entries: e
+            .list("ENTRIES", "ENTRY")
+            .map(|n| {
+                Ok(Entry {
+                    event_id: n.req_num("eventid")?,
+                    entry_time: n.time("entrytime")?,
+                    entry_course: course(&n, "entrycourse"),
+                })
+            })
+            .collect::<Result<_>>()?,
+        results: e
+            .list("RESULTS", "RESULT")
+            .map(|r| result(&r))
+            .collect::<Result<_>>()?,
+    })
+}
+
+fn club(e: &Element) -> Result<Club> {
+    Ok(Club {
+        name: e.text("name")?.to_owned(),
+        short_name: e.opt("shortname").map(str::to_owned),
+        code: e.opt("code").map(str::to_owned),
+        nation: e.opt("nation").map(str::to_owned),
+        region: e.opt("region").map(str::to_owned),
+        athletes: e
+            .list("ATHLETES", "ATHLETE")
+            .map(|a| {
+                athlete(&a).with_context(|| {
+                    format!(
+                        "error parsing athlete {}",
+                        a.opt("athleteid").unwrap_or("?")
+                    )
+                })
+            })
+            .collect::<Result<_>>()?,
+        relays: e
+            .list("RELAYS", "RELAY")
+            .map(|r| {
+                Ok(Relay {
+                    number: r.num("number")?,
+                    gender: gender_from_code(r.text("gender")?).context("invalid relay gender")?,
+                    age_min: r.age("agemin")?,
+                    age_max: r.age("agemax")?,
+                    name: r.opt("name").map(str::to_owned),
+                    results: r
+                        .list("RESULTS", "RESULT")
+                        .map(|r| result(&r))
+                        .collect::<Result<_>>()?,
+                })
+            })
+            .collect::<Result<_>>()?,
+    })
+}
+
+fn meet(e: &Element) -> Result<Meet> {
+    let meet_course = course(e, "course");
+    Ok(Meet {
+        name: e.text("name")?.to_owned(),
+        city: e.opt("city").map(str::to_owned),
+        nation: e.opt("nation").map(str::to_owned),
+        course: meet_course.clone(),
+        age_date: match e.child("AGEDATE") {
+            Some(a) => Some(AgeDate {
+                kind: a.text("type")?.to_owned(),
+                value: a.date("value")?.context("AGEDATE has no value")?,
+            }),
+            None => None,
+        },
+        sessions: e
+            .list("SESSIONS", "SESSION")
+            .map(|s| {
+                let session_course = course(&s, "course");
+                let event_course = session_course.as_ref().or(meet_course.as_ref());
+                Ok(Session {
+                    number: s.req_num("number")?,
+                    date: s.date("date")?.context("SESSION has no date")?,
+                    name: s.opt("name").map(str::to_owned),
+                    course: session_course.clone(),
+                    events: s
+                        .list("EVENTS", "EVENT")
+                        .map(|e| {
+                            event(&e, event_course).with_context(|| {
+                                format!("error parsing event {}", e.opt("eventid").unwrap_or("?"))
+                            })
+                        })
+                        .collect::<Result<_>>()?,
+                })
+            })
+            .collect::<Result<_>>()?,
+        clubs: e
+            .list("CLUBS", "CLUB")
+            .map(|c| club(&c))
+            .collect::<Result<_>>()?,
+    })
+}
+
+impl FromStr for Lenex {
+    type Err = Error;
+
+    /// Parses a LENEX document, as found in a `.lef` file.
+    fn from_str(s: &str) -> Result<Self> {
+        let doc = Document::parse(s)?;
+        let root = Element(doc.root_element());
+        if !root.0.has_tag_name("LENEX") {
+            bail!("not a LENEX document: {}", root.0.tag_name().name());
+        }
+        let constructor = root
+            .child("CONSTRUCTOR")
+            .context("LENEX document has no CONSTRUCTOR")?;
+        Ok(Lenex {
+            version: root.text("version")?.to_owned(),
+            constructor: Constructor {
+                name: constructor.text("name")?.to_owned(),
+                version: constructor.text("version")?.to_owned(),
+            },
+            meets: root
+                .list("MEETS", "MEET")
+                .map(|m| meet(&m))
+                .collect::<Result<_>>()?,
+        })
+    }
+}
+
+impl Lenex {
+    /// Reads the document in a zipped `.lxf` file, which is its only `.lef`
+    /// file.
+    pub fn from_lxf<R: Read + Seek>(reader: R) -> Result<Self> {
+        let mut archive = zip::ZipArchive::new(reader)?;
+        let name = archive
+            .file_names()
+            .find(|n| n.to_ascii_lowercase().ends_with(".lef"))
+            .context("LXF file has no .lef file")?
+            .to_owned();
+        let mut text = String::new();
+        archive.by_name(&name)?.read_to_string(&mut text)?;
+        Lenex::from_str(&text).with_context(|| format!("error parsing {}", name))
+    }
+}
@@ -0,0 +1,275 @@
+use std::{
+    fmt::{self, Display, Formatter},
+    io::{Seek, Write},
+};
+
+use anyhow::Result;
+use chrono::NaiveDate;
+use zip::{write::FileOptions, CompressionMethod, ZipWriter};
+
+use super::{
+    course_code, gender_code, parse::DATE_FMT, stroke_code, AgeGroup, Athlete, Club, Event, Lenex,
+    Meet, Relay, Session, SwimResult,
+};
+use crate::common::{self, Course, SwimTime};
+
+/// An element being written, with its attributes in the order given.
+struct Element {
+    tag: &'static str,
+    attrs: Vec<(&'static str, String)>,
+    children: Vec<Element>,
+}
+
+impl Element {
+    fn new(tag: &'static str) -> Self {
+        Element {
+            tag,
+            attrs: Vec::new(),
+            children: Vec::new(),
+        }
+    }
+
+    fn attr(mut self, name: &'static str, value: impl ToString) -> Self {
+        self.attrs.push((name, value.to_string()));
+        self
+    }
+
+    fn opt(self, name: &'static str, value: Option<impl ToString>) -> Self {
+        match value {
+            Some(v) => self.attr(name, v),
+            None => self,
+        }
+    }
+
+    fn date(self, name: &'static str, value: NaiveDate) -> Self {
+        self.attr(name, value.format(DATE_FMT))
+    }
+
+    fn time(self, name: &'static str, value: &Option<SwimTime>) -> Self {
+        self.attr(
+            name,
+            value
+                .as_ref()
+                .map_or("NT".to_owned(), |t| format_time(t.seconds)),
+        )
+    }
+
+    /// Ages are written as -1 where there is no limit.
+    fn age(self, name: &'static str, value: Option<u8>) -> Self {
+        self.attr(name, value.map_or(-1, i16::from))
+    }
+
+    fn child(mut self, child: Element) -> Self {
+        self.children.push(child);
+        self
+    }
+
+    /// Adds the list element named `tag` holding `items`, unless there are
+    /// none.
+    fn list(self, tag: &'static str, items: impl IntoIterator<Item = Element>) -> Self {
+        let list = Element {
+            children: items.into_iter().collect(),
+            ..Element::new(tag)
+        };
+        if list.children.is_empty() {
+            self
+        } else {
+            self.child(list)
+        }
+    }
+
+    fn write(&self, f: &mut Formatter<'_>, depth: usize) -> fmt::Result {
+        write!(f, "{:indent$}<{}", "", self.tag, indent = 2 * depth)?;
+        for (name, value) in &self.attrs {
+            write!(f, " {}=\"{}\"", name, escape(value))?;
+        }
+        if self.children.is_empty() {
+            return writeln!(f, " />");
+        }
+        writeln!(f, ">")?;
+        for child in &self.children {
+            child.write(f, depth + 1)?;
+        }
+        writeln!(f, "{:indent$}</{}>", "", self.tag, indent = 2 * depth)
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Formats times like `00:01:02.34`.
+fn format_time(seconds: f32) -> String {
+    let hundredths = common::hundredths(seconds);
+    format!(
+        "{:02}:{:02}:{:02}.{:02}",
+        hundredths / 360_000,
+        hundredths / 6000 % 60,
+        hundredths / 100 % 60,
+        hundredths % 100
+    )
+}
+
+fn course(e: Element, name: &'static str, course: Option<&Course>) -> Element {
+    e.opt(name, course.and_then(course_code))
+}
+
+fn age_group(g: &AgeGroup) -> Element {
+    Element::new("AGEGROUP")
+        .attr("agegroupid", g.id)
+        .age("agemin", g.min)
+        .age("agemax", g.max)
+        .opt("gender", g.gender.as_ref().map(gender_code))
+        .opt("name", g.name.as_ref())
+        .list(
+            "RANKINGS",
+            g.rankings.iter().map(|r| {
+                Element::new("RANKING")
+                    .attr("place", r.place)
+                    .attr("resultid", r.result_id)
+            }),
+        )
+}
+
+fn event(e: &Event) -> Element {
+    let distance = e.event.0.clone() as u16 / u16::from(e.relay_count);
+    let mut style = Element::new("SWIMSTYLE").attr("distance", distance);
+    if e.relay_count > 1 {
+        style = style.attr("relaycount", e.relay_count);
+    }
+    Element::new("EVENT")
+        .attr("eventid", e.id)
+        .opt("number", e.number)
+        .opt("gender", e.gender.as_ref().map(gender_code))
+        .opt("round", e.round.map(|r| r.code()))
+        .child(style.opt("stroke", stroke_code(&e.event.1)))
+        .list("AGEGROUPS", e.age_groups.iter().map(age_group))
+}
+
+fn session(s: &Session) -> Element {
+    let e = Element::new("SESSION")
+        .attr("number", s.number)
+        .date("date", s.date)
+        .opt("name", s.name.as_ref());
+    course(e, "course", s.course.as_ref()).list("EVENTS", s.events.iter().map(event))
+}
+
+fn result(r: &SwimResult) -> Element {
+    Element::new("RESULT")
+        .attr("resultid", r.id)
+        .attr("eventid", r.event_id)
+        .time("swimtime", &r.swim_time)
+        .opt("status", r.status.map(|s| s.code()))
+        .opt("points", r.points)
+        .opt("heatid", r.heat_id)
+        .opt("lane", r.lane)
+        .list(
+            "SPLITS",
+            r.splits.iter().map(|s| {
+                Element::new("SPLIT")
+                    .attr("distance", s.distance)
+                    .attr("swimtime", format_time(s.seconds))
+            }),
+        )
+        .list(
+            "RELAYPOSITIONS",
+            r.relay_positions.iter().map(|p| {
+                Element::new("RELAYPOSITION")
+                    .attr("athleteid", p.athlete_id)
+                    .attr("number", p.number)
+            }),
+        )
+}
+
+fn athlete(a: &Athlete) -> Element {
+    let mut e = Element::new("ATHLETE")
+        .attr("athleteid", a.id)
+        .attr("lastname", &a.last_name)
+        .attr("firstname", &a.first_name)
+        .attr("gender", gender_code(&a.gender));
+    if let Some(birth_date) = a.birth_date {
+        e = e.date("birthdate", birth_date);
+    }
+    e.opt("license", a.license.as_ref())
+        .opt("nation", a.nation.as_ref())
+        .list(
+            "ENTRIES",
+            a.entries.iter().map(|n| {
+                let e = Element::new("ENTRY")
+                    .attr("eventid", n.event_id)
+                    .time("entrytime", &n.entry_time);
+                course(e, "entrycourse", n.entry_course.as_ref())
+            }),
+        )
+        .list("RESULTS", a.results.iter().map(result))
+}
+
+fn relay(r: &Relay) -> Element {
+    Element::new("RELAY")
+        .opt("number", r.number)
+        .attr("gender", gender_code(&r.gender))
+        .age("agemin", r.age_min)
+        .age("agemax", r.age_max)
+        .opt("name", r.name.as_ref())
+        .list("RESULTS", r.results.iter().map(result))
+}
+
+fn club(c: &Club) -> Element {
+    Element::new("CLUB")
+        .attr("name", &c.name)
+        .opt("shortname", c.short_name.as_ref())
+        .opt("code", c.code.as_ref())
+        .opt("nation", c.nation.as_ref())
+        .opt("region", c.region.as_ref())
+        .list("ATHLETES", c.athletes.iter().map(athlete))
+        .list("RELAYS", c.relays.iter().map(relay))
+}
+
+fn meet(m: &Meet) -> Element {
+    let mut e = Element::new("MEET")
+        .attr("name", &m.name)
+        .opt("city", m.city.as_ref())
+        .opt("nation", m.nation.as_ref());
+    e = course(e, "course", m.course.as_ref());
+    if let Some(age_date) = &m.age_date {
+        e = e.child(
+            Element::new("AGEDATE")
+                .attr("type", &age_date.kind)
+                .date("value", age_date.value),
+        );
+    }
+    e.list("SESSIONS", m.sessions.iter().map(session))
+        .list("CLUBS", m.clubs.iter().map(club))
+}
+
+impl Display for Lenex {
+    /// Writes the document as XML, as in a `.lef` file.
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(f, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        Element::new("LENEX")
+            .attr("version", &self.version)
+            .child(
+                Element::new("CONSTRUCTOR")
+                    .attr("name", &self.constructor.name)
+                    .attr("version", &self.constructor.version),
+            )
+            .list("MEETS", self.meets.iter().map(meet))
+            .write(f, 0)
+    }
+}
+
+impl Lenex {
+    /// Writes the document zipped, as in a `.lxf` file, under the name
+    /// `name.lef`.
+    pub fn write_lxf<W: Write + Seek>(&self, writer: W, name: &str) -> Result<()> {
+        let mut zip = ZipWriter::new(writer);
+        let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+        zip.start_file(format!("{}.lef", name), options)?;
+        write!(zip, "{}", self)?;
+        zip.finish()?;
+        Ok(())
+    }
+}
@@ -1,2 +1,13 @@
+pub mod agegroup;
 pub mod common;
+pub mod hy3;
+pub mod lenex;
+pub mod meetmanager;
+pub mod psych;
+pub mod qualify;
+pub mod relay;
+pub mod scoring;
+pub mod sdif;
+#[cfg(test)]
+mod testing;
 pub mod usas;
@@ -0,0 +1,268 @@
+//! Result reports from Hy-Tek's Meet Manager, as meets post them in text or
+//! in HTML, which wraps the same text in `<pre>` blocks.
+//!
+//! Reports are parsed with [`FromStr`](std::str::FromStr), or with
+//! [`Report::parse`] to choose the course of events given only in meters,
+//! which Meet Manager doesn't tell apart. Page headers, qualifying standards
+//! and other lines that aren't results are skipped.
+
+mod parse;
+
+use chrono::NaiveDate;
+
+use crate::{
+    common::{Gender, SwimEvent},
+    sdif::{Round, Time},
+};
+
+/// A results report, which may cover any number of events.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Report {
+    /// Meet name from the page header
+    pub meet_name: Option<String>,
+    pub start_date: Option<NaiveDate>,
+    pub end_date: Option<NaiveDate>,
+    pub events: Vec<EventResults>,
+}
+
+/// The results of an event, from a header like "Event 12 Girls 13-14 100
+/// Yard Butterfly" and the lines that follow it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EventResults {
+    pub number: u16,
+    pub gender: Gender,
+    pub min_age: Option<u8>,
+    pub max_age: Option<u8>,
+    pub event: SwimEvent,
+    pub swims: Vec<Swim>,
+    pub relays: Vec<RelaySwim>,
+}
+
+/// An individual swim.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Swim {
+    /// Name as reported, such as "Swimmer, Alpha A"
+    pub name: String,
+    /// Age, which college meets replace with a class year
+    pub age: Option<u8>,
+    pub result: SwimResult,
+}
+
+/// A relay swim.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RelaySwim {
+    /// Relay letter, such as `A`
+    pub relay: String,
+    pub legs: Vec<RelayLeg>,
+    pub result: SwimResult,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RelayLeg {
+    /// Leg from 1 to 4
+    pub leg: u8,
+    pub name: String,
+    pub age: Option<u8>,
+}
+
+/// A result line and the lines following it, common to individual and
+/// relay swims.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SwimResult {
+    /// Place, or `None` for swims that weren't placed, shown as `---`
+    pub place: Option<u16>,
+    /// Whether the place is shared, shown as `*2`
+    pub tied: bool,
+    pub team: String,
+    /// Seed time, or the prelims time in the results of finals
+    pub seed: Option<Time>,
+    pub round: Round,
+    pub time: Time,
+    /// Swum out of competition, shown with an `x` before the time
+    pub exhibition: bool,
+    /// Qualified for the next round, shown with a `q` after the time
+    pub qualified: bool,
+    /// Time standard achieved, such as `BB`
+    pub time_standard: Option<String>,
+    pub points: Option<f32>,
+    pub dq_reason: Option<String>,
+    pub splits: Vec<Split>,
+}
+
+/// A cumulative split. Reports give splits without their distances, so
+/// these are worked out from the event's distance and number of splits.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Split {
+    pub distance: u16,
+    pub seconds: f32,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::common::{Course, Distance, Stroke, SwimTime};
+
+    fn fixture() -> Report {
+        let text = std::fs::read_to_string("testdata/mm_results.txt").unwrap();
+        Report::from_str(&text).unwrap()
+    }
+
+    #[test]
+    fn test_parse_report() {
+        let report = fixture();
+        assert_eq!(
+            report.meet_name.as_deref(),
+            Some("Anytown Spring Invitational")
+        );
+        assert_eq!(
+            report.start_date,
+            Some(NaiveDate::from_ymd_opt(2024, 3, 15).unwrap())
+        );
+        assert_eq!(
+            report.end_date,
+            Some(NaiveDate::from_ymd_opt(2024, 3, 17).unwrap())
+        );
+        assert_eq!(report.events.len(), 4);
+
+        let event = &report.events[0];
+        assert_eq!(event.number, 12);
+        assert_eq!(event.gender, Gender::Female);
+        assert_eq!((event.min_age, event.max_age), (Some(13), Some(14)));
+        assert_eq!(
+            event.event,
+            SwimEvent(Distance::_100, Stroke::Butterfly, Course::SCY)
+        );
+        // The event continues after a page break
+        assert_eq!(event.swims.len(), 5);
+
+        let swim = &event.swims[0];
+        assert_eq!(swim.name, "Swimmer, Alpha A");
+        assert_eq!(swim.age, Some(14));
+        let r = &swim.result;
+        assert_eq!(r.place, Some(1));
+        assert_eq!(r.team, "Anytown Aquatics-PC");
+        assert_eq!(r.round, Round::Finals);
+        assert_eq!(r.seed.as_ref().and_then(Time::seconds), Some(62.34));
+        assert_eq!(r.time.seconds(), Some(60.55));
+        assert_eq!(r.time_standard.as_deref(), Some("BB"));
+        assert_eq!(r.points, Some(20.0));
+        assert_eq!(
+            r.splits,
+            vec![
+                Split {
+                    distance: 50,
+                    seconds: 28.9
+                },
+                Split {
+                    distance: 100,
+                    seconds: 60.55
+                }
+            ]
+        );
+
+        let r = &event.swims[1].result;
+        assert_eq!((r.place, r.tied), (Some(2), true));
+        assert_eq!(r.points, Some(16.5));
+        assert_eq!(r.seed, Some(Time::NoTime));
+
+        let r = &event.swims[2].result;
+        assert_eq!(r.place, None);
+        assert!(r.exhibition);
+        assert_eq!(r.time.seconds(), Some(64.01));
+
+        let r = &event.swims[3].result;
+        assert_eq!(r.time, Time::Disqualified);
+        assert_eq!(r.dq_reason.as_deref(), Some("Non-simultaneous kick"));
+        assert!(r.splits.is_empty());
+
+        let r = &event.swims[4].result;
+        assert_eq!(r.time, Time::Scratch);
+        assert_eq!(r.team, "Othertown Swim Club-PC");
+
+        // Prelims, with qualifiers for the final
+        let event = &report.events[1];
+        assert_eq!(event.gender, Gender::Male);
+        assert_eq!((event.min_age, event.max_age), (Some(15), None));
+        assert_eq!(event.event.1, Stroke::IndividualMedley);
+        let r = &event.swims[0].result;
+        assert_eq!(r.round, Round::Prelims);
+        assert!(r.qualified);
+        assert_eq!(r.splits.len(), 4);
+        assert_eq!(r.splits[3].distance, 200);
+        assert!(!event.swims[1].result.qualified);
+
+        let event = &report.events[2];
+        assert_eq!(event.event.1, Stroke::MedleyRelay);
+        assert_eq!((event.min_age, event.max_age), (Some(11), Some(12)));
+        let relay = &event.relays[0];
+        assert_eq!(relay.relay, "A");
+        assert_eq!(relay.result.team, "Anytown Aquatics-PC");
+        assert_eq!(relay.result.place, Some(1));
+        assert_eq!(relay.result.points, Some(32.0));
+        assert_eq!(relay.legs.len(), 4);
+        assert_eq!(
+            relay.legs[3],
+            RelayLeg {
+                leg: 4,
+                name: "Swimmer, Foxtrot".to_owned(),
+                age: Some(11)
+            }
+        );
+        assert_eq!(relay.result.splits.len(), 4);
+        assert_eq!(relay.result.splits[1].seconds, 68.6);
+        assert_eq!(event.relays[1].result.time, Time::Disqualified);
+        assert_eq!(
+            event.relays[1].result.dq_reason.as_deref(),
+            Some("Early take-off swimmer #3")
+        );
+
+        let event = &report.events[3];
+        assert_eq!(event.event.2, Course::LCM);
+        assert_eq!((event.min_age, event.max_age), (None, Some(10)));
+        assert_eq!(event.swims[0].result.time.seconds(), Some(35.12));
+    }
+
+    #[test]
+    fn test_parse_html_report() {
+        let html = std::fs::read_to_string("testdata/mm_results.htm").unwrap();
+        let report = Report::from_str(&html).unwrap();
+        assert_eq!(report, fixture());
+    }
+
+    #[test]
+    fn test_meter_course() {
+        let text = std::fs::read_to_string("testdata/mm_results.txt").unwrap();
+        let report = Report::parse(&text, Course::SCM).unwrap();
+        assert_eq!(report.events[3].event.2, Course::SCM);
+        assert_eq!(report.events[0].event.2, Course::SCY);
+    }
+
+    #[test]
+    fn test_many_splits() {
+        // A 1650 split every 25 yards, 66 splits in all
+        let splits: Vec<String> = (1..=66)
+            .map(|i| SwimTime {
+                seconds: 15.0 * i as f32,
+                relay: false,
+            })
+            .map(|t| t.to_string())
+            .collect();
+        let mut text = "Event 1  Girls 13-14 1650 Yard Freestyle
+===============================================================================
+    Name                    Age Team                    Seed Time  Finals Time
+===============================================================================
+  1 Swimmer, Alpha A         14 Anytown Aquatics-PC      16:30.00    16:30.00
+"
+        .to_owned();
+        for line in splits.chunks(6) {
+            text.push_str(&format!("        {}\n", line.join("        ")));
+        }
+        let report = Report::from_str(&text).unwrap();
+        let splits = &report.events[0].swims[0].result.splits;
+        assert_eq!(splits.len(), 66);
+        assert_eq!((splits[0].distance, splits[0].seconds), (25, 15.0));
+        assert_eq!(splits[65].distance, 1650);
+    }
+}
@@ -0,0 +1,365 @@
+use std::{borrow::Cow, str::FromStr};
+
+use anyhow::{bail, Context, Error, Result};
+use chrono::NaiveDate;
+use lazy_static::lazy_static;
+use num_enum::TryFromPrimitive;
+use regex::{Captures, Regex};
+
+use super::{EventResults, RelayLeg, RelaySwim, Report, Split, Swim, SwimResult};
+use crate::{
+    common::{Course, Distance, Gender, Stroke, SwimEvent, SwimTime},
+    sdif::{Round, Time},
+};
+
+const DATE_FMT: &str = "%-m/%-d/%Y";
+
+/// A time in a result line, or why there isn't one. Times may be marked as
+/// exhibition swims with a leading `x` or `X`, or as judges' decisions with
+/// `J`, and may be followed by the letter of their course.
+const TIME: &str = r"(?:NT|NS|DQ|SCR|DFS|DNF|[xXJ]?(?:\d+:)?\d{1,2}\.\d{2}[YLS]?)";
+
+/// The end of a result line, from the seed time to the points.
+const RESULT_TAIL: &str = r"(?:\s+(?P<seed>TIME))?\s+(?P<time>TIME)\s?(?P<q>[qQ])?(?:\s+(?P<std>[A-Z]{1,4}))?(?:\s+(?P<points>\d+(?:\.\d+)?))?\s*$";
+
+lazy_static! {
+    static ref RE_EVENT: Regex = Regex::new(
+        r"(?x)^\s*\(?Event\s+(?P<number>\d+)\s+
+        (?P<gender>Girls|Boys|Women|Men|Female|Male|Mixed)\s+
+        (?:(?P<min>\d+)-(?P<max>\d+)\s+|(?P<over>\d+)\s+&\s+Over\s+|(?P<under>\d+)\s+&\s+Under\s+|Open\s+)?
+        (?P<distance>\d+)\s+(?P<course>Yard|LC\s+Meter|SC\s+Meter|Meter)\s+
+        (?P<stroke>Freestyle\s+Relay|Medley\s+Relay|Freestyle|Backstroke|Breaststroke|Butterfly|IM|Individual\s+Medley)\b"
+    )
+    .unwrap();
+    static ref RE_MEET: Regex = Regex::new(
+        r"^\s*(?P<name>\S.*?)\s+-\s+(?P<start>\d{1,2}/\d{1,2}/\d{4})(?:\s+to\s+(?P<end>\d{1,2}/\d{1,2}/\d{4}))?\s*$"
+    )
+    .unwrap();
+    static ref RE_SWIM: Regex = Regex::new(
+        &(r"^\s*(?P<place>\d+|\*\d+|-{2,3})\s+(?P<name>[^,\d]+,[^\d]*?)\s+(?P<age>\d{1,2}|FR|SO|JR|SR)\s+(?P<team>.+?)".to_owned()
+            + &RESULT_TAIL.replace("TIME", TIME))
+    )
+    .unwrap();
+    static ref RE_RELAY: Regex = Regex::new(
+        &(r"^\s*(?P<place>\d+|\*\d+|-{2,3})\s+(?P<team>.+?)\s+(?P<relay>[A-Z])".to_owned()
+            + &RESULT_TAIL.replace("TIME", TIME))
+    )
+    .unwrap();
+    static ref RE_LEG: Regex = Regex::new(
+        r"(?P<leg>[1-8])\)\s+(?:r:[+-]?\d+\.\d+\s+)?(?P<name>[^,\d()]+,[^\d()]*?)\s+[WMFB]?(?P<age>\d{1,2})\b"
+    )
+    .unwrap();
+    static ref RE_SPLITS: Regex = Regex::new(
+        r"^\s*(?:r:[+-]?\d+\.\d+\s+)?(?:(?:\d+:)?\d+\.\d{2}\s*(?:\(\s*(?:\d+:)?\d+\.\d{2}\s*\)\s*)?)+$"
+    )
+    .unwrap();
+    static ref RE_SPLIT_TIME: Regex = Regex::new(r"(\(\s*)?((?:\d+:)?\d+\.\d{2})").unwrap();
+    static ref RE_REACTION: Regex = Regex::new(r"r:[+-]?\d+\.\d+").unwrap();
+    static ref RE_PRE: Regex = Regex::new(r"(?is)<pre[^>]*>(.*?)</pre>").unwrap();
+    static ref RE_TAG: Regex = Regex::new(r"<[^>]+>").unwrap();
+}
+
+/// The text of an HTML report, from its `<pre>` blocks.
+fn html_text(html: &str) -> String {
+    RE_PRE
+        .captures_iter(html)
+        .map(|c| RE_TAG.replace_all(&c[1], "").into_owned())
+        .collect::<Vec<_>>()
+        .join("\n")
+        .replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Reads a time, and whether it was swum as an exhibition.
+fn parse_time(s: &str, course: &Course) -> Result<(Time, bool)> {
+    let time = match s {
+        "NT" => Time::NoTime,
+        "NS" => Time::NoSwim,
+        "DQ" => Time::Disqualified,
+        // Declared false starts withdraw the swimmer
+        "SCR" | "DFS" => Time::Scratch,
+        "DNF" => Time::DidNotFinish,
+        _ => {
+            let exhibition = s.starts_with(['x', 'X']);
+            let s = s.trim_start_matches(['x', 'X', 'J']);
+            let (s, course) = match s.chars().last() {
+                Some('Y') => (&s[..s.len() - 1], Course::SCY),
+                Some('L') => (&s[..s.len() - 1], Course::LCM),
+                Some('S') => (&s[..s.len() - 1], Course::SCM),
+                _ => (s, course.clone()),
+            };
+            return Ok((Time::Swum(SwimTime::from_str(s)?, course), exhibition));
+        }
+    };
+    Ok((time, false))
+}
+
+fn event_header(c: &Captures, meter_course: &Course) -> Result<EventResults> {
+    let gender = match &c["gender"] {
+        "Girls" | "Women" | "Female" => Gender::Female,
+        "Boys" | "Men" | "Male" => Gender::Male,
+        _ => Gender::Mixed,
+    };
+    let age = |name| c.name(name).map(|m| m.as_str().parse()).transpose();
+    let (min_age, max_age) = match (age("min")?, age("max")?, age("over")?, age("under")?) {
+        (Some(min), Some(max), _, _) => (Some(min), Some(max)),
+        (_, _, Some(over), _) => (Some(over), None),
+        (_, _, _, Some(under)) => (None, Some(under)),
+        _ => (None, None),
+    };
+    let distance = Distance::try_from_primitive(c["distance"].parse()?)?;
+    let course = match c["course"].split_whitespace().next() {
+        Some("Yard") => Course::SCY,
+        Some("LC") => Course::LCM,
+        Some("SC") => Course::SCM,
+        _ => meter_course.clone(),
+    };
+    let stroke = match c["stroke"].split_whitespace().collect::<Vec<_>>()[..] {
+        ["Freestyle", "Relay"] => Stroke::FreestyleRelay,
+        ["Medley", "Relay"] => Stroke::MedleyRelay,
+        ["Freestyle"] => Stroke::Freestyle,
+        ["Backstroke"] => Stroke::Backstroke,
+        ["Breaststroke"] => Stroke::Breaststroke,
+        ["Butterfly"] => Stroke::Butterfly,
+        _ => Stroke::IndividualMedley,
+    };
+    Ok(EventResults {
+        number: c["number"].parse()?,
+        gender,
+        min_age,
+        max_age,
+        event: SwimEvent(distance, stroke, course),
+        swims: Vec::new(),
+        relays: Vec::new(),
+    })
+}
+
+fn result(c: &Captures, round: Round, course: &Course) -> Result<SwimResult> {
+    let place = c["place"].trim_start_matches('*');
+    let (time, exhibition) = parse_time(&c["time"], course)?;
+    Ok(SwimResult {
+        place: place.parse().ok(),
+        tied: c["place"].starts_with('*'),
+        team: c["team"].to_owned(),
+        seed: match c.name("seed") {
+            Some(seed) => Some(parse_time(seed.as_str(), course)?.0),
+            None => None,
+        },
+        round,
+        time,
+        exhibition,
+        qualified: c.name("q").is_some(),
+        time_standard: c.name("std").map(|m| m.as_str().to_owned()),
+        points: c.name("points").map(|m| m.as_str().parse()).transpose()?,
+        dq_reason: None,
+        splits: Vec::new(),
+    })
+}
+
+/// Gives splits their distances, assuming they are evenly spaced over the
+/// event.
+fn split_distances(event: &mut EventResults) {
+    let distance = event.event.0.clone() as u16;
+    let results = event
+        .swims
+        .iter_mut()
+        .map(|s| &mut s.result)
+        .chain(event.relays.iter_mut().map(|r| &mut r.result));
+    for result in results {
+        let n = result.splits.len() as u32;
+        for (i, split) in result.splits.iter_mut().enumerate() {
+            // Never more than the event's distance, so fits back into a u16
+            split.distance = (u32::from(distance) * (i as u32 + 1) / n) as u16;
+        }
+    }
+}
+
+fn is_relay(event: &EventResults) -> bool {
+    matches!(event.event.1, Stroke::FreestyleRelay | Stroke::MedleyRelay)
+}
+
+struct Parser {
+    meter_course: Course,
+    report: Report,
+    round: Round,
+    /// Whether the line after the last result may give a reason for a DQ
+    dq_reason: bool,
+}
+
+impl Parser {
+    fn event(&mut self) -> Option<&mut EventResults> {
+        self.report.events.last_mut()
+    }
+
+    fn last_result(&mut self) -> Option<&mut SwimResult> {
+        let event = self.event()?;
+        if is_relay(event) {
+            event.relays.last_mut().map(|r| &mut r.result)
+        } else {
+            event.swims.last_mut().map(|s| &mut s.result)
+        }
+    }
+
+    fn line(&mut self, line: &str) -> Result<()> {
+        if line.trim().is_empty() {
+            self.dq_reason = false;
+            return Ok(());
+        }
+
+        if let Some(c) = RE_EVENT.captures(line) {
+            let event = event_header(&c, &self.meter_course)?;
+            // Events are headed again after page breaks
+            if self.event().map(|e| e.number) != Some(event.number) {
+                if let Some(last) = self.event() {
+                    split_distances(last);
+                }
+                self.report.events.push(event);
+                self.round = Round::Finals;
+            }
+            self.dq_reason = false;
+            return Ok(());
+        }
+
+        let event = match self.report.events.last() {
+            Some(event) => event,
+            None => {
+                if let (None, Some(c)) = (&self.report.meet_name, RE_MEET.captures(line)) {
+                    self.report.meet_name = Some(c["name"].to_owned());
+                    self.report.start_date =
+                        Some(NaiveDate::parse_from_str(&c["start"], DATE_FMT)?);
+                    self.report.end_date = match c.name("end") {
+                        Some(end) => Some(NaiveDate::parse_from_str(end.as_str(), DATE_FMT)?),
+                        None => self.report.start_date,
+                    };
+                }
+                return Ok(());
+            }
+        };
+        let relay = is_relay(event);
+        let course = event.event.2.clone();
+
+        // The column headings tell which round the times are from, from the
+        // last of the time columns
+        let columns = ["Prelim Time", "Swim-off Time", "Finals Time"];
+        if let Some((_, i)) = columns
+            .iter()
+            .enumerate()
+            .filter_map(|(i, c)| Some((line.rfind(c)?, i)))
+            .max()
+        {
+            self.round = [Round::Prelims, Round::SwimOff, Round::Finals][i];
+            return Ok(());
+        }
+
+        let re = if relay { &*RE_RELAY } else { &*RE_SWIM };
+        if let Some(c) = re.captures(line) {
+            let result = result(&c, self.round, &course)?;
+            self.dq_reason = result.time == Time::Disqualified;
+            let event = self.event().unwrap();
+            if relay {
+                event.relays.push(RelaySwim {
+                    relay: c["relay"].to_owned(),
+                    legs: Vec::new(),
+                    result,
+                });
+            } else {
+                event.swims.push(Swim {
+                    name: c["name"].to_owned(),
+                    age: c["age"].parse().ok(),
+                    result,
+                });
+            }
+            return Ok(());
+        }
+
+        if RE_SPLITS.is_match(line) {
+            let line = RE_REACTION.replace_all(line, "");
+            let result = self.last_result().context("splits don't follow a result")?;
+            for c in RE_SPLIT_TIME.captures_iter(&line) {
+                // Times in brackets are the splits between, which follow
+                // from the cumulative ones
+                if c.get(1).is_none() {
+                    result.splits.push(Split {
+                        distance: 0,
+                        seconds: SwimTime::from_str(&c[2])?.seconds,
+                    });
+                }
+            }
+            return Ok(());
+        }
+
+        if relay {
+            let legs: Vec<RelayLeg> = RE_LEG
+                .captures_iter(line)
+                .map(|c| RelayLeg {
+                    leg: c["leg"].parse().unwrap(),
+                    name: c["name"].to_owned(),
+                    age: c["age"].parse().ok(),
+                })
+                .collect();
+            if !legs.is_empty() {
+                if let Some(r) = self.event().and_then(|e| e.relays.last_mut()) {
+                    r.legs.extend(legs);
+                }
+                return Ok(());
+            }
+        }
+
+        if self.dq_reason && line.starts_with(char::is_whitespace) {
+            if let Some(result) = self.last_result() {
+                result.dq_reason = Some(line.trim().to_owned());
+            }
+            self.dq_reason = false;
+        }
+        Ok(())
+    }
+}
+
+impl Report {
+    /// Parses a text or HTML report. Events given only in meters, rather
+    /// than LC or SC meters, are taken to be in `meter_course`.
+    pub fn parse(s: &str, meter_course: Course) -> Result<Self> {
+        let text = if RE_PRE.is_match(s) {
+            Cow::Owned(html_text(s))
+        } else {
+            Cow::Borrowed(s)
+        };
+        let mut parser = Parser {
+            meter_course,
+            report: Report {
+                meet_name: None,
+                start_date: None,
+                end_date: None,
+                events: Vec::new(),
+            },
+            round: Round::Finals,
+            dq_reason: false,
+        };
+        for (i, line) in text.lines().enumerate() {
+            parser
+                .line(line)
+                .with_context(|| format!("error parsing report line {}: {}", i + 1, line))?;
+        }
+        if let Some(last) = parser.event() {
+            split_distances(last);
+        }
+        if parser.report.events.is_empty() {
+            bail!("no events found in report");
+        }
+        Ok(parser.report)
+    }
+}
+
+impl FromStr for Report {
+    type Err = Error;
+
+    /// Parses a text or HTML report, taking events in meters to be long
+    /// course.
+    fn from_str(s: &str) -> Result<Self> {
+        Report::parse(s, Course::LCM)
+    }
+}
@@ -0,0 +1,408 @@
+//! Psych sheets: each event's entries in order of seed time, with the heat
+//! and lane each is seeded into.
+//!
+//! Heats are seeded by the usual USA Swimming rules. The fastest entry in a
+//! heat swims in the center lane, the next fastest to its right, and so on
+//! alternating outwards. Timed finals are seeded slowest heat first, with the
+//! first heat made up to at least three entries from the second. Prelims
+//! circle seed their fastest heats, dealing the fastest entries across them
+//! in turn, and seed the rest as timed finals.
+//!
+//! Sheets are written as text, in the layout of Meet Manager's reports, with
+//! [`Display`](std::fmt::Display), or as HTML.
+
+use std::fmt::{self, Display, Formatter, Write};
+
+use crate::{
+    common::{Course, Gender, Stroke, SwimEvent, SwimTime},
+    scoring::{Entry, EventEntries},
+};
+
+/// Entries a timed final's first heat is made up to.
+const MIN_FIRST_HEAT: usize = 3;
+
+/// How an event's heats are seeded.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Seeding {
+    /// Slowest heats first, with the fastest entries in the last heat
+    TimedFinal,
+    /// Prelims, with this many of the fastest heats circle seeded
+    Circle(u16),
+}
+
+/// An entry with its place on the psych sheet and its seeded heat and lane.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SeededEntry {
+    /// Position on the psych sheet, from 1
+    pub rank: u16,
+    pub entry: Entry,
+    /// Heat, from 1
+    pub heat: u16,
+    /// Lane, from 1
+    pub lane: u8,
+}
+
+/// An event and its seeded entries.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SeededEvent {
+    pub number: u16,
+    pub gender: Gender,
+    pub min_age: Option<u8>,
+    pub max_age: Option<u8>,
+    pub event: SwimEvent,
+    /// Entries in order of seed time, with those without a time last
+    pub entries: Vec<SeededEntry>,
+    pub heats: u16,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PsychSheet {
+    pub meet_name: Option<String>,
+    pub events: Vec<SeededEvent>,
+}
+
+impl PsychSheet {
+    /// Seeds `events` into heats of `lanes` lanes. Entries with the same seed
+    /// time keep the order they were entered in.
+    pub fn new(
+        meet_name: Option<String>,
+        events: &[EventEntries],
+        lanes: u8,
+        seeding: Seeding,
+    ) -> Self {
+        let events = events
+            .iter()
+            .map(|e| {
+                let mut entries: Vec<&Entry> = e.entries.iter().collect();
+                entries.sort_by_key(|n| n.seed.as_ref().map_or(u32::MAX, SwimTime::hundredths));
+                let positions = seed_heats(entries.len(), lanes, seeding);
+                SeededEvent {
+                    number: e.number,
+                    gender: e.gender.clone(),
+                    min_age: e.min_age,
+                    max_age: e.max_age,
+                    event: e.event.clone(),
+                    heats: positions.iter().map(|(h, _)| *h).max().unwrap_or_default(),
+                    entries: entries
+                        .into_iter()
+                        .zip(positions)
+                        .enumerate()
+                        .map(|(i, (n, (heat, lane)))| SeededEntry {
+                            rank: i as u16 + 1,
+                            entry: n.clone(),
+                            heat,
+                            lane,
+                        })
+                        .collect(),
+                }
+            })
+            .collect();
+        PsychSheet { meet_name, events }
+    }
+
+    /// Writes the sheet as an HTML page, with a table per event.
+    pub fn to_html(&self) -> String {
+        let title = match &self.meet_name {
+            Some(name) => format!("{} - Psych Sheet", escape(name)),
+            None => "Psych Sheet".to_owned(),
+        };
+        let mut html = String::new();
+        html.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+        let _ = writeln!(html, "<title>{}</title>\n</head>\n<body>", title);
+        let _ = writeln!(html, "<h1>{}</h1>", title);
+        for e in &self.events {
+            let _ = writeln!(html, "<h2>{}</h2>", escape(&e.title()));
+            html.push_str("<table>\n<tr><th></th><th>Name</th><th>Age</th><th>Team</th>");
+            html.push_str("<th>Seed Time</th><th>Heat</th><th>Lane</th></tr>\n");
+            for n in &e.entries {
+                let _ = writeln!(
+                    html,
+                    "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                    n.rank,
+                    escape(&n.entry.name),
+                    n.entry.age.map(|a| a.to_string()).unwrap_or_default(),
+                    escape(&n.entry.team),
+                    seed(&n.entry.seed),
+                    n.heat,
+                    n.lane
+                );
+            }
+            html.push_str("</table>\n");
+        }
+        html.push_str("</body>\n</html>\n");
+        html
+    }
+}
+
+impl SeededEvent {
+    /// The event's title as Meet Manager gives it, such as "Event 12  Girls
+    /// 13-14 100 Yard Butterfly".
+    pub fn title(&self) -> String {
+        format!("Event {}  {}", self.number, self.name())
+    }
+
+    /// The event's name, such as "Girls 13-14 100 Yard Butterfly".
+    pub fn name(&self) -> String {
+        let senior = self.min_age.is_none() && self.max_age.is_none();
+        let gender = match (&self.gender, senior) {
+            (Gender::Female, false) => "Girls",
+            (Gender::Female, true) => "Women",
+            (Gender::Male, false) => "Boys",
+            (Gender::Male, true) => "Men",
+            (Gender::Mixed, _) => "Mixed",
+        };
+        let ages = match (self.min_age, self.max_age) {
+            (Some(min), Some(max)) if min == max => format!("{} ", min),
+            (Some(min), Some(max)) => format!("{}-{} ", min, max),
+            (Some(min), None) => format!("{} & Over ", min),
+            (None, Some(max)) => format!("{} & Under ", max),
+            (None, None) => String::new(),
+        };
+        let SwimEvent(distance, stroke, course) = &self.event;
+        let course = match course {
+            Course::SCY => "Yard",
+            Course::SCM => "SC Meter",
+            Course::LCM => "LC Meter",
+            Course::All => "Meter",
+        };
+        let stroke = match stroke {
+            Stroke::Freestyle | Stroke::All => "Freestyle",
+            Stroke::Backstroke => "Backstroke",
+            Stroke::Breaststroke => "Breaststroke",
+            Stroke::Butterfly => "Butterfly",
+            Stroke::IndividualMedley => "IM",
+            Stroke::FreestyleRelay => "Freestyle Relay",
+            Stroke::MedleyRelay => "Medley Relay",
+        };
+        format!(
+            "{} {}{} {} {}",
+            gender,
+            ages,
+            distance.clone() as u16,
+            course,
+            stroke
+        )
+    }
+
+    /// The entries seeded in `heat`, in lane order.
+    pub fn heat(&self, heat: u16) -> Vec<&SeededEntry> {
+        let mut entries: Vec<&SeededEntry> =
+            self.entries.iter().filter(|n| n.heat == heat).collect();
+        entries.sort_by_key(|n| n.lane);
+        entries
+    }
+}
+
+const RULE: &str =
+    "===============================================================================";
+
+impl Display for PsychSheet {
+    /// Writes the sheet as text, with an event's entries under a header like
+    /// those of Meet Manager's reports.
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if let Some(name) = &self.meet_name {
+            writeln!(f, "{:^79}", name)?;
+        }
+        writeln!(f, "{:^79}", "Psych Sheet")?;
+        for e in &self.events {
+            writeln!(f)?;
+            writeln!(f, "{}", e.title())?;
+            writeln!(f, "{}", RULE)?;
+            writeln!(
+                f,
+                "    {:<24}{:>3} {:<24}{:>9}  {:>4} {:>4}",
+                "Name", "Age", "Team", "Seed Time", "Heat", "Lane"
+            )?;
+            writeln!(f, "{}", RULE)?;
+            for n in &e.entries {
+                writeln!(
+                    f,
+                    "{:>3} {:<24}{:>3} {:<24}{:>9}  {:>4} {:>4}",
+                    n.rank,
+                    n.entry.name,
+                    n.entry.age.map(|a| a.to_string()).unwrap_or_default(),
+                    n.entry.team,
+                    seed(&n.entry.seed),
+                    n.heat,
+                    n.lane
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn seed(time: &Option<SwimTime>) -> String {
+    time.as_ref().map_or("NT".to_owned(), |t| {
+        SwimTime {
+            relay: false,
+            ..t.clone()
+        }
+        .to_string()
+    })
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Lanes in the order they are filled: the center lane, then alternately
+/// right and left of it.
+fn lane_order(lanes: u8) -> Vec<u8> {
+    let center = lanes.div_ceil(2);
+    let mut order = vec![center];
+    for i in 1..=center {
+        for lane in [center.checked_add(i), center.checked_sub(i)]
+            .into_iter()
+            .flatten()
+        {
+            if (1..=lanes).contains(&lane) && order.len() < usize::from(lanes) {
+                order.push(lane);
+            }
+        }
+    }
+    order
+}
+
+/// The heat and lane of each of `n` entries, fastest first.
+fn seed_heats(n: usize, lanes: u8, seeding: Seeding) -> Vec<(u16, u8)> {
+    let size = usize::from(lanes.max(1));
+    let heats = n.div_ceil(size);
+    let circle = match seeding {
+        Seeding::Circle(c) if heats > 1 => usize::from(c).min(heats),
+        _ => 0,
+    };
+    // The entries of each heat, from the last heat, fastest first
+    let mut seeded: Vec<Vec<usize>> = vec![Vec::new(); heats];
+    let circled = if circle == heats { n } else { circle * size };
+    for i in 0..circled {
+        seeded[i % circle].push(i);
+    }
+    // The rest are timed finals, filling heats from the last
+    let rest = heats - circle;
+    if rest > 0 {
+        let mut sizes = vec![size; rest];
+        sizes[rest - 1] = n - circled - (rest - 1) * size;
+        if rest > 1 && sizes[rest - 1] < MIN_FIRST_HEAT {
+            let moved = MIN_FIRST_HEAT - sizes[rest - 1];
+            sizes[rest - 1] += moved;
+            sizes[rest - 2] -= moved;
+        }
+        let mut next = circled;
+        for (h, s) in sizes.into_iter().enumerate() {
+            seeded[circle + h].extend(next..next + s);
+            next += s;
+        }
+    }
+
+    let order = lane_order(lanes);
+    let mut positions = vec![(0, 0); n];
+    for (h, entries) in seeded.iter().enumerate() {
+        let heat = (heats - h) as u16;
+        for (i, lane) in entries.iter().zip(&order) {
+            positions[*i] = (heat, *lane);
+        }
+    }
+    positions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries(n: usize, with_times: usize) -> EventEntries {
+        EventEntries {
+            number: 12,
+            gender: Gender::Female,
+            min_age: Some(13),
+            max_age: Some(14),
+            event: "100 FL SCY".parse().unwrap(),
+            entries: (0..n)
+                .map(|i| Entry {
+                    name: format!("Swimmer {}", i + 1),
+                    team: "Anytown Aquatics".to_owned(),
+                    age: Some(14),
+                    seed: (i < with_times).then_some(SwimTime {
+                        seconds: 60.0 + i as f32,
+                        relay: false,
+                    }),
+                })
+                .rev()
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_lane_order() {
+        assert_eq!(lane_order(8), [4, 5, 3, 6, 2, 7, 1, 8]);
+        assert_eq!(lane_order(6), [3, 4, 2, 5, 1, 6]);
+        assert_eq!(lane_order(5), [3, 4, 2, 5, 1]);
+        assert_eq!(lane_order(1), [1]);
+        let order = lane_order(u8::MAX);
+        assert_eq!(order.len(), 255);
+        assert_eq!((order[0], order[253], order[254]), (128, 255, 1));
+    }
+
+    #[test]
+    fn test_timed_final() {
+        let sheet = PsychSheet::new(None, &[entries(18, 17)], 8, Seeding::TimedFinal);
+        let e = &sheet.events[0];
+        assert_eq!(e.heats, 3);
+        assert_eq!(e.entries[0].entry.name, "Swimmer 1");
+        assert_eq!((e.entries[0].heat, e.entries[0].lane), (3, 4));
+        assert_eq!((e.entries[1].heat, e.entries[1].lane), (3, 5));
+        // Two left for the first heat, so it takes one from the second
+        assert_eq!(e.heat(1).len(), 3);
+        assert_eq!(e.heat(2).len(), 7);
+        assert_eq!(e.heat(3).len(), 8);
+        assert_eq!(e.entries[17].entry.seed, None);
+        assert_eq!(e.entries[17].heat, 1);
+        assert_eq!(e.entries[15].heat, 1);
+        assert_eq!(e.entries[15].lane, 4);
+    }
+
+    #[test]
+    fn test_circle_seeding() {
+        let sheet = PsychSheet::new(None, &[entries(30, 30)], 8, Seeding::Circle(3));
+        let e = &sheet.events[0];
+        assert_eq!(e.heats, 4);
+        let position = |rank: usize| (e.entries[rank - 1].heat, e.entries[rank - 1].lane);
+        assert_eq!(position(1), (4, 4));
+        assert_eq!(position(2), (3, 4));
+        assert_eq!(position(3), (2, 4));
+        assert_eq!(position(4), (4, 5));
+        assert_eq!(position(24), (2, 8));
+        assert_eq!(position(25), (1, 4));
+        assert_eq!(e.heat(1).len(), 6);
+
+        // Fewer heats than are circle seeded
+        let sheet = PsychSheet::new(None, &[entries(10, 10)], 8, Seeding::Circle(3));
+        let e = &sheet.events[0];
+        assert_eq!(e.heats, 2);
+        assert_eq!((e.heat(1).len(), e.heat(2).len()), (5, 5));
+        assert_eq!((e.entries[1].heat, e.entries[1].lane), (1, 4));
+    }
+
+    #[test]
+    fn test_output() {
+        let sheet = PsychSheet::new(
+            Some("Anytown Spring Invitational".to_owned()),
+            &[entries(2, 1)],
+            8,
+            Seeding::TimedFinal,
+        );
+        let text = sheet.to_string();
+        assert!(text.contains("Event 12  Girls 13-14 100 Yard Butterfly\n"));
+        assert!(text.contains(
+            "  1 Swimmer 1                14 Anytown Aquatics          1:00.00     1    4\n"
+        ));
+        assert!(text.contains(" NT "));
+
+        let html = sheet.to_html();
+        assert!(html.contains("<h2>Event 12  Girls 13-14 100 Yard Butterfly</h2>"));
+        assert!(html.contains("<td>Swimmer 2</td><td>14</td><td>Anytown Aquatics</td><td>NT</td>"));
+    }
+}
@@ -0,0 +1,347 @@
+//! Checks swimmers' times against a meet's qualifying standards, listing the
+//! events each swimmer qualifies for and the standards they narrowly miss.
+//!
+//! Times are taken from Top Times results, one swimmer at a time, and only
+//! count if they were swum within the meet's qualifying window. Times from
+//! other courses count if the meet accepts them, once converted to the
+//! course of the standard.
+
+use std::collections::HashMap;
+
+use chrono::NaiveDate;
+
+use crate::{
+    agegroup::BirthWindow,
+    common::{hundredths, Course, Gender, SwimEvent},
+    usas::toptimes::TopTime,
+};
+
+/// A meet's qualifying standards and the rules for meeting them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Standards {
+    pub standards: Vec<Standard>,
+    /// First day of the qualifying window
+    pub from_date: NaiveDate,
+    /// Last day of the qualifying window
+    pub to_date: NaiveDate,
+    /// Courses, other than that of each standard, whose times are accepted
+    pub conversions: Vec<Conversion>,
+    /// Date swimmers' ages are taken on, usually the meet's first day
+    pub age_up_date: Option<NaiveDate>,
+}
+
+/// The time to beat in an event for an age group and gender.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Standard {
+    pub event: SwimEvent,
+    /// `Mixed` for standards open to either gender
+    pub gender: Gender,
+    pub min_age: Option<u8>,
+    pub max_age: Option<u8>,
+    /// Times equal to or faster than this qualify
+    pub time: f32,
+}
+
+/// Acceptance of times swum in another course.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Conversion {
+    pub from: Course,
+    /// Factor to multiply times by, or `None` to use the standard factors of
+    /// [`SwimEvent::convert`]. Meets that convert with their own factors
+    /// usually publish one per course pair.
+    pub factor: Option<f32>,
+}
+
+/// A swimmer's best time in the window against a standard.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Check {
+    pub swimmer_id: Option<usize>,
+    pub swimmer_name: String,
+    pub gender: Gender,
+    pub age: u8,
+    /// The standard's event
+    pub event: SwimEvent,
+    pub standard: f32,
+    /// The time used, as swum
+    pub time: f32,
+    pub course: Course,
+    pub date: NaiveDate,
+    pub meet_name: String,
+    /// The time used, converted to the course of the standard
+    pub converted: f32,
+}
+
+impl Check {
+    /// Seconds over the standard, negative when the standard is beaten.
+    pub fn gap(&self) -> f32 {
+        (i64::from(hundredths(self.converted)) - i64::from(hundredths(self.standard))) as f32
+            / 100.0
+    }
+
+    /// Whether the time equals or beats the standard, as both are printed.
+    pub fn qualifies(&self) -> bool {
+        hundredths(self.converted) <= hundredths(self.standard)
+    }
+}
+
+/// The result of checking a group of swimmers, sorted by swimmer and then by
+/// the order of the standards.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Report {
+    pub qualified: Vec<Check>,
+    /// Times over a standard by no more than the near miss margin
+    pub near_misses: Vec<Check>,
+}
+
+impl Standard {
+    fn applies(&self, gender: &Gender, age: u8) -> bool {
+        (self.gender == Gender::Mixed || self.gender == *gender)
+            && self.min_age.is_none_or(|min| age >= min)
+            && self.max_age.is_none_or(|max| age <= max)
+    }
+}
+
+impl Standards {
+    /// Checks `times` against every standard. A swimmer's age is their age on
+    /// the age-up date, if their times settle it, or else the oldest age found
+    /// in their times, which is their age on the latest swim. Their gender is
+    /// the first found other than `Mixed`.
+    ///
+    /// `near_miss` is the margin, as a fraction of the standard, within which
+    /// a time that doesn't qualify is reported as a near miss. A standard of
+    /// 1:00.00 and a `near_miss` of 0.02 report times up to 1:01.20.
+    pub fn check(&self, times: &[TopTime], near_miss: f32) -> Report {
+        let mut swimmers: HashMap<String, Vec<&TopTime>> = HashMap::new();
+        for t in times {
+            let key = t
+                .swimmer_id
+                .map_or_else(|| t.swimmer_name.clone(), |id| id.to_string());
+            swimmers.entry(key).or_default().push(t);
+        }
+        let mut swimmers: Vec<Vec<&TopTime>> = swimmers.into_values().collect();
+        swimmers.sort_by(|a, b| {
+            (&a[0].swimmer_name, a[0].swimmer_id).cmp(&(&b[0].swimmer_name, b[0].swimmer_id))
+        });
+
+        let mut report = Report::default();
+        for swims in swimmers {
+            let age = self
+                .age_up_date
+                .and_then(|on| {
+                    BirthWindow::infer(swims.iter().map(|t| (t.date, t.age)))?.age_on(on)
+                })
+                .unwrap_or_else(|| swims.iter().map(|t| t.age).max().unwrap_or_default());
+            let gender = swims
+                .iter()
+                .map(|t| &t.gender)
+                .find(|g| **g != Gender::Mixed)
+                .unwrap_or(&Gender::Mixed);
+            for standard in self.standards.iter().filter(|s| s.applies(gender, age)) {
+                let best = swims
+                    .iter()
+                    .filter(|t| t.date >= self.from_date && t.date <= self.to_date)
+                    .filter_map(|t| Some((*t, self.convert(t, &standard.event)?)))
+                    .min_by(|(_, a), (_, b)| a.total_cmp(b));
+                let (t, converted) = match best {
+                    Some(b) => b,
+                    None => continue,
+                };
+                let check = Check {
+                    swimmer_id: t.swimmer_id,
+                    swimmer_name: t.swimmer_name.clone(),
+                    gender: gender.clone(),
+                    age,
+                    event: standard.event.clone(),
+                    standard: standard.time,
+                    time: t.time,
+                    course: t.course.clone(),
+                    date: t.date,
+                    meet_name: t.meet_name.clone(),
+                    converted,
+                };
+                if check.qualifies() {
+                    report.qualified.push(check);
+                } else if hundredths(converted) <= hundredths(standard.time * (1.0 + near_miss)) {
+                    report.near_misses.push(check);
+                }
+            }
+        }
+        report
+    }
+
+    /// The time `t` counts as in `event`, or `None` if it was swum in another
+    /// event or in a course that isn't accepted.
+    fn convert(&self, t: &TopTime, event: &SwimEvent) -> Option<f32> {
+        let swum = SwimEvent(t.distance.clone(), t.stroke.clone(), t.course.clone());
+        if swum == *event {
+            return Some(t.time);
+        }
+        let conversion = self.conversions.iter().find(|c| c.from == t.course)?;
+        let (converted, seconds) = swum.convert(t.time, &event.2)?;
+        if converted != *event {
+            return None;
+        }
+        Some(conversion.factor.map_or(seconds, |f| t.time * f))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        common::{Distance, Stroke, SwimTime},
+        testing::top_time,
+    };
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    fn time(name: &str, id: usize, event: &str, seconds: f32, on: &str, age: u8) -> TopTime {
+        TopTime {
+            age,
+            date: date(on),
+            swimmer_id: Some(id),
+            swimmer_name: name.to_owned(),
+            ..top_time(event, seconds)
+        }
+    }
+
+    fn standards() -> Standards {
+        let standard = |event: &str, time| Standard {
+            event: event.parse().unwrap(),
+            gender: Gender::Female,
+            min_age: Some(13),
+            max_age: Some(14),
+            time,
+        };
+        Standards {
+            standards: vec![
+                standard("100 FR SCY", 55.0),
+                standard("500 FR SCY", 330.0),
+                standard("100 FL SCY", 62.0),
+            ],
+            from_date: date("2024-03-01"),
+            to_date: date("2025-03-01"),
+            age_up_date: None,
+            conversions: vec![Conversion {
+                from: Course::LCM,
+                factor: None,
+            }],
+        }
+    }
+
+    #[test]
+    fn test_convert() {
+        let event: SwimEvent = "400 FR LCM".parse().unwrap();
+        let (converted, seconds) = event.convert(300.0, &Course::SCY).unwrap();
+        assert_eq!(
+            converted,
+            SwimEvent(Distance::_500, Stroke::Freestyle, Course::SCY)
+        );
+        assert!((seconds - 336.13).abs() < 0.01);
+
+        let event: SwimEvent = "100 FR SCY".parse().unwrap();
+        let (_, seconds) = event.convert(50.0, &Course::SCM).unwrap();
+        assert!((seconds - 55.5).abs() < 0.01);
+
+        let event: SwimEvent = "100 IM SCY".parse().unwrap();
+        assert_eq!(event.convert(60.0, &Course::LCM), None);
+    }
+
+    #[test]
+    fn test_check() {
+        let times = vec![
+            time("Swimmer, Alpha", 1, "100 FR SCY", 56.0, "2024-05-01", 13),
+            time("Swimmer, Alpha", 1, "100 FR SCY", 54.9, "2024-12-01", 14),
+            // Faster, but before the window
+            time("Swimmer, Alpha", 1, "100 FL SCY", 61.0, "2024-01-01", 13),
+            time("Swimmer, Alpha", 1, "100 FL SCY", 63.0, "2024-06-01", 13),
+            // 4:52 in long course converts to 5:27.17 in yards
+            time("Swimmer, Alpha", 1, "400 FR LCM", 292.0, "2024-07-01", 14),
+            // Too old for the standards
+            time("Swimmer, Bravo", 2, "100 FR SCY", 50.0, "2024-12-01", 15),
+        ];
+        let report = standards().check(&times, 0.02);
+
+        assert_eq!(report.qualified.len(), 2);
+        let check = &report.qualified[0];
+        assert_eq!(check.swimmer_name, "Swimmer, Alpha");
+        assert_eq!(check.age, 14);
+        assert_eq!(check.time, 54.9);
+        assert_eq!(check.date, date("2024-12-01"));
+        assert!(check.qualifies());
+
+        let check = &report.qualified[1];
+        assert_eq!(check.event.0, Distance::_500);
+        assert_eq!(check.course, Course::LCM);
+        assert_eq!(check.time, 292.0);
+        assert!(check.converted < 330.0);
+
+        assert_eq!(report.near_misses.len(), 1);
+        let check = &report.near_misses[0];
+        assert_eq!(check.event.1, Stroke::Butterfly);
+        assert_eq!(check.time, 63.0);
+        assert!((check.gap() - 1.0).abs() < 0.001);
+
+        // Alpha turned 14 in June 2024, so is 15 by a meet in August 2025
+        let mut standards = standards();
+        standards.age_up_date = Some(date("2025-08-01"));
+        let report = standards.check(&times, 0.02);
+        assert!(report.qualified.is_empty());
+        standards.age_up_date = Some(date("2025-03-01"));
+        assert_eq!(standards.check(&times, 0.02).qualified.len(), 2);
+    }
+
+    #[test]
+    fn test_conversion_factor() {
+        let mut standards = standards();
+        standards.conversions[0].factor = Some(1.2);
+        let times = vec![time(
+            "Swimmer, Alpha",
+            1,
+            "400 FR LCM",
+            292.0,
+            "2024-07-01",
+            14,
+        )];
+        let report = standards.check(&times, 0.1);
+        assert!(report.qualified.is_empty());
+        assert_eq!(report.near_misses.len(), 1);
+        assert!((report.near_misses[0].converted - 350.4).abs() < 0.01);
+
+        standards.conversions.clear();
+        let report = standards.check(&times, 0.1);
+        assert_eq!(report, Report::default());
+    }
+
+    #[test]
+    fn test_equal_time() {
+        // Parsed, 1:08.23 is 68.229996 while the same time read back from the
+        // times store, in hundredths, is 68.23
+        let standard: SwimTime = "1:08.23".parse().unwrap();
+        let seconds = 6823_f32 / 100.0;
+        assert_ne!(standard.seconds, seconds);
+
+        let mut standards = standards();
+        standards.standards[0].time = standard.seconds;
+        let times = vec![time(
+            "Swimmer, Alpha",
+            1,
+            "100 FR SCY",
+            seconds,
+            "2024-12-01",
+            14,
+        )];
+        let report = standards.check(&times, 0.02);
+        assert_eq!(report.qualified.len(), 1);
+        assert_eq!(report.qualified[0].gap(), 0.0);
+
+        // 2% over 1:06.89 is 1:08.2278, which is 1:08.23 as printed
+        standards.standards[0].time = "1:06.89".parse::<SwimTime>().unwrap().seconds;
+        let report = standards.check(&times, 0.02);
+        assert!(report.qualified.is_empty());
+        assert_eq!(report.near_misses.len(), 1);
+        assert!((report.near_misses[0].gap() - 1.34).abs() < 0.001);
+    }
+}
@@ -0,0 +1,368 @@
+//! Picks the fastest relay lineups from a team's swimmers and their best
+//! times.
+//!
+//! A medley relay is an assignment of swimmers to the backstroke,
+//! breaststroke, butterfly and freestyle legs, and a freestyle relay a choice
+//! of four swimmers. Lineups are found by a branch-and-bound search over the
+//! legs, so every lineup returned is the fastest possible from the swimmers
+//! left. The A relay is the fastest from every eligible swimmer, the B relay
+//! the fastest from those not on the A relay, and so on.
+
+use num_enum::TryFromPrimitive;
+
+use crate::common::{Distance, Gender, Stroke, SwimEvent, SwimTime};
+
+/// A swimmer who may be put on a relay.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Swimmer {
+    pub name: String,
+    pub gender: Gender,
+    pub age: u8,
+    /// Best times, in any course. Only the fastest time per event is used.
+    pub times: Vec<(SwimEvent, SwimTime)>,
+}
+
+/// A relay event and the rules for who may swim it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Relay {
+    /// A freestyle or medley relay, such as `200 MED-R SCY`
+    pub event: SwimEvent,
+    /// `Mixed` for relays of two men and two women
+    pub gender: Gender,
+    pub min_age: Option<u8>,
+    pub max_age: Option<u8>,
+    /// Seconds taken off the individual times of the second to fourth legs,
+    /// which start from a relay exchange rather than a flat start
+    pub flying_start: f32,
+}
+
+/// A leg of a lineup, and the time expected of it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Leg {
+    pub swimmer: String,
+    /// The individual event swum on this leg, such as `50 BK SCY`
+    pub event: SwimEvent,
+    /// The swimmer's time in the event, less the flying start allowance on
+    /// legs after the first
+    pub time: SwimTime,
+}
+
+/// Four swimmers in the order they swim.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lineup {
+    pub legs: Vec<Leg>,
+    /// Sum of the legs' times
+    pub time: SwimTime,
+}
+
+/// The swimmers eligible for one leg, fastest first, as indices into the
+/// eligible swimmers with their time on the leg.
+type Candidates = Vec<(usize, f32)>;
+
+impl Relay {
+    /// The individual events of the four legs, in order, or `None` if the
+    /// event isn't a freestyle or medley relay of four equal legs.
+    pub fn legs(&self) -> Option<[SwimEvent; 4]> {
+        let SwimEvent(distance, stroke, course) = &self.event;
+        let leg = Distance::try_from_primitive(distance.clone() as u16 / 4).ok()?;
+        let leg = |stroke| SwimEvent(leg.clone(), stroke, course.clone());
+        match stroke {
+            Stroke::FreestyleRelay => Some([
+                leg(Stroke::Freestyle),
+                leg(Stroke::Freestyle),
+                leg(Stroke::Freestyle),
+                leg(Stroke::Freestyle),
+            ]),
+            Stroke::MedleyRelay => Some([
+                leg(Stroke::Backstroke),
+                leg(Stroke::Breaststroke),
+                leg(Stroke::Butterfly),
+                leg(Stroke::Freestyle),
+            ]),
+            _ => None,
+        }
+    }
+
+    fn eligible(&self, swimmer: &Swimmer) -> bool {
+        (self.gender == Gender::Mixed || self.gender == swimmer.gender)
+            && self.min_age.is_none_or(|min| swimmer.age >= min)
+            && self.max_age.is_none_or(|max| swimmer.age <= max)
+    }
+
+    /// The fastest `count` lineups, A relay first, from the eligible
+    /// `swimmers` whose names aren't in `used`. Fewer are returned if there
+    /// aren't enough swimmers with times for the legs.
+    ///
+    /// A swimmer's time on a leg is their best time in the leg's event in the
+    /// relay's course or, failing that, their best time in another course
+    /// converted with [`SwimEvent::convert`].
+    pub fn lineups(&self, swimmers: &[Swimmer], used: &[&str], count: usize) -> Vec<Lineup> {
+        let events = match self.legs() {
+            Some(l) => l,
+            None => return Vec::new(),
+        };
+        let mut swimmers: Vec<&Swimmer> = swimmers
+            .iter()
+            .filter(|s| self.eligible(s) && !used.contains(&s.name.as_str()))
+            .collect();
+
+        let mut lineups = Vec::new();
+        while lineups.len() < count {
+            let candidates: Vec<Candidates> = events
+                .iter()
+                .enumerate()
+                .map(|(i, event)| {
+                    let flying_start = if i == 0 { 0.0 } else { self.flying_start };
+                    let mut c: Candidates = swimmers
+                        .iter()
+                        .enumerate()
+                        .filter_map(|(j, s)| Some((j, best_time(s, event)? - flying_start)))
+                        .collect();
+                    c.sort_by(|a, b| a.1.total_cmp(&b.1));
+                    c
+                })
+                .collect();
+            let genders: Vec<&Gender> = swimmers.iter().map(|s| &s.gender).collect();
+            let mut search = Search {
+                candidates: &candidates,
+                genders: &genders,
+                mixed: self.gender == Gender::Mixed,
+                chosen: Vec::new(),
+                best: None,
+            };
+            search.run(0.0);
+            let legs = match search.best {
+                Some((_, legs)) => legs,
+                None => break,
+            };
+
+            lineups.push(Lineup {
+                legs: legs
+                    .iter()
+                    .zip(&events)
+                    .map(|(&(j, time), event)| Leg {
+                        swimmer: swimmers[j].name.clone(),
+                        event: event.clone(),
+                        time: SwimTime {
+                            seconds: time,
+                            relay: false,
+                        },
+                    })
+                    .collect(),
+                time: SwimTime {
+                    seconds: legs.iter().map(|(_, t)| t).sum(),
+                    relay: false,
+                },
+            });
+            let mut on_relay: Vec<usize> = legs.iter().map(|(j, _)| *j).collect();
+            on_relay.sort_unstable();
+            for j in on_relay.into_iter().rev() {
+                swimmers.remove(j);
+            }
+        }
+        lineups
+    }
+}
+
+/// A swimmer's time in `event`: their best in the event's course, or their
+/// best converted from another course if they have none in it.
+fn best_time(swimmer: &Swimmer, event: &SwimEvent) -> Option<f32> {
+    let times = swimmer.times.iter();
+    times
+        .clone()
+        .filter(|(e, _)| e == event)
+        .map(|(_, t)| t.seconds)
+        .reduce(f32::min)
+        .or_else(|| {
+            times
+                .filter_map(|(e, t)| match e.convert(t.seconds, &event.2)? {
+                    (converted, seconds) if converted == *event => Some(seconds),
+                    _ => None,
+                })
+                .reduce(f32::min)
+        })
+}
+
+/// Depth-first search for the fastest assignment of swimmers to legs.
+struct Search<'a> {
+    candidates: &'a [Candidates],
+    genders: &'a [&'a Gender],
+    /// Whether the relay must be two men and two women
+    mixed: bool,
+    chosen: Vec<(usize, f32)>,
+    best: Option<(f32, Vec<(usize, f32)>)>,
+}
+
+impl Search<'_> {
+    fn run(&mut self, total: f32) {
+        let leg = self.chosen.len();
+        if leg == self.candidates.len() {
+            if self.best.as_ref().is_none_or(|(best, _)| total < *best) {
+                self.best = Some((total, self.chosen.clone()));
+            }
+            return;
+        }
+        // No remaining leg can be swum faster than its fastest candidate
+        let bound: f32 = self.candidates[leg..]
+            .iter()
+            .map(|c| c.first().map_or(f32::INFINITY, |(_, t)| *t))
+            .sum();
+        for &(j, time) in &self.candidates[leg] {
+            if self
+                .best
+                .as_ref()
+                .is_some_and(|(best, _)| total + time + bound - self.candidates[leg][0].1 >= *best)
+            {
+                // Candidates are sorted, so the rest are no better
+                break;
+            }
+            if !self.allowed(j) {
+                continue;
+            }
+            self.chosen.push((j, time));
+            self.run(total + time);
+            self.chosen.pop();
+        }
+    }
+
+    /// Whether swimmer `j` can swim the next leg: they aren't already on the
+    /// relay, and a mixed relay doesn't get more than two of one gender.
+    fn allowed(&self, j: usize) -> bool {
+        if self.chosen.iter().any(|(k, _)| *k == j) {
+            return false;
+        }
+        !self.mixed
+            || self
+                .chosen
+                .iter()
+                .filter(|(k, _)| self.genders[*k] == self.genders[j])
+                .count()
+                < 2
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::Course;
+
+    fn swimmer(name: &str, gender: Gender, age: u8, times: &[(&str, f32)]) -> Swimmer {
+        Swimmer {
+            name: name.to_owned(),
+            gender,
+            age,
+            times: times
+                .iter()
+                .map(|(e, t)| {
+                    (
+                        e.parse().unwrap(),
+                        SwimTime {
+                            seconds: *t,
+                            relay: false,
+                        },
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    fn relay(event: &str, gender: Gender) -> Relay {
+        Relay {
+            event: event.parse().unwrap(),
+            gender,
+            min_age: None,
+            max_age: None,
+            flying_start: 0.0,
+        }
+    }
+
+    fn names(lineup: &Lineup) -> Vec<&str> {
+        lineup.legs.iter().map(|l| l.swimmer.as_str()).collect()
+    }
+
+    fn team() -> Vec<Swimmer> {
+        use Gender::{Female, Male};
+        vec![
+            swimmer(
+                "Alpha",
+                Female,
+                14,
+                &[
+                    ("50 BK SCY", 28.0),
+                    ("50 FL SCY", 27.0),
+                    ("50 FR SCY", 25.0),
+                ],
+            ),
+            swimmer(
+                "Bravo",
+                Female,
+                14,
+                &[
+                    ("50 BK SCY", 29.0),
+                    ("50 BR SCY", 33.0),
+                    ("50 FR SCY", 26.0),
+                ],
+            ),
+            swimmer(
+                "Charlie",
+                Female,
+                13,
+                &[
+                    ("50 FL SCY", 28.5),
+                    ("50 BR SCY", 32.0),
+                    ("50 FR SCY", 26.5),
+                ],
+            ),
+            swimmer("Delta", Female, 12, &[("50 FR SCY", 27.0)]),
+            swimmer(
+                "Echo",
+                Male,
+                14,
+                &[("50 BR SCY", 29.5), ("50 FR SCY", 24.0)],
+            ),
+            // Only a long course time, converted to 31.62 in yards
+            swimmer("Foxtrot", Male, 14, &[("50 BK LCM", 35.8)]),
+        ]
+    }
+
+    #[test]
+    fn test_medley_lineup() {
+        let lineups = relay("200 MED-R SCY", Gender::Female).lineups(&team(), &[], 3);
+        // Putting Alpha on fly instead of backstroke saves a second overall
+        assert_eq!(lineups.len(), 1);
+        assert_eq!(names(&lineups[0]), ["Bravo", "Charlie", "Alpha", "Delta"]);
+        assert_eq!(lineups[0].time.seconds, 29.0 + 32.0 + 27.0 + 27.0);
+        assert_eq!(
+            lineups[0].legs[1].event,
+            SwimEvent(Distance::_50, Stroke::Breaststroke, Course::SCY)
+        );
+    }
+
+    #[test]
+    fn test_free_lineups() {
+        let mut relay = relay("200 FR-R SCY", Gender::Female);
+        relay.flying_start = 0.5;
+        let lineups = relay.lineups(&team(), &[], 2);
+        assert_eq!(lineups.len(), 1);
+        assert_eq!(names(&lineups[0]), ["Alpha", "Bravo", "Charlie", "Delta"]);
+        assert_eq!(lineups[0].legs[0].time.seconds, 25.0);
+        assert_eq!(lineups[0].legs[1].time.seconds, 25.5);
+        assert_eq!(lineups[0].time.seconds, 104.5 - 1.5);
+
+        // Swimmers already used elsewhere
+        let lineups = relay.lineups(&team(), &["Delta"], 1);
+        assert!(lineups.is_empty());
+
+        relay.max_age = Some(13);
+        assert!(relay.lineups(&team(), &[], 1).is_empty());
+    }
+
+    #[test]
+    fn test_mixed_lineup() {
+        let lineups = relay("200 MED-R SCY", Gender::Mixed).lineups(&team(), &[], 2);
+        assert_eq!(lineups.len(), 1);
+        let lineup = &lineups[0];
+        assert_eq!(names(lineup), ["Foxtrot", "Echo", "Alpha", "Bravo"]);
+        assert!((lineup.legs[0].time.seconds - 31.62).abs() < 0.01);
+    }
+}
@@ -0,0 +1,525 @@
+//! Projects the outcome of a dual meet or invitational from seed times.
+//!
+//! Each event's entries are placed in order of their seed times, as if every
+//! swim went exactly to seed, and points are awarded from a scoring table.
+//! Entries can be built from Top Times results with
+//! [`EventEntries::add_times`] and [`EventEntries::add_relay_times`].
+
+use std::collections::HashMap;
+
+use crate::{
+    common::{Gender, Stroke, SwimEvent, SwimTime},
+    usas::toptimes::{TopRelayTime, TopTime},
+};
+
+/// Points for each place, first place first. Places beyond the table score
+/// nothing.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScoringTable {
+    pub individual: Vec<f32>,
+    pub relay: Vec<f32>,
+}
+
+impl ScoringTable {
+    /// A table with relays worth double the individual points.
+    pub fn relay_double(individual: Vec<f32>) -> Self {
+        let relay = individual.iter().map(|p| p * 2.0).collect();
+        ScoringTable { individual, relay }
+    }
+
+    /// Dual meet scoring, 6-4-3-2-1, with double points for relays.
+    pub fn dual() -> Self {
+        Self::relay_double(vec![6.0, 4.0, 3.0, 2.0, 1.0])
+    }
+
+    /// Championship scoring for an A and B final of eight, 20-17-16-15-14-
+    /// 13-12-11 and 9-7-6-5-4-3-2-1, with double points for relays.
+    pub fn championship() -> Self {
+        Self::relay_double(vec![
+            20.0, 17.0, 16.0, 15.0, 14.0, 13.0, 12.0, 11.0, 9.0, 7.0, 6.0, 5.0, 4.0, 3.0, 2.0, 1.0,
+        ])
+    }
+}
+
+/// Limits on entries, each unlimited if `None`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EntryLimits {
+    /// Entries per team in an event. A team's fastest entries are kept.
+    pub per_team: Option<usize>,
+    /// Entries per team in an event that may score. Others are placed, but
+    /// are passed over when points are awarded.
+    pub scoring_per_team: Option<usize>,
+    /// Individual events per swimmer. A swimmer entered in more is entered
+    /// in the first events in meet order.
+    pub per_swimmer: Option<usize>,
+}
+
+/// A seed time and whether it was converted from another course.
+type Seed = (bool, f32);
+
+/// An entry on a psych sheet.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    /// The swimmer's name, or the relay's name, such as `A`
+    pub name: String,
+    pub team: String,
+    pub age: Option<u8>,
+    /// Seed time in the event's course, or `None` for no time
+    pub seed: Option<SwimTime>,
+}
+
+/// An event on a meet's program, and its entries.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EventEntries {
+    pub number: u16,
+    /// `Mixed` for events open to either gender, or mixed relays
+    pub gender: Gender,
+    pub min_age: Option<u8>,
+    pub max_age: Option<u8>,
+    pub event: SwimEvent,
+    pub entries: Vec<Entry>,
+}
+
+/// An entry's projected finish in an event.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Placing {
+    /// Place, shared by entries with the same seed time
+    pub place: u16,
+    pub entry: Entry,
+    pub points: f32,
+}
+
+/// The projected result of an event.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EventScore {
+    pub number: u16,
+    pub event: SwimEvent,
+    /// Entries with a seed time, in order of finish
+    pub placings: Vec<Placing>,
+    /// Points scored by each team that scored, most first
+    pub team_points: Vec<(String, f32)>,
+    /// Points the projected winner of the meet scores in the event less those
+    /// of the runner-up, or 0 for a meet of one team
+    pub swing: f32,
+}
+
+/// The projected result of a meet.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Projection {
+    pub events: Vec<EventScore>,
+    /// Total points of each team, most first
+    pub team_scores: Vec<(String, f32)>,
+}
+
+impl EventEntries {
+    fn is_relay(&self) -> bool {
+        matches!(self.event.1, Stroke::FreestyleRelay | Stroke::MedleyRelay)
+    }
+
+    fn entered(&self, name: &str, team: &str) -> bool {
+        self.entries
+            .iter()
+            .any(|n| n.name == name && n.team == team)
+    }
+
+    fn admits(&self, gender: &Gender, age: Option<u8>) -> bool {
+        (self.gender == Gender::Mixed || self.gender == *gender)
+            && age.is_none_or(|age| {
+                self.min_age.is_none_or(|min| age >= min)
+                    && self.max_age.is_none_or(|max| age <= max)
+            })
+    }
+
+    /// Enters each swimmer eligible for the event with their best time in
+    /// it. Times in other courses are converted with [`SwimEvent::convert`]
+    /// and only used for swimmers without a time in the event's course. A
+    /// swimmer's age is the oldest found in their times. Swimmers already
+    /// entered under the same name and team are left as they are.
+    pub fn add_times(&mut self, times: &[TopTime]) {
+        let mut best: HashMap<String, (&TopTime, Seed)> = HashMap::new();
+        let mut ages: HashMap<String, u8> = HashMap::new();
+        for t in times {
+            let key = t
+                .swimmer_id
+                .map_or_else(|| t.swimmer_name.clone(), |id| id.to_string());
+            let age = ages.entry(key.clone()).or_default();
+            *age = (*age).max(t.age);
+            let swum = SwimEvent(t.distance.clone(), t.stroke.clone(), t.course.clone());
+            let seed = match seed(&swum, t.time, &self.event) {
+                Some(s) => s,
+                None => continue,
+            };
+            if best.get(&key).is_none_or(|(_, b)| seed < *b) {
+                best.insert(key, (t, seed));
+            }
+        }
+        let mut entries: Vec<(String, (&TopTime, Seed))> = best.into_iter().collect();
+        entries.sort_by(|(a, (s, _)), (b, (t, _))| (&s.swimmer_name, a).cmp(&(&t.swimmer_name, b)));
+        for (key, (t, (_, seconds))) in entries {
+            let age = ages[&key];
+            if !self.admits(&t.gender, Some(age)) || self.entered(&t.swimmer_name, &t.team_name) {
+                continue;
+            }
+            self.entries.push(Entry {
+                name: t.swimmer_name.clone(),
+                team: t.team_name.clone(),
+                age: Some(age),
+                seed: Some(SwimTime {
+                    seconds,
+                    relay: false,
+                }),
+            });
+        }
+    }
+
+    /// Enters each relay eligible for the event with its best time in it,
+    /// as for [`add_times`](Self::add_times). Relays are told apart by team
+    /// and relay name.
+    pub fn add_relay_times(&mut self, times: &[TopRelayTime]) {
+        let mut best: HashMap<(String, String), Seed> = HashMap::new();
+        for t in times.iter().filter(|t| self.admits(&t.gender, None)) {
+            let swum = SwimEvent(t.distance.clone(), t.stroke.clone(), t.course.clone());
+            let seed = match seed(&swum, t.time, &self.event) {
+                Some(s) => s,
+                None => continue,
+            };
+            let key = (
+                t.team_name.clone(),
+                t.relay_name.clone().unwrap_or_default(),
+            );
+            if best.get(&key).is_none_or(|b| seed < *b) {
+                best.insert(key, seed);
+            }
+        }
+        let mut entries: Vec<((String, String), Seed)> = best.into_iter().collect();
+        entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+        for ((team, name), (_, seconds)) in entries {
+            if self.entered(&name, &team) {
+                continue;
+            }
+            self.entries.push(Entry {
+                name,
+                team,
+                age: None,
+                seed: Some(SwimTime {
+                    seconds,
+                    relay: true,
+                }),
+            });
+        }
+    }
+}
+
+/// A time swum in `swum` as a seed in `event`. Seeds compare with times swum in
+/// the event's course first, and then by time.
+fn seed(swum: &SwimEvent, seconds: f32, event: &SwimEvent) -> Option<Seed> {
+    if swum == event {
+        return Some((false, seconds));
+    }
+    match swum.convert(seconds, &event.2)? {
+        (converted, seconds) if converted == *event => Some((true, seconds)),
+        _ => None,
+    }
+}
+
+/// Projects `events`, in meet order, by placing each event's entries by seed
+/// time and awarding points from `table`. Entries without a seed time aren't
+/// placed. Entries tied on seed time share their place and the points of the
+/// places they fill.
+pub fn project(events: &[EventEntries], table: &ScoringTable, limits: &EntryLimits) -> Projection {
+    let mut swims: HashMap<(String, String), usize> = HashMap::new();
+    let mut events: Vec<EventScore> = events
+        .iter()
+        .map(|e| {
+            let mut entries: Vec<&Entry> = e.entries.iter().filter(|n| n.seed.is_some()).collect();
+            entries.sort_by_key(|n| n.seed.as_ref().map(SwimTime::hundredths));
+
+            // Apply the entry limits, fastest entries first
+            let mut per_team: HashMap<&str, usize> = HashMap::new();
+            entries.retain(|n| {
+                if !e.is_relay() {
+                    if let Some(max) = limits.per_swimmer {
+                        let key = (n.team.clone(), n.name.clone());
+                        if swims.get(&key).is_some_and(|count| *count >= max) {
+                            return false;
+                        }
+                    }
+                }
+                let count = per_team.entry(&n.team).or_default();
+                *count += 1;
+                limits.per_team.is_none_or(|max| *count <= max)
+            });
+            if !e.is_relay() {
+                for n in &entries {
+                    *swims.entry((n.team.clone(), n.name.clone())).or_default() += 1;
+                }
+            }
+
+            let points = if e.is_relay() {
+                &table.relay
+            } else {
+                &table.individual
+            };
+            score_event(e, &entries, points, limits.scoring_per_team)
+        })
+        .collect();
+
+    let mut totals: HashMap<&str, f32> = HashMap::new();
+    for e in &events {
+        for (team, points) in &e.team_points {
+            *totals.entry(team).or_default() += points;
+        }
+        for p in &e.placings {
+            totals.entry(&p.entry.team).or_default();
+        }
+    }
+    let team_scores = sort_points(totals);
+
+    if let [(winner, _), (runner_up, _), ..] = team_scores.as_slice() {
+        for e in &mut events {
+            let points = |team: &str| {
+                e.team_points
+                    .iter()
+                    .find(|(t, _)| t == team)
+                    .map_or(0.0, |(_, p)| *p)
+            };
+            e.swing = points(winner) - points(runner_up);
+        }
+    }
+    Projection {
+        events,
+        team_scores,
+    }
+}
+
+fn score_event(
+    e: &EventEntries,
+    entries: &[&Entry],
+    points: &[f32],
+    scoring_per_team: Option<usize>,
+) -> EventScore {
+    let mut scorers: HashMap<&str, usize> = HashMap::new();
+    let mut placings: Vec<Placing> = Vec::new();
+    // The next place to award points for, counting scorers only
+    let mut scoring_place = 0;
+    for group in entries.chunk_by(|a, b| {
+        a.seed.as_ref().map(SwimTime::hundredths) == b.seed.as_ref().map(SwimTime::hundredths)
+    }) {
+        let place = placings.len() as u16 + 1;
+        let scoring: Vec<bool> = group
+            .iter()
+            .map(|n| {
+                let count = scorers.entry(&n.team).or_default();
+                *count += 1;
+                scoring_per_team.is_none_or(|max| *count <= max)
+            })
+            .collect();
+        let n_scoring = scoring.iter().filter(|s| **s).count();
+        let shared = if n_scoring == 0 {
+            0.0
+        } else {
+            let end = (scoring_place + n_scoring).min(points.len());
+            let total: f32 = points
+                .get(scoring_place..end)
+                .unwrap_or_default()
+                .iter()
+                .sum();
+            total / n_scoring as f32
+        };
+        scoring_place += n_scoring;
+        for (n, scores) in group.iter().zip(scoring) {
+            placings.push(Placing {
+                place,
+                entry: (*n).clone(),
+                points: if scores { shared } else { 0.0 },
+            });
+        }
+    }
+
+    let mut team_points: HashMap<&str, f32> = HashMap::new();
+    for p in placings.iter().filter(|p| p.points > 0.0) {
+        *team_points.entry(&p.entry.team).or_default() += p.points;
+    }
+    EventScore {
+        number: e.number,
+        event: e.event.clone(),
+        team_points: sort_points(team_points),
+        placings,
+        swing: 0.0,
+    }
+}
+
+/// Teams and their points, most first and then by name.
+fn sort_points(points: HashMap<&str, f32>) -> Vec<(String, f32)> {
+    let mut points: Vec<(String, f32)> = points
+        .into_iter()
+        .map(|(team, p)| (team.to_owned(), p))
+        .collect();
+    points.sort_by(|(a, x), (b, y)| y.total_cmp(x).then_with(|| a.cmp(b)));
+    points
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{common::Course, testing::top_time};
+
+    fn entry(name: &str, team: &str, seed: Option<f32>) -> Entry {
+        Entry {
+            name: name.to_owned(),
+            team: team.to_owned(),
+            age: None,
+            seed: seed.map(|seconds| SwimTime {
+                seconds,
+                relay: false,
+            }),
+        }
+    }
+
+    fn event(number: u16, event: &str, entries: Vec<Entry>) -> EventEntries {
+        EventEntries {
+            number,
+            gender: Gender::Female,
+            min_age: None,
+            max_age: None,
+            event: event.parse().unwrap(),
+            entries,
+        }
+    }
+
+    fn dual_meet() -> Vec<EventEntries> {
+        vec![
+            event(
+                1,
+                "200 MED-R SCY",
+                vec![
+                    entry("A", "Anytown", Some(120.0)),
+                    entry("B", "Anytown", Some(125.0)),
+                    entry("A", "Othertown", Some(122.0)),
+                ],
+            ),
+            event(
+                2,
+                "100 FR SCY",
+                vec![
+                    entry("Alpha", "Anytown", Some(55.0)),
+                    entry("Bravo", "Othertown", Some(54.0)),
+                    entry("Charlie", "Othertown", Some(56.0)),
+                    entry("Delta", "Othertown", Some(56.0)),
+                    entry("Echo", "Anytown", Some(58.0)),
+                    entry("Foxtrot", "Anytown", None),
+                ],
+            ),
+        ]
+    }
+
+    #[test]
+    fn test_project() {
+        let projection = project(&dual_meet(), &ScoringTable::dual(), &EntryLimits::default());
+
+        let relay = &projection.events[0];
+        assert_eq!(relay.placings.len(), 3);
+        assert_eq!(relay.placings[1].entry.team, "Othertown");
+        assert_eq!(relay.placings[1].points, 8.0);
+        assert_eq!(
+            relay.team_points,
+            vec![("Anytown".to_owned(), 18.0), ("Othertown".to_owned(), 8.0)]
+        );
+
+        let free = &projection.events[1];
+        // No time, so not placed
+        assert_eq!(free.placings.len(), 5);
+        assert_eq!(free.placings[0].entry.name, "Bravo");
+        // Charlie and Delta tie for third, sharing the points for third and
+        // fourth
+        assert_eq!((free.placings[2].place, free.placings[3].place), (3, 3));
+        assert_eq!(free.placings[2].points, 2.5);
+        assert_eq!(free.placings[4].place, 5);
+        assert_eq!(free.placings[4].points, 1.0);
+        assert_eq!(
+            free.team_points,
+            vec![("Othertown".to_owned(), 11.0), ("Anytown".to_owned(), 5.0)]
+        );
+
+        assert_eq!(
+            projection.team_scores,
+            vec![("Anytown".to_owned(), 23.0), ("Othertown".to_owned(), 19.0)]
+        );
+        assert_eq!(relay.swing, 10.0);
+        assert_eq!(free.swing, -6.0);
+    }
+
+    #[test]
+    fn test_entry_limits() {
+        let mut events = dual_meet();
+        events.push(event(
+            3,
+            "200 FR SCY",
+            vec![
+                entry("Bravo", "Othertown", Some(120.0)),
+                entry("Echo", "Anytown", Some(125.0)),
+            ],
+        ));
+        let limits = EntryLimits {
+            per_team: Some(3),
+            scoring_per_team: Some(1),
+            per_swimmer: Some(1),
+        };
+        let projection = project(&events, &ScoringTable::dual(), &limits);
+
+        let relay = &projection.events[0];
+        assert_eq!(relay.placings[2].points, 0.0);
+        // Othertown's relay takes the points for second
+        assert_eq!(relay.placings[1].points, 8.0);
+
+        let free = &projection.events[1];
+        assert_eq!(free.placings.len(), 5);
+        assert_eq!(free.placings[0].points, 6.0);
+        // Alpha scores second place points, as Othertown's other swimmers
+        // don't score
+        assert_eq!(free.placings[1].points, 4.0);
+        assert_eq!(free.placings[2].points, 0.0);
+
+        // Bravo and Echo have already swum an individual event
+        assert!(projection.events[2].placings.is_empty());
+    }
+
+    #[test]
+    fn test_championship_table() {
+        let table = ScoringTable::championship();
+        assert_eq!(table.individual.len(), 16);
+        assert_eq!(table.individual[8], 9.0);
+        assert_eq!(table.relay[0], 40.0);
+    }
+
+    #[test]
+    fn test_add_times() {
+        let time = |name: &str, id, event: &str, seconds, age| TopTime {
+            age,
+            swimmer_id: Some(id),
+            swimmer_name: name.to_owned(),
+            ..top_time(event, seconds)
+        };
+        let times = vec![
+            time("Alpha", 1, "100 FR SCY", 56.0, 14),
+            time("Alpha", 1, "100 FR LCM", 61.0, 14),
+            time("Bravo", 2, "100 FR LCM", 61.0, 14),
+            time("Bravo", 2, "100 BK SCY", 61.0, 14),
+            time("Charlie", 3, "100 FR SCY", 50.0, 15),
+        ];
+        let mut e = event(1, "100 FR SCY", Vec::new());
+        e.max_age = Some(14);
+        e.add_times(&times);
+        assert_eq!(e.entries.len(), 2);
+        assert_eq!(e.entries[0].name, "Alpha");
+        assert_eq!(e.entries[0].seed.as_ref().unwrap().seconds, 56.0);
+        assert_eq!(e.entries[1].name, "Bravo");
+        assert_eq!(e.entries[1].age, Some(14));
+        let seed = e.entries[1].seed.as_ref().unwrap().seconds;
+        assert!((seed - 53.88).abs() < 0.01);
+        assert_eq!(e.event.2, Course::SCY);
+
+        e.add_times(&times);
+        assert_eq!(e.entries.len(), 2);
+    }
+}
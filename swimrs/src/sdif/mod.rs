@@ -0,0 +1,591 @@
+//! USA Swimming's Standard Data Interchange Format (SDIF) version 3, the
+//! fixed-width format of `.sd3` and `.cl2` meet entry and result files.
+//!
+//! Files are parsed with [`FromStr`](std::str::FromStr) and written with
+//! [`Display`](std::fmt::Display). Only the records describing meets, teams,
+//! swimmers and swims are read: A0, B1, C1, D0, D3, E0, F0, G0 and Z0. Other
+//! records are skipped.
+
+mod parse;
+mod write;
+
+use anyhow::{bail, Result};
+use chrono::NaiveDate;
+
+use crate::common::{Course, Gender, Stroke, SwimEvent, SwimTime};
+
+/// An SDIF file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SdifFile {
+    pub header: Header,
+    pub meets: Vec<Meet>,
+}
+
+/// The A0 record, describing the file itself.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Header {
+    pub file_type: FileType,
+    pub software_name: Option<String>,
+    pub software_version: Option<String>,
+    pub contact_name: Option<String>,
+    pub contact_phone: Option<String>,
+    pub created: Option<NaiveDate>,
+    /// LSC that submitted the file
+    pub lsc: Option<String>,
+}
+
+/// What an SDIF file holds (FILE Code 003).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FileType {
+    MeetRegistrations,
+    MeetResults,
+    Ovc,
+    NationalAgeGroupRecord,
+    LscAgeGroupRecord,
+    LscMotivationalList,
+    NationalRecordsAndRankings,
+    TeamSelection,
+    LscBestTimes,
+    UsaSwimmingRegistration,
+    TopSixteen,
+    VendorDefined,
+}
+
+/// A meet, from a B1 record, with the teams that follow it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Meet {
+    pub name: String,
+    pub address: Option<String>,
+    pub city: Option<String>,
+    pub state: Option<String>,
+    pub postal_code: Option<String>,
+    pub country: Option<String>,
+    pub meet_type: Option<MeetType>,
+    pub start_date: Option<NaiveDate>,
+    pub end_date: Option<NaiveDate>,
+    /// Altitude in feet
+    pub altitude: Option<u16>,
+    /// Course of the meet, used for swims whose times don't give one
+    pub course: Option<Course>,
+    pub teams: Vec<Team>,
+}
+
+/// Kind of meet (MEET Code 005).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MeetType {
+    Invitational,
+    Regional,
+    LscChampionship,
+    Zone,
+    ZoneChampionship,
+    NationalChampionship,
+    Juniors,
+    Seniors,
+    Dual,
+    TimeTrials,
+    International,
+    Open,
+    League,
+}
+
+/// A team, from a C1 record, with the swims that follow it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Team {
+    /// Team code, such as `PCSCSC`: the two-letter LSC code followed by the
+    /// club code of up to five characters
+    pub code: String,
+    pub name: String,
+    pub short_name: Option<String>,
+    pub city: Option<String>,
+    pub state: Option<String>,
+    pub country: Option<String>,
+    pub swims: Vec<Swim>,
+    pub relays: Vec<Relay>,
+}
+
+/// A swimmer, as identified in D0 and F0 records.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Swimmer {
+    /// Name written `Last, First M`
+    pub name: String,
+    /// USA Swimming member ID. The 14-character form from D3 and F0 records
+    /// is preferred to the older 12-character form when both are present
+    pub uss_id: Option<String>,
+    pub birth_date: Option<NaiveDate>,
+    pub age: Option<u8>,
+    pub gender: Option<Gender>,
+    pub citizen: Option<String>,
+    pub preferred_name: Option<String>,
+}
+
+/// A time as recorded in a swim, relay or relay leg.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Time {
+    /// A time swum in the given course
+    Swum(SwimTime, Course),
+    /// No time, often given as the seed time of an entry (NT)
+    NoTime,
+    /// Did not swim (NS)
+    NoSwim,
+    /// Did not finish (DNF)
+    DidNotFinish,
+    /// Disqualified (DQ)
+    Disqualified,
+    /// Scratched (SCR)
+    Scratch,
+}
+
+impl Time {
+    /// Seconds swum, if this is a time.
+    pub fn seconds(&self) -> Option<f32> {
+        match self {
+            Time::Swum(t, _) => Some(t.seconds),
+            _ => None,
+        }
+    }
+}
+
+/// Session of a meet that a time or split was swum in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Round {
+    Prelims,
+    SwimOff,
+    Finals,
+}
+
+/// A split from a G0 record.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Split {
+    pub round: Round,
+    /// Distance covered at the split
+    pub distance: u16,
+    pub seconds: f32,
+    /// Whether the split is the time since the start, rather than since the
+    /// previous split
+    pub cumulative: bool,
+}
+
+/// Age range of an event (AGE Code 025). Either end may be open.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct AgeGroup {
+    pub min: Option<u8>,
+    pub max: Option<u8>,
+}
+
+impl AgeGroup {
+    /// Parses codes like `1112`, `UN10`, `15OV` and `UNOV`.
+    pub fn from_code(code: &str) -> Result<Self> {
+        if code.len() != 4 || !code.is_ascii() {
+            bail!("Unexpected event age code: {}", code);
+        }
+        let min = match &code[..2] {
+            "UN" => None,
+            m => Some(m.parse()?),
+        };
+        let max = match &code[2..] {
+            "OV" => None,
+            m => Some(m.parse()?),
+        };
+        Ok(AgeGroup { min, max })
+    }
+
+    pub fn code(&self) -> String {
+        let min = self.min.map_or("UN".to_owned(), |m| format!("{:02}", m));
+        let max = self.max.map_or("OV".to_owned(), |m| format!("{:02}", m));
+        min + &max
+    }
+}
+
+/// Results of one event, common to individual and relay swims.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EventResult {
+    /// Distance and stroke of the event, with the course of its times, or
+    /// of the meet if there are none
+    pub event: SwimEvent,
+    pub event_number: Option<String>,
+    pub event_gender: Gender,
+    pub age_group: Option<AgeGroup>,
+    pub date: Option<NaiveDate>,
+    pub seed: Option<Time>,
+    pub prelims: Option<Time>,
+    pub swim_off: Option<Time>,
+    pub finals: Option<Time>,
+    pub prelims_heat: Option<u8>,
+    pub prelims_lane: Option<u8>,
+    pub finals_heat: Option<u8>,
+    pub finals_lane: Option<u8>,
+    pub prelims_place: Option<u16>,
+    pub finals_place: Option<u16>,
+    pub points: Option<f32>,
+    pub splits: Vec<Split>,
+}
+
+/// An individual swim, from a D0 record and the D3 and G0 records following
+/// it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Swim {
+    pub swimmer: Swimmer,
+    pub result: EventResult,
+}
+
+/// A relay swim, from an E0 record and the F0 and G0 records following it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Relay {
+    /// Letter distinguishing a team's relays in the same event, such as `A`
+    pub name: String,
+    pub total_age: Option<u16>,
+    pub result: EventResult,
+    pub swimmers: Vec<RelaySwimmer>,
+}
+
+/// A swimmer named on a relay, from an F0 record.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RelaySwimmer {
+    pub swimmer: Swimmer,
+    pub prelims_leg: Option<Leg>,
+    pub swim_off_leg: Option<Leg>,
+    pub finals_leg: Option<Leg>,
+    pub leg_time: Option<Time>,
+}
+
+/// Place of a swimmer in a relay's order (ORDER Code 024).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Leg {
+    NotSwum,
+    /// Leg 1 to 4
+    Leg(u8),
+    Alternate,
+}
+
+impl FileType {
+    fn from_code(code: &str) -> Option<Self> {
+        Some(match code {
+            "01" => FileType::MeetRegistrations,
+            "02" => FileType::MeetResults,
+            "03" => FileType::Ovc,
+            "04" => FileType::NationalAgeGroupRecord,
+            "05" => FileType::LscAgeGroupRecord,
+            "06" => FileType::LscMotivationalList,
+            "07" => FileType::NationalRecordsAndRankings,
+            "08" => FileType::TeamSelection,
+            "09" => FileType::LscBestTimes,
+            "10" => FileType::UsaSwimmingRegistration,
+            "16" => FileType::TopSixteen,
+            "20" => FileType::VendorDefined,
+            _ => return None,
+        })
+    }
+
+    fn code(&self) -> &'static str {
+        match self {
+            FileType::MeetRegistrations => "01",
+            FileType::MeetResults => "02",
+            FileType::Ovc => "03",
+            FileType::NationalAgeGroupRecord => "04",
+            FileType::LscAgeGroupRecord => "05",
+            FileType::LscMotivationalList => "06",
+            FileType::NationalRecordsAndRankings => "07",
+            FileType::TeamSelection => "08",
+            FileType::LscBestTimes => "09",
+            FileType::UsaSwimmingRegistration => "10",
+            FileType::TopSixteen => "16",
+            FileType::VendorDefined => "20",
+        }
+    }
+}
+
+impl MeetType {
+    fn from_code(code: char) -> Option<Self> {
+        Some(match code {
+            '1' => MeetType::Invitational,
+            '2' => MeetType::Regional,
+            '3' => MeetType::LscChampionship,
+            '4' => MeetType::Zone,
+            '5' => MeetType::ZoneChampionship,
+            '6' => MeetType::NationalChampionship,
+            '7' => MeetType::Juniors,
+            '8' => MeetType::Seniors,
+            '9' => MeetType::Dual,
+            '0' => MeetType::TimeTrials,
+            'A' => MeetType::International,
+            'B' => MeetType::Open,
+            'C' => MeetType::League,
+            _ => return None,
+        })
+    }
+
+    fn code(&self) -> char {
+        match self {
+            MeetType::Invitational => '1',
+            MeetType::Regional => '2',
+            MeetType::LscChampionship => '3',
+            MeetType::Zone => '4',
+            MeetType::ZoneChampionship => '5',
+            MeetType::NationalChampionship => '6',
+            MeetType::Juniors => '7',
+            MeetType::Seniors => '8',
+            MeetType::Dual => '9',
+            MeetType::TimeTrials => '0',
+            MeetType::International => 'A',
+            MeetType::Open => 'B',
+            MeetType::League => 'C',
+        }
+    }
+}
+
+impl Round {
+    fn from_code(code: char) -> Option<Self> {
+        Some(match code {
+            'P' => Round::Prelims,
+            'S' => Round::SwimOff,
+            'F' => Round::Finals,
+            _ => return None,
+        })
+    }
+
+    fn code(&self) -> char {
+        match self {
+            Round::Prelims => 'P',
+            Round::SwimOff => 'S',
+            Round::Finals => 'F',
+        }
+    }
+}
+
+impl Leg {
+    fn from_code(code: char) -> Option<Self> {
+        Some(match code {
+            '0' => Leg::NotSwum,
+            '1'..='4' => Leg::Leg(code as u8 - b'0'),
+            'A' => Leg::Alternate,
+            _ => return None,
+        })
+    }
+
+    fn code(&self) -> char {
+        match self {
+            Leg::NotSwum => '0',
+            Leg::Leg(n) => (b'0' + n) as char,
+            Leg::Alternate => 'A',
+        }
+    }
+}
+
+/// COURSE Code 013. Codes `1` to `3` are read as well as the letters that
+/// are written.
+fn course_from_code(code: char) -> Option<Course> {
+    match code {
+        '1' | 'S' => Some(Course::SCM),
+        '2' | 'Y' => Some(Course::SCY),
+        '3' | 'L' => Some(Course::LCM),
+        _ => None,
+    }
+}
+
+fn course_code(course: &Course) -> char {
+    match course {
+        Course::SCM => 'S',
+        Course::SCY => 'Y',
+        Course::LCM => 'L',
+        Course::All => ' ',
+    }
+}
+
+/// STROKE Code 012, which numbers strokes as [`Stroke`] does.
+fn stroke_from_code(code: char) -> Option<Stroke> {
+    Some(match code {
+        '1' => Stroke::Freestyle,
+        '2' => Stroke::Backstroke,
+        '3' => Stroke::Breaststroke,
+        '4' => Stroke::Butterfly,
+        '5' => Stroke::IndividualMedley,
+        '6' => Stroke::FreestyleRelay,
+        '7' => Stroke::MedleyRelay,
+        _ => return None,
+    })
+}
+
+fn stroke_code(stroke: &Stroke) -> char {
+    (b'0' + stroke.clone() as u8) as char
+}
+
+/// SEX Code 010 for swimmers and EVENT SEX Code 011 for events, where `X`
+/// is a mixed event.
+fn gender_from_code(code: char) -> Option<Gender> {
+    match code {
+        'M' => Some(Gender::Male),
+        'F' => Some(Gender::Female),
+        'X' => Some(Gender::Mixed),
+        _ => None,
+    }
+}
+
+fn gender_code(gender: &Gender) -> char {
+    match gender {
+        Gender::Male => 'M',
+        Gender::Female => 'F',
+        Gender::Mixed => 'X',
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::{
+        common::{Distance, LSC},
+        usas::toptimes::{parse_top_times, TopTime},
+    };
+
+    fn fixture() -> SdifFile {
+        let text = std::fs::read_to_string("testdata/sdif_results.cl2").unwrap();
+        SdifFile::from_str(&text).unwrap()
+    }
+
+    #[test]
+    fn test_parse_sdif() {
+        let file = fixture();
+        assert_eq!(file.header.file_type, FileType::MeetResults);
+        assert_eq!(file.header.software_name.as_deref(), Some("Hy-Tek, Ltd"));
+        assert_eq!(file.header.lsc.as_deref(), Some("PC"));
+
+        assert_eq!(file.meets.len(), 1);
+        let meet = &file.meets[0];
+        assert_eq!(meet.name, "Anytown Spring Invitational");
+        assert_eq!(meet.meet_type, Some(MeetType::Invitational));
+        assert_eq!(meet.course, Some(Course::SCY));
+        assert_eq!(meet.altitude, Some(120));
+        assert_eq!(
+            meet.end_date,
+            Some(NaiveDate::from_ymd_opt(2024, 3, 17).unwrap())
+        );
+
+        assert_eq!(meet.teams.len(), 2);
+        let team = &meet.teams[0];
+        assert_eq!(team.code, "PCANYT");
+        assert_eq!(meet.teams[1].code, "PCOTHRS");
+        assert_eq!(team.swims.len(), 3);
+
+        let swim = &team.swims[0];
+        assert_eq!(swim.swimmer.name, "Swimmer, Alpha A");
+        assert_eq!(swim.swimmer.uss_id.as_deref(), Some("010203ALPSWI01"));
+        assert_eq!(swim.swimmer.preferred_name.as_deref(), Some("Ally"));
+        assert_eq!(swim.swimmer.gender, Some(Gender::Female));
+        let r = &swim.result;
+        assert_eq!(
+            r.event,
+            SwimEvent(Distance::_100, Stroke::Freestyle, Course::SCY)
+        );
+        assert_eq!(r.age_group, Some(AgeGroup::from_code("1314").unwrap()));
+        assert_eq!(r.finals.as_ref().and_then(Time::seconds), Some(60.55));
+        assert_eq!(r.finals_place, Some(1));
+        assert_eq!(r.points, Some(20.0));
+        assert_eq!(r.splits.len(), 4);
+        assert_eq!(r.splits[2].round, Round::Prelims);
+        assert_eq!(r.splits[2].seconds, 29.6);
+
+        let r = &team.swims[1].result;
+        assert_eq!(r.seed, Some(Time::NoTime));
+        assert_eq!(r.finals, Some(Time::Disqualified));
+        assert_eq!(r.event.2, Course::SCY);
+
+        // A mile's splits span four G0 records
+        let splits = &team.swims[2].result.splits;
+        assert_eq!(splits.len(), 33);
+        assert_eq!(splits[32].distance, 1650);
+        assert!((splits[32].seconds - 1092.34).abs() < 0.01);
+
+        let relay = &team.relays[0];
+        assert_eq!(relay.name, "A");
+        assert_eq!(relay.total_age, Some(45));
+        assert_eq!(relay.result.event_gender, Gender::Female);
+        assert_eq!(relay.result.event.1, Stroke::MedleyRelay);
+        assert_eq!(relay.result.finals_place, Some(3));
+        assert_eq!(relay.swimmers.len(), 5);
+        assert_eq!(relay.swimmers[3].finals_leg, Some(Leg::Leg(4)));
+        assert_eq!(relay.swimmers[4].finals_leg, Some(Leg::Alternate));
+        assert_eq!(relay.swimmers[4].leg_time, None);
+        assert_eq!(relay.result.splits.len(), 4);
+
+        // Seed times may be in another course than the event
+        let r = &meet.teams[1].swims[0].result;
+        assert_eq!(r.event.2, Course::SCY);
+        assert!(matches!(r.seed, Some(Time::Swum(_, Course::LCM))));
+    }
+
+    #[test]
+    fn test_malformed_splits() {
+        let text = std::fs::read_to_string("testdata/sdif_results.cl2").unwrap();
+        // Replaces the sequence number and split distance of the first G0
+        // record
+        let with_g0 = |sequence: &str, distance: &str| {
+            let line = text.lines().find(|l| l.starts_with("G0")).unwrap();
+            let mut g0 = line.to_owned();
+            g0.replace_range(55..56, sequence);
+            g0.replace_range(58..62, distance);
+            text.replacen(line, &g0, 1)
+        };
+        assert!(SdifFile::from_str(&with_g0("1", "  50")).is_ok());
+        assert!(SdifFile::from_str(&with_g0("0", "  50")).is_err());
+        assert!(SdifFile::from_str(&with_g0("9", "9999")).is_err());
+    }
+
+    #[test]
+    fn test_sdif_round_trip() {
+        let file = fixture();
+        let text = file.to_string();
+        assert!(text.lines().all(|l| l.chars().count() == 160));
+        assert_eq!(SdifFile::from_str(&text).unwrap(), file);
+        // Writing is stable once the file has been through the writer
+        assert_eq!(SdifFile::from_str(&text).unwrap().to_string(), text);
+    }
+
+    #[test]
+    fn test_sdif_from_top_times() {
+        let html = std::fs::read_to_string("testdata/top_times_small.html").unwrap();
+        let times: Vec<TopTime> = parse_top_times(html, Gender::Male)
+            .unwrap()
+            .into_iter()
+            .filter(|t| t.lsc == Some(LSC::MI) || t.team_name == "Unattached")
+            .collect();
+        assert!(!times.is_empty());
+
+        let file = SdifFile::from_top_times("MIUN", "Unattached", &times).unwrap();
+        let parsed = SdifFile::from_str(&file.to_string()).unwrap();
+        assert_eq!(parsed, file);
+
+        let swims: Vec<&Swim> = parsed
+            .meets
+            .iter()
+            .flat_map(|m| &m.teams)
+            .flat_map(|t| &t.swims)
+            .collect();
+        assert_eq!(swims.len(), times.len());
+        for (swim, t) in swims.iter().zip(&times) {
+            assert_eq!(swim.swimmer.name, t.swimmer_name);
+            assert_eq!(swim.result.date, Some(t.date));
+            assert_eq!(
+                swim.result.finals.as_ref().and_then(Time::seconds),
+                Some(t.time)
+            );
+        }
+
+        assert!(SdifFile::from_top_times("M", "Unattached", &times).is_err());
+    }
+
+    #[test]
+    fn test_age_group_codes() {
+        for code in ["1112", "UN10", "15OV", "UNOV"] {
+            assert_eq!(AgeGroup::from_code(code).unwrap().code(), code);
+        }
+        assert_eq!(
+            AgeGroup::from_code("UN08").unwrap(),
+            AgeGroup {
+                min: None,
+                max: Some(8)
+            }
+        );
+        assert!(AgeGroup::from_code("11-12").is_err());
+    }
+}
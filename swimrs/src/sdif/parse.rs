@@ -0,0 +1,379 @@
+use std::{error::Error as StdError, str::FromStr};
+
+use anyhow::{bail, Context, Error, Result};
+use chrono::NaiveDate;
+use num_enum::TryFromPrimitive;
+
+use super::{
+    course_from_code, gender_from_code, stroke_from_code, AgeGroup, EventResult, FileType, Header,
+    Leg, Meet, MeetType, Relay, RelaySwimmer, Round, SdifFile, Split, Swim, Swimmer, Team, Time,
+};
+use crate::common::{Course, Distance, SwimEvent, SwimTime};
+
+pub(super) const DATE_FMT: &str = "%m%d%Y";
+
+/// Number of split times in each G0 record.
+pub(super) const SPLITS_PER_RECORD: usize = 10;
+
+/// A fixed-width record. Columns are numbered from 1, as in the SDIF
+/// specification.
+struct Record<'a>(&'a str);
+
+impl Record<'_> {
+    fn text(&self, col: usize, len: usize) -> String {
+        self.0
+            .chars()
+            .skip(col - 1)
+            .take(len)
+            .collect::<String>()
+            .trim()
+            .to_owned()
+    }
+
+    fn opt(&self, col: usize, len: usize) -> Option<String> {
+        Some(self.text(col, len)).filter(|t| !t.is_empty())
+    }
+
+    fn char(&self, col: usize) -> Option<char> {
+        self.0.chars().nth(col - 1).filter(|c| !c.is_whitespace())
+    }
+
+    fn num<T>(&self, col: usize, len: usize) -> Result<Option<T>>
+    where
+        T: FromStr,
+        T::Err: StdError + Send + Sync + 'static,
+    {
+        match self.opt(col, len) {
+            Some(n) => Ok(Some(
+                n.parse()
+                    .with_context(|| format!("invalid number: {}", n))?,
+            )),
+            None => Ok(None),
+        }
+    }
+
+    fn date(&self, col: usize) -> Result<Option<NaiveDate>> {
+        match self.opt(col, 8) {
+            Some(d) => Ok(Some(
+                NaiveDate::parse_from_str(&d, DATE_FMT)
+                    .with_context(|| format!("invalid date: {}", d))?,
+            )),
+            None => Ok(None),
+        }
+    }
+
+    /// Reads a time and its course code. Times without a course are taken
+    /// to be in `default_course`.
+    fn time(
+        &self,
+        col: usize,
+        course_col: usize,
+        default_course: Option<&Course>,
+    ) -> Result<Option<Time>> {
+        let text = match self.opt(col, 8) {
+            Some(t) => t,
+            None => return Ok(None),
+        };
+        let course_code = self.char(course_col);
+        Ok(Some(match text.as_str() {
+            "NT" => Time::NoTime,
+            "NS" => Time::NoSwim,
+            "DNF" => Time::DidNotFinish,
+            "DQ" => Time::Disqualified,
+            "SCR" => Time::Scratch,
+            // Disqualified swims may keep their time, with course code X
+            _ if course_code == Some('X') => Time::Disqualified,
+            t => {
+                let time = SwimTime::from_str(t)?;
+                let course = course_code
+                    .and_then(course_from_code)
+                    .or_else(|| default_course.cloned())
+                    .with_context(|| format!("no course for time {}", t))?;
+                Time::Swum(time, course)
+            }
+        }))
+    }
+
+    /// Reads the swimmer columns shared by D0 and F0 records, which are laid
+    /// out alike but for the attach code that D0 records have between the
+    /// ID and citizenship.
+    fn swimmer(&self, name_col: usize, citizen_col: usize) -> Result<Swimmer> {
+        Ok(Swimmer {
+            name: self.text(name_col, 28),
+            uss_id: self.opt(name_col + 28, 12),
+            birth_date: self.date(citizen_col + 3)?,
+            // College class codes may stand in place of an age
+            age: self.num(citizen_col + 11, 2).unwrap_or(None),
+            gender: self.char(citizen_col + 13).and_then(gender_from_code),
+            citizen: self.opt(citizen_col, 3),
+            preferred_name: None,
+        })
+    }
+
+    /// Reads the event and result columns shared by D0 and E0 records. These
+    /// are laid out alike in two runs: the event, starting with its sex code
+    /// at `event_col`, and the result, starting with the date of the swim at
+    /// `date_col`. Columns are numbered below as in D0 records.
+    fn result(
+        &self,
+        event_col: usize,
+        date_col: usize,
+        default_course: Option<&Course>,
+    ) -> Result<EventResult> {
+        let col = |c: usize| match c {
+            67..=80 => event_col + c - 67,
+            _ => date_col + c - 81,
+        };
+        let event_gender = self
+            .char(col(67))
+            .and_then(gender_from_code)
+            .context("missing event sex code")?;
+        let distance: u16 = self.num(col(68), 4)?.context("missing event distance")?;
+        let distance = Distance::try_from_primitive(distance)?;
+        let stroke = self
+            .char(col(72))
+            .and_then(stroke_from_code)
+            .context("missing stroke code")?;
+        let age_group = match self.opt(col(77), 4) {
+            Some(code) => Some(AgeGroup::from_code(&code)?),
+            None => None,
+        };
+
+        let seed = self.time(col(89), col(97), default_course)?;
+        let prelims = self.time(col(98), col(106), default_course)?;
+        let swim_off = self.time(col(107), col(115), default_course)?;
+        let finals = self.time(col(116), col(124), default_course)?;
+        let course = [&finals, &prelims, &swim_off, &seed]
+            .into_iter()
+            .find_map(|t| match t {
+                Some(Time::Swum(_, course)) => Some(course.clone()),
+                _ => None,
+            })
+            .or_else(|| default_course.cloned())
+            .context("no course for event")?;
+
+        Ok(EventResult {
+            event: SwimEvent(distance, stroke, course),
+            event_number: self.opt(col(73), 4),
+            event_gender,
+            age_group,
+            date: self.date(col(81))?,
+            seed,
+            prelims,
+            swim_off,
+            finals,
+            prelims_heat: self.num(col(125), 2)?,
+            prelims_lane: self.num(col(127), 2)?,
+            finals_heat: self.num(col(129), 2)?,
+            finals_lane: self.num(col(131), 2)?,
+            prelims_place: self.num(col(133), 3)?,
+            finals_place: self.num(col(136), 3)?,
+            points: self.num(col(139), 4)?,
+            splits: Vec::new(),
+        })
+    }
+}
+
+/// Which swim the D3, F0 and G0 records that follow belong to.
+enum Last {
+    None,
+    Swim,
+    Relay,
+}
+
+struct Parser {
+    header: Option<Header>,
+    meets: Vec<Meet>,
+    last: Last,
+}
+
+impl Parser {
+    fn meet(&mut self) -> Result<&mut Meet> {
+        self.meets
+            .last_mut()
+            .context("record before the first B1 record")
+    }
+
+    fn team(&mut self) -> Result<&mut Team> {
+        self.meet()?
+            .teams
+            .last_mut()
+            .context("record before the first C1 record")
+    }
+
+    fn swim(&mut self) -> Result<&mut Swim> {
+        match self.last {
+            Last::Swim => Ok(self.team()?.swims.last_mut().unwrap()),
+            _ => bail!("record doesn't follow a D0 record"),
+        }
+    }
+
+    fn relay(&mut self) -> Result<&mut Relay> {
+        match self.last {
+            Last::Relay => Ok(self.team()?.relays.last_mut().unwrap()),
+            _ => bail!("record doesn't follow an E0 record"),
+        }
+    }
+
+    fn record(&mut self, r: &Record) -> Result<()> {
+        match &r.text(1, 2)[..] {
+            "A0" => {
+                self.header = Some(Header {
+                    file_type: FileType::from_code(&r.text(12, 2))
+                        .with_context(|| format!("unknown file code: {}", r.text(12, 2)))?,
+                    software_name: r.opt(44, 20),
+                    software_version: r.opt(64, 10),
+                    contact_name: r.opt(74, 20),
+                    contact_phone: r.opt(94, 12),
+                    created: r.date(106)?,
+                    lsc: r.opt(156, 2),
+                });
+            }
+            "B1" => {
+                self.meets.push(Meet {
+                    name: r.text(12, 30),
+                    address: r.opt(42, 22),
+                    city: r.opt(86, 20),
+                    state: r.opt(106, 2),
+                    postal_code: r.opt(108, 10),
+                    country: r.opt(118, 3),
+                    meet_type: r.char(121).and_then(MeetType::from_code),
+                    start_date: r.date(122)?,
+                    end_date: r.date(130)?,
+                    altitude: r.num(138, 4)?,
+                    course: r.char(150).and_then(course_from_code),
+                    teams: Vec::new(),
+                });
+                self.last = Last::None;
+            }
+            "C1" => {
+                // The fifth character of a club code is kept apart from the
+                // rest, at the end of the record
+                let mut code = r.text(12, 6);
+                code.extend(r.char(150));
+                self.meet()?.teams.push(Team {
+                    code,
+                    name: r.text(18, 30),
+                    short_name: r.opt(48, 16),
+                    city: r.opt(108, 20),
+                    state: r.opt(128, 2),
+                    country: r.opt(140, 3),
+                    swims: Vec::new(),
+                    relays: Vec::new(),
+                });
+                self.last = Last::None;
+            }
+            "D0" => {
+                let course = self.meet()?.course.clone();
+                let swim = Swim {
+                    swimmer: r.swimmer(12, 53)?,
+                    result: r.result(67, 81, course.as_ref())?,
+                };
+                self.team()?.swims.push(swim);
+                self.last = Last::Swim;
+            }
+            "D3" => {
+                let swimmer = &mut self.swim()?.swimmer;
+                if let Some(id) = r.opt(3, 14) {
+                    swimmer.uss_id = Some(id);
+                }
+                swimmer.preferred_name = r.opt(17, 15);
+            }
+            "E0" => {
+                let course = self.meet()?.course.clone();
+                let relay = Relay {
+                    name: r.text(12, 1),
+                    total_age: r.num(35, 3)?,
+                    result: r.result(21, 38, course.as_ref())?,
+                    swimmers: Vec::new(),
+                };
+                self.team()?.relays.push(relay);
+                self.last = Last::Relay;
+            }
+            "F0" => {
+                let course = self.meet()?.course.clone();
+                let mut swimmer = r.swimmer(23, 63)?;
+                if let Some(id) = r.opt(93, 14) {
+                    swimmer.uss_id = Some(id);
+                }
+                swimmer.preferred_name = r.opt(107, 15);
+                let leg = |col| r.char(col).and_then(Leg::from_code);
+                let relay_swimmer = RelaySwimmer {
+                    swimmer,
+                    prelims_leg: leg(77),
+                    swim_off_leg: leg(78),
+                    finals_leg: leg(79),
+                    leg_time: r.time(80, 88, course.as_ref())?,
+                };
+                self.relay()?.swimmers.push(relay_swimmer);
+            }
+            "G0" => {
+                let splits = match self.last {
+                    Last::Swim => &mut self.swim()?.result.splits,
+                    Last::Relay => &mut self.relay()?.result.splits,
+                    Last::None => bail!("G0 record doesn't follow a D0 or E0 record"),
+                };
+                let sequence: usize = r.num(56, 1)?.unwrap_or(1);
+                if sequence == 0 {
+                    bail!("invalid split sequence number: 0");
+                }
+                let split_distance: u16 = r.num(59, 4)?.context("missing split distance")?;
+                let cumulative = r.char(63) != Some('I');
+                let round = r
+                    .char(144)
+                    .and_then(Round::from_code)
+                    .unwrap_or(Round::Finals);
+                for i in 0..SPLITS_PER_RECORD {
+                    if let Some(t) = r.opt(64 + 8 * i, 8) {
+                        let n = (sequence - 1) * SPLITS_PER_RECORD + i + 1;
+                        let distance = u16::try_from(n)
+                            .ok()
+                            .and_then(|n| split_distance.checked_mul(n))
+                            .with_context(|| {
+                                format!(
+                                    "split distance out of range: split {} of {}",
+                                    n, split_distance
+                                )
+                            })?;
+                        splits.push(Split {
+                            round,
+                            distance,
+                            seconds: SwimTime::from_str(&t)?.seconds,
+                            cumulative,
+                        });
+                    }
+                }
+            }
+            // Counts in the Z0 record aren't checked, since files are often
+            // concatenated or trimmed by hand
+            "Z0" => self.last = Last::None,
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for SdifFile {
+    type Err = Error;
+
+    /// Parses the records of an SDIF file, one per line.
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parser = Parser {
+            header: None,
+            meets: Vec::new(),
+            last: Last::None,
+        };
+        for (i, line) in s.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            parser
+                .record(&Record(line))
+                .with_context(|| format!("error parsing SDIF record on line {}", i + 1))?;
+        }
+        Ok(SdifFile {
+            header: parser.header.context("SDIF file has no A0 record")?,
+            meets: parser.meets,
+        })
+    }
+}
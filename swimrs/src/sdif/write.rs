@@ -0,0 +1,465 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::{self, Display, Formatter},
+};
+
+use anyhow::{bail, Result};
+use chrono::{offset::Local, NaiveDate};
+
+use super::{
+    course_code, gender_code,
+    parse::{DATE_FMT, SPLITS_PER_RECORD},
+    stroke_code, EventResult, FileType, Header, Meet, Relay, Round, SdifFile, Split, Swim, Swimmer,
+    Team, Time,
+};
+use crate::{
+    common::{Course, Gender, SwimEvent, SwimTime},
+    usas::toptimes::TopTime,
+};
+
+/// Length of every SDIF record.
+const RECORD_LEN: usize = 160;
+
+/// ORG Code 001 for USA Swimming, which every record but D3 begins with.
+const ORG_USS: char = '1';
+
+const SDIF_VERSION: &str = "V3";
+
+/// A fixed-width record being written. Columns are numbered from 1, as in
+/// the SDIF specification, and values too long for their field are cut
+/// short.
+struct RecordBuf(Vec<char>);
+
+impl RecordBuf {
+    fn new(code: &str) -> Self {
+        let mut r = RecordBuf(vec![' '; RECORD_LEN]);
+        r.left(1, 2, code);
+        if code != "D3" {
+            r.char(3, Some(ORG_USS));
+        }
+        r
+    }
+
+    fn left(&mut self, col: usize, len: usize, value: &str) {
+        for (i, c) in value.chars().take(len).enumerate() {
+            self.0[col - 1 + i] = c;
+        }
+    }
+
+    fn right(&mut self, col: usize, len: usize, value: &str) {
+        let n = value.chars().count().min(len);
+        self.left(col + len - n, n, value);
+    }
+
+    fn opt(&mut self, col: usize, len: usize, value: &Option<String>) {
+        if let Some(v) = value {
+            self.left(col, len, v);
+        }
+    }
+
+    fn num<T: ToString>(&mut self, col: usize, len: usize, value: Option<T>) {
+        if let Some(v) = value {
+            self.right(col, len, &v.to_string());
+        }
+    }
+
+    fn char(&mut self, col: usize, value: Option<char>) {
+        if let Some(c) = value {
+            self.0[col - 1] = c;
+        }
+    }
+
+    fn date(&mut self, col: usize, value: Option<NaiveDate>) {
+        if let Some(d) = value {
+            self.left(col, 8, &d.format(DATE_FMT).to_string());
+        }
+    }
+
+    fn time(&mut self, col: usize, course_col: usize, value: &Option<Time>) {
+        let text = match value {
+            Some(Time::Swum(t, course)) => {
+                self.char(course_col, Some(course_code(course)));
+                format_time(t.seconds)
+            }
+            Some(Time::NoTime) => "NT".to_owned(),
+            Some(Time::NoSwim) => "NS".to_owned(),
+            Some(Time::DidNotFinish) => "DNF".to_owned(),
+            Some(Time::Disqualified) => "DQ".to_owned(),
+            Some(Time::Scratch) => "SCR".to_owned(),
+            None => return,
+        };
+        self.right(col, 8, &text);
+    }
+
+    /// Writes the columns read by `Record::swimmer`.
+    fn swimmer(&mut self, s: &Swimmer, name_col: usize, citizen_col: usize) {
+        self.left(name_col, 28, &s.name);
+        self.opt(name_col + 28, 12, &s.uss_id);
+        self.opt(citizen_col, 3, &s.citizen);
+        self.date(citizen_col + 3, s.birth_date);
+        self.num(citizen_col + 11, 2, s.age);
+        self.char(citizen_col + 13, s.gender.as_ref().map(gender_code));
+    }
+
+    /// Writes the columns read by `Record::result`.
+    fn result(&mut self, r: &EventResult, event_col: usize, date_col: usize) {
+        let col = |c: usize| match c {
+            67..=80 => event_col + c - 67,
+            _ => date_col + c - 81,
+        };
+        let SwimEvent(distance, stroke, _) = &r.event;
+        self.char(col(67), Some(gender_code(&r.event_gender)));
+        self.right(col(68), 4, &(distance.clone() as u16).to_string());
+        self.char(col(72), Some(stroke_code(stroke)));
+        self.opt(col(73), 4, &r.event_number);
+        self.opt(col(77), 4, &r.age_group.map(|a| a.code()));
+        self.date(col(81), r.date);
+        self.time(col(89), col(97), &r.seed);
+        self.time(col(98), col(106), &r.prelims);
+        self.time(col(107), col(115), &r.swim_off);
+        self.time(col(116), col(124), &r.finals);
+        self.num(col(125), 2, r.prelims_heat);
+        self.num(col(127), 2, r.prelims_lane);
+        self.num(col(129), 2, r.finals_heat);
+        self.num(col(131), 2, r.finals_lane);
+        self.num(col(133), 3, r.prelims_place);
+        self.num(col(136), 3, r.finals_place);
+        self.num(col(139), 4, r.points);
+    }
+}
+
+impl Display for RecordBuf {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}\r\n", self.0.iter().collect::<String>())
+    }
+}
+
+/// Formats seconds as SDIF writes times, such as `59.87` or `1:04.02`, the
+/// same as a [`SwimTime`] without the relay marker.
+fn format_time(seconds: f32) -> String {
+    SwimTime {
+        seconds,
+        relay: false,
+    }
+    .to_string()
+}
+
+/// Number of each kind of record written, for the Z0 record.
+#[derive(Default)]
+struct Counts {
+    b: u32,
+    c: u32,
+    d: u32,
+    e: u32,
+    f: u32,
+    g: u32,
+    swimmers: HashSet<String>,
+}
+
+/// Writes G0 records for the splits of one swim, ten to a record, in a run
+/// of records for each round.
+fn write_splits(
+    f: &mut Formatter<'_>,
+    swimmer: Option<&Swimmer>,
+    splits: &[Split],
+    counts: &mut Counts,
+) -> fmt::Result {
+    let mut rounds: Vec<Round> = Vec::new();
+    for s in splits {
+        if !rounds.contains(&s.round) {
+            rounds.push(s.round);
+        }
+    }
+    for round in rounds {
+        let splits: Vec<&Split> = splits
+            .iter()
+            .filter(|s| s.round == round && s.distance > 0)
+            .collect();
+        let first = match splits.first() {
+            Some(s) => s,
+            None => continue,
+        };
+        // Splits are evenly spaced, though some may be missing. Each is
+        // written in the slot for its distance
+        let split_distance = splits.iter().fold(0, |d, s| gcd(d, s.distance));
+        let slot = |s: &Split| (s.distance / split_distance) as usize - 1;
+        let n_slots = splits.iter().map(|s| slot(s) + 1).max().unwrap_or(0);
+        for i in 0..n_slots.div_ceil(SPLITS_PER_RECORD) {
+            let mut r = RecordBuf::new("G0");
+            if let Some(s) = swimmer {
+                r.left(16, 28, &s.name);
+                r.opt(44, 12, &s.uss_id);
+            }
+            r.num(56, 1, Some(i + 1));
+            r.num(57, 2, Some(n_slots));
+            r.num(59, 4, Some(split_distance));
+            r.char(63, Some(if first.cumulative { 'C' } else { 'I' }));
+            for s in splits.iter().filter(|s| slot(s) / SPLITS_PER_RECORD == i) {
+                let col = 64 + 8 * (slot(s) % SPLITS_PER_RECORD);
+                r.right(col, 8, &format_time(s.seconds));
+            }
+            r.char(144, Some(round.code()));
+            write!(f, "{}", r)?;
+            counts.g += 1;
+        }
+    }
+    Ok(())
+}
+
+fn gcd(a: u16, b: u16) -> u16 {
+    match b {
+        0 => a,
+        b => gcd(b, a % b),
+    }
+}
+
+fn write_swim(f: &mut Formatter<'_>, swim: &Swim, counts: &mut Counts) -> fmt::Result {
+    let s = &swim.swimmer;
+    let mut r = RecordBuf::new("D0");
+    r.swimmer(s, 12, 53);
+    r.result(&swim.result, 67, 81);
+    write!(f, "{}", r)?;
+    counts.d += 1;
+    counts.swimmers.insert(s.name.clone());
+
+    // IDs too long for the D0 record are written in full in a D3 record
+    let long_id = s.uss_id.as_ref().filter(|id| id.chars().count() > 12);
+    if long_id.is_some() || s.preferred_name.is_some() {
+        let mut r = RecordBuf::new("D3");
+        r.opt(3, 14, &s.uss_id);
+        r.opt(17, 15, &s.preferred_name);
+        write!(f, "{}", r)?;
+        counts.d += 1;
+    }
+
+    write_splits(f, Some(s), &swim.result.splits, counts)
+}
+
+fn write_relay(
+    f: &mut Formatter<'_>,
+    team: &Team,
+    relay: &Relay,
+    counts: &mut Counts,
+) -> fmt::Result {
+    let mut r = RecordBuf::new("E0");
+    r.left(12, 1, &relay.name);
+    r.left(13, 6, &team.code);
+    r.num(19, 2, Some(relay.swimmers.len()));
+    r.num(35, 3, relay.total_age);
+    r.result(&relay.result, 21, 38);
+    write!(f, "{}", r)?;
+    counts.e += 1;
+
+    for rs in &relay.swimmers {
+        let s = &rs.swimmer;
+        let mut r = RecordBuf::new("F0");
+        r.left(16, 6, &team.code);
+        r.left(22, 1, &relay.name);
+        r.swimmer(s, 23, 63);
+        r.char(77, rs.prelims_leg.map(|l| l.code()));
+        r.char(78, rs.swim_off_leg.map(|l| l.code()));
+        r.char(79, rs.finals_leg.map(|l| l.code()));
+        r.time(80, 88, &rs.leg_time);
+        r.opt(93, 14, &s.uss_id);
+        r.opt(107, 15, &s.preferred_name);
+        write!(f, "{}", r)?;
+        counts.f += 1;
+    }
+
+    write_splits(f, None, &relay.result.splits, counts)
+}
+
+fn write_team(f: &mut Formatter<'_>, team: &Team, counts: &mut Counts) -> fmt::Result {
+    let mut r = RecordBuf::new("C1");
+    r.left(12, 6, &team.code);
+    r.char(150, team.code.chars().nth(6));
+    r.left(18, 30, &team.name);
+    r.opt(48, 16, &team.short_name);
+    r.opt(108, 20, &team.city);
+    r.opt(128, 2, &team.state);
+    r.opt(140, 3, &team.country);
+    write!(f, "{}", r)?;
+    counts.c += 1;
+
+    for swim in &team.swims {
+        write_swim(f, swim, counts)?;
+    }
+    for relay in &team.relays {
+        write_relay(f, team, relay, counts)?;
+    }
+    Ok(())
+}
+
+fn write_meet(f: &mut Formatter<'_>, meet: &Meet, counts: &mut Counts) -> fmt::Result {
+    let mut r = RecordBuf::new("B1");
+    r.left(12, 30, &meet.name);
+    r.opt(42, 22, &meet.address);
+    r.opt(86, 20, &meet.city);
+    r.opt(106, 2, &meet.state);
+    r.opt(108, 10, &meet.postal_code);
+    r.opt(118, 3, &meet.country);
+    r.char(121, meet.meet_type.map(|t| t.code()));
+    r.date(122, meet.start_date);
+    r.date(130, meet.end_date);
+    r.num(138, 4, meet.altitude);
+    r.char(150, meet.course.as_ref().map(course_code));
+    write!(f, "{}", r)?;
+    counts.b += 1;
+
+    for team in &meet.teams {
+        write_team(f, team, counts)?;
+    }
+    Ok(())
+}
+
+impl Display for SdifFile {
+    /// Writes the file as SDIF records, each ending in CRLF. The counts in
+    /// the Z0 record are computed from what is written.
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let h = &self.header;
+        let mut r = RecordBuf::new("A0");
+        r.left(4, 8, SDIF_VERSION);
+        r.left(12, 2, h.file_type.code());
+        r.opt(44, 20, &h.software_name);
+        r.opt(64, 10, &h.software_version);
+        r.opt(74, 20, &h.contact_name);
+        r.opt(94, 12, &h.contact_phone);
+        r.date(106, h.created);
+        r.opt(156, 2, &h.lsc);
+        write!(f, "{}", r)?;
+
+        let mut counts = Counts::default();
+        for meet in &self.meets {
+            write_meet(f, meet, &mut counts)?;
+        }
+
+        let mut r = RecordBuf::new("Z0");
+        r.left(12, 2, h.file_type.code());
+        r.num(44, 3, Some(counts.b));
+        r.num(47, 3, Some(self.meets.len()));
+        r.num(50, 4, Some(counts.c));
+        r.num(54, 4, Some(counts.c));
+        r.num(58, 6, Some(counts.d));
+        r.num(64, 6, Some(counts.swimmers.len()));
+        r.num(70, 5, Some(counts.e));
+        r.num(75, 6, Some(counts.f));
+        r.num(81, 6, Some(counts.g));
+        write!(f, "{}", r)
+    }
+}
+
+impl SdifFile {
+    /// Builds a results file of one team's times, such as the times from a
+    /// Top Times search for the team's swimmers. Times are grouped into
+    /// meets by meet ID, or by name where there is no ID, in the order they
+    /// first appear. `team_code` is the LSC code followed by the club code,
+    /// such as `PCSCSC`.
+    pub fn from_top_times(team_code: &str, team_name: &str, times: &[TopTime]) -> Result<Self> {
+        if !(3..=7).contains(&team_code.chars().count()) {
+            bail!("Unexpected SDIF team code: {}", team_code);
+        }
+
+        let mut meets: Vec<(Meet, Vec<Swim>)> = Vec::new();
+        let mut index: HashMap<String, usize> = HashMap::new();
+        for t in times {
+            if t.course == Course::All {
+                bail!("time has no course: {:?}", t);
+            }
+            let key = t
+                .meet_id
+                .map_or_else(|| t.meet_name.clone(), |id| id.to_string());
+            let i = *index.entry(key).or_insert_with(|| {
+                meets.push((
+                    Meet {
+                        name: t.meet_name.clone(),
+                        address: None,
+                        city: None,
+                        state: None,
+                        postal_code: None,
+                        country: None,
+                        meet_type: None,
+                        start_date: Some(t.date),
+                        end_date: Some(t.date),
+                        altitude: None,
+                        course: Some(t.course.clone()),
+                        teams: Vec::new(),
+                    },
+                    Vec::new(),
+                ));
+                meets.len() - 1
+            });
+
+            let (meet, swims) = &mut meets[i];
+            meet.start_date = meet.start_date.min(Some(t.date));
+            meet.end_date = meet.end_date.max(Some(t.date));
+            if meet.course.as_ref() != Some(&t.course) {
+                // Meets with mixed courses leave each time to give its own
+                meet.course = None;
+            }
+            swims.push(Swim {
+                swimmer: Swimmer {
+                    name: t.swimmer_name.clone(),
+                    uss_id: None,
+                    birth_date: None,
+                    age: Some(t.age),
+                    gender: Some(t.gender.clone()).filter(|g| *g != Gender::Mixed),
+                    citizen: None,
+                    preferred_name: None,
+                },
+                result: EventResult {
+                    event: SwimEvent(t.distance.clone(), t.stroke.clone(), t.course.clone()),
+                    event_number: None,
+                    event_gender: t.gender.clone(),
+                    age_group: None,
+                    date: Some(t.date),
+                    seed: None,
+                    prelims: None,
+                    swim_off: None,
+                    finals: Some(Time::Swum(
+                        SwimTime {
+                            seconds: t.time,
+                            relay: false,
+                        },
+                        t.course.clone(),
+                    )),
+                    prelims_heat: None,
+                    prelims_lane: None,
+                    finals_heat: None,
+                    finals_lane: None,
+                    prelims_place: None,
+                    finals_place: None,
+                    points: None,
+                    splits: Vec::new(),
+                },
+            });
+        }
+
+        Ok(SdifFile {
+            header: Header {
+                file_type: FileType::MeetResults,
+                software_name: Some("swimrs".to_owned()),
+                software_version: Some(env!("CARGO_PKG_VERSION").to_owned()),
+                contact_name: None,
+                contact_phone: None,
+                created: Some(Local::now().naive_local().date()),
+                lsc: Some(team_code.chars().take(2).collect()),
+            },
+            meets: meets
+                .into_iter()
+                .map(|(mut meet, swims)| {
+                    meet.teams.push(Team {
+                        code: team_code.to_owned(),
+                        name: team_name.to_owned(),
+                        short_name: None,
+                        city: None,
+                        state: None,
+                        country: None,
+                        swims,
+                        relays: Vec::new(),
+                    });
+                    meet
+                })
+                .collect(),
+        })
+    }
+}
@@ -0,0 +1,37 @@
+//! Results for tests.
+
+use chrono::NaiveDate;
+
+use crate::{
+    common::{Gender, SwimEvent},
+    usas::toptimes::TopTime,
+};
+
+/// A Top Times result for `event`, like "100 FR SCY", swum by a 14 year old
+/// girl on June 1st, 2024. Tests override the fields they care about.
+pub fn top_time(event: &str, seconds: f32) -> TopTime {
+    let SwimEvent(distance, stroke, course) = event.parse().unwrap();
+    TopTime {
+        age: 14,
+        course,
+        date: NaiveDate::from_ymd_opt(2024, 6, 1).unwrap(),
+        distance,
+        foreign: None,
+        gender: Gender::Female,
+        lsc: None,
+        meet_id: Some(1),
+        meet_name: "Spring Invitational".to_owned(),
+        power_points: None,
+        rank: None,
+        relay: false,
+        sanctioned: None,
+        stroke,
+        swimmer_id: Some(1),
+        swimmer_name: "Swimmer, Alpha".to_owned(),
+        team_name: "Anytown Aquatics".to_owned(),
+        time: seconds,
+        time_alt_adj: None,
+        time_id: None,
+        time_standard: None,
+    }
+}
@@ -4,19 +4,23 @@ use std::{
     str::FromStr,
 };
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Error, Result};
 use chrono::{offset::Local, NaiveDate};
 use itertools::Itertools;
 use lazy_static::lazy_static;
 use maplit::hashmap;
+use num_enum::TryFromPrimitive;
 use regex::Regex;
-use reqwest::{Client, ClientBuilder};
+use reqwest::{Client, ClientBuilder, Response};
 use serde::{Deserialize, Serialize};
 use tracing::debug;
 
 use crate::common::{Course, Distance, Gender, Stroke, SwimEvent, SwimTime, TimeType, Zone, LSC};
 
 const DATE_FMT: &str = "%-m/%-d/%Y";
+const ID_DATE_FMT: &str = "%Y-%m-%d";
+const ID_VERSION: &str = "v1";
+const DEFAULT_MAX_RESULTS: u32 = 50000;
 const URL_PAGE: &str = "https://www.usaswimming.org/times/popular-resources/event-rank-search";
 const URL_API: &str =
     "https://www.usaswimming.org/api/Times_TimesSearchTopTimesEventRankSearch/ListTimes";
@@ -26,6 +30,8 @@ lazy_static! {
         r"(?s)GetProgression\(.*?, .*?, (.*?),.*?GetIndividualReport\(.*?, (.*?),.*'(.*?)'\)"
     )
     .unwrap();
+    static ref RE_RELAY_SCRIPT: Regex =
+        Regex::new(r"(?s)Get(?:Relay|Individual)Report\(.*?, (\d+),.*?'([0-9/]+)'\)").unwrap();
 }
 
 #[derive(Debug, Clone)]
@@ -33,8 +39,10 @@ pub struct TopTimesClient {
     client: Client,
 }
 
-/// Input for Top Times / Event Rank Search
+/// Input for Top Times / Event Rank Search. Fields missing when deserializing
+/// take their values from [`TopTimesRequest::default`].
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct TopTimesRequest {
     pub gender: Gender,
     pub distance: Distance,
@@ -52,7 +60,7 @@ pub struct TopTimesRequest {
     pub max_results: u32,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct TopTime {
     pub age: u8,
     pub course: Course,
@@ -148,6 +156,135 @@ pub fn parse_top_times(raw_html: String, gender: Gender) -> Result<Vec<TopTime>>
         .collect::<Result<Vec<TopTime>>>()
 }
 
+/// A relay time from a Top Times / Event Rank Search for relays.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TopRelayTime {
+    pub age_group: Option<String>,
+    pub course: Course,
+    pub date: Option<NaiveDate>,
+    pub distance: Distance,
+    pub gender: Gender,
+    pub lsc: Option<LSC>,
+    pub meet_id: Option<usize>,
+    pub meet_name: String,
+    pub rank: Option<usize>,
+    pub relay_name: Option<String>,
+    pub sanctioned: Option<bool>,
+    pub stroke: Stroke,
+    pub team_name: String,
+    pub time: f32,
+    pub time_standard: Option<String>,
+}
+
+/// Parses relay results. Unlike [`parse_top_times`], columns are located by
+/// their header rather than their position, since relay searches omit some of
+/// the swimmer columns found in individual results.
+pub fn parse_top_relay_times(raw_html: String, gender: Gender) -> Result<Vec<TopRelayTime>> {
+    let dom = tl::parse(&raw_html, tl::ParserOptions::default())?;
+    let parser = dom.parser();
+
+    let headers: Vec<String> = dom
+        .query_selector("th")
+        .context("error parsing selector")?
+        .filter_map(|x| x.get(parser))
+        .filter(|x| {
+            x.as_tag()
+                .and_then(|t| t.attributes().class())
+                .map(|c| c.as_utf8_str() != "usas-hide-desktop")
+                .unwrap_or(true)
+        })
+        .map(|x| {
+            let text = x.inner_text(parser);
+            let text = text.split("&nbsp;").next().unwrap_or_default();
+            text.trim().to_uppercase()
+        })
+        .collect();
+    if headers.is_empty() {
+        return Ok(Vec::new());
+    }
+    let col = |names: &[&str]| headers.iter().position(|h| names.contains(&h.as_str()));
+    let rank_col = col(&["RANK"]);
+    let time_col = col(&["TIME"]).context("relay results have no time column")?;
+    let relay_name_col = col(&["FULL NAME", "RELAY NAME", "NAME"]);
+    let age_col = col(&["AGE", "AGE GROUP"]);
+    let lsc_col = col(&["LSC"]);
+    let event_col = col(&["EVENT"]).context("relay results have no event column")?;
+    let team_col =
+        col(&["TEAM NAME", "CLUB NAME", "TEAM"]).context("relay results have no team column")?;
+    let meet_col = col(&["MEET NAME", "MEET"]).context("relay results have no meet column")?;
+    let standard_col = col(&["TIME STANDARD"]);
+    let sanctioned_col = col(&["SANCTIONED?"]);
+    let results_col = col(&["RESULTS"]);
+
+    let cells: Vec<&tl::Node> = dom
+        .query_selector("td.usas-hide-mobile")
+        .context("error parsing selector")?
+        .filter_map(|x| x.get(parser))
+        .collect();
+
+    cells
+        .chunks_exact(headers.len())
+        .map(|r| {
+            let text = |i: usize| {
+                r[i].inner_text(parser)
+                    .replace("<br>", "")
+                    .trim()
+                    .to_owned()
+            };
+            let opt_text = |i: Option<usize>| i.map(text).filter(|t| !t.is_empty());
+
+            let rank = match opt_text(rank_col) {
+                Some(r) => Some(r.parse::<usize>()?),
+                None => None,
+            };
+            let SwimTime { seconds, .. } = SwimTime::from_str(&text(time_col))?;
+            let lsc = match opt_text(lsc_col) {
+                Some(l) => Some(LSC::from_str(&l)?),
+                None => None,
+            };
+            let SwimEvent(distance, stroke, course) = SwimEvent::from_str(&text(event_col))?;
+
+            let (meet_id, date) = match results_col
+                .map(|i| r[i].inner_html(parser))
+                .as_deref()
+                .and_then(|script| RE_RELAY_SCRIPT.captures(script))
+            {
+                Some(caps) => (
+                    Some(
+                        caps.get(1)
+                            .context("meet_id not found")?
+                            .as_str()
+                            .parse::<usize>()?,
+                    ),
+                    Some(NaiveDate::parse_from_str(
+                        caps.get(2).context("date not found")?.as_str(),
+                        DATE_FMT,
+                    )?),
+                ),
+                None => (None, None),
+            };
+
+            Ok(TopRelayTime {
+                age_group: opt_text(age_col),
+                course,
+                date,
+                distance,
+                gender: gender.clone(),
+                lsc,
+                meet_id,
+                meet_name: text(meet_col),
+                rank,
+                relay_name: opt_text(relay_name_col),
+                sanctioned: opt_text(sanctioned_col).map(|s| s == "Yes"),
+                stroke,
+                team_name: text(team_col),
+                time: seconds,
+                time_standard: opt_text(standard_col),
+            })
+        })
+        .collect::<Result<Vec<TopRelayTime>>>()
+}
+
 impl TopTimesClient {
     /// Creates a TopTimesClient from the provided Reqwest client builder.
     /// Enables the cookie jar, which is required for HTTP requests to
@@ -165,19 +302,18 @@ impl TopTimesClient {
         Ok(())
     }
 
+    /// Sends a USA Swimming Top Times / Event Rank Search using the given
+    /// request parameters and returns the response, whatever its status.
+    pub async fn send(&self, req: TopTimesRequest) -> Result<Response> {
+        let form = HashMap::from(req);
+        let resp = self.client.post(URL_API).form(&form).send().await?;
+        Ok(resp)
+    }
+
     /// Performs a USA Swimming Top Times / Event Rank Search using the given
     /// request parameters and returns the raw HTML response.
     pub async fn fetch_html(&self, req: TopTimesRequest) -> Result<String> {
-        let form = HashMap::from(req);
-        let resp = self
-            .client
-            .post(URL_API)
-            .form(&form)
-            .send()
-            .await?
-            .error_for_status()?
-            .text()
-            .await?;
+        let resp = self.send(req).await?.error_for_status()?.text().await?;
         Ok(resp)
     }
 
@@ -188,6 +324,14 @@ impl TopTimesClient {
         let raw_html = self.fetch_html(req).await?;
         parse_top_times(raw_html, gender)
     }
+
+    /// Performs a USA Swimming Top Times / Event Rank Search for relays using
+    /// the given request parameters and returns a list of parsed relay times.
+    pub async fn fetch_top_relay_times(&self, req: TopTimesRequest) -> Result<Vec<TopRelayTime>> {
+        let gender = req.gender.clone();
+        let raw_html = self.fetch_html(req).await?;
+        parse_top_relay_times(raw_html, gender)
+    }
 }
 
 impl Default for TopTimesRequest {
@@ -211,7 +355,7 @@ impl Default for TopTimesRequest {
             time_type: TimeType::Individual,
             members_only: false,
             best_only: false,
-            max_results: 50000,
+            max_results: DEFAULT_MAX_RESULTS,
         }
     }
 }
@@ -228,7 +372,7 @@ impl Display for TopTimesRequest {
         };
         let lscs = match &self.lscs {
             Some(l) => l
-                .into_iter()
+                .iter()
                 .map(|lsc| lsc.to_string())
                 .collect::<Vec<String>>()
                 .join("+"),
@@ -256,6 +400,227 @@ impl Display for TopTimesRequest {
     }
 }
 
+impl TopTimesRequest {
+    /// Returns a stable, versioned identifier for this request, such as
+    /// `v1_gender=male_from=2008-08-09_to=2008-08-17_agemin=23_agemax=23`.
+    ///
+    /// Only the gender and date range are always present. Every other field
+    /// is omitted when it has its default value, so adding a new field
+    /// with a default leaves existing identifiers unchanged. Identifiers are
+    /// lowercase and safe to use as file names, and can be parsed back into
+    /// the request with [`FromStr`].
+    pub fn id(&self) -> String {
+        let mut parts = vec![
+            ID_VERSION.to_owned(),
+            format!("gender={}", self.gender.to_string().to_lowercase()),
+            format!("from={}", self.from_date.format(ID_DATE_FMT)),
+            format!("to={}", self.to_date.format(ID_DATE_FMT)),
+        ];
+        if self.distance != Distance::All {
+            parts.push(format!("distance={}", self.distance.clone() as u16));
+        }
+        if self.stroke != Stroke::All {
+            parts.push(format!("stroke={}", self.stroke.to_string().to_lowercase()));
+        }
+        if self.course != Course::All {
+            parts.push(format!("course={}", self.course.to_string().to_lowercase()));
+        }
+        if let Some(age) = self.start_age {
+            parts.push(format!("agemin={}", age));
+        }
+        if let Some(age) = self.end_age {
+            parts.push(format!("agemax={}", age));
+        }
+        if self.zone != Zone::All {
+            parts.push(format!("zone={}", self.zone.to_string().to_lowercase()));
+        }
+        if let Some(lscs) = &self.lscs {
+            let lscs = lscs
+                .iter()
+                .map(|lsc| lsc.to_string().to_lowercase())
+                .collect::<Vec<String>>()
+                .join("+");
+            parts.push(format!("lscs={}", lscs));
+        }
+        if self.time_type != TimeType::Individual {
+            parts.push(format!(
+                "type={}",
+                self.time_type.to_string().to_lowercase()
+            ));
+        }
+        if self.members_only {
+            parts.push("members=only".to_owned());
+        }
+        if self.best_only {
+            parts.push("best=only".to_owned());
+        }
+        if self.max_results != DEFAULT_MAX_RESULTS {
+            parts.push(format!("max={}", self.max_results));
+        }
+        parts.join("_")
+    }
+
+    /// Parses an identifier in the format used before versioned identifiers,
+    /// the lowercased [`Display`] output, such as:
+    ///
+    /// ```text
+    /// male/lcm_fr__200/all_all/individual_false_false_50000/23_all/2008-08-09_2008-08-17
+    /// ```
+    pub fn from_legacy_id(s: &str) -> Result<Self> {
+        let parts: Vec<&str> = s.split('/').collect();
+        let [gender, event, area, kind, ages, dates] = parts[..] else {
+            bail!("malformed legacy request ID: {}", s);
+        };
+        let split = |part: &str, n: usize| -> Result<Vec<String>> {
+            let fields: Vec<String> = part.splitn(n, '_').map(str::to_owned).collect();
+            if fields.len() != n {
+                bail!("malformed legacy request ID segment: {}", part);
+            }
+            Ok(fields)
+        };
+        let age = |value: &str| -> Result<Option<u8>> {
+            match value {
+                "all" => Ok(None),
+                _ => Ok(Some(value.parse()?)),
+            }
+        };
+
+        let event = split(event, 3)?;
+        let area = split(area, 2)?;
+        let kind = split(kind, 4)?;
+        let ages = split(ages, 2)?;
+        let dates = split(dates, 2)?;
+        Ok(TopTimesRequest {
+            gender: match gender {
+                "male" => Gender::Male,
+                "female" => Gender::Female,
+                "mixed" => Gender::Mixed,
+                _ => bail!("unknown gender in legacy request ID: {}", gender),
+            },
+            course: match event[0].as_str() {
+                "all" => Course::All,
+                c => Course::from_str(&c.to_uppercase())?,
+            },
+            stroke: match event[1].as_str() {
+                "all" => Stroke::All,
+                s => Stroke::from_str(&s.to_uppercase())?,
+            },
+            distance: match event[2].as_str() {
+                "all" => Distance::All,
+                d => Distance::try_from_primitive(d.trim_start_matches('_').parse::<u16>()?)?,
+            },
+            zone: match area[0].as_str() {
+                "all" => Zone::All,
+                "central" => Zone::Central,
+                "eastern" => Zone::Eastern,
+                "southern" => Zone::Southern,
+                "western" => Zone::Western,
+                z => bail!("unknown zone in legacy request ID: {}", z),
+            },
+            lscs: match area[1].as_str() {
+                "all" => None,
+                lscs => Some(
+                    lscs.split('+')
+                        .map(|lsc| LSC::from_str(&lsc.to_uppercase()))
+                        .collect::<Result<Vec<LSC>, _>>()?,
+                ),
+            },
+            time_type: match kind[0].as_str() {
+                "individual" => TimeType::Individual,
+                "relay" => TimeType::Relay,
+                t => bail!("unknown time type in legacy request ID: {}", t),
+            },
+            members_only: kind[1].parse()?,
+            best_only: kind[2].parse()?,
+            max_results: kind[3].parse()?,
+            start_age: age(&ages[0])?,
+            end_age: age(&ages[1])?,
+            from_date: NaiveDate::parse_from_str(&dates[0], ID_DATE_FMT)?,
+            to_date: NaiveDate::parse_from_str(&dates[1], ID_DATE_FMT)?,
+        })
+    }
+}
+
+impl FromStr for TopTimesRequest {
+    type Err = Error;
+
+    /// Parses an identifier produced by [`TopTimesRequest::id`].
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parts = s.split('_');
+        match parts.next() {
+            Some(ID_VERSION) => {}
+            Some(v) => bail!("unsupported request ID version: {}", v),
+            None => bail!("empty request ID"),
+        }
+
+        let mut gender = None;
+        let mut from_date = None;
+        let mut to_date = None;
+        let mut req = TopTimesRequest::default();
+
+        for part in parts {
+            let (key, value) = part
+                .split_once('=')
+                .with_context(|| format!("malformed request ID segment: {}", part))?;
+            match key {
+                "gender" => {
+                    gender = Some(match value {
+                        "male" => Gender::Male,
+                        "female" => Gender::Female,
+                        "mixed" => Gender::Mixed,
+                        _ => bail!("unknown gender in request ID: {}", value),
+                    })
+                }
+                "from" => from_date = Some(NaiveDate::parse_from_str(value, ID_DATE_FMT)?),
+                "to" => to_date = Some(NaiveDate::parse_from_str(value, ID_DATE_FMT)?),
+                "distance" => req.distance = Distance::try_from_primitive(value.parse::<u16>()?)?,
+                "stroke" => req.stroke = Stroke::from_str(&value.to_uppercase())?,
+                "course" => req.course = Course::from_str(&value.to_uppercase())?,
+                "agemin" => req.start_age = Some(value.parse::<u8>()?),
+                "agemax" => req.end_age = Some(value.parse::<u8>()?),
+                "zone" => {
+                    req.zone = match value {
+                        "central" => Zone::Central,
+                        "eastern" => Zone::Eastern,
+                        "southern" => Zone::Southern,
+                        "western" => Zone::Western,
+                        _ => bail!("unknown zone in request ID: {}", value),
+                    }
+                }
+                // An empty list is written as `lscs=`
+                "lscs" if value.is_empty() => req.lscs = Some(Vec::new()),
+                "lscs" => {
+                    req.lscs = Some(
+                        value
+                            .split('+')
+                            .map(|lsc| LSC::from_str(&lsc.to_uppercase()))
+                            .collect::<Result<Vec<LSC>, _>>()?,
+                    )
+                }
+                "type" => {
+                    req.time_type = match value {
+                        "individual" => TimeType::Individual,
+                        "relay" => TimeType::Relay,
+                        _ => bail!("unknown time type in request ID: {}", value),
+                    }
+                }
+                "members" | "best" if value != "only" => {
+                    bail!("unknown {} in request ID: {}", key, value)
+                }
+                "members" => req.members_only = true,
+                "best" => req.best_only = true,
+                "max" => req.max_results = value.parse::<u32>()?,
+                _ => bail!("unknown key in request ID: {}", key),
+            }
+        }
+
+        req.gender = gender.context("request ID is missing gender")?;
+        req.from_date = from_date.context("request ID is missing from date")?;
+        req.to_date = to_date.context("request ID is missing to date")?;
+        Ok(req)
+    }
+}
+
 impl From<TopTimesRequest> for HashMap<&str, String> {
     fn from(req: TopTimesRequest) -> Self {
         debug!("Converting TopTimesRequest to HashMap: {:?}", req);
@@ -325,8 +690,8 @@ mod tests {
             distance: Distance::_200,
             stroke: Stroke::Freestyle,
             course: Course::LCM,
-            from_date: NaiveDate::from_ymd(2008, 8, 9),
-            to_date: NaiveDate::from_ymd(2008, 8, 17),
+            from_date: NaiveDate::from_ymd_opt(2008, 8, 9).unwrap(),
+            to_date: NaiveDate::from_ymd_opt(2008, 8, 17).unwrap(),
             start_age: Some(23),
             end_age: None,
             zone: Zone::All,
@@ -363,6 +728,133 @@ mod tests {
         assert_eq!(map.get("Lscs").unwrap(), "All");
     }
 
+    #[test]
+    fn top_times_request_id_round_trip() {
+        let req = TopTimesRequest {
+            gender: Gender::Female,
+            distance: Distance::_200,
+            stroke: Stroke::MedleyRelay,
+            course: Course::SCY,
+            from_date: NaiveDate::from_ymd_opt(2008, 8, 9).unwrap(),
+            to_date: NaiveDate::from_ymd_opt(2008, 8, 17).unwrap(),
+            start_age: Some(11),
+            end_age: Some(12),
+            zone: Zone::Western,
+            lscs: Some(vec![LSC::US, LSC::Unattached]),
+            time_type: TimeType::Relay,
+            members_only: true,
+            best_only: true,
+            max_results: 100,
+        };
+        let id = req.id();
+        assert_eq!(
+            id,
+            "v1_gender=female_from=2008-08-09_to=2008-08-17_distance=200_stroke=med-r_\
+             course=scy_agemin=11_agemax=12_zone=western_lscs=us+un_type=relay_members=only_\
+             best=only_max=100"
+        );
+
+        let parsed = TopTimesRequest::from_str(&id).unwrap();
+        assert_eq!(parsed.id(), id);
+        assert_eq!(parsed.lscs, req.lscs);
+        assert_eq!(parsed.end_age, Some(12));
+    }
+
+    #[test]
+    fn top_times_request_id_edge_cases() {
+        let req = TopTimesRequest {
+            from_date: NaiveDate::from_ymd_opt(2008, 8, 9).unwrap(),
+            to_date: NaiveDate::from_ymd_opt(2008, 8, 17).unwrap(),
+            ..TopTimesRequest::default()
+        };
+        for lscs in [None, Some(vec![]), Some(vec![LSC::US])] {
+            let req = TopTimesRequest {
+                lscs: lscs.clone(),
+                ..req.clone()
+            };
+            let parsed = TopTimesRequest::from_str(&req.id()).unwrap();
+            assert_eq!(parsed.lscs, lscs);
+            assert_eq!(parsed.id(), req.id());
+        }
+
+        let id = req.id();
+        for flag in ["members", "best"] {
+            let parsed = TopTimesRequest::from_str(&format!("{}_{}=only", id, flag)).unwrap();
+            assert!(parsed.members_only || parsed.best_only);
+            for value in ["yes", "false", ""] {
+                let id = format!("{}_{}={}", id, flag, value);
+                assert!(TopTimesRequest::from_str(&id).is_err(), "{}", id);
+            }
+        }
+        assert!(TopTimesRequest::from_str(&format!("{}_lscs=us++un", id)).is_err());
+    }
+
+    #[test]
+    fn top_times_request_legacy_id() {
+        let req = TopTimesRequest {
+            gender: Gender::Female,
+            distance: Distance::_200,
+            stroke: Stroke::MedleyRelay,
+            course: Course::SCY,
+            from_date: NaiveDate::from_ymd_opt(2008, 8, 9).unwrap(),
+            to_date: NaiveDate::from_ymd_opt(2008, 8, 17).unwrap(),
+            start_age: Some(11),
+            end_age: None,
+            zone: Zone::Western,
+            lscs: Some(vec![LSC::US, LSC::Unattached]),
+            time_type: TimeType::Relay,
+            members_only: true,
+            best_only: false,
+            max_results: 100,
+        };
+        let legacy = req.to_string().to_lowercase();
+        let parsed = TopTimesRequest::from_legacy_id(&legacy).unwrap();
+        assert_eq!(parsed.id(), req.id());
+
+        let parsed = TopTimesRequest::from_legacy_id(
+            "male/all_all_all/all_all/individual_false_false_50000/all_all/2022-01-01_2022-01-01",
+        )
+        .unwrap();
+        assert_eq!(parsed.id(), "v1_gender=male_from=2022-01-01_to=2022-01-01");
+
+        assert!(TopTimesRequest::from_legacy_id(&req.id()).is_err());
+    }
+
+    #[test]
+    fn top_times_request_id_omits_defaults() {
+        let req = TopTimesRequest {
+            gender: Gender::Male,
+            from_date: NaiveDate::from_ymd_opt(2022, 1, 1).unwrap(),
+            to_date: NaiveDate::from_ymd_opt(2022, 1, 1).unwrap(),
+            start_age: Some(23),
+            ..TopTimesRequest::default()
+        };
+        let id = req.id();
+        assert_eq!(id, "v1_gender=male_from=2022-01-01_to=2022-01-01_agemin=23");
+
+        let parsed = TopTimesRequest::from_str(&id).unwrap();
+        assert_eq!(parsed.end_age, None);
+        assert_eq!(parsed.max_results, 50000);
+        assert_eq!(parsed.time_type, TimeType::Individual);
+
+        assert!(TopTimesRequest::from_str("v2_gender=male").is_err());
+        assert!(TopTimesRequest::from_str("v1_gender=male_from=2022-01-01").is_err());
+        assert!(TopTimesRequest::from_str("v1_gender=male_bogus=1").is_err());
+    }
+
+    #[test]
+    fn top_times_request_deserialize_defaults() {
+        let json = r#"{"gender": "Female", "from_date": "2022-01-01", "start_age": 11}"#;
+        let req: TopTimesRequest = serde_json::from_str(json).unwrap();
+        assert_eq!(req.gender, Gender::Female);
+        assert_eq!(req.from_date, NaiveDate::from_ymd_opt(2022, 1, 1).unwrap());
+        assert_eq!(req.start_age, Some(11));
+        assert_eq!(req.end_age, None);
+        assert_eq!(req.stroke, Stroke::All);
+        assert_eq!(req.time_type, TimeType::Individual);
+        assert_eq!(req.max_results, DEFAULT_MAX_RESULTS);
+    }
+
     #[tokio::test]
     async fn test_fetch_top_times() {
         let client = TopTimesClient::new(Client::builder()).unwrap();
@@ -373,8 +865,8 @@ mod tests {
             distance: Distance::_200,
             stroke: Stroke::Freestyle,
             course: Course::LCM,
-            from_date: NaiveDate::from_ymd(2008, 8, 9),
-            to_date: NaiveDate::from_ymd(2008, 8, 17),
+            from_date: NaiveDate::from_ymd_opt(2008, 8, 9).unwrap(),
+            to_date: NaiveDate::from_ymd_opt(2008, 8, 17).unwrap(),
             start_age: Some(23),
             end_age: Some(23),
             zone: Zone::All,
@@ -413,6 +905,111 @@ mod tests {
         assert!((last.time - 112.66).abs() < 0.01);
     }
 
+    #[test]
+    fn test_parse_top_relay_times() {
+        let html = std::fs::read_to_string("testdata/top_relay_times_small.html").unwrap();
+        let times = parse_top_relay_times(html, Gender::Female).unwrap();
+
+        assert_eq!(times.len(), 3);
+
+        let first = &times[0];
+        assert_eq!(first.relay_name.as_deref(), Some("Anytown Aquatics A"));
+        assert_eq!(first.team_name, "Anytown Aquatics");
+        assert_eq!(first.age_group.as_deref(), Some("15-18"));
+        assert_eq!(first.lsc, Some(LSC::PC));
+        assert_eq!(first.distance, Distance::_400);
+        assert_eq!(first.stroke, Stroke::FreestyleRelay);
+        assert_eq!(first.course, Course::SCY);
+        assert_eq!(first.gender, Gender::Female);
+        assert_eq!(first.meet_name, "Anytown Spring Invitational");
+        assert_eq!(first.meet_id, Some(41001));
+        assert_eq!(
+            first.date,
+            Some(NaiveDate::from_ymd_opt(2024, 3, 16).unwrap())
+        );
+        assert_eq!(first.rank, Some(1));
+        assert_eq!(first.time_standard.as_deref(), Some("Winter Juniors"));
+        assert_eq!(first.sanctioned, Some(true));
+        assert!((first.time - 185.12).abs() < 0.01);
+
+        assert_eq!(times[1].time_standard, None);
+
+        // Without a results link there's no meet ID or date
+        let last = &times[2];
+        assert_eq!(last.relay_name.as_deref(), Some("Anytown Aquatics B"));
+        assert_eq!(last.lsc, None);
+        assert_eq!(last.meet_id, None);
+        assert_eq!(last.date, None);
+        assert_eq!(last.sanctioned, Some(false));
+    }
+
+    #[test]
+    fn test_parse_top_relay_times_by_header() {
+        // Individual results share the relay layout's headers, so they exercise
+        // header-based column lookup
+        let html = std::fs::read_to_string("testdata/top_times_small.html").unwrap();
+        let times = parse_top_relay_times(html, Gender::Male).unwrap();
+
+        assert_eq!(times.len(), 16);
+
+        let first = times.first().unwrap();
+        assert_eq!(first.relay_name.as_deref(), Some("Phelps, Michael"));
+        assert_eq!(first.team_name, "Unattached");
+        assert_eq!(first.meet_name, "2008 Olympic Games");
+        assert_eq!(first.meet_id, Some(38805));
+        assert_eq!(
+            first.date,
+            Some(NaiveDate::from_ymd_opt(2008, 8, 12).unwrap())
+        );
+        assert_eq!(first.rank, Some(1));
+        assert_eq!(first.age_group.as_deref(), Some("23"));
+        assert!((first.time - 102.96).abs() < 0.01);
+    }
+
+    #[test]
+    fn top_times_csv_round_trip() {
+        let html = std::fs::read_to_string("testdata/top_times_small.html").unwrap();
+        let times = parse_top_times(html, Gender::Male).unwrap();
+
+        let mut writer = csv::Writer::from_writer(vec![]);
+        for t in &times {
+            writer.serialize(t).unwrap();
+        }
+        let data = writer.into_inner().unwrap();
+        let parsed = csv::Reader::from_reader(data.as_slice())
+            .deserialize()
+            .collect::<Result<Vec<TopTime>, _>>()
+            .unwrap();
+        assert_eq!(parsed, times);
+
+        let json = serde_json::to_string(&times).unwrap();
+        let parsed: Vec<TopTime> = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, times);
+    }
+
+    #[test]
+    fn top_relay_times_csv_round_trip() {
+        let html = std::fs::read_to_string("testdata/top_times_small.html").unwrap();
+        let mut times = parse_top_relay_times(html, Gender::Mixed).unwrap();
+        times[0].date = None;
+        times[0].lsc = Some(LSC::Unattached);
+
+        let mut writer = csv::Writer::from_writer(vec![]);
+        for t in &times {
+            writer.serialize(t).unwrap();
+        }
+        let data = writer.into_inner().unwrap();
+        let parsed = csv::Reader::from_reader(data.as_slice())
+            .deserialize()
+            .collect::<Result<Vec<TopRelayTime>, _>>()
+            .unwrap();
+        assert_eq!(parsed, times);
+
+        // LSCs are also accepted as they are displayed
+        let lsc: LSC = serde_json::from_str("\"UN\"").unwrap();
+        assert_eq!(lsc, LSC::Unattached);
+    }
+
     #[test]
     fn test_parse_top_times_large() {
         let html = std::fs::read_to_string("testdata/top_times_large.html").unwrap();